@@ -0,0 +1,56 @@
+; Bounces a ball across row 12 of the text screen, changing its color
+; on every move. Each move takes about one video frame (10000
+; instructions), so --capture-video shows it moving.
+;   nasm -f bin -o bounce.bin bounce.asm
+bits 16
+org 0
+
+        mov ax, 0xb800
+        mov es, ax
+        xor di, di
+        mov ax, 0x0720
+        mov cx, 2000
+clear:  stosw
+        loop clear
+
+        mov di, 160+66          ; row 1, centered
+        mov si, title
+        mov ah, 0x1f
+text:   lodsb
+        cmp al, 0
+        je go
+        stosw
+        jmp text
+
+go:     xor si, si              ; column
+        mov dx, 1               ; direction
+        mov bp, 158             ; moves
+        mov bl, 9               ; color
+step:   mov di, si
+        add di, di
+        add di, 12*160
+        mov ax, 0x0720
+        stosw
+        add si, dx
+        cmp si, strict word 79
+        jne left
+        mov dx, -1
+left:   cmp si, strict word 0
+        jne draw
+        mov dx, 1
+draw:   inc bl
+        and bl, 7
+        or bl, 8
+        mov di, si
+        add di, di
+        add di, 12*160
+        mov ah, bl
+        mov al, 'O'
+        stosw
+        mov cx, 9970
+delay:  loop delay
+        dec bp
+        jnz step
+        hlt
+
+title:  db "6emu text mode", 0
@@ -0,0 +1,33 @@
+; Prints "Hello, world!" through INT 10h, AH=0Eh (teletype).
+; There is no BIOS, so the program installs its own handler: it sends
+; each character to the 0xE9 debug port and to the text screen.
+;   nasm -f bin -o hello.bin hello.asm
+bits 16
+org 0
+
+        jmp short start
+msg:    db "Hello, world!", 13, 10, 0
+
+        times 0x40-($-$$) db 0
+        dw 0, 0                 ; INT 10h vector, filled in below
+
+start:  mov word [0x40], video
+        mov word [0x42], 0
+        mov ax, 0xb800
+        mov es, ax
+        xor di, di              ; cursor
+        mov si, msg
+next:   lodsb
+        cmp al, 0
+        je done
+        mov ah, 0x0e
+        int 0x10
+        jmp next
+done:   hlt
+
+video:  out 0xe9, al
+        cmp al, ' '
+        jb .ret                 ; control characters only go to the port
+        mov ah, 0x07
+        stosw
+.ret:   iret
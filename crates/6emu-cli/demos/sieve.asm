@@ -0,0 +1,52 @@
+; Sieve of Eratosthenes: writes the primes below 200 to the 0xE9
+; debug port as decimal numbers.
+;   nasm -f bin -o sieve.bin sieve.asm
+bits 16
+org 0
+
+N       equ 200
+sieve   equ 0x400
+
+        mov cx, N
+        xor bx, bx
+clear:  mov byte [bx+sieve], 0
+        inc bx
+        loop clear
+
+        mov bx, 2
+outer:  cmp byte [bx+sieve], 0
+        jne next
+        mov ax, bx
+        call print_num
+        mov si, bx
+        add si, bx
+mark:   cmp si, N
+        jae next
+        mov byte [si+sieve], 1
+        add si, bx
+        jmp mark
+next:   inc bx
+        cmp bx, N
+        jb outer
+        mov al, 10
+        out 0xe9, al
+        hlt
+
+; AX (below 2560) in decimal, followed by a space
+print_num:
+        xor cx, cx
+split:  mov dl, 10
+        div dl
+        push ax                 ; AH = digit
+        inc cx
+        mov ah, 0
+        cmp al, 0
+        jne split
+digit:  pop ax
+        mov al, ah
+        add al, '0'
+        out 0xe9, al
+        loop digit
+        mov al, ' '
+        out 0xe9, al
+        ret
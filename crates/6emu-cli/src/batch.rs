@@ -0,0 +1,314 @@
+use std::{fs, path::Path, process::exit};
+
+use sixemu_core::{cpu::Cpu, snapshot::FLAGS};
+
+use crate::{
+    json::{self, Json},
+    json_str, preset,
+};
+
+// a case that does not say otherwise stops after this many instructions
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+// bytes per row of a memory diff
+const DIFF_ROW: usize = 16;
+
+/// One program run and what the machine must look like afterwards:
+///
+/// ```json
+/// {
+///   "name": "inc ax",
+///   "program": "40f4",
+///   "initial": {"registers": {"AX": 1}},
+///   "max_instructions": 100,
+///   "expect": {
+///     "registers": {"AX": 2},
+///     "flags": {"Zero": false},
+///     "memory": [{"at": "0000:2000", "hex": "0102"}, {"at": 0, "len": 1024, "fnv1a": "0x..."}],
+///     "output": "done\n"
+///   }
+/// }
+/// ```
+///
+/// The program is hex bytes or a `file` next to the case file, loaded
+/// like `-f`; `initial` is applied as `--regs` would. A case file holds
+/// one case or an array of them.
+pub struct Case {
+    pub name: String,
+    pub program: Vec<u8>,
+    pub initial: Option<Json>,
+    pub max_instructions: u64,
+    pub expect: Json,
+}
+
+impl Case {
+    fn parse(doc: &Json, default_name: String, dir: &Path) -> Result<Self, String> {
+        let name = doc
+            .get("name")
+            .and_then(Json::as_str)
+            .map_or(default_name, str::to_string);
+        let program = match (
+            doc.get("program").and_then(Json::as_str),
+            doc.get("file").and_then(Json::as_str),
+        ) {
+            (Some(hex), None) => preset::parse_hex(hex).ok_or_else(|| format!("{}: program is not hex", name))?,
+            (None, Some(file)) => {
+                let path = dir.join(file);
+                fs::read(&path).map_err(|e| format!("{}: {}: {}", name, path.display(), e))?
+            }
+            _ => return Err(format!("{}: needs either program or file", name)),
+        };
+        let max_instructions = match doc.get("max_instructions") {
+            Some(n) => n
+                .as_u32()
+                .ok_or_else(|| format!("{}: max_instructions is not a number", name))? as u64,
+            None => MAX_INSTRUCTIONS,
+        };
+        Ok(Self {
+            program,
+            initial: doc.get("initial").cloned(),
+            max_instructions,
+            expect: doc.get("expect").cloned().unwrap_or(Json::Obj(Vec::new())),
+            name,
+        })
+    }
+
+    /// Reads every case in a case file.
+    pub fn read(path: &str) -> Result<Vec<Self>, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let doc = json::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        match doc {
+            Json::Arr(cases) => cases
+                .iter()
+                .enumerate()
+                .map(|(i, case)| Self::parse(case, format!("{}[{}]", path, i), dir))
+                .collect(),
+            case => Ok(vec![Self::parse(&case, path.to_string(), dir)?]),
+        }
+    }
+
+    /// Runs the case and lists every way the result differs from the
+    /// expectation; empty means it passed.
+    pub fn run(&self) -> Result<Vec<String>, String> {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&self.program);
+        if let Some(initial) = &self.initial {
+            preset::apply(initial, &mut cpu).map_err(|e| format!("initial: {}", e))?;
+        }
+        while cpu.stats.instructions < self.max_instructions {
+            let Some(i) = cpu.fetch() else {
+                if let Some(why) = cpu.decode_fault.take() {
+                    return Err(format!("{:04x}:{:04x}: {}", cpu.regs.cs, cpu.regs.ip, why));
+                }
+                break;
+            };
+            cpu.execute(&i);
+            if cpu.halt {
+                break;
+            }
+        }
+        check(&self.expect, &cpu).map_err(|e| format!("expect: {}", e))
+    }
+}
+
+/// 64-bit FNV-1a, the hash memory expectations use.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Compares the machine against an expectation.
+pub fn check(expect: &Json, cpu: &Cpu) -> Result<Vec<String>, String> {
+    let mut diffs = Vec::new();
+    if let Some(Json::Obj(regs)) = expect.get("registers") {
+        for (name, value) in regs {
+            let got = preset::register(cpu, name).ok_or_else(|| format!("registers.{}: unknown register", name))?;
+            let want = value
+                .as_u32()
+                .ok_or_else(|| format!("registers.{}: expected a number", name))?;
+            if want != got as u32 {
+                diffs.push(format!("{}: expected {:#06x}, got {:#06x}", name, want, got));
+            }
+        }
+    }
+    if let Some(Json::Obj(flags)) = expect.get("flags") {
+        for (name, value) in flags {
+            let Some(&(_, bit)) = FLAGS.iter().find(|(flag, _)| flag == name) else {
+                return Err(format!("flags.{}: unknown flag", name));
+            };
+            let Json::Bool(want) = *value else {
+                return Err(format!("flags.{}: expected true or false", name));
+            };
+            let got = cpu.regs.flags.to_u16() & 1 << bit != 0;
+            if want != got {
+                diffs.push(format!("{}: expected {}, got {}", name, want, got));
+            }
+        }
+    }
+    if let Some(Json::Arr(ranges)) = expect.get("memory") {
+        let mem = cpu.mem.cursor.get_ref();
+        for (i, range) in ranges.iter().enumerate() {
+            let addr =
+                preset::address(range).ok_or_else(|| format!("memory[{}]: expected an address in at", i))? as usize;
+            let at = format!("{:04x}:{:04x}", addr >> 4, addr & 0xf);
+            if let Some(want) = preset::bytes(range) {
+                let got = mem
+                    .get(addr..addr + want.len())
+                    .ok_or_else(|| format!("memory[{}]: does not fit below 1 MiB", i))?;
+                diffs.extend(bytes_diff(&at, &want, got));
+            } else if let (Some(len), Some(want)) = (
+                range.get("len").and_then(Json::as_u32),
+                range.get("fnv1a").and_then(Json::as_str),
+            ) {
+                let want = u64::from_str_radix(want.trim_start_matches("0x"), 16)
+                    .map_err(|_| format!("memory[{}]: fnv1a is not hex", i))?;
+                let got = mem
+                    .get(addr..addr + len as usize)
+                    .map(fnv1a)
+                    .ok_or_else(|| format!("memory[{}]: does not fit below 1 MiB", i))?;
+                if want != got {
+                    diffs.push(format!(
+                        "memory {} ({} bytes): fnv1a expected {:#018x}, got {:#018x}",
+                        at, len, want, got
+                    ));
+                }
+            } else {
+                return Err(format!("memory[{}]: expected bytes, hex or len and fnv1a", i));
+            }
+        }
+    }
+    if let Some(want) = expect.get("output") {
+        let want = want.as_str().ok_or("output: expected a string")?.as_bytes();
+        let got = &cpu.output[..];
+        if want != got {
+            let at = want.iter().zip(got).take_while(|(a, b)| a == b).count();
+            diffs.push(format!("output differs at byte {}", at));
+            diffs.push(format!("  expected {}", json_str(want)));
+            diffs.push(format!("  got      {}", json_str(got)));
+        }
+    }
+    Ok(diffs)
+}
+
+// the first differing row of a memory range, side by side
+fn bytes_diff(at: &str, want: &[u8], got: &[u8]) -> Vec<String> {
+    let wrong = want.iter().zip(got).filter(|(a, b)| a != b).count();
+    let Some(first) = want.iter().zip(got).position(|(a, b)| a != b) else {
+        return Vec::new();
+    };
+    let row = first / DIFF_ROW * DIFF_ROW..(first / DIFF_ROW * DIFF_ROW + DIFF_ROW).min(want.len());
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+    let marks: Vec<&str> = row
+        .clone()
+        .map(|i| if want[i] != got[i] { "^^" } else { "  " })
+        .collect();
+    vec![
+        format!(
+            "memory {}: {} of {} bytes differ, first at +{:#x}",
+            at,
+            wrong,
+            want.len(),
+            first
+        ),
+        format!("  +{:<4x} expected {}", row.start, hex(&want[row.clone()])),
+        format!("        got      {}", hex(&got[row])),
+        format!("                 {}", marks.join(" ").trim_end()),
+    ]
+}
+
+/// `emu8086 test CASES...`: runs every case and exits non-zero if any
+/// fails.
+pub fn main(paths: &[String]) {
+    if paths.is_empty() {
+        eprintln!("test needs at least one case file");
+        exit(1);
+    }
+    let (mut passed, mut failed) = (0, 0);
+    for path in paths {
+        let cases = Case::read(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        });
+        for case in cases {
+            match case.run() {
+                Ok(diffs) if diffs.is_empty() => {
+                    passed += 1;
+                    println!("ok   {}", case.name);
+                }
+                Ok(diffs) => {
+                    failed += 1;
+                    println!("FAIL {}", case.name);
+                    for diff in diffs {
+                        println!("    {}", diff);
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("FAIL {}: {}", case.name, e);
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod batch_test {
+    use std::path::Path;
+
+    use super::{fnv1a, Case};
+    use crate::json;
+
+    fn case(text: &str) -> Case {
+        Case::parse(&json::parse(text).unwrap(), "case".to_string(), Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn expectations() {
+        // mov [0x2000],ax; out 0xe9,al; hlt
+        let pass = case(&format!(
+            r#"{{
+                "program": "a3 00 20 e6 e9 f4",
+                "initial": {{"registers": {{"AX": "0x4241"}}}},
+                "expect": {{
+                    "registers": {{"AX": 16961, "IP": 6}},
+                    "flags": {{"Carry": false}},
+                    "memory": [{{"at": "0200:0000", "bytes": [65, 66]}}, {{"at": 8192, "len": 2, "fnv1a": "{:x}"}}],
+                    "output": "A"
+                }}
+            }}"#,
+            fnv1a(b"AB")
+        ));
+        assert_eq!(pass.run().unwrap(), Vec::<String>::new());
+
+        let fail = case(
+            r#"{
+                "program": "a3 00 20 e6 e9 f4",
+                "initial": {"registers": {"AX": "0x4241"}},
+                "expect": {
+                    "registers": {"AX": 1},
+                    "memory": [{"at": 8190, "hex": "0000 4143"}],
+                    "output": "AB"
+                }
+            }"#,
+        );
+        assert_eq!(
+            fail.run().unwrap(),
+            vec![
+                "AX: expected 0x0001, got 0x4241",
+                "memory 01ff:000e: 1 of 4 bytes differ, first at +0x3",
+                "  +0    expected 00 00 41 43",
+                "        got      00 00 41 42",
+                "                          ^^",
+                "output differs at byte 1",
+                "  expected \"AB\"",
+                "  got      \"A\"",
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use sixemu_core::png;
+use sixemu_devices::{
+    cga::Registers,
+    video::{self, FRAME_INSTRUCTIONS},
+};
+
+use crate::gif;
+
+/// Writes the screen as it is now to `path`: the text screen as plain
+/// text (see `video::text_screen`), or a PNG of what the adapter shows
+/// when it is in a graphics mode or the path ends in `.png`.
+pub fn write_screen(path: &str, mem: &[u8], registers: Registers) -> io::Result<()> {
+    if !registers.graphics() && !path.ends_with(".png") {
+        return fs::write(path, video::text_screen(mem));
+    }
+    let pixels = video::render_screen(mem, registers, false);
+    let mut w = BufWriter::new(File::create(path)?);
+    png::write_rgb(&mut w, video::TEXT_WIDTH as u32, video::TEXT_HEIGHT as u32, &video::to_rgb(&pixels))
+}
+
+/// Periodic snapshots of the screen, written as numbered PNG files into
+/// a directory or collected into one animated GIF.
+pub struct VideoCapture {
+    path: PathBuf,
+    gif: bool,
+    // the adapter's mode, and whether to show it as a composite monitor
+    registers: Rc<Cell<Registers>>,
+    composite: bool,
+    every: u64,
+    count: u64,
+    // gif frames with their delay; identical consecutive frames are merged
+    frames: Vec<(Vec<u8>, u16)>,
+}
+
+impl VideoCapture {
+    /// Captures every `every` frames of the screen the adapter with
+    /// `registers` shows. A path ending in `.gif` gets an animation,
+    /// anything else is a directory (created if needed).
+    pub fn new(path: &str, every: u64, registers: Rc<Cell<Registers>>, composite: bool) -> io::Result<Self> {
+        let gif = path.ends_with(".gif");
+        if !gif {
+            fs::create_dir_all(path)?;
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+            gif,
+            registers,
+            composite,
+            every: every.max(1) * FRAME_INSTRUCTIONS,
+            count: 0,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Call after each instruction with the executed instruction count.
+    pub fn tick(&mut self, instructions: u64, mem: &[u8]) -> io::Result<()> {
+        if instructions.is_multiple_of(self.every) {
+            self.snapshot(mem)?;
+        }
+        Ok(())
+    }
+
+    fn delay(&self) -> u16 {
+        // centiseconds at 60 frames per second, at least the 2 cs most
+        // viewers honour
+        (self.every / FRAME_INSTRUCTIONS * 100 / 60).clamp(2, u16::MAX as u64) as u16
+    }
+
+    pub fn snapshot(&mut self, mem: &[u8]) -> io::Result<()> {
+        let pixels = video::render_screen(mem, self.registers.get(), self.composite);
+        if self.gif {
+            let delay = self.delay();
+            match self.frames.last_mut() {
+                Some((last, d)) if *last == pixels => *d = d.saturating_add(delay),
+                _ => self.frames.push((pixels, delay)),
+            }
+        } else {
+            let name = self.path.join(format!("frame_{:05}.png", self.count));
+            let mut w = BufWriter::new(File::create(name)?);
+            png::write_rgb(
+                &mut w,
+                video::TEXT_WIDTH as u32,
+                video::TEXT_HEIGHT as u32,
+                &video::to_rgb(&pixels),
+            )?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Takes a last snapshot of the final screen and writes the GIF.
+    pub fn finish(&mut self, mem: &[u8]) -> io::Result<()> {
+        self.snapshot(mem)?;
+        if self.gif {
+            let mut w = BufWriter::new(File::create(&self.path)?);
+            gif::write_animation(
+                &mut w,
+                video::TEXT_WIDTH as u16,
+                video::TEXT_HEIGHT as u16,
+                &video::SCREEN_PALETTE,
+                &self.frames,
+            )?;
+        }
+        Ok(())
+    }
+}
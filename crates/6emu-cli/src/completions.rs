@@ -0,0 +1,217 @@
+use std::fmt::Write;
+
+use crate::{
+    config::{env_name, OPTIONS, SUBCOMMANDS},
+    json_str,
+};
+
+const BIN: &str = "emu8086";
+
+fn takes_path(value: &str) -> bool {
+    value == "FILE" || value == "PATH"
+}
+
+fn flags() -> Vec<String> {
+    let mut flags = Vec::new();
+    for opt in OPTIONS {
+        if let Some(c) = opt.short {
+            flags.push(format!("-{}", c));
+        }
+        flags.push(format!("--{}", opt.name));
+    }
+    flags
+}
+
+pub fn bash() -> String {
+    let mut s = String::new();
+    let path_flags: Vec<String> = OPTIONS
+        .iter()
+        .filter(|o| o.value.is_some_and(takes_path))
+        .flat_map(|o| o.short.map(|c| format!("-{}", c)).into_iter().chain([format!("--{}", o.name)]))
+        .collect();
+    let num_flags: Vec<String> = OPTIONS
+        .iter()
+        .filter(|o| o.value.is_some_and(|v| !takes_path(v)))
+        .map(|o| format!("--{}", o.name))
+        .collect();
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|c| c.name).collect();
+
+    let _ = writeln!(s, "_{}() {{", BIN);
+    let _ = writeln!(s, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(s, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(s, "    if [ \"$COMP_CWORD\" -eq 2 ]; then");
+    let _ = writeln!(s, "        case \"$prev\" in");
+    for cmd in SUBCOMMANDS.iter().filter(|c| !c.args.is_empty()) {
+        let _ = writeln!(
+            s,
+            "            {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+            cmd.name,
+            cmd.args.join(" ")
+        );
+    }
+    let _ = writeln!(s, "        esac");
+    let _ = writeln!(s, "    fi");
+    let file_commands: Vec<&str> = SUBCOMMANDS.iter().filter(|c| c.args.is_empty()).map(|c| c.name).collect();
+    if !file_commands.is_empty() {
+        let _ = writeln!(s, "    if [ \"$COMP_CWORD\" -ge 2 ]; then");
+        let _ = writeln!(s, "        case \"${{COMP_WORDS[1]}}\" in");
+        let _ = writeln!(
+            s,
+            "            {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;",
+            file_commands.join("|")
+        );
+        let _ = writeln!(s, "        esac");
+        let _ = writeln!(s, "    fi");
+    }
+    let _ = writeln!(s, "    case \"$prev\" in");
+    let _ = writeln!(s, "        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;", path_flags.join("|"));
+    let _ = writeln!(s, "        {}) return ;;", num_flags.join("|"));
+    let _ = writeln!(s, "    esac");
+    let _ = writeln!(s, "    local words=\"{}\"", flags().join(" "));
+    let _ = writeln!(s, "    if [ \"$COMP_CWORD\" -eq 1 ]; then");
+    let _ = writeln!(s, "        words=\"{} $words\"", subcommands.join(" "));
+    let _ = writeln!(s, "    fi");
+    let _ = writeln!(s, "    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))");
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s, "complete -o filenames -F _{} {}", BIN, BIN);
+    s
+}
+
+// text inside a single-quoted zsh `_arguments` spec
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+pub fn zsh() -> String {
+    let mut s = String::new();
+    let _ = writeln!(s, "#compdef {}", BIN);
+    let _ = writeln!(s);
+    let _ = writeln!(s, "_{}() {{", BIN);
+    for cmd in SUBCOMMANDS {
+        if cmd.args.is_empty() {
+            let _ = writeln!(s, "    if (( CURRENT > 2 )) && [[ $words[2] == {} ]]; then", cmd.name);
+            let _ = writeln!(s, "        _files");
+        } else {
+            let _ = writeln!(s, "    if (( CURRENT == 3 )) && [[ $words[2] == {} ]]; then", cmd.name);
+            let _ = writeln!(s, "        _values {} {}", cmd.name, cmd.args.join(" "));
+        }
+        let _ = writeln!(s, "        return");
+        let _ = writeln!(s, "    fi");
+    }
+    let _ = writeln!(s, "    _arguments -s \\");
+    for opt in OPTIONS {
+        let names = match opt.short {
+            Some(c) => format!("'(-{c} --{n})'{{-{c},--{n}}}'", c = c, n = opt.name),
+            None => format!("'--{}", opt.name),
+        };
+        let value = match opt.value {
+            Some(v) if takes_path(v) => format!(":{}:_files", v),
+            Some(v) => format!(":{}: ", v),
+            None => String::new(),
+        };
+        let _ = writeln!(s, "        {}[{}]{}' \\", names, zsh_escape(opt.help), value);
+    }
+    let commands: Vec<String> = SUBCOMMANDS
+        .iter()
+        .map(|c| format!("{}\\:\"{}\"", c.name, zsh_escape(c.help)))
+        .collect();
+    let _ = writeln!(s, "        '1::command:(({}))'", commands.join(" "));
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "_{} \"$@\"", BIN);
+    s
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+pub fn fish() -> String {
+    let mut s = String::new();
+    for opt in OPTIONS {
+        let short = opt.short.map(|c| format!(" -s {}", c)).unwrap_or_default();
+        let value = match opt.value {
+            Some(v) if takes_path(v) => " -r -F",
+            Some(_) => " -x",
+            None => "",
+        };
+        let _ = writeln!(
+            s,
+            "complete -c {}{} -l {}{} -d '{}'",
+            BIN,
+            short,
+            opt.name,
+            value,
+            fish_escape(opt.help)
+        );
+    }
+    for cmd in SUBCOMMANDS {
+        let _ = writeln!(
+            s,
+            "complete -c {} -n __fish_use_subcommand -x -a {} -d '{}'",
+            BIN,
+            cmd.name,
+            fish_escape(cmd.help)
+        );
+        if cmd.args.is_empty() {
+            let _ = writeln!(s, "complete -c {} -n '__fish_seen_subcommand_from {}' -F", BIN, cmd.name);
+        } else {
+            let _ = writeln!(
+                s,
+                "complete -c {} -n '__fish_seen_subcommand_from {}' -x -a '{}'",
+                BIN,
+                cmd.name,
+                cmd.args.join(" ")
+            );
+        }
+    }
+    s
+}
+
+/// Describes every option and subcommand for wrapper tooling.
+pub fn cli_json() -> String {
+    let mut s = String::new();
+    let _ = writeln!(s, "{{");
+    let _ = writeln!(s, "\"name\":{},", json_str(BIN.as_bytes()));
+    let _ = writeln!(s, "\"options\":[");
+    for (i, opt) in OPTIONS.iter().enumerate() {
+        let short = match opt.short {
+            Some(c) => json_str(c.to_string().as_bytes()),
+            None => "null".to_string(),
+        };
+        let value = match opt.value {
+            Some(v) => json_str(v.as_bytes()),
+            None => "null".to_string(),
+        };
+        let _ = writeln!(
+            s,
+            "{{\"name\":{},\"short\":{},\"value\":{},\"env\":{},\"help\":{}}}{}",
+            json_str(opt.name.as_bytes()),
+            short,
+            value,
+            json_str(env_name(opt.name).as_bytes()),
+            json_str(opt.help.as_bytes()),
+            if i + 1 < OPTIONS.len() { "," } else { "" }
+        );
+    }
+    let _ = writeln!(s, "],");
+    let _ = writeln!(s, "\"subcommands\":[");
+    for (i, cmd) in SUBCOMMANDS.iter().enumerate() {
+        let args: Vec<String> = cmd.args.iter().map(|a| json_str(a.as_bytes())).collect();
+        let _ = writeln!(
+            s,
+            "{{\"name\":{},\"usage\":{},\"args\":[{}],\"help\":{}}}{}",
+            json_str(cmd.name.as_bytes()),
+            json_str(cmd.usage.as_bytes()),
+            args.join(","),
+            json_str(cmd.help.as_bytes()),
+            if i + 1 < SUBCOMMANDS.len() { "," } else { "" }
+        );
+    }
+    let _ = writeln!(s, "]");
+    let _ = writeln!(s, "}}");
+    s
+}
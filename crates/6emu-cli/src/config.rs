@@ -0,0 +1,337 @@
+use std::{collections::BTreeMap, env, fs};
+
+/// A command-line option. Every option can also be given as an
+/// environment variable (`SIXEMU_` + upper-cased name, `-` as `_`) or as a
+/// key in the config file; the command line wins over the environment,
+/// which wins over the file.
+pub struct Opt {
+    pub name: &'static str,
+    pub short: Option<char>,
+    /// Name of the argument, `None` for switches.
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+const fn opt(name: &'static str, value: Option<&'static str>, help: &'static str) -> Opt {
+    Opt {
+        name,
+        short: None,
+        value,
+        help,
+    }
+}
+
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        name: "file",
+        short: Some('f'),
+        value: Some("FILE"),
+        help: "binary file",
+    },
+    opt("stdin", None, "read binary from stdin"),
+    opt("asm", Some("FILE"), "assemble FILE with the built-in assembler and run it"),
+    opt("manifest", Some("FILE"), "load several binaries at the addresses a TOML manifest lists"),
+    opt("watch", None, "rerun the -f binary or --asm source, printing a fresh dump, whenever it changes"),
+    opt("regs", Some("FILE"), "set registers, flags and memory from a json file (the dump's own shape) before the run"),
+    opt("demo", Some("NAME"), "run a bundled program (hello, sieve, bounce)"),
+    opt("fda", Some("FILE"), "floppy image in drive A: (INT 13h)"),
+    opt("fdb", Some("FILE"), "floppy image in drive B:"),
+    opt("hda", Some("FILE"), "hard disk image 80h, read in chunks as needed"),
+    opt("hdb", Some("FILE"), "hard disk image 81h"),
+    opt("rom", Some("FILE"), "map a ROM image read-only at FILE@SEG (default c800); comma-separate several"),
+    opt("umb", Some("RANGES"), "make only these upper memory ranges RAM, e.g. c800-cfff,d000-efff"),
+    opt("ivt", Some("TARGET"), "point all interrupt vectors at an IRET stub (iret), that plus disk/clock trampolines (hle), or at SEG:OFF"),
+    opt("poison", Some("RANGES"), "fail any guest access to these ranges, e.g. 0x500-0x5ff,0050:0200-0050:02ff"),
+    opt("boot", None, "boot from the floppy in A: instead of loading a binary"),
+    opt("com1", Some("LINK"), "connect COM1 to another instance (listen:HOST:PORT or HOST:PORT) or to stdio"),
+    opt("com2", Some("LINK"), "connect COM2 the same way"),
+    opt("switches", Some("N"), "the DIP switches port 62h reads, bit 0 for switch 1 (default 0x2d: one floppy, 640K, CGA 80x25)"),
+    opt("virtual-clock", Some("EPOCH"), "derive the clock from the instruction count, starting at EPOCH (secs or YYYY-MM-DDTHH:MM:SS)"),
+    opt("timer", Some("PACING"), "pace the 18.2 Hz timer by emulated cycles (default) or the host's wall clock"),
+    opt("speed-limit", Some("N"), "run at most N instructions per host second (control: speed N|off)"),
+    opt("control", Some("ADDR"), "accept control commands (insert a FILE, eject a, speed N) on a tcp address"),
+    opt("config", Some("FILE"), "read options from a flat TOML file"),
+    opt("max-instructions", Some("N"), "stop after N instructions"),
+    opt("stats", None, "include execution counters in the dump"),
+    opt("devices", None, "include the state of the devices (com1, cmos, timer) in the dump"),
+    opt("trace", Some("FILE"), "log every executed instruction"),
+    opt("trace-format", Some("FORMAT"), "write the --trace log as text (default), csv, jsonl or binary"),
+    opt("syntax", Some("SYNTAX"), "write --trace instructions in intel (default) or att syntax"),
+    opt("trace-rotate", Some("SIZE"), "start a new --trace file once it reaches SIZE bytes (K, M or G suffix)"),
+    opt("trace-keep", Some("N"), "rotated --trace files kept besides the current one (default 3)"),
+    opt("crash-trace", Some("FILE"), "on a failure, replay from the last checkpoint and trace it into FILE"),
+    opt("checkpoint-every", Some("N"), "instructions between --crash-trace checkpoints (default 100000)"),
+    opt("checkpoints", Some("K"), "--crash-trace checkpoints kept in memory (default 4)"),
+    opt("heatmap", Some("FILE"), "write per-address execution counts (.csv, otherwise png)"),
+    opt("cfg", Some("FILE"), "write the executed control-flow graph as graphviz dot"),
+    opt("cfg-counts", None, "annotate the graph with execution counts"),
+    opt("branches", Some("FILE"), "write per-site conditional jump outcomes and their flag writers"),
+    opt("coverage", Some("FILE"), "write coverage by file offset (.info for lcov, otherwise json)"),
+    opt("chrome-trace", Some("FILE"), "write a chrome trace event timeline (chrome://tracing, perfetto)"),
+    opt("html-trace", Some("FILE"), "write a self-contained html page stepping through the run"),
+    opt("save-state", Some("FILE"), "save registers and memory after the run, for emu8086 diff"),
+    opt("pipe", None, "run as a filter: stdin feeds int 21h reads, guest output streams to stdout instead of the dump"),
+    opt("delta", None, "print what the run changed (registers, flags, memory ranges) instead of the dump"),
+    opt("terminal", None, "draw the text screen in this terminal and type into int 16h; Ctrl-] quits, Ctrl-\\ pastes"),
+    opt("keyboard", Some("LAYOUT"), "the host keyboard layout --terminal translates: us, uk, de or fr (default us)"),
+    opt("codepage", Some("CP"), "the code page --terminal shows the text screen in: 437 or 850 (default 437)"),
+    opt("paste-delay", Some("N"), "video frames between keys pasted with Ctrl-\\ in --terminal (default 1)"),
+    opt("refresh", Some("HZ"), "times a second of host time --terminal redraws the screen at most (default 60)"),
+    opt("screen", Some("FILE"), "dump the text screen after the run, or a PNG in graphics modes or for FILE.png"),
+    opt("capture-video", Some("PATH"), "snapshot the screen into PATH/frame_N.png (or PATH.gif)"),
+    opt("capture-every", Some("N"), "capture every N video frames"),
+    opt("composite", None, "capture 640x200 graphics in the artifact colors of a composite monitor"),
+    opt("explain", None, "narrate every step on stderr (addresses, operands, flags)"),
+    opt("debug", None, "stop before the first instruction and read step/finish/skip commands from stdin"),
+    opt("cs-write", Some("POLICY"), "what mov cs and pop cs do: allow (like the 8086, default), warn or fault"),
+    opt("exceptions", Some("POLICY"), "what a divide error does: stop the run (default) or deliver it through int 0"),
+    opt("cpu", Some("MODEL"), "answer CPU-detection tricks as an 8086 (default), 80186 or 80286 would"),
+    opt("prefetch", Some("MODE"), "whether code written just ahead of IP runs: coherent (default) or authentic (6-byte queue)"),
+    opt("exit", Some("LIST"), "what ends the run: hlt (default), dos (int 21h ah=4ch), jump-self, port[=N] (out to f4h)"),
+    opt("reset", Some("ACTION"), "what the reset line (command feh to port 64h) does: exit (default) or reboot at ffff:0000"),
+    opt("shutdown-port", Some("N"), "end the run when the guest writes to port N, as powering off"),
+    opt("livelock", Some("N"), "stop with an error after N unchanged loop iterations with interrupts off (default 1000), or off"),
+    opt("hypercall", Some("N"), "service INT N on the host (AH=0 putc, 1 puts, 2 exit, 3 getc)"),
+    opt("dump-cli-json", None, "describe the options and subcommands as json and exit"),
+];
+
+/// A subcommand, given as the first argument instead of options.
+pub struct Subcommand {
+    pub name: &'static str,
+    /// Fixed choices for the first argument; empty means file names.
+    pub args: &'static [&'static str],
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+pub const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "completions",
+        args: &["bash", "zsh", "fish"],
+        usage: "completions SHELL",
+        help: "print a completion script for bash, zsh or fish",
+    },
+    Subcommand {
+        name: "cp",
+        args: &[],
+        usage: "cp SRC DEST",
+        help: "copy a file into or out of a FAT12 image, named as IMAGE::FILE.EXT",
+    },
+    Subcommand {
+        name: "diff",
+        args: &[],
+        usage: "diff [--json] BEFORE AFTER",
+        help: "list the registers, flags and memory ranges that differ between two --save-state snapshots",
+    },
+    Subcommand {
+        name: "disasm",
+        args: &[],
+        usage: "disasm [-f] FILE [--org N] [--syntax att]",
+        help: "list a binary's instructions with their addresses and bytes, as if loaded at offset N, without running it",
+    },
+    Subcommand {
+        name: "test",
+        args: &[],
+        usage: "test CASES...",
+        help: "run json test cases and compare registers, flags, memory and output with their expectations",
+    },
+];
+
+fn find(name: &str) -> Option<&'static Opt> {
+    OPTIONS.iter().find(|o| o.name == name)
+}
+
+pub fn env_name(name: &str) -> String {
+    format!("SIXEMU_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// Option values merged from the config file, environment and command line.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    values: BTreeMap<&'static str, String>,
+}
+
+impl Config {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Whether a switch is on.
+    pub fn flag(&self, name: &str) -> bool {
+        self.get(name) == Some("true")
+    }
+
+    fn set(&mut self, opt: &'static Opt, value: &str, from: &str) -> Result<(), String> {
+        let value = match opt.value {
+            Some(_) => value.to_string(),
+            None => match value {
+                "1" | "true" | "yes" | "on" => "true".to_string(),
+                "0" | "false" | "no" | "off" | "" => "false".to_string(),
+                _ => return Err(format!("{}: {} expects true or false", from, opt.name)),
+            },
+        };
+        self.values.insert(opt.name, value);
+        Ok(())
+    }
+
+    /// Reads `key = value` lines; values are quoted strings, numbers or
+    /// booleans. Tables are not supported.
+    pub fn parse_file(&mut self, text: &str, path: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let from = format!("{}:{}", path, n + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("{}: expected key = value", from));
+            };
+            let key = key.trim().replace('_', "-");
+            let Some(opt) = find(&key) else {
+                return Err(format!("{}: unknown option {}", from, key));
+            };
+            self.set(opt, &parse_value(value.trim(), &from)?, &from)?;
+        }
+        Ok(())
+    }
+
+    fn merge_env(&mut self, vars: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        for opt in OPTIONS {
+            let name = env_name(opt.name);
+            if let Some(value) = vars(&name) {
+                self.set(opt, &value, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let opt = match (arg.strip_prefix("--"), arg.strip_prefix('-')) {
+                (Some(long), _) => find(long),
+                (None, Some(short)) => OPTIONS
+                    .iter()
+                    .find(|o| o.short.is_some_and(|c| short == c.to_string())),
+                _ => None,
+            };
+            let Some(opt) = opt else {
+                return Err(format!("unknown option {}", arg));
+            };
+            match opt.value {
+                Some(_) => match args.next() {
+                    Some(value) => self.set(opt, value, arg)?,
+                    None => return Err(format!("{} needs a value", arg)),
+                },
+                None => self.set(opt, "true", arg)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// strips quotes and trailing comments from a TOML value
+pub(crate) fn parse_value(value: &str, from: &str) -> Result<String, String> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let tail = chars.as_str().trim();
+                    if !tail.is_empty() && !tail.starts_with('#') {
+                        return Err(format!("{}: unexpected text after string", from));
+                    }
+                    return Ok(out);
+                }
+                '\\' => match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c @ ('"' | '\\')) => out.push(c),
+                    _ => return Err(format!("{}: bad escape", from)),
+                },
+                c => out.push(c),
+            }
+        }
+        Err(format!("{}: unterminated string", from))
+    } else {
+        let value = value.split('#').next().unwrap_or("").trim();
+        if value.is_empty() {
+            return Err(format!("{}: missing value", from));
+        }
+        Ok(value.to_string())
+    }
+}
+
+/// Merges the config file (`--config` or `SIXEMU_CONFIG`), the environment
+/// and the command line `args` (without the program name).
+pub fn load(args: &[String]) -> Result<Config, String> {
+    load_with(args, |name| env::var(name).ok())
+}
+
+fn load_with(args: &[String], vars: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+    let mut cli = Config::default();
+    cli.merge_args(args)?;
+    let mut env = Config::default();
+    env.merge_env(&vars)?;
+
+    let mut config = Config::default();
+    if let Some(path) = cli.get("config").or(env.get("config")) {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        config.parse_file(&text, path)?;
+    }
+    config.values.extend(env.values);
+    config.values.extend(cli.values);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::{load_with, Config};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn file_values() {
+        let mut config = Config::default();
+        config
+            .parse_file(
+                "# run settings\nmax_instructions = 1000\nstats = true\n\
+                 trace = \"out \\\"1\\\".log\" # comment\nhypercall = 0xff\n",
+                "t.toml",
+            )
+            .unwrap();
+        assert_eq!(config.get("max-instructions"), Some("1000"));
+        assert!(config.flag("stats"));
+        assert_eq!(config.get("trace"), Some("out \"1\".log"));
+        assert_eq!(config.get("hypercall"), Some("0xff"));
+
+        assert_eq!(
+            config.parse_file("bogus = 1", "t.toml"),
+            Err("t.toml:1: unknown option bogus".to_string())
+        );
+        assert!(config.parse_file("[table]", "t.toml").is_err());
+        assert!(config.parse_file("stats = maybe", "t.toml").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let env = |name: &str| match name {
+            "SIXEMU_MAX_INSTRUCTIONS" => Some("50".to_string()),
+            "SIXEMU_TRACE" => Some("env.log".to_string()),
+            "SIXEMU_STATS" => Some("1".to_string()),
+            _ => None,
+        };
+        let config = load_with(&args(&["-f", "a.bin", "--trace", "cli.log"]), env).unwrap();
+        assert_eq!(config.get("file"), Some("a.bin"));
+        assert_eq!(config.get("trace"), Some("cli.log"));
+        assert_eq!(config.get("max-instructions"), Some("50"));
+        assert!(config.flag("stats"));
+        assert!(!config.flag("explain"));
+
+        assert!(load_with(&args(&["--nope"]), |_| None).is_err());
+        assert!(load_with(&args(&["--trace"]), |_| None).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+};
+
+use sixemu_devices::{
+    floppy::{self, Floppy},
+    governor::Governor,
+};
+
+const HELP: &str = "\
+insert DRIVE PATH  put a floppy image into a: or b:
+eject DRIVE        remove the disk, writing back changes
+drives             show what is in each drive
+speed [N|off]      show or cap the instructions run per second
+help               this list";
+
+/// Line-based commands from TCP clients (`nc localhost PORT`), polled
+/// between instructions. Every command answers with any output lines
+/// followed by `ok` or `error: message`.
+pub struct Control {
+    listener: TcpListener,
+    // each client with its unfinished line
+    clients: Vec<(TcpStream, Vec<u8>)>,
+}
+
+impl Control {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accepts new clients and runs the complete lines they have sent.
+    /// `instructions` is the count so far, for commands that pace the run.
+    pub fn poll(&mut self, floppy: &mut Floppy, governor: &mut Governor, instructions: u64) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.clients.push((stream, Vec::new()));
+            }
+        }
+
+        self.clients.retain_mut(|(stream, pending)| {
+            let mut buf = [0u8; 512];
+            loop {
+                match stream.read(&mut buf) {
+                    Ok(0) => return false,
+                    Ok(n) => pending.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => return false,
+                }
+            }
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let reply = match command(line.trim(), floppy, governor, instructions) {
+                    Ok(text) if text.is_empty() => "ok\n".to_string(),
+                    Ok(text) => format!("{}\nok\n", text),
+                    Err(e) => format!("error: {}\n", e),
+                };
+                if stream.write_all(reply.as_bytes()).is_err() {
+                    return false;
+                }
+            }
+            true
+        });
+    }
+}
+
+fn drive(name: Option<&str>) -> Result<usize, String> {
+    let name = name.ok_or("missing drive")?;
+    floppy::drive_index(name).ok_or_else(|| format!("no drive {}", name))
+}
+
+/// Runs one control command against the machine.
+pub fn command(line: &str, floppy: &mut Floppy, governor: &mut Governor, instructions: u64) -> Result<String, String> {
+    let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut args = rest.trim().splitn(2, ' ');
+    match name {
+        "insert" => {
+            let drive = drive(args.next())?;
+            let path = args.next().map(str::trim).ok_or("missing image path")?;
+            floppy.insert(drive, Path::new(path)).map_err(|e| e.to_string())?;
+            Ok(String::new())
+        }
+        "eject" => {
+            let drive = drive(args.next())?;
+            floppy.eject(drive).map_err(|e| e.to_string())?;
+            Ok(String::new())
+        }
+        "drives" => {
+            let lines: Vec<String> = floppy
+                .drives
+                .iter()
+                .zip(["a", "b"])
+                .map(|(disk, name)| match disk {
+                    Some(disk) => format!(
+                        "{}: {}{}",
+                        name,
+                        disk.path.display(),
+                        if disk.read_only { " (read-only)" } else { "" }
+                    ),
+                    None => format!("{}: empty", name),
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        "speed" => {
+            match args.next().map(str::trim) {
+                None | Some("") => {}
+                Some("off") => governor.set_limit(None, instructions),
+                Some(n) => match n.parse() {
+                    Ok(n) if n > 0 => governor.set_limit(Some(n), instructions),
+                    _ => return Err(format!("bad speed {} (instructions per second or off)", n)),
+                },
+            }
+            Ok(match governor.limit() {
+                Some(n) => format!("speed: {} instructions/s", n),
+                None => "speed: unlimited".to_string(),
+            })
+        }
+        "help" => Ok(HELP.to_string()),
+        _ => Err(format!("unknown command {} (try help)", name)),
+    }
+}
+
+#[cfg(test)]
+mod control_test {
+    use super::command;
+    use sixemu_devices::{floppy::Floppy, governor::Governor};
+
+    #[test]
+    fn commands() {
+        let mut floppy = Floppy::default();
+        let mut governor = Governor::new(None);
+        let mut command = |line: &str| command(line, &mut floppy, &mut governor, 0);
+        assert_eq!(command("drives"), Ok("a: empty\nb: empty".to_string()));
+        assert_eq!(command("eject b"), Ok(String::new()));
+        assert_eq!(command("eject c"), Err("no drive c".to_string()));
+        assert_eq!(command("insert a"), Err("missing image path".to_string()));
+        assert!(command("insert a /nonexistent/disk.img").is_err());
+        assert!(command("format a").is_err());
+        assert_eq!(command("speed"), Ok("speed: unlimited".to_string()));
+        assert_eq!(command("speed 50000"), Ok("speed: 50000 instructions/s".to_string()));
+        assert!(command("speed fast").is_err());
+        assert_eq!(command("speed off"), Ok("speed: unlimited".to_string()));
+    }
+}
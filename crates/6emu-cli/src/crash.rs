@@ -0,0 +1,167 @@
+use std::{
+    collections::VecDeque,
+    io,
+    panic::{self, AssertUnwindSafe},
+};
+
+use sixemu_core::{
+    cpu::{Cpu, RunExit},
+    disasm::Syntax,
+    snapshot::Checkpoint,
+};
+use sixemu_devices::timer::SystemTimer;
+
+use crate::tracelog::{Format, Step, TraceFile, TraceLog};
+
+/// Instructions between checkpoints unless `--checkpoint-every` says.
+pub const CHECKPOINT_EVERY: u64 = 100_000;
+
+/// Checkpoints taken every so many instructions, the last `keep` of
+/// them kept in memory, so that a run that fails can be replayed from
+/// the newest one into a trace of just the lead-up to the failure.
+pub struct CrashTrace {
+    path: String,
+    format: Format,
+    syntax: Syntax,
+    every: u64,
+    keep: usize,
+    // with the timer as it was, since it decides when ticks fall
+    ring: VecDeque<(Checkpoint, SystemTimer)>,
+}
+
+impl CrashTrace {
+    pub fn new(path: &str, format: Format, syntax: Syntax, every: u64, keep: usize) -> Self {
+        Self {
+            path: path.to_string(),
+            format,
+            syntax,
+            every: every.max(1),
+            keep: keep.max(1),
+            ring: VecDeque::new(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Call before each instruction; takes a checkpoint when one is
+    /// due, the first before the run starts.
+    pub fn poll(&mut self, cpu: &Cpu, timer: &SystemTimer) {
+        if !cpu.stats.instructions.is_multiple_of(self.every) {
+            return;
+        }
+        if self
+            .ring
+            .back()
+            .is_some_and(|(c, _)| c.stats.instructions == cpu.stats.instructions)
+        {
+            return;
+        }
+        if self.ring.len() == self.keep {
+            self.ring.pop_front();
+        }
+        self.ring.push_back((Checkpoint::of(cpu), timer.clone()));
+    }
+
+    /// Rewinds to the newest checkpoint and runs again, tracing every
+    /// instruction, until the instruction that failed after `until`
+    /// executed ones fails again or the replay runs past it. Other
+    /// devices are not rewound, so the replay can take another path.
+    /// Returns the checkpoint it started from and whether the failure
+    /// came back.
+    pub fn replay(
+        &mut self,
+        cpu: &mut Cpu,
+        timer: &mut SystemTimer,
+        mut service: impl FnMut(&mut Cpu, u8),
+        until: u64,
+    ) -> io::Result<(u64, bool)> {
+        let Some((checkpoint, saved)) = self.ring.back() else {
+            return Err(io::Error::other("no checkpoint was taken before the failure"));
+        };
+        checkpoint.restore(cpu);
+        *timer = saved.clone();
+        let from = cpu.stats.instructions;
+        let mut log = TraceLog::new(TraceFile::create(&self.path, None, 0)?, self.format)?;
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let mut failed = false;
+        while cpu.stats.instructions <= until && !cpu.halt {
+            let step = Step::decode(cpu, self.syntax);
+            let run = panic::catch_unwind(AssertUnwindSafe(|| match cpu.fetch() {
+                Some(i) => {
+                    cpu.execute(&i);
+                    true
+                }
+                None => false,
+            }));
+            if let Some(step) = step {
+                log.record(&step, cpu)?;
+            }
+            match run {
+                Ok(true) => {}
+                Ok(false) => {
+                    failed = cpu.decode_fault.is_some();
+                    break;
+                }
+                Err(_) => {
+                    failed = true;
+                    break;
+                }
+            }
+            if let Some(vector) = cpu.host_int.take() {
+                service(cpu, vector);
+            }
+            timer.poll(cpu);
+            if matches!(cpu.exit_reason, Some(RunExit::Livelock | RunExit::FatalFault)) {
+                failed = true;
+                break;
+            }
+        }
+        panic::set_hook(hook);
+        log.finish()?;
+        Ok((from, failed))
+    }
+}
+
+#[cfg(test)]
+mod crash_test {
+    use std::{fs, panic};
+
+    use sixemu_core::{cpu::Cpu, disasm::Syntax};
+    use sixemu_devices::{
+        clock::Clock,
+        timer::{Pacing, SystemTimer},
+    };
+
+    use super::CrashTrace;
+    use crate::tracelog::Format;
+
+    #[test]
+    fn replay() {
+        let path = std::env::temp_dir().join(format!("6emu-crash-{}.log", std::process::id()));
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        // mov cx,50; l: loop l; xor ax,ax; div al
+        cpu.load_code_vec(&[0xb9, 0x32, 0x00, 0xe2, 0xfe, 0x31, 0xc0, 0xf6, 0xf0]);
+        let mut timer = SystemTimer::new(Pacing::Cycles, &mut cpu, Clock::Virtual { epoch: 0 });
+        let mut crash = CrashTrace::new(path.to_str().unwrap(), Format::Text, Syntax::Intel, 20, 2);
+        let failed = panic::catch_unwind(panic::AssertUnwindSafe(|| loop {
+            crash.poll(&cpu, &timer);
+            let i = cpu.fetch().unwrap();
+            cpu.execute(&i);
+        }));
+        assert!(failed.is_err());
+        let until = cpu.stats.instructions;
+        assert_eq!(until, 53);
+
+        let replayed = crash.replay(&mut cpu, &mut timer, |_, _| {}, until).unwrap();
+        assert_eq!(replayed, (40, true));
+        let log = fs::read_to_string(&path).unwrap();
+        // the loop's last 11 turns, the xor and the div
+        assert_eq!(log.lines().count(), 13);
+        assert!(log.lines().last().unwrap().starts_with("0000:0007  f6 f0"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    cell::Cell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    capture,
+    manifest::{parse_addr, physical},
+};
+use sixemu_devices::{cga::Registers, timer::SystemTimer};
+use sixemu_core::{
+    cpu::{Cpu, DeviceState, RunExit},
+    debugger::{Debugger, FrameKind},
+};
+
+const HELP: &str = "\
+step [N]        run N instructions (default 1, also an empty line)
+continue        run without stopping
+finish          run until the current call or interrupt returns
+skip            move past the next instruction without running it
+a ADDR INSTR    assemble INSTR at ADDR (hex offset in CS, or SEG:OFF) and write it
+find A..B BYTES list where BYTES (hex, or \"text\") are between A and B (offsets in DS)
+regs            show the registers
+info [DEVICE]   show the state of the devices (com1, cmos, timer, ...)
+screen FILE     write the screen as it is now (text, or PNG in graphics modes)
+bt              list the calls the program is inside
+quit            stop the run and dump the state
+help            this list";
+
+// matches find lists before only counting the rest
+const FIND_SHOWN: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Step(u64),
+    Continue,
+    Finish,
+    Skip,
+    /// Segment (CS when not given), offset and the instruction.
+    Assemble(Option<u16>, u16, String),
+    /// Where to search from and to, inclusive (DS when not given), and
+    /// the bytes to look for.
+    Find((Option<u16>, u16), (Option<u16>, u16), Vec<u8>),
+    Regs,
+    Info(Option<String>),
+    /// Where to write the screen.
+    Screen(String),
+    Backtrace,
+    Quit,
+    Help,
+}
+
+// SEG:OFF, or a hex offset in a segment the command picks
+fn address(text: &str) -> Result<(Option<u16>, u16), String> {
+    match text.split_once(':') {
+        Some(_) => parse_addr(text).map(|(seg, off)| (Some(seg), off)),
+        None => u16::from_str_radix(text, 16).ok().map(|off| (None, off)),
+    }
+    .ok_or_else(|| format!("bad address {}", text))
+}
+
+// "text" or hex bytes, spaced or not
+fn pattern(text: &str) -> Result<Vec<u8>, String> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').ok_or("unterminated text")?;
+        return Ok(quoted.as_bytes().to_vec());
+    }
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("bad bytes {}", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("bad bytes {}", text)))
+        .collect()
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("a ").or_else(|| line.strip_prefix("assemble ")) {
+        let (addr, text) = rest.trim().split_once(char::is_whitespace).ok_or("missing instruction")?;
+        let (seg, off) = address(addr)?;
+        return Ok(Command::Assemble(seg, off, text.trim().to_string()));
+    }
+    if let Some(rest) = line.strip_prefix("find ") {
+        let (range, text) = rest.trim().split_once(char::is_whitespace).ok_or("missing bytes or text")?;
+        let (from, to) = range.split_once("..").ok_or("expected a range FROM..TO")?;
+        return Ok(Command::Find(address(from)?, address(to)?, pattern(text.trim())?));
+    }
+    let mut words = line.split_whitespace();
+    let command = match words.next().unwrap_or("step") {
+        "step" | "s" => match words.next() {
+            None => Command::Step(1),
+            Some(n) => match n.parse() {
+                Ok(n) if n > 0 => Command::Step(n),
+                _ => return Err(format!("bad step count {}", n)),
+            },
+        },
+        "continue" | "c" => Command::Continue,
+        "finish" | "f" => Command::Finish,
+        "skip" => Command::Skip,
+        "regs" | "r" => Command::Regs,
+        "info" | "i" => Command::Info(words.next().map(str::to_string)),
+        "screen" => Command::Screen(words.next().ok_or("missing file")?.to_string()),
+        "bt" => Command::Backtrace,
+        "quit" | "q" => Command::Quit,
+        "help" | "h" => Command::Help,
+        name => return Err(format!("unknown command {} (try help)", name)),
+    };
+    match words.next() {
+        Some(extra) => Err(format!("unexpected {}", extra)),
+        None => Ok(command),
+    }
+}
+
+/// Stops the run before instructions to read commands from stdin,
+/// answering on stderr.
+#[derive(Default)]
+pub struct Debug {
+    tracker: Debugger,
+    // instructions left to run before the next prompt
+    steps: u64,
+    running: bool,
+    // the adapter's mode, for `screen`
+    registers: Rc<Cell<Registers>>,
+}
+
+fn location(cpu: &mut Cpu) -> String {
+    let (cs, ip) = (cpu.regs.cs, cpu.regs.ip);
+    match cpu.decode() {
+        Ok(Some((i, next))) => {
+            let bytes: Vec<String> = cpu
+                .code_bytes(ip, next.wrapping_sub(ip) as u8)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            format!("{:04x}:{:04x}  {:<12} {}", cs, ip, bytes.join(" "), i)
+        }
+        Ok(None) => format!("{:04x}:{:04x}  end of program", cs, ip),
+        Err(why) => format!("{:04x}:{:04x}  {}", cs, ip, why),
+    }
+}
+
+fn stopped(cpu: &Cpu, why: RunExit) -> String {
+    match why {
+        RunExit::EndOfProgram => "ran past the end of the program".to_string(),
+        RunExit::DecodeFault => format!("stopped: {}", cpu.decode_fault.as_deref().unwrap_or("decode fault")),
+        why => format!("stopped: {:?}", why),
+    }
+}
+
+impl Debug {
+    /// A debugger whose `screen` shows what the adapter with `registers`
+    /// does.
+    pub fn new(registers: Rc<Cell<Registers>>) -> Self {
+        Self {
+            registers,
+            ..Self::default()
+        }
+    }
+
+    /// Called before each instruction; prompts unless stepping or
+    /// continuing. `host` services interrupts left to it while `finish`
+    /// runs. Returns false to end the run.
+    /// `timer` is shown by `info` with the devices on the bus.
+    pub fn before(&mut self, cpu: &mut Cpu, timer: &SystemTimer, mut host: impl FnMut(&mut Cpu, u8)) -> bool {
+        if self.running || self.steps > 0 {
+            self.steps = self.steps.saturating_sub(1);
+            self.tracker.before_step(cpu);
+            return true;
+        }
+        let stdin = io::stdin();
+        loop {
+            eprint!("{}\n(6emu) ", location(cpu));
+            let _ = io::stderr().flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) => {}
+            }
+            match parse(&line) {
+                Ok(Command::Step(n)) => {
+                    self.steps = n - 1;
+                    break;
+                }
+                Ok(Command::Continue) => {
+                    self.running = true;
+                    break;
+                }
+                Ok(Command::Finish) => match self.tracker.finish(cpu, &mut host) {
+                    Ok(None) => {}
+                    Ok(Some(why)) => {
+                        eprintln!("{}", stopped(cpu, why));
+                        return false;
+                    }
+                    Err(e) => eprintln!("error: {}", e),
+                },
+                Ok(Command::Skip) => {
+                    if self.tracker.skip(cpu).is_none() {
+                        match cpu.decode() {
+                            Err(why) => eprintln!("stopped: {}", why),
+                            Ok(_) => eprintln!("{}", stopped(cpu, RunExit::EndOfProgram)),
+                        }
+                        return false;
+                    }
+                }
+                Ok(Command::Assemble(seg, off, text)) => {
+                    let seg = seg.unwrap_or(cpu.regs.cs);
+                    match self.tracker.assemble(cpu, (seg, off), &text) {
+                        Ok(bytes) => {
+                            let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                            eprintln!("{:04x}:{:04x}  {}", seg, off, bytes.join(" "));
+                        }
+                        Err(e) => eprintln!("error: {}", e),
+                    }
+                }
+                Ok(Command::Find(from, to, needle)) => {
+                    let seg = from.0.unwrap_or(cpu.regs.ds);
+                    let (start, end) = (physical((seg, from.1)), physical((to.0.unwrap_or(seg), to.1)));
+                    if end < start {
+                        eprintln!("error: the range ends before it starts");
+                        continue;
+                    }
+                    let found = cpu.mem.find(start, end, &needle);
+                    for at in found.iter().take(FIND_SHOWN) {
+                        // in the segment searched from, while it reaches
+                        let (seg, off) = match at.checked_sub((seg as u32) << 4) {
+                            Some(off) if off <= 0xffff => (seg, off as u16),
+                            _ => ((at >> 4) as u16, (at & 0xf) as u16),
+                        };
+                        eprintln!("{:04x}:{:04x}", seg, off);
+                    }
+                    match found.len() {
+                        n if n > FIND_SHOWN => eprintln!("{} found, {} not shown", n, n - FIND_SHOWN),
+                        n => eprintln!("{} found", n),
+                    }
+                }
+                Ok(Command::Regs) => {
+                    let r = &cpu.regs;
+                    eprintln!(
+                        "AX {:04x} BX {:04x} CX {:04x} DX {:04x} SI {:04x} DI {:04x} BP {:04x} SP {:04x}",
+                        r.ax, r.bx, r.cx, r.dx, r.si, r.di, r.bp, r.sp
+                    );
+                    eprintln!(
+                        "CS {:04x} DS {:04x} ES {:04x} SS {:04x} IP {:04x} FLAGS {:04x}",
+                        r.cs,
+                        r.ds,
+                        r.es,
+                        r.ss,
+                        r.ip,
+                        cpu.flags_image()
+                    );
+                }
+                Ok(Command::Info(name)) => {
+                    let mut devices = cpu.device_states();
+                    devices.push(timer.debug_state());
+                    let shown: Vec<&DeviceState> = devices
+                        .iter()
+                        .filter(|d| name.as_ref().is_none_or(|name| d.name == *name))
+                        .collect();
+                    if shown.is_empty() {
+                        let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+                        eprintln!("error: no device {} (try {})", name.unwrap_or_default(), names.join(", "));
+                    }
+                    for device in shown {
+                        eprintln!("{}", device.name);
+                        for (label, value) in &device.fields {
+                            eprintln!("  {:<10} {}", label, value);
+                        }
+                    }
+                }
+                Ok(Command::Screen(path)) => {
+                    match capture::write_screen(&path, cpu.mem.cursor.get_ref(), self.registers.get()) {
+                        Ok(()) => eprintln!("screen written to {}", path),
+                        Err(e) => eprintln!("error: {}: {}", path, e),
+                    }
+                }
+                Ok(Command::Backtrace) if self.tracker.frames.is_empty() => eprintln!("not inside a call"),
+                Ok(Command::Backtrace) => {
+                    for (depth, frame) in self.tracker.frames.iter().rev().enumerate() {
+                        let kind = match frame.kind {
+                            FrameKind::Near => "call",
+                            FrameKind::Far => "call far",
+                            FrameKind::Interrupt => "int",
+                        };
+                        eprintln!(
+                            "#{} {:<8} at {:04x}:{:04x}, returns to {:04x}:{:04x}",
+                            depth, kind, frame.call.0, frame.call.1, frame.ret.0, frame.ret.1
+                        );
+                    }
+                }
+                Ok(Command::Quit) => return false,
+                Ok(Command::Help) => eprintln!("{}", HELP),
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+        self.tracker.before_step(cpu);
+        true
+    }
+
+    /// Called after each instruction ran.
+    pub fn after(&mut self, cpu: &Cpu) {
+        self.tracker.after_step(cpu);
+    }
+}
+
+#[cfg(test)]
+mod debug_test {
+    use super::{parse, Command};
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("\n"), Ok(Command::Step(1)));
+        assert_eq!(parse("step 20"), Ok(Command::Step(20)));
+        assert_eq!(parse("c"), Ok(Command::Continue));
+        assert_eq!(parse("finish"), Ok(Command::Finish));
+        assert_eq!(parse("skip"), Ok(Command::Skip));
+        assert_eq!(parse("bt"), Ok(Command::Backtrace));
+        assert_eq!(parse("info com1"), Ok(Command::Info(Some("com1".to_string()))));
+        assert_eq!(parse("screen shot.png"), Ok(Command::Screen("shot.png".to_string())));
+        assert!(parse("screen").is_err());
+        assert!(parse("step 0").is_err());
+        assert!(parse("skip 2").is_err());
+        assert!(parse("jump 100").is_err());
+        assert_eq!(
+            parse("a 0100 mov ax,5\n"),
+            Ok(Command::Assemble(None, 0x100, "mov ax,5".to_string()))
+        );
+        assert_eq!(
+            parse("a b800:10 db 'A'"),
+            Ok(Command::Assemble(Some(0xb800), 0x10, "db 'A'".to_string()))
+        );
+        assert!(parse("a 0100").is_err());
+        assert!(parse("a xyz nop").is_err());
+        assert_eq!(
+            parse("find 0..ffff \"Hello\""),
+            Ok(Command::Find((None, 0), (None, 0xffff), b"Hello".to_vec()))
+        );
+        assert_eq!(
+            parse("find b800:0..b800:0f9f cd 21 b4"),
+            Ok(Command::Find((Some(0xb800), 0), (Some(0xb800), 0xf9f), vec![0xcd, 0x21, 0xb4]))
+        );
+        assert!(parse("find 0..ff").is_err());
+        assert!(parse("find 0 cd21").is_err());
+        assert!(parse("find 0..ff cd2").is_err());
+    }
+}
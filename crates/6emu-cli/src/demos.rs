@@ -0,0 +1,60 @@
+/// A guest program bundled with the binary (sources in `demos/`).
+pub struct Demo {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub image: &'static [u8],
+}
+
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "hello",
+        help: "hello world through an INT 10h teletype handler",
+        image: include_bytes!("../demos/hello.bin"),
+    },
+    Demo {
+        name: "sieve",
+        help: "the primes below 200 on the debug port",
+        image: include_bytes!("../demos/sieve.bin"),
+    },
+    Demo {
+        name: "bounce",
+        help: "a ball bouncing across the text screen (try --capture-video)",
+        image: include_bytes!("../demos/bounce.bin"),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Demo> {
+    DEMOS.iter().find(|d| d.name == name)
+}
+
+#[cfg(test)]
+mod demos_test {
+    use super::find;
+    use sixemu_core::cpu::Cpu;
+    use sixemu_devices::video;
+
+    fn run(name: &str) -> Cpu {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(find(name).unwrap().image);
+        cpu.fire();
+        assert!(cpu.halt);
+        cpu
+    }
+
+    #[test]
+    fn hello() {
+        let cpu = run("hello");
+        assert_eq!(cpu.output, b"Hello, world!\r\n");
+        assert!(video::text_screen(cpu.mem.cursor.get_ref()).starts_with("Hello, world!\n\n"));
+    }
+
+    #[test]
+    fn sieve() {
+        let cpu = run("sieve");
+        let out = String::from_utf8(cpu.output).unwrap();
+        assert!(out.starts_with("2 3 5 7 11 13 "));
+        assert!(out.ends_with("191 193 197 199 \n"));
+        assert_eq!(out.split_whitespace().count(), 46);
+    }
+}
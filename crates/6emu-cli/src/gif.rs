@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+
+const MIN_CODE_SIZE: u8 = 5;
+const CLEAR: u16 = 1 << MIN_CODE_SIZE;
+const END: u16 = CLEAR + 1;
+// literals sent between clear codes, so the code width stays at 6 bits
+const RUN: usize = 12;
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u32) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+// LZW stream that never builds up a dictionary: every pixel is sent as a
+// literal and the table is reset before the code width would grow
+fn lzw_literal(pixels: &[u8]) -> Vec<u8> {
+    let width = MIN_CODE_SIZE as u32 + 1;
+    let mut bw = BitWriter {
+        out: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+    for run in pixels.chunks(RUN) {
+        bw.put(CLEAR, width);
+        for &p in run {
+            bw.put(p as u16 & (CLEAR - 1), width);
+        }
+    }
+    bw.put(END, width);
+    bw.finish()
+}
+
+/// Writes a looping animation with a 32-color palette. Each frame holds
+/// `width * height` palette indices and its delay in 1/100 s.
+pub fn write_animation(
+    w: &mut impl Write,
+    width: u16,
+    height: u16,
+    palette: &[[u8; 3]; 32],
+    frames: &[(Vec<u8>, u16)],
+) -> io::Result<()> {
+    w.write_all(b"GIF89a")?;
+    w.write_all(&width.to_le_bytes())?;
+    w.write_all(&height.to_le_bytes())?;
+    // global color table of 2^(4+1) entries
+    w.write_all(&[0xf4, 0, 0])?;
+    for rgb in palette {
+        w.write_all(rgb)?;
+    }
+    w.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+    for (pixels, delay) in frames {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        w.write_all(&[0x21, 0xf9, 4, 0])?;
+        w.write_all(&delay.to_le_bytes())?;
+        w.write_all(&[0, 0])?;
+
+        w.write_all(&[0x2c, 0, 0, 0, 0])?;
+        w.write_all(&width.to_le_bytes())?;
+        w.write_all(&height.to_le_bytes())?;
+        w.write_all(&[0, MIN_CODE_SIZE])?;
+        for block in lzw_literal(pixels).chunks(255) {
+            w.write_all(&[block.len() as u8])?;
+            w.write_all(block)?;
+        }
+        w.write_all(&[0])?;
+    }
+    w.write_all(&[0x3b])
+}
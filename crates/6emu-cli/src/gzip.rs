@@ -0,0 +1,310 @@
+use std::io::{self, Write};
+
+use sixemu_core::png::crc32;
+
+// input compressed as one deflate block; matches stay inside a block
+const BLOCK: usize = 1 << 16;
+const WINDOW: usize = 1 << 15;
+const HASH_BITS: u32 = 15;
+// candidates looked at per position before taking the best so far
+const MAX_CHAIN: usize = 32;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, width: u32) {
+        self.acc |= (value as u64) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    // Huffman codes go out most significant bit first
+    fn code(&mut self, code: u32, width: u32) {
+        self.put(code.reverse_bits() >> (32 - width), width);
+    }
+
+    // a literal or length symbol in the fixed code
+    fn symbol(&mut self, sym: u16) {
+        let sym = sym as u32;
+        match sym {
+            0..=143 => self.code(0x30 + sym, 8),
+            144..=255 => self.code(0x190 + sym - 144, 9),
+            256..=279 => self.code(sym - 256, 7),
+            _ => self.code(0xc0 + sym - 280, 8),
+        }
+    }
+
+    fn matched(&mut self, len: usize, dist: usize) {
+        let i = LENGTH_BASE.iter().rposition(|&b| b as usize <= len).unwrap();
+        self.symbol(257 + i as u16);
+        self.put((len - LENGTH_BASE[i] as usize) as u32, LENGTH_EXTRA[i] as u32);
+        let d = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+        self.code(d as u32, 5);
+        self.put((dist - DIST_BASE[d] as usize) as u32, DIST_EXTRA[d] as u32);
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.put(0, 8 - self.bits);
+        }
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+// one fixed-Huffman block of `data`, with LZ77 matches found through
+// hash chains
+fn deflate_block(bw: &mut BitWriter, data: &[u8], last: bool) {
+    bw.put(last as u32, 1);
+    bw.put(1, 2);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |at: usize, head: &mut [usize], prev: &mut [usize]| {
+        if at + MIN_MATCH <= data.len() {
+            let h = hash(&data[at..]);
+            prev[at] = head[h];
+            head[h] = at;
+        }
+    };
+    let mut at = 0;
+    while at < data.len() {
+        let (mut best, mut dist) = (0, 0);
+        if at + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(&data[at..])];
+            let max = MAX_MATCH.min(data.len() - at);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || at - candidate > WINDOW {
+                    break;
+                }
+                let len = (0..max).take_while(|&i| data[candidate + i] == data[at + i]).count();
+                if len > best {
+                    (best, dist) = (len, at - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+            }
+        }
+        if best >= MIN_MATCH {
+            bw.matched(best, dist);
+            for i in at..at + best {
+                insert(i, &mut head, &mut prev);
+            }
+            at += best;
+        } else {
+            bw.symbol(data[at] as u16);
+            insert(at, &mut head, &mut prev);
+            at += 1;
+        }
+    }
+    bw.symbol(256);
+}
+
+/// Compresses what is written to it into a gzip stream, a block at a
+/// time. `finish` writes the trailer; without it the stream is cut
+/// short.
+pub struct GzipWriter<W: Write> {
+    w: W,
+    buf: Vec<u8>,
+    bits: BitWriter,
+    crc: u32,
+    size: u32,
+}
+
+impl<W: Write> GzipWriter<W> {
+    pub fn new(mut w: W) -> io::Result<Self> {
+        // deflate, no name or time, unknown OS
+        w.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+        Ok(Self {
+            w,
+            buf: Vec::with_capacity(BLOCK),
+            bits: BitWriter {
+                out: Vec::new(),
+                acc: 0,
+                bits: 0,
+            },
+            crc: !0,
+            size: 0,
+        })
+    }
+
+    fn compress(&mut self, last: bool) -> io::Result<()> {
+        self.crc = crc32(&self.buf, self.crc);
+        self.size = self.size.wrapping_add(self.buf.len() as u32);
+        deflate_block(&mut self.bits, &self.buf, last);
+        self.buf.clear();
+        self.w.write_all(&self.bits.out)?;
+        self.bits.out.clear();
+        Ok(())
+    }
+
+    /// Compresses what is left and writes the checksum and size,
+    /// returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress(true)?;
+        self.bits.align();
+        self.w.write_all(&self.bits.out)?;
+        self.w.write_all(&(!self.crc).to_le_bytes())?;
+        self.w.write_all(&self.size.to_le_bytes())?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.w
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(BLOCK - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == BLOCK {
+            self.compress(false)?;
+        }
+        Ok(n)
+    }
+
+    // only what has been compressed so far reaches the writer
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod gzip_test {
+    use std::io::Write;
+
+    use sixemu_core::png::crc32;
+
+    use super::{GzipWriter, BLOCK, DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA, WINDOW};
+
+    // extra bits are read least significant first
+    fn extra(bit: &mut impl FnMut() -> u32, n: u8) -> usize {
+        (0..n).fold(0, |v, i| v | (bit() as usize) << i)
+    }
+
+    // inflates the fixed-Huffman blocks GzipWriter writes, checking the
+    // trailer; also says how far back the farthest match reached
+    fn gunzip(gz: &[u8]) -> (Vec<u8>, usize) {
+        assert_eq!(gz[..10], [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+        let mut pos = 10 * 8;
+        let mut bit = || {
+            let b = (gz[pos / 8] >> (pos % 8)) & 1;
+            pos += 1;
+            b as u32
+        };
+        let mut out: Vec<u8> = Vec::new();
+        let mut farthest = 0;
+        loop {
+            let last = bit();
+            assert_eq!(bit() | bit() << 1, 1, "not a fixed-Huffman block");
+            loop {
+                // codes are read most significant bit first
+                let mut code = (0..7).fold(0, |c, _| c << 1 | bit());
+                let sym = if code < 0x18 {
+                    256 + code
+                } else {
+                    code = code << 1 | bit();
+                    match code {
+                        0x30..=0xbf => code - 0x30,
+                        0xc0..=0xc7 => 280 + code - 0xc0,
+                        _ => 144 + (code << 1 | bit()) - 0x190,
+                    }
+                } as usize;
+                match sym {
+                    0..=255 => out.push(sym as u8),
+                    256 => break,
+                    _ => {
+                        let len = LENGTH_BASE[sym - 257] as usize + extra(&mut bit, LENGTH_EXTRA[sym - 257]);
+                        let d = (0..5).fold(0, |c, _| c << 1 | bit()) as usize;
+                        let dist = DIST_BASE[d] as usize + extra(&mut bit, DIST_EXTRA[d]);
+                        farthest = farthest.max(dist);
+                        for _ in 0..len {
+                            out.push(out[out.len() - dist]);
+                        }
+                    }
+                }
+            }
+            if last == 1 {
+                break;
+            }
+        }
+        let trailer = &gz[pos.div_ceil(8)..];
+        assert_eq!(trailer.len(), 8);
+        assert_eq!(trailer[..4], (!crc32(&out, !0)).to_le_bytes());
+        assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+        (out, farthest)
+    }
+
+    #[test]
+    fn known_streams() {
+        let gzip = |data: &[u8]| {
+            let mut w = GzipWriter::new(Vec::new()).unwrap();
+            w.write_all(data).unwrap();
+            w.finish().unwrap()
+        };
+        // checked against gzip -dc; the second is three literals and a
+        // match nine long, three back
+        assert_eq!(gzip(b""), b"\x1f\x8b\x08\0\0\0\0\0\0\xff\x03\0\0\0\0\0\0\0\0\0");
+        assert_eq!(
+            &gzip(b"abcabcabcabc")[10..],
+            b"\x4b\x4c\x4a\x86\x23\x00\x34\x2a\x6e\x5a\x0c\0\0\0"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        // three and a half blocks of noise, every other 4 KiB of it a
+        // copy of what came just under WINDOW before
+        let mut x = 0x2545f491u32;
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..BLOCK * 7 / 2 {
+            let copy = i >= WINDOW && (i / 4096) % 2 == 1;
+            data.push(if copy {
+                data[i - (WINDOW - 8)]
+            } else {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            });
+        }
+        let mut w = GzipWriter::new(Vec::new()).unwrap();
+        // written in uneven pieces that straddle the blocks
+        for piece in data.chunks(10_007) {
+            w.write_all(piece).unwrap();
+        }
+        let (out, farthest) = gunzip(&w.finish().unwrap());
+        assert!(out == data);
+        assert_eq!(farthest, WINDOW - 8);
+    }
+}
@@ -0,0 +1,1272 @@
+use std::{
+    env::args,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    process::exit,
+    thread,
+    time::Duration,
+};
+
+use capture::VideoCapture;
+use config::Config;
+use manifest::{parse_addr, physical, Manifest};
+use report::{Kind, Recent, Report};
+use crash::{CrashTrace, CHECKPOINT_EVERY};
+use tracelog::{Step, TraceFile, TraceLog};
+use control::Control;
+#[cfg(feature = "debugger")]
+use debug::Debug;
+use terminal::Terminal;
+use sixemu_core::{
+    asm,
+    branches::BranchProfile,
+    cpu::{Cpu, CpuModel, CsWrite, DeviceState, Exceptions, ExitConventions, Prefetch, RunExit, EXIT_PORT},
+    disasm::{self, Syntax},
+    explain,
+    machine::{Bus, Device},
+    profile::Profile,
+    snapshot::Snapshot,
+    trace::{Recording, Timeline},
+};
+use sixemu_devices::{
+    bios,
+    cga::Cga,
+    clock::{self, Clock, Cmos},
+    dos::{self, Console},
+    fat12::Fat12,
+    floppy::{self, Floppy},
+    font::CodePage,
+    governor::Governor,
+    harddisk::{self, HardDisk, HardDisks},
+    keyboard::{self, Layout},
+    pit::Pit,
+    ppi::{self, Ppi},
+    timer::{Pacing, SystemTimer},
+    uart::{self, Uart},
+    video::{self, FRAME_INSTRUCTIONS},
+};
+
+mod batch;
+mod capture;
+mod completions;
+mod config;
+mod control;
+mod crash;
+#[cfg(feature = "debugger")]
+mod debug;
+mod demos;
+mod gif;
+mod gzip;
+mod json;
+mod manifest;
+mod preset;
+mod report;
+mod terminal;
+mod tracelog;
+
+fn print_usement() -> ! {
+    println!("Usage: ./app options");
+    println!("       ./app command");
+
+    for opt in config::OPTIONS {
+        let short = opt.short.map(|c| format!("-{}, ", c)).unwrap_or_default();
+        let value = opt.value.map(|v| format!(" {}", v)).unwrap_or_default();
+        println!("   {}--{}{} {}", short, opt.name, value, opt.help);
+    }
+    println!();
+    println!("Commands:");
+    for cmd in config::SUBCOMMANDS {
+        println!("   {} {}", cmd.usage, cmd.help);
+    }
+    println!();
+    println!("Every option can also be set as SIXEMU_<NAME> (e.g. SIXEMU_MAX_INSTRUCTIONS)");
+    println!("or as `name = value` in the --config file; the command line wins over the");
+    println!("environment, which wins over the file.");
+
+    exit(1);
+}
+
+fn parse_num(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+// `START-END,...`, both ends included, each SEG:OFF or physical
+fn parse_ranges(list: &str) -> Option<Vec<(u32, u32)>> {
+    list.split(',')
+        .map(|range| {
+            let (start, last) = range.split_once('-')?;
+            let (start, last) = (physical(parse_addr(start)?), physical(parse_addr(last)?));
+            (start <= last).then_some((start, last + 1))
+        })
+        .collect()
+}
+
+fn json_str(bytes: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            b'\n' => s.push_str("\\n"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\u{:04x}", b)),
+        }
+    }
+    s.push('"');
+    s
+}
+
+fn write_report(path: &str, what: &str, f: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+    let res = File::create(path).and_then(|file| f(&mut BufWriter::new(file)));
+    if let Err(e) = res {
+        eprintln!("Failed to write {} {}: {}", what, path, e);
+        exit(1);
+    }
+}
+
+// `cp host.txt disk.img::FILE.TXT` and the reverse
+fn copy(args: &[String]) {
+    let [src, dest] = args else {
+        print_usement();
+    };
+    let fail = |what: &str, e: io::Error| -> ! {
+        eprintln!("Failed to copy {}: {}", what, e);
+        exit(1);
+    };
+    let open = |image: &str| match fs::read(image).and_then(Fat12::open) {
+        Ok(disk) => disk,
+        Err(e) => fail(image, e),
+    };
+
+    match (src.split_once("::"), dest.split_once("::")) {
+        (Some((image, name)), None) => {
+            let data = open(image).read_file(name).unwrap_or_else(|e| fail(src, e));
+            fs::write(dest, data).unwrap_or_else(|e| fail(dest, e));
+        }
+        (None, Some((image, name))) => {
+            let data = fs::read(src).unwrap_or_else(|e| fail(src, e));
+            let mut disk = open(image);
+            disk.write_file(name, &data).unwrap_or_else(|e| fail(dest, e));
+            fs::write(image, disk.into_image()).unwrap_or_else(|e| fail(image, e));
+        }
+        _ => {
+            eprintln!("cp needs exactly one IMAGE::FILE side");
+            exit(1);
+        }
+    }
+}
+
+/// What runs alongside the CPU in `exec_dump_state`.
+struct Run {
+    stats: bool,
+    devices: bool,
+    explain: bool,
+    max_instructions: Option<u64>,
+    capture: Option<VideoCapture>,
+    trace: Option<TraceLog<TraceFile>>,
+    floppy: Floppy,
+    disks: HardDisks,
+    clock: Clock,
+    timer: SystemTimer,
+    /// Devices that raise IRQs, ticked after every instruction.
+    bus: Bus,
+    governor: Governor,
+    control: Option<Control>,
+    #[cfg(feature = "debugger")]
+    debug: Option<Debug>,
+    crash: Option<CrashTrace>,
+    recent: Recent,
+    /// State before the run, for `--delta`.
+    delta: Option<Snapshot>,
+    /// Standard input for `int 21h`, for `--pipe`; output then streams
+    /// to stdout.
+    console: Option<Console>,
+    /// The host terminal as screen and keyboard, for `--terminal`.
+    terminal: Option<Terminal>,
+    /// How `--trace` writes instructions.
+    syntax: Syntax,
+}
+
+// services an interrupt the cpu left to the host
+fn service(cpu: &mut Cpu, vector: u8, floppy: &mut Floppy, disks: &mut HardDisks, clock: Clock) {
+    match vector {
+        floppy::DISK_INT => harddisk::int13(cpu, floppy, disks),
+        clock::TIME_INT => clock::int1a(cpu, clock),
+        video::VIDEO_INT => video::int10(cpu),
+        _ => {}
+    }
+}
+
+// lists a binary's instructions front to back, running nothing
+fn disasm(args: &[String]) {
+    let (mut path, mut org, mut syntax) = (None, 0, Syntax::Intel);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--file" => path = args.next(),
+            "--org" => match args.next().and_then(|n| parse_num(n)) {
+                Some(n) if n <= 0xffff => org = n as u16,
+                _ => print_usement(),
+            },
+            "--syntax" => match args.next().and_then(|name| Syntax::parse(name)) {
+                Some(s) => syntax = s,
+                None => print_usement(),
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => print_usement(),
+        }
+    }
+    let Some(path) = path else {
+        print_usement();
+    };
+    let code = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        exit(1);
+    });
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for line in disasm::disassemble(&code, org, syntax) {
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        if writeln!(out, "{:04x}  {:<12} {}", line.addr, bytes.join(" "), line.text).is_err() {
+            return;
+        }
+    }
+    let _ = out.flush();
+}
+
+// exits 0 when the snapshots match and 1 when they differ, as diff does
+fn diff(args: &[String]) -> ! {
+    let (json, paths) = match args {
+        [flag, paths @ ..] if flag == "--json" => (true, paths),
+        paths => (false, paths),
+    };
+    let [before, after] = paths else {
+        print_usement();
+    };
+    let read = |path: &str| match File::open(path).and_then(|f| Snapshot::read(&mut BufReader::new(f))) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Failed to read snapshot {}: {}", path, e);
+            exit(2);
+        }
+    };
+    let diff = read(before).diff(&read(after));
+    let mut out = io::stdout().lock();
+    let written = if json { diff.write_json(&mut out) } else { diff.write_text(&mut out) };
+    if let Err(e) = written {
+        eprintln!("Failed to write diff: {}", e);
+        exit(2);
+    }
+    exit(if diff.is_empty() { 0 } else { 1 });
+}
+
+// hands back the report of a failed run, first replaying its lead-up
+// into the crash trace if one was asked for
+fn fail(
+    report: Report,
+    cpu: &mut Cpu,
+    crash: &mut Option<CrashTrace>,
+    timer: &mut SystemTimer,
+    floppy: &mut Floppy,
+    disks: &mut HardDisks,
+    clock: Clock,
+) -> Box<Report> {
+    if let Some(crash) = crash {
+        let service = |cpu: &mut Cpu, vector| service(cpu, vector, floppy, disks, clock);
+        match crash.replay(cpu, timer, service, report.instructions) {
+            Ok((from, true)) => eprintln!("{}: replayed from instruction {} to the failure", crash.path(), from),
+            Ok((from, false)) => eprintln!(
+                "{}: replayed from instruction {}, but the failure did not come back",
+                crash.path(),
+                from
+            ),
+            Err(e) => eprintln!("Failed to write crash trace {}: {}", crash.path(), e),
+        }
+    }
+    Box::new(report)
+}
+
+fn exec_dump_state(cpu: &mut Cpu, run: &mut Run) -> Result<(), Box<Report>> {
+    let halt_reason = exec(cpu, run)?;
+    if let Some(before) = &run.delta {
+        if let Err(e) = before.diff(&Snapshot::of(cpu)).write_text(&mut io::stdout().lock()) {
+            eprintln!("Failed to write delta: {}", e);
+            exit(1);
+        }
+        return Ok(());
+    }
+    if run.console.is_some() {
+        return Ok(());
+    }
+    let devices = run.devices.then(|| {
+        let mut states = cpu.device_states();
+        states.push(run.timer.debug_state());
+        states
+    });
+    if let Err(e) = write_dump(&mut io::stdout().lock(), cpu, halt_reason, run.stats, devices.as_deref()) {
+        eprintln!("Failed to write the dump: {}", e);
+        exit(1);
+    }
+    Ok(())
+}
+
+// runs to the end and says how it ended, as the dump's halt_reason
+fn exec(cpu: &mut Cpu, run: &mut Run) -> Result<&'static str, Box<Report>> {
+    let Run {
+        explain,
+        max_instructions,
+        capture,
+        trace,
+        floppy,
+        disks,
+        clock,
+        timer,
+        bus,
+        governor,
+        control,
+        #[cfg(feature = "debugger")]
+        debug,
+        crash,
+        recent,
+        console,
+        terminal,
+        syntax,
+        ..
+    } = run;
+    let (explain, max_instructions) = (*explain, *max_instructions);
+    // a failing instruction panics; the report replaces the panic message
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    // a decode fault ends the run with the dump, like the end of the
+    // program, and is listed with the faults
+    let stopped = |cpu: &mut Cpu| {
+        let reason = cpu.fetch_failure();
+        if let Some(why) = cpu.decode_fault.take() {
+            cpu.faults.push(format!("{:04x}:{:04x}: {}", cpu.regs.cs, cpu.regs.ip, why));
+            cpu.exit_reason = Some(reason);
+        }
+        reason.name()
+    };
+    let halt_reason = loop {
+        if max_instructions.is_some_and(|max| cpu.stats.instructions >= max) {
+            break "instruction-limit";
+        }
+        if let Some(crash) = crash {
+            crash.poll(cpu, timer);
+        }
+        #[cfg(feature = "debugger")]
+        if let Some(debug) = debug {
+            let service = |cpu: &mut Cpu, vector| service(cpu, vector, floppy, disks, *clock);
+            match panic::catch_unwind(AssertUnwindSafe(|| debug.before(cpu, timer, service))) {
+                Err(payload) => {
+                    panic::set_hook(hook);
+                    let at = (cpu.regs.cs, cpu.regs.ip);
+                    return Err(fail(Report::fault(Kind::Exception, report::panic_message(&*payload), at, cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+                }
+                Ok(false) => break "debugger",
+                Ok(true) => {}
+            }
+        }
+
+        let (cs, ip) = (cpu.regs.cs, cpu.regs.ip);
+        let start = cpu.code_addr(ip);
+        let step = if explain {
+            // explain fetches and executes in one go, so the traced step
+            // is decoded ahead of it
+            let run = || {
+                let step = trace.as_ref().and_then(|_| Step::decode(cpu, *syntax));
+                explain::step(cpu).map(|text| (text, step))
+            };
+            match panic::catch_unwind(AssertUnwindSafe(run)) {
+                Err(payload) => {
+                    panic::set_hook(hook);
+                    return Err(fail(Report::fault(Kind::Exception, report::panic_message(&*payload), (cs, ip), cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+                }
+                Ok(Some((text, step))) => {
+                    eprint!("{}", text);
+                    let line = text.lines().next().unwrap_or("");
+                    recent.push_line(line.trim_end().to_string());
+                    step
+                }
+                Ok(None) => break stopped(cpu),
+            }
+        } else {
+            match panic::catch_unwind(AssertUnwindSafe(|| cpu.fetch())) {
+                Err(payload) => {
+                    panic::set_hook(hook);
+                    return Err(fail(Report::fault(Kind::Decode, report::panic_message(&*payload), (cs, ip), cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+                }
+                Ok(Some(i)) => {
+                    let step = trace.as_ref().map(|_| {
+                        let end = cpu.code_addr(cpu.regs.ip).max(start);
+                        Step {
+                            cs,
+                            ip,
+                            bytes: cpu.mem.cursor.get_ref()[start as usize..end as usize].to_vec(),
+                            text: syntax.format(&i),
+                        }
+                    });
+                    recent.push(cs, ip, start, cpu.code_addr(cpu.regs.ip));
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| cpu.execute(&i))) {
+                        panic::set_hook(hook);
+                        return Err(fail(Report::fault(Kind::Exception, report::panic_message(&*payload), (cs, ip), cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+                    }
+                    step
+                }
+                Ok(None) => break stopped(cpu),
+            }
+        };
+        #[cfg(feature = "debugger")]
+        if let Some(debug) = debug {
+            debug.after(cpu);
+        }
+
+        if let Some(vector) = cpu.host_int.take() {
+            match (vector, console.as_mut(), terminal.as_mut()) {
+                (dos::DOS_INT, Some(console), _) => dos::int21(cpu, console),
+                (keyboard::KEYBOARD_INT, _, Some(terminal)) => terminal.int16(cpu),
+                _ => service(cpu, vector, floppy, disks, *clock),
+            }
+        }
+        if console.is_some() && !cpu.output.is_empty() {
+            let mut out = io::stdout().lock();
+            if out.write_all(&cpu.output).and_then(|_| out.flush()).is_err() {
+                // the reader went away, as `head` does
+                break "output-closed";
+            }
+            cpu.output.clear();
+        }
+        if let (Some(log), Some(step)) = (trace.as_mut(), step) {
+            if let Err(e) = log.record(&step, cpu) {
+                terminal::restore();
+                eprintln!("Failed to write trace: {}", e);
+                exit(1);
+            }
+        }
+        timer.poll(cpu);
+        bus.tick(cpu);
+        governor.poll(cpu.stats.instructions);
+
+        if let Some(terminal) = terminal {
+            if cpu.stats.instructions.is_multiple_of(FRAME_INSTRUCTIONS) && !terminal.poll(cpu) {
+                break "quit";
+            }
+        }
+
+        if let Some(control) = control {
+            if cpu.stats.instructions.is_multiple_of(FRAME_INSTRUCTIONS) {
+                control.poll(floppy, governor, cpu.stats.instructions);
+            }
+        }
+
+        if let Some(capture) = capture {
+            if let Err(e) = capture.tick(cpu.stats.instructions, cpu.mem.cursor.get_ref()) {
+                terminal::restore();
+                eprintln!("Failed to capture video: {}", e);
+                exit(1);
+            }
+        }
+
+        if cpu.exit_reason == Some(RunExit::Livelock) {
+            panic::set_hook(hook);
+            let message = format!(
+                "{} loop iterations with interrupts disabled and nothing changing",
+                cpu.livelock_limit.unwrap_or_default()
+            );
+            return Err(fail(Report::fault(Kind::Livelock, message, (cpu.regs.cs, cpu.regs.ip), cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+        }
+        if cpu.exit_reason == Some(RunExit::FatalFault) {
+            panic::set_hook(hook);
+            let message = cpu.fatal_fault.clone().unwrap_or_default();
+            return Err(fail(Report::fault(Kind::FatalFault, message, (cs, ip), cpu, recent), cpu, crash, timer, floppy, disks, *clock));
+        }
+        if cpu.halt {
+            break cpu.exit_reason.unwrap_or(RunExit::Halted).name();
+        }
+    };
+    panic::set_hook(hook);
+    // the dump goes to a cooked terminal
+    *terminal = None;
+    if let Some(Err(e)) = trace.as_mut().map(|w| w.finish()) {
+        eprintln!("Failed to write trace: {}", e);
+        exit(1);
+    }
+    if let Some(capture) = capture {
+        if let Err(e) = capture.finish(cpu.mem.cursor.get_ref()) {
+            eprintln!("Failed to capture video: {}", e);
+            exit(1);
+        }
+    }
+    Ok(halt_reason)
+}
+
+// the state the run left, as JSON
+fn write_dump(
+    out: &mut impl Write,
+    cpu: &Cpu,
+    halt_reason: &str,
+    stats: bool,
+    devices: Option<&[DeviceState]>,
+) -> io::Result<()> {
+    writeln!(out, "{{")?;
+        writeln!(out, "\"registers\":{{")?;
+            writeln!(out, "\"AX\":{},", cpu.regs.ax)?;
+            writeln!(out, "\"BX\":{},", cpu.regs.bx)?;
+            writeln!(out, "\"CX\":{},", cpu.regs.cx)?;
+            writeln!(out, "\"DX\":{},", cpu.regs.dx)?;
+            writeln!(out, "\"SI\":{},", cpu.regs.si)?;
+            writeln!(out, "\"DI\":{},", cpu.regs.di)?;
+            writeln!(out, "\"SP\":{},", cpu.regs.sp)?;
+            writeln!(out, "\"BP\":{},", cpu.regs.bp)?;
+            writeln!(out, "\"CS\":{},", cpu.regs.cs)?;
+            writeln!(out, "\"DS\":{},", cpu.regs.ds)?;
+            writeln!(out, "\"ES\":{},", cpu.regs.es)?;
+            writeln!(out, "\"SS\":{},", cpu.regs.ss)?;
+            writeln!(out, "\"IP\":{}", cpu.regs.ip)?;
+        writeln!(out, "}},")?;
+            writeln!(out, "\"flags\": {{")?;
+            writeln!(out, "\"Parity\":{},",cpu.regs.flags.pf())?;
+            writeln!(out, "\"Overflow\":{},",&cpu.regs.flags.of())?;
+            writeln!(out, "\"Sign\":{},",&cpu.regs.flags.sf())?;
+            writeln!(out, "\"Carry\":{},",&cpu.regs.flags.cf())?;
+            writeln!(out, "\"Zero\":{},",&cpu.regs.flags.zf())?;
+            writeln!(out, "\"Aux\":{},",&cpu.regs.flags.af())?;
+            writeln!(out, "\"Direction\":{},",&cpu.regs.flags.df())?;
+            writeln!(out, "\"Interrupt\":{},",&cpu.regs.flags.i_f())?;
+            writeln!(out, "\"Trap\":{}",&cpu.regs.flags.tf())?;
+        writeln!(out, "}},")?;
+        writeln!(out, "\"instructions\":{},", cpu.stats.instructions)?;
+        writeln!(out, "\"cycles\":{},", cpu.cycles())?;
+        writeln!(out, "\"halted\":{},", cpu.halt)?;
+        writeln!(out, "\"halt_reason\":\"{}\",", halt_reason)?;
+        let faults: Vec<String> = cpu.faults.iter().map(|fault| json_str(fault.as_bytes())).collect();
+        writeln!(out, "\"faults\":[{}],", faults.join(","))?;
+        writeln!(out, "\"output\":{}{}", json_str(&cpu.output), if stats || devices.is_some() { "," } else { "" })?;
+        if stats {
+            writeln!(out, "\"stats\":{{")?;
+            let fields = cpu.stats.fields();
+            for (i, (name, val)) in fields.iter().enumerate() {
+                let sep = if i + 1 < fields.len() { "," } else { "" };
+                writeln!(out, "\"{}\":{}{}", name, val, sep)?;
+            }
+            writeln!(out, "}}{}", if devices.is_some() { "," } else { "" })?;
+        }
+        if let Some(devices) = devices {
+            let states: Vec<String> = devices
+                .iter()
+                .map(|state| {
+                    let fields: Vec<String> = state
+                        .fields
+                        .iter()
+                        .map(|(label, value)| format!("\"{}\":{}", label, json_str(value.as_bytes())))
+                        .collect();
+                    format!("\"{}\":{{{}}}", state.name, fields.join(","))
+                })
+                .collect();
+            writeln!(out, "\"devices\":{{{}}}", states.join(","))?;
+        }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("completions") => {
+            match args.get(1).map(String::as_str) {
+                Some("bash") => print!("{}", completions::bash()),
+                Some("zsh") => print!("{}", completions::zsh()),
+                Some("fish") => print!("{}", completions::fish()),
+                _ => print_usement(),
+            }
+            return;
+        }
+        Some("cp") => {
+            copy(&args[1..]);
+            return;
+        }
+        Some("test") => {
+            batch::main(&args[1..]);
+            return;
+        }
+        Some("diff") => diff(&args[1..]),
+        Some("disasm") => {
+            disasm(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
+    let config = match config::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            print_usement();
+        }
+    };
+
+    if config.flag("dump-cli-json") {
+        print!("{}", completions::cli_json());
+        return;
+    }
+
+    if config.flag("watch") {
+        watch(&config);
+    }
+    match run(&config) {
+        Ok(Some(code)) => exit(code as i32),
+        Ok(None) => {}
+        Err(report) => report.exit(),
+    }
+}
+
+// how often --watch looks at the file
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// reruns the program given with -f or --asm every time the file changes,
+// a run that fails included
+fn watch(config: &Config) -> ! {
+    let Some(path) = config.get("file").or(config.get("asm")) else {
+        eprintln!("--watch needs a program given with -f or --asm");
+        exit(1);
+    };
+    let mut last = fs::read(path).ok();
+    loop {
+        if let Err(report) = run(config) {
+            report.print();
+        }
+        eprintln!("Watching {} for changes (ctrl-c to stop)", path);
+        last = Some(next_version(path, last.as_deref()));
+    }
+}
+
+// waits until the file's contents differ from `last`; a new version only
+// counts once it stays the same for an interval, so a half-written file
+// is not run
+fn next_version(path: &str, last: Option<&[u8]>) -> Vec<u8> {
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let Ok(data) = fs::read(path) else {
+            continue;
+        };
+        if Some(data.as_slice()) == last {
+            continue;
+        }
+        thread::sleep(WATCH_INTERVAL);
+        if fs::read(path).is_ok_and(|again| again == data) {
+            return data;
+        }
+    }
+}
+
+// runs the program once, returning the exit code the guest asked for, or
+// the report of why it could not be loaded or failed
+fn run(config: &Config) -> Result<Option<u8>, Box<Report>> {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let mut floppy = Floppy::default();
+    for (drive, name) in ["fda", "fdb"].iter().enumerate() {
+        if let Some(path) = config.get(name) {
+            if let Err(e) = floppy.insert(drive, Path::new(path)) {
+                return Err(Report::load(format!("Failed to insert {}: {}", path, e)).into());
+            }
+        }
+    }
+    let mut disks = HardDisks::default();
+    for name in ["hda", "hdb"] {
+        if let Some(path) = config.get(name) {
+            match HardDisk::open(Path::new(path)) {
+                Ok(disk) => disks.disks.push(disk),
+                Err(e) => return Err(Report::load(format!("Failed to open {}: {}", path, e)).into()),
+            }
+        }
+    }
+    let clock = match config.get("virtual-clock") {
+        Some(epoch) => match clock::parse_epoch(epoch) {
+            Some(epoch) => Clock::Virtual { epoch },
+            None => {
+                eprintln!("--virtual-clock expects seconds or YYYY-MM-DDTHH:MM:SS, got {}", epoch);
+                print_usement();
+            }
+        },
+        None => Clock::Host,
+    };
+    cpu.host_ints.extend([floppy::DISK_INT, clock::TIME_INT]);
+    let pipe = config.flag("pipe");
+    if pipe {
+        let stdio_com = ["com1", "com2"].iter().any(|&name| config.get(name) == Some("stdio"));
+        if config.flag("stdin") || config.flag("debug") || stdio_com {
+            eprintln!("--pipe hands stdin to int 21h, so --stdin, --debug and a stdio serial port cannot read it");
+            print_usement();
+        }
+        if config.flag("delta") {
+            eprintln!("--pipe and --delta both write to stdout; pick one");
+            print_usement();
+        }
+        cpu.host_ints.push(dos::DOS_INT);
+    }
+    let terminal = config.flag("terminal");
+    let layout = match config.get("keyboard").map(Layout::parse) {
+        None => Layout::Us,
+        Some(Some(layout)) => layout,
+        Some(None) => {
+            eprintln!("--keyboard expects us, uk, de or fr");
+            print_usement();
+        }
+    };
+    let code_page = match config.get("codepage").map(CodePage::parse) {
+        None => CodePage::Cp437,
+        Some(Some(code_page)) => code_page,
+        Some(None) => {
+            eprintln!("--codepage expects 437 or 850");
+            print_usement();
+        }
+    };
+    if terminal {
+        if pipe || config.flag("debug") {
+            eprintln!("--terminal draws on stdout and reads the keyboard, so it cannot go with --pipe or --debug");
+            print_usement();
+        }
+        cpu.host_ints.push(keyboard::KEYBOARD_INT);
+    }
+    let switches = match config.get("switches").map(parse_num) {
+        None => ppi::DEFAULT_SWITCHES,
+        Some(Some(switches @ 0..=0xff)) => switches as u8,
+        Some(_) => {
+            eprintln!("--switches expects a byte, bit 0 for switch 1");
+            print_usement();
+        }
+    };
+    let mut bus = Bus::default();
+    let cga = Cga::new();
+    let cga_registers = cga.registers();
+    let ppi = Ppi::new(switches);
+    let scan_codes = ppi.keyboard();
+    let devices: [Box<dyn Device>; 3] = [Box::new(Pit::new()), Box::new(ppi), Box::new(cga)];
+    for device in devices {
+        bus.attach(&mut cpu, device).expect("the devices have ports of their own");
+    }
+    cpu.io.push(Box::new(Cmos::new(clock)));
+    for (name, base) in [("com1", uart::COM1), ("com2", uart::COM2)] {
+        if let Some(spec) = config.get(name) {
+            let link = match spec {
+                "stdio" => Ok(Box::new(uart::stdio_link()) as Box<dyn uart::Link>),
+                spec => uart::tcp_link(spec).map(|link| Box::new(link) as Box<dyn uart::Link>),
+            };
+            match link {
+                Ok(link) => cpu.io.push(Box::new(Uart::new(base, link))),
+                Err(e) => {
+                    eprintln!("Failed to connect {} to {}: {}", name, spec, e);
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    // before loading, so a program or manifest covering the IVT wins
+    match config.get("ivt") {
+        None => {}
+        Some("iret") => cpu.init_ivt(),
+        // host services get trampolines the guest can hook and chain to
+        Some("hle") => {
+            cpu.init_ivt();
+            video::init_bda(&mut cpu);
+            cpu.host_ints.push(video::VIDEO_INT);
+            for vector in std::mem::take(&mut cpu.host_ints) {
+                cpu.install_trampoline(vector);
+            }
+        }
+        Some(target) => match parse_addr(target) {
+            Some((seg, off)) => (0..=255).for_each(|vector| cpu.set_vector(vector, seg, off)),
+            None => {
+                eprintln!("--ivt expects iret, hle or SEG:OFF, got {}", target);
+                print_usement();
+            }
+        },
+    }
+
+    let mut image_name = String::from("stdin");
+    if config.flag("boot") {
+        if let Err(e) = floppy.boot(&mut cpu, 0) {
+            return Err(Report::load(format!("Failed to boot: {}", e)).into());
+        }
+        image_name = config.get("fda").unwrap_or_default().to_string();
+    } else if let Some(path) = config.get("manifest") {
+        if let Err(e) = Manifest::read(path).and_then(|manifest| manifest.load(&mut cpu)) {
+            return Err(Report::load(format!("Failed to load manifest: {}", e)).into());
+        }
+        image_name = path.to_string();
+    } else if let Some(name) = config.get("file") {
+        match fs::read(name) {
+            Ok(image) => cpu.load_code_vec(&image),
+            Err(e) => return Err(Report::load(format!("Failed to open file {}: {}", name, e)).into()),
+        }
+        image_name = name.to_string();
+    } else if let Some(name) = config.get("asm") {
+        let code = fs::read_to_string(name)
+            .map_err(|e| format!("Failed to open file {}: {}", name, e))
+            .and_then(|source| asm::assemble(&source).map_err(|e| format!("Failed to assemble {}: {}", name, e)));
+        match code {
+            Ok(code) => cpu.load_code_vec(&code),
+            Err(e) => return Err(Report::load(e).into()),
+        }
+        image_name = name.to_string();
+    } else if config.flag("stdin") {
+        cpu.load_code_stdin();
+    } else if let Some(name) = config.get("demo") {
+        match demos::find(name) {
+            Some(demo) => cpu.load_code_vec(demo.image),
+            None => {
+                eprintln!("No demo named {}. Available demos:", name);
+                for demo in demos::DEMOS {
+                    eprintln!("   {:<8} {}", demo.name, demo.help);
+                }
+                exit(1);
+            }
+        }
+        image_name = format!("demo:{}", name);
+    } else {
+        print_usement();
+    }
+    if let Some(path) = config.get("regs") {
+        if let Err(e) = preset::load(path, &mut cpu) {
+            return Err(Report::load(format!("Failed to apply --regs {}", e)).into());
+        }
+    }
+
+    let num = |name: &str| -> Option<u32> {
+        let value = config.get(name)?;
+        match parse_num(value) {
+            Some(n) => Some(n),
+            None => {
+                eprintln!("--{} expects a number, got {}", name, value);
+                print_usement();
+            }
+        }
+    };
+
+    let stats = config.flag("stats");
+    let devices = config.flag("devices");
+    let explain = config.flag("explain");
+    if cfg!(not(feature = "debugger")) && config.flag("debug") {
+        eprintln!("--debug needs the debugger feature, which this build left out");
+        print_usement();
+    }
+    if config.flag("debug") && config.flag("stdin") {
+        eprintln!("--debug reads its commands from stdin, so the binary cannot come from there");
+        print_usement();
+    }
+    let heatmap = config.get("heatmap");
+    let cfg = config.get("cfg");
+    let branches = config.get("branches");
+    let coverage = config.get("coverage");
+    let chrome_trace = config.get("chrome-trace");
+    let html_trace = config.get("html-trace");
+    let screen = config.get("screen");
+    let max_instructions = num("max-instructions").map(u64::from);
+
+    if heatmap.is_some() || cfg.is_some() || coverage.is_some() {
+        cpu.profile = Some(Profile::new());
+    }
+    if branches.is_some() {
+        cpu.branches = Some(BranchProfile::new());
+    }
+    if chrome_trace.is_some() {
+        cpu.timeline = Some(Timeline::new());
+    }
+    if html_trace.is_some() {
+        cpu.recording = Some(Recording::new());
+    }
+
+    cpu.cs_write = match config.get("cs-write") {
+        None | Some("allow") => CsWrite::Allow,
+        Some("warn") => CsWrite::Warn,
+        Some("fault") => CsWrite::Fault,
+        Some(other) => {
+            eprintln!("--cs-write expects allow, warn or fault, got {}", other);
+            print_usement();
+        }
+    };
+
+    cpu.exceptions = match config.get("exceptions") {
+        None | Some("stop") => Exceptions::Stop,
+        Some("deliver") => Exceptions::Deliver,
+        Some(other) => {
+            eprintln!("--exceptions expects stop or deliver, got {}", other);
+            print_usement();
+        }
+    };
+
+    cpu.quirks = match config.get("cpu") {
+        None | Some("8086") | Some("8088") => CpuModel::I8086,
+        Some("80186") | Some("80188") => CpuModel::I80186,
+        Some("80286") => CpuModel::I80286,
+        Some(other) => {
+            eprintln!("--cpu expects 8086, 80186 or 80286, got {}", other);
+            print_usement();
+        }
+    }
+    .quirks();
+
+    cpu.prefetch = match config.get("prefetch") {
+        None | Some("coherent") => Prefetch::Coherent,
+        Some("authentic") => Prefetch::Authentic,
+        Some(other) => {
+            eprintln!("--prefetch expects coherent or authentic, got {}", other);
+            print_usement();
+        }
+    };
+
+    if let Some(list) = config.get("exit") {
+        let mut exits = ExitConventions {
+            hlt: false,
+            ..ExitConventions::default()
+        };
+        for name in list.split(',').map(str::trim) {
+            match name {
+                "hlt" => exits.hlt = true,
+                "dos" => exits.dos = true,
+                "jump-self" => exits.jump_to_self = true,
+                "port" => exits.port = Some(EXIT_PORT),
+                _ => match name.strip_prefix("port=").and_then(parse_num) {
+                    Some(port) if port <= 0xffff => exits.port = Some(port as u16),
+                    _ => {
+                        eprintln!("--exit expects hlt, dos, jump-self, port or port=N, got {}", name);
+                        print_usement();
+                    }
+                },
+            }
+        }
+        cpu.exits = exits;
+    }
+    // a filter ends the DOS way
+    cpu.exits.dos |= pipe;
+    cpu.power.reboot = match config.get("reset") {
+        None | Some("exit") => false,
+        Some("reboot") => true,
+        Some(other) => {
+            eprintln!("--reset expects exit or reboot, got {}", other);
+            print_usement();
+        }
+    };
+    cpu.power.shutdown_port = match num("shutdown-port") {
+        Some(port @ 0..=0xffff) => Some(port as u16),
+        Some(port) => {
+            eprintln!("--shutdown-port expects a port below 10000h, got {:x}h", port);
+            print_usement();
+        }
+        None => None,
+    };
+
+    match config.get("livelock") {
+        None => {}
+        Some("off") => cpu.livelock_limit = None,
+        Some(_) => cpu.livelock_limit = num("livelock"),
+    }
+
+    match num("hypercall") {
+        Some(v) if v <= 0xff => cpu.hypercall = Some(v as u8),
+        Some(_) => print_usement(),
+        None => {}
+    }
+
+    // option roms are initialized as the BIOS would, before the program
+    for spec in config.get("rom").into_iter().flat_map(|list| list.split(',')) {
+        let (path, segment) = match spec.rsplit_once('@') {
+            Some((path, seg)) => match u16::from_str_radix(seg.trim_start_matches("0x"), 16) {
+                Ok(segment) => (path, segment),
+                Err(_) => {
+                    eprintln!("--rom expects FILE@SEG with a hex segment, got {}", spec);
+                    print_usement();
+                }
+            },
+            None => (spec, bios::OPTION_ROM_SEGMENT),
+        };
+        if let Err(e) = fs::read(path).and_then(|image| bios::load_rom(&mut cpu, &image, segment)) {
+            return Err(Report::load(format!("Failed to load rom {}: {}", path, e)).into());
+        }
+    }
+    if let Some(list) = config.get("poison") {
+        match parse_ranges(list) {
+            Some(ranges) => cpu.poison = ranges,
+            None => {
+                eprintln!("--poison expects ranges like 0x500-0x5ff or 0050:0000-0050:00ff, got {}", list);
+                print_usement();
+            }
+        }
+    }
+
+    if let Some(list) = config.get("umb") {
+        if let Err(e) = bios::parse_umbs(list).and_then(|umbs| bios::map_umbs(&mut cpu, &umbs)) {
+            return Err(Report::load(format!("--umb: {}", e)).into());
+        }
+    }
+    for rom in bios::scan_option_roms(&cpu) {
+        let res = bios::init_option_rom(&mut cpu, rom, |cpu, vector| service(cpu, vector, &mut floppy, &mut disks, clock));
+        if let Err(e) = res {
+            return Err(Report::load(e).into());
+        }
+    }
+
+    let pacing = match config.get("timer") {
+        None | Some("cycles") => Pacing::Cycles,
+        Some("wall") => Pacing::WallClock,
+        Some(other) => {
+            eprintln!("--timer expects cycles or wall, got {}", other);
+            print_usement();
+        }
+    };
+    let timer = SystemTimer::new(pacing, &mut cpu, clock);
+
+    let capture_every = match num("capture-every") {
+        Some(0) => print_usement(),
+        Some(n) => n as u64,
+        None => 1,
+    };
+    let composite = config.flag("composite");
+    let capture = config.get("capture-video").map(|path| {
+        match VideoCapture::new(path, capture_every, cga_registers.clone(), composite) {
+            Ok(capture) => capture,
+            Err(e) => {
+                eprintln!("Failed to capture video to {}: {}", path, e);
+                exit(1);
+            }
+        }
+    });
+
+    let syntax = match config.get("syntax").map(Syntax::parse) {
+        None => Syntax::Intel,
+        Some(Some(syntax)) => syntax,
+        Some(None) => {
+            eprintln!("--syntax expects intel or att");
+            print_usement();
+        }
+    };
+    let trace_format = match config.get("trace-format").map(tracelog::Format::parse) {
+        None => tracelog::Format::default(),
+        Some(Some(format)) => format,
+        Some(None) => {
+            eprintln!("--trace-format expects text, csv, jsonl or binary");
+            print_usement();
+        }
+    };
+    let trace_rotate = config.get("trace-rotate").map(|size| match tracelog::parse_size(size) {
+        Some(size) => size,
+        None => {
+            eprintln!("--trace-rotate expects a size such as 4096, 512K or 64M");
+            print_usement();
+        }
+    });
+    let trace_keep = num("trace-keep").unwrap_or(3) as usize;
+    let trace = config.get("trace").map(|path| match TraceFile::create(path, trace_rotate, trace_keep) {
+        Ok(file) => match TraceLog::new(file, trace_format) {
+            Ok(log) => log,
+            Err(e) => {
+                eprintln!("Failed to write trace {}: {}", path, e);
+                exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to write trace {}: {}", path, e);
+            exit(1);
+        }
+    });
+
+    let crash = config.get("crash-trace").map(|path| {
+        let every = num("checkpoint-every").map_or(CHECKPOINT_EVERY, u64::from);
+        let keep = num("checkpoints").unwrap_or(4) as usize;
+        CrashTrace::new(path, trace_format, syntax, every, keep)
+    });
+
+    let control = config.get("control").map(|addr| match Control::bind(addr) {
+        Ok(control) => control,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", addr, e);
+            exit(1);
+        }
+    });
+
+    // what the adapter shows, for --screen once the run is over
+    let screen_registers = cga_registers.clone();
+    let mut run = Run {
+        stats,
+        devices,
+        explain,
+        max_instructions,
+        capture,
+        trace,
+        floppy,
+        disks,
+        clock,
+        timer,
+        bus,
+        governor: Governor::new(num("speed-limit").map(u64::from)),
+        control,
+        #[cfg(feature = "debugger")]
+        debug: config.flag("debug").then(|| Debug::new(cga_registers.clone())),
+        crash,
+        recent: Recent::default(),
+        delta: config.flag("delta").then(|| Snapshot::of(&cpu)),
+        console: pipe.then(Console::stdin),
+        terminal: terminal.then(|| {
+            let paste_delay = num("paste-delay").map_or(1, u64::from);
+            let refresh = num("refresh").map_or(60, |hz| hz.max(1));
+            match Terminal::open(layout, code_page, paste_delay, refresh, cga_registers, scan_codes) {
+                Ok(terminal) => terminal,
+                Err(e) => {
+                    eprintln!("--terminal needs a terminal: {}", e);
+                    exit(1);
+                }
+            }
+        }),
+        syntax,
+    };
+    exec_dump_state(&mut cpu, &mut run)?;
+    if let Err(e) = run.floppy.flush().and_then(|_| run.disks.flush()) {
+        eprintln!("Failed to write back disk images: {}", e);
+        exit(1);
+    }
+
+    if let Some(profile) = &cpu.profile {
+        if let Some(path) = heatmap {
+            write_report(path, "heatmap", |w| {
+                if path.ends_with(".csv") {
+                    profile.write_csv(w)
+                } else {
+                    profile.write_heatmap_png(w)
+                }
+            });
+        }
+        if let Some(path) = cfg {
+            write_report(path, "cfg", |w| profile.write_cfg_dot(w, config.flag("cfg-counts")));
+        }
+        if let Some(path) = coverage {
+            // a boot or preset may leave the load address past the end
+            let size = (cpu.prog_size as u32).saturating_sub(cpu.load_addr);
+            write_report(path, "coverage", |w| {
+                if path.ends_with(".info") {
+                    profile.write_lcov(w, &image_name, cpu.load_addr, size)
+                } else {
+                    profile.write_coverage_json(w, cpu.load_addr, size)
+                }
+            });
+        }
+    }
+
+    if let (Some(profile), Some(path)) = (&cpu.branches, branches) {
+        write_report(path, "branch profile", |w| profile.write_report(w));
+    }
+
+    if let (Some(timeline), Some(path)) = (&mut cpu.timeline, chrome_trace) {
+        timeline.finish();
+        write_report(path, "chrome trace", |w| timeline.write_chrome_json(w));
+    }
+
+    if let (Some(recording), Some(path)) = (&cpu.recording, html_trace) {
+        let image = (cpu.load_addr, cpu.prog_size as u32);
+        write_report(path, "html trace", |w| {
+            recording.write_html(w, cpu.mem.cursor.get_ref(), image, &cpu.regs)
+        });
+    }
+
+    if let Some(path) = config.get("save-state") {
+        write_report(path, "snapshot", |w| Snapshot::of(&cpu).write(w));
+    }
+
+    if let Some(path) = screen {
+        if let Err(e) = capture::write_screen(path, cpu.mem.cursor.get_ref(), screen_registers.get()) {
+            eprintln!("Failed to write screen {}: {}", path, e);
+            exit(1);
+        }
+    }
+
+    match cpu.exit_reason {
+        Some(RunExit::DecodeFault) => Ok(Some(1)),
+        _ => Ok(cpu.exit_code),
+    }
+}
+
+#[cfg(test)]
+mod main_test {
+    use sixemu_core::{cpu::Cpu, disasm::Syntax, machine::Bus};
+    use sixemu_devices::{
+        clock::Clock,
+        floppy::Floppy,
+        governor::Governor,
+        harddisk::HardDisks,
+        timer::{Pacing, SystemTimer},
+    };
+
+    use super::{exec, write_dump, Run};
+    use crate::{
+        json::{self, Json},
+        report::Recent,
+    };
+
+    // the dump a bare run of `code` ends with
+    fn dump(code: &[u8], max_instructions: Option<u64>) -> Json {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        let clock = Clock::Virtual { epoch: 0 };
+        let mut run = Run {
+            stats: false,
+            devices: false,
+            explain: false,
+            max_instructions,
+            capture: None,
+            trace: None,
+            floppy: Floppy::default(),
+            disks: HardDisks::default(),
+            clock,
+            timer: SystemTimer::new(Pacing::Cycles, &mut cpu, clock),
+            bus: Bus::default(),
+            governor: Governor::new(None),
+            control: None,
+            #[cfg(feature = "debugger")]
+            debug: None,
+            crash: None,
+            recent: Recent::default(),
+            delta: None,
+            console: None,
+            terminal: None,
+            syntax: Syntax::Intel,
+        };
+        let halt_reason = exec(&mut cpu, &mut run).unwrap_or_else(|_| panic!("the run failed"));
+        let mut out = Vec::new();
+        write_dump(&mut out, &cpu, halt_reason, false, None).unwrap();
+        json::parse(&String::from_utf8(out).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn halt_fields() {
+        let fields = |dump: &Json| {
+            let faults = match dump.get("faults") {
+                Some(Json::Arr(faults)) => faults.iter().map(|f| f.as_str().unwrap().to_string()).collect(),
+                faults => panic!("faults: {:?}", faults),
+            };
+            let reason = dump.get("halt_reason").and_then(Json::as_str).unwrap().to_string();
+            (dump.get("halted").cloned(), reason, faults)
+        };
+        // inc ax; hlt
+        let hlt = dump(&[0x40, 0xf4], None);
+        assert_eq!(fields(&hlt), (Some(Json::Bool(true)), "hlt".to_string(), vec![]));
+        assert_eq!(hlt.get("instructions"), Some(&Json::Num(2.0)));
+
+        // jmp $ runs until the limit
+        let limit = dump(&[0xeb, 0xfe], Some(5));
+        assert_eq!(fields(&limit), (Some(Json::Bool(false)), "instruction-limit".to_string(), vec![]));
+        assert_eq!(limit.get("instructions"), Some(&Json::Num(5.0)));
+
+        // inc ax; mov sreg4,ax
+        let decode = dump(&[0x40, 0x8e, 0xe0, 0xf4], None);
+        let fault = "0000:0001: invalid encoding 8e e0: segment register field above 3".to_string();
+        assert_eq!(fields(&decode), (Some(Json::Bool(false)), "decode-fault".to_string(), vec![fault]));
+        assert_eq!(decode.get("instructions"), Some(&Json::Num(1.0)));
+        assert_eq!(decode.get("registers").and_then(|r| r.get("IP")), Some(&Json::Num(1.0)));
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sixemu_core::cpu::Cpu;
+
+use crate::{config::parse_value, parse_num};
+
+/// A file placed at a physical address.
+#[derive(Debug, PartialEq)]
+pub struct Blob {
+    pub path: PathBuf,
+    pub addr: u32,
+}
+
+/// Several binaries to scatter through memory before the run, e.g. IVT
+/// stubs, data tables and code:
+///
+/// ```toml
+/// entry = "0000:0100"
+///
+/// [[blob]]
+/// file = "vectors.bin"
+/// at = "0000:0000"
+///
+/// [[blob]]
+/// file = "code.bin"
+/// at = "0000:0100"
+/// ```
+///
+/// Addresses are `SEGMENT:OFFSET` in hex or a physical address. Files
+/// are relative to the manifest. Execution starts at `entry`, or at the
+/// first blob when it is left out.
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    pub blobs: Vec<Blob>,
+    pub entry: Option<(u16, u16)>,
+}
+
+pub(crate) fn parse_addr(s: &str) -> Option<(u16, u16)> {
+    match s.split_once(':') {
+        Some((seg, off)) => Some((
+            u16::from_str_radix(seg.trim(), 16).ok()?,
+            u16::from_str_radix(off.trim(), 16).ok()?,
+        )),
+        None => {
+            let addr = parse_num(s.trim()).filter(|&a| a < 0x100000)?;
+            Some(((addr >> 4) as u16, (addr & 0xf) as u16))
+        }
+    }
+}
+
+pub(crate) fn physical((seg, off): (u16, u16)) -> u32 {
+    (seg as u32 * 16 + off as u32) & 0xfffff
+}
+
+impl Manifest {
+    /// Parses a manifest; `dir` is where relative file names start.
+    pub fn parse(text: &str, path: &str, dir: &Path) -> Result<Self, String> {
+        let mut manifest = Manifest {
+            blobs: Vec::new(),
+            entry: None,
+        };
+        // file and address of the [[blob]] being read
+        let mut blob: Option<(Option<String>, Option<u32>, String)> = None;
+        let finish = |blob: Option<(Option<String>, Option<u32>, String)>, blobs: &mut Vec<Blob>| match blob {
+            Some((Some(file), Some(addr), _)) => {
+                blobs.push(Blob {
+                    path: dir.join(file),
+                    addr,
+                });
+                Ok(())
+            }
+            Some((_, _, from)) => Err(format!("{}: a blob needs both file and at", from)),
+            None => Ok(()),
+        };
+
+        for (n, line) in text.lines().enumerate() {
+            let from = format!("{}:{}", path, n + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if line.split('#').next().unwrap_or("").trim() != "[[blob]]" {
+                    return Err(format!("{}: only [[blob]] tables are allowed", from));
+                }
+                finish(blob.take(), &mut manifest.blobs)?;
+                blob = Some((None, None, from));
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("{}: expected key = value or [[blob]]", from));
+            };
+            let value = parse_value(value.trim(), &from)?;
+            let addr = || parse_addr(&value).ok_or_else(|| format!("{}: bad address {}", from, value));
+            match (key.trim(), &mut blob) {
+                ("entry", None) => manifest.entry = Some(addr()?),
+                ("file", Some((file, _, _))) => *file = Some(value.clone()),
+                ("at", Some((_, at, _))) => *at = Some(physical(addr()?)),
+                (key, _) => return Err(format!("{}: unexpected key {}", from, key)),
+            }
+        }
+        finish(blob, &mut manifest.blobs)?;
+
+        if manifest.blobs.is_empty() {
+            return Err(format!("{}: no [[blob]] to load", path));
+        }
+        Ok(manifest)
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        Self::parse(&text, path, dir)
+    }
+
+    /// Copies every blob into memory and points CS:IP at the entry. The
+    /// blob holding the entry counts as the program, so the run ends
+    /// when execution leaves it.
+    pub fn load(&self, cpu: &mut Cpu) -> Result<(), String> {
+        let mut code = None;
+        let entry = self
+            .entry
+            .unwrap_or(((self.blobs[0].addr >> 4) as u16, (self.blobs[0].addr & 0xf) as u16));
+        for blob in &self.blobs {
+            let data = fs::read(&blob.path).map_err(|e| format!("{}: {}", blob.path.display(), e))?;
+            let start = blob.addr as usize;
+            let end = start + data.len();
+            if end > 0x100000 {
+                return Err(format!(
+                    "{}: does not fit below 1 MiB at {:05x}",
+                    blob.path.display(),
+                    start
+                ));
+            }
+            cpu.mem.cursor.get_mut()[start..end].copy_from_slice(&data);
+            if (start..end).contains(&(physical(entry) as usize)) {
+                code = Some((start as u32, end as u32));
+            }
+        }
+        let Some((start, end)) = code else {
+            return Err(format!("entry {:04x}:{:04x} is not inside any blob", entry.0, entry.1));
+        };
+        cpu.regs.cs = entry.0;
+        cpu.regs.ip = entry.1;
+        cpu.load_addr = start;
+        cpu.prog_size = (end - entry.0 as u32 * 16).min(0x10000) as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod manifest_test {
+    use std::{env, fs, path::Path};
+
+    use sixemu_core::cpu::Cpu;
+
+    use super::{Blob, Manifest};
+
+    #[test]
+    fn parse() {
+        let text = "entry = \"0000:0100\"\n\n[[blob]]\nfile = \"ivt.bin\"\nat = 0\n\n[[blob]] # code\nfile = \"code.bin\"\nat = \"0010:0000\"\n";
+        let manifest = Manifest::parse(text, "m.toml", Path::new("dir")).unwrap();
+        assert_eq!(manifest.entry, Some((0, 0x100)));
+        assert_eq!(
+            manifest.blobs,
+            vec![
+                Blob {
+                    path: Path::new("dir/ivt.bin").to_path_buf(),
+                    addr: 0
+                },
+                Blob {
+                    path: Path::new("dir/code.bin").to_path_buf(),
+                    addr: 0x100
+                },
+            ]
+        );
+        assert!(Manifest::parse("[[blob]]\nfile = \"a.bin\"\n", "m.toml", Path::new(".")).is_err());
+        assert!(Manifest::parse("entry = \"0:0\"\n", "m.toml", Path::new(".")).is_err());
+        assert!(Manifest::parse("[[blob]]\nsize = 3\n", "m.toml", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn load() {
+        let dir = env::temp_dir().join(format!("sixemu-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // the code reads a word from the data blob: mov ax,[0x2000]; hlt
+        fs::write(dir.join("code.bin"), [0xa1, 0x00, 0x20, 0xf4]).unwrap();
+        fs::write(dir.join("data.bin"), [0x34, 0x12]).unwrap();
+        let text = "entry = \"0000:0500\"\n[[blob]]\nfile = \"data.bin\"\nat = 0x2000\n[[blob]]\nfile = \"code.bin\"\nat = \"0050:0000\"\n";
+        let manifest = Manifest::parse(text, "m.toml", &dir).unwrap();
+
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        manifest.load(&mut cpu).unwrap();
+        cpu.fire();
+        assert_eq!(cpu.regs.ax, 0x1234);
+        assert_eq!((cpu.load_addr, cpu.prog_size), (0x500, 0x504));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
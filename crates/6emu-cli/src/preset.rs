@@ -0,0 +1,206 @@
+use std::fs;
+
+use sixemu_core::{cpu::Cpu, snapshot::FLAGS};
+
+use crate::{
+    json::{self, Json},
+    manifest::{parse_addr, physical},
+};
+
+/// Starting machine state read from JSON, in the shape of the dump so a
+/// captured dump can be fed back in:
+///
+/// ```json
+/// {
+///   "registers": {"AX": 4660, "CS": "0x0050", "IP": 0},
+///   "flags": {"Carry": true, "Zero": false},
+///   "memory": [{"at": "0000:0400", "hex": "cd20"}, {"at": 8192, "bytes": [1, 2]}]
+/// }
+/// ```
+///
+/// Registers are the 16-bit ones, segments, IP, FLAGS or 8-bit halves;
+/// flags use the dump's names. Anything left out keeps its value, and
+/// other keys such as `output` and `stats` are ignored.
+pub fn apply(doc: &Json, cpu: &mut Cpu) -> Result<(), String> {
+    if !matches!(doc, Json::Obj(_)) {
+        return Err("expected an object".to_string());
+    }
+    if let Some(Json::Obj(regs)) = doc.get("registers") {
+        for (name, value) in regs {
+            let val = value
+                .as_u32()
+                .filter(|&v| v <= 0xffff)
+                .ok_or_else(|| format!("registers.{}: expected a 16-bit number", name))?;
+            set_register(cpu, name, val as u16)?;
+        }
+    }
+    if let Some(Json::Obj(flags)) = doc.get("flags") {
+        for (name, value) in flags {
+            let Some(&(_, bit)) = FLAGS.iter().find(|(flag, _)| flag == name) else {
+                return Err(format!("flags.{}: unknown flag", name));
+            };
+            let Json::Bool(set) = value else {
+                return Err(format!("flags.{}: expected true or false", name));
+            };
+            let bits = cpu.regs.flags.to_u16() & !(1 << bit) | (*set as u16) << bit;
+            cpu.regs.flags.set_from_u16(bits);
+        }
+    }
+    if let Some(Json::Arr(pokes)) = doc.get("memory") {
+        for (i, poke) in pokes.iter().enumerate() {
+            let addr = address(poke).ok_or_else(|| format!("memory[{}]: expected an address in at", i))?;
+            let bytes = bytes(poke).ok_or_else(|| format!("memory[{}]: expected bytes or hex", i))?;
+            let start = addr as usize;
+            let Some(dest) = cpu.mem.cursor.get_mut().get_mut(start..start + bytes.len()) else {
+                return Err(format!("memory[{}]: does not fit below 1 MiB", i));
+            };
+            dest.copy_from_slice(&bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Reads a preset file and applies it.
+pub fn load(path: &str, cpu: &mut Cpu) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    json::parse(&text)
+        .and_then(|doc| apply(&doc, cpu))
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+/// The physical address in a memory entry's `at`.
+pub(crate) fn address(entry: &Json) -> Option<u32> {
+    match entry.get("at")? {
+        Json::Str(s) => parse_addr(s).map(physical),
+        n => n.as_u32().filter(|&a| a < 0x100000),
+    }
+}
+
+/// The contents of a memory entry, from `bytes` or `hex`.
+pub(crate) fn bytes(entry: &Json) -> Option<Vec<u8>> {
+    match (entry.get("bytes"), entry.get("hex").and_then(Json::as_str)) {
+        (Some(Json::Arr(items)), None) => items
+            .iter()
+            .map(|b| b.as_u32().filter(|&b| b <= 0xff).map(|b| b as u8))
+            .collect(),
+        (None, Some(hex)) => parse_hex(hex),
+        _ => None,
+    }
+}
+
+/// Hex bytes, whitespace allowed between them.
+pub(crate) fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Reads a register by the name the preset uses.
+pub(crate) fn register(cpu: &Cpu, name: &str) -> Option<u16> {
+    let regs = &cpu.regs;
+    Some(match name {
+        "AX" => regs.ax,
+        "BX" => regs.bx,
+        "CX" => regs.cx,
+        "DX" => regs.dx,
+        "SI" => regs.si,
+        "DI" => regs.di,
+        "SP" => regs.sp,
+        "BP" => regs.bp,
+        "CS" => regs.cs,
+        "DS" => regs.ds,
+        "ES" => regs.es,
+        "SS" => regs.ss,
+        "IP" => regs.ip,
+        "FLAGS" => regs.flags.to_u16(),
+        "AL" => regs.get_al() as u16,
+        "AH" => regs.get_ah() as u16,
+        "BL" => regs.get_bl() as u16,
+        "BH" => regs.get_bh() as u16,
+        "CL" => regs.get_cl() as u16,
+        "CH" => regs.get_ch() as u16,
+        "DL" => regs.get_dl() as u16,
+        "DH" => regs.get_dh() as u16,
+        _ => return None,
+    })
+}
+
+fn set_register(cpu: &mut Cpu, name: &str, val: u16) -> Result<(), String> {
+    let regs = &mut cpu.regs;
+    let byte = || u8::try_from(val).map_err(|_| format!("registers.{}: expected an 8-bit number", name));
+    match name {
+        "AX" => regs.ax = val,
+        "BX" => regs.bx = val,
+        "CX" => regs.cx = val,
+        "DX" => regs.dx = val,
+        "SI" => regs.si = val,
+        "DI" => regs.di = val,
+        "SP" => regs.sp = val,
+        "BP" => regs.bp = val,
+        "CS" => regs.cs = val,
+        "DS" => regs.ds = val,
+        "ES" => regs.es = val,
+        "SS" => regs.ss = val,
+        "IP" => regs.ip = val,
+        "FLAGS" => regs.flags.set_from_u16(val),
+        "AL" => regs.set_al(byte()?),
+        "AH" => regs.set_ah(byte()?),
+        "BL" => regs.set_bl(byte()?),
+        "BH" => regs.set_bh(byte()?),
+        "CL" => regs.set_cl(byte()?),
+        "CH" => regs.set_ch(byte()?),
+        "DL" => regs.set_dl(byte()?),
+        "DH" => regs.set_dh(byte()?),
+        _ => return Err(format!("registers.{}: unknown register", name)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod preset_test {
+    use sixemu_core::cpu::Cpu;
+
+    use super::apply;
+    use crate::json;
+
+    #[test]
+    fn apply_preset() {
+        let doc = json::parse(
+            r#"{
+                "registers": {"AX": 4660, "CS": "0x0050", "IP": 0, "BH": 255},
+                "flags": {"Carry": true, "Zero": true, "Interrupt": false},
+                "memory": [{"at": "0050:0000", "hex": "40 f4"}, {"at": 8192, "bytes": [1, 2]}],
+                "output": ""
+            }"#,
+        )
+        .unwrap();
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.prog_size = 2;
+        apply(&doc, &mut cpu).unwrap();
+        assert_eq!(
+            (cpu.regs.ax, cpu.regs.bx, cpu.regs.cs, cpu.regs.ip),
+            (0x1234, 0xff00, 0x50, 0)
+        );
+        assert!(cpu.regs.flags.cf() && cpu.regs.flags.zf() && !cpu.regs.flags.i_f());
+        assert_eq!(cpu.mem.cursor.get_ref()[0x2000..0x2002], [1, 2]);
+
+        // inc ax; hlt from the poked bytes
+        cpu.fire();
+        assert_eq!(cpu.regs.ax, 0x1235);
+
+        for bad in [
+            r#"{"registers": {"XX": 1}}"#,
+            r#"{"registers": {"AL": 256}}"#,
+            r#"{"flags": {"Carry": 1}}"#,
+            r#"{"memory": [{"at": "0000:0000", "hex": "abc"}]}"#,
+            r#"{"memory": [{"at": 1048575, "bytes": [1, 2]}]}"#,
+        ] {
+            assert!(apply(&json::parse(bad).unwrap(), &mut cpu).is_err(), "{}", bad);
+        }
+    }
+}
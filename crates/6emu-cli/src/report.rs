@@ -0,0 +1,266 @@
+use std::{any::Any, collections::VecDeque, process::exit};
+
+use sixemu_core::cpu::Cpu;
+
+use crate::{json_str, terminal};
+
+// instructions kept for the report
+const RECENT: usize = 16;
+// words of the stack shown from SS:SP up
+const STACK_WORDS: u16 = 8;
+
+/// Why a run stopped early.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// The bytes at CS:IP could not be fetched.
+    Decode,
+    /// Executing the instruction failed, e.g. a division by zero.
+    Exception,
+    /// The program, an image or a preset could not be loaded.
+    Load,
+    /// The guest looped with interrupts disabled and nothing changing.
+    Livelock,
+    /// A fault under `--exceptions deliver` that could not be taken.
+    FatalFault,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Decode => "decode",
+            Kind::Exception => "exception",
+            Kind::Load => "load",
+            Kind::Livelock => "livelock",
+            Kind::FatalFault => "fatal-fault",
+        }
+    }
+}
+
+enum Step {
+    /// CS:IP and the physical range of the instruction's bytes.
+    Fetched(u16, u16, u32, u32),
+    /// A line already formatted, e.g. by explain mode.
+    Line(String),
+}
+
+/// The last instructions executed.
+#[derive(Default)]
+pub struct Recent(VecDeque<Step>);
+
+impl Recent {
+    fn push_step(&mut self, step: Step) {
+        if self.0.len() == RECENT {
+            self.0.pop_front();
+        }
+        self.0.push_back(step);
+    }
+
+    pub fn push(&mut self, cs: u16, ip: u16, start: u32, end: u32) {
+        self.push_step(Step::Fetched(cs, ip, start, end));
+    }
+
+    pub fn push_line(&mut self, line: String) {
+        self.push_step(Step::Line(line));
+    }
+
+    fn lines(&self, cpu: &Cpu) -> Vec<String> {
+        let mem = cpu.mem.cursor.get_ref();
+        self.0
+            .iter()
+            .map(|step| match *step {
+                Step::Fetched(cs, ip, start, end) => {
+                    let bytes: Vec<String> = mem[start as usize..end.max(start) as usize]
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect();
+                    format!("{:04x}:{:04x}  {}", cs, ip, bytes.join(" "))
+                }
+                Step::Line(ref line) => line.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A failed run: printed as a line of text on stderr and as
+/// `{"error": {...}}` on stdout in place of the dump, so a wrapping tool
+/// can triage it without scraping the text.
+pub struct Report {
+    pub kind: Kind,
+    pub message: String,
+    /// CS:IP of the failing instruction.
+    pub at: Option<(u16, u16)>,
+    pub instructions: u64,
+    /// The instructions leading up to the failure, oldest first.
+    pub recent: Vec<String>,
+    /// Problems the run went on past before it, as in the dump.
+    pub faults: Vec<String>,
+    /// The registers, flags last, as the run stopped; empty when
+    /// nothing ran.
+    pub registers: Vec<(&'static str, u16)>,
+    /// The words on top of the stack, from SS:SP up.
+    pub stack: Vec<u16>,
+}
+
+impl Report {
+    pub fn load(message: String) -> Self {
+        Self {
+            kind: Kind::Load,
+            message,
+            at: None,
+            instructions: 0,
+            recent: Vec::new(),
+            faults: Vec::new(),
+            registers: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn fault(kind: Kind, message: String, (cs, ip): (u16, u16), cpu: &Cpu, recent: &Recent) -> Self {
+        Self {
+            kind,
+            message,
+            at: Some((cs, ip)),
+            instructions: cpu.stats.instructions,
+            recent: recent.lines(cpu),
+            faults: cpu.faults.clone(),
+            registers: registers(cpu),
+            stack: stack(cpu),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let (address, cs, ip) = match self.at {
+            Some((cs, ip)) => (
+                ((cs as u32 * 16 + ip as u32) & 0xfffff).to_string(),
+                cs.to_string(),
+                ip.to_string(),
+            ),
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        let recent: Vec<String> = self.recent.iter().map(|line| json_str(line.as_bytes())).collect();
+        let faults: Vec<String> = self.faults.iter().map(|fault| json_str(fault.as_bytes())).collect();
+        let registers: Vec<String> =
+            self.registers.iter().map(|(name, val)| format!("\"{}\":{}", name, val)).collect();
+        let stack: Vec<String> = self.stack.iter().map(u16::to_string).collect();
+        format!(
+            "{{\"error\":{{\"kind\":\"{}\",\"message\":{},\"address\":{},\"cs\":{},\"ip\":{},\"instructions\":{},\"recent\":[{}],\"faults\":[{}],\"registers\":{{{}}},\"stack\":[{}]}}}}",
+            self.kind.name(),
+            json_str(self.message.as_bytes()),
+            address,
+            cs,
+            ip,
+            self.instructions,
+            recent.join(","),
+            faults.join(","),
+            registers.join(","),
+            stack.join(",")
+        )
+    }
+
+    /// Prints the report both ways.
+    pub fn print(&self) {
+        terminal::restore();
+        match self.at {
+            Some((cs, ip)) => eprintln!("{} error at {:04x}:{:04x}: {}", self.kind.name(), cs, ip, self.message),
+            None => eprintln!("{}", self.message),
+        }
+        if !self.registers.is_empty() {
+            let registers: Vec<String> =
+                self.registers.iter().map(|(name, val)| format!("{}={:04x}", name, val)).collect();
+            let stack: Vec<String> = self.stack.iter().map(|word| format!("{:04x}", word)).collect();
+            eprintln!("{}", registers.join(" "));
+            eprintln!("stack {}", stack.join(" "));
+        }
+        println!("{}", self.to_json());
+    }
+
+    /// Prints the report and exits with status 1.
+    pub fn exit(&self) -> ! {
+        self.print();
+        exit(1);
+    }
+}
+
+// in the order of the dump, with the flags register as the 8086 pushes it
+fn registers(cpu: &Cpu) -> Vec<(&'static str, u16)> {
+    let regs = &cpu.regs;
+    vec![
+        ("AX", regs.ax),
+        ("BX", regs.bx),
+        ("CX", regs.cx),
+        ("DX", regs.dx),
+        ("SI", regs.si),
+        ("DI", regs.di),
+        ("SP", regs.sp),
+        ("BP", regs.bp),
+        ("CS", regs.cs),
+        ("DS", regs.ds),
+        ("ES", regs.es),
+        ("SS", regs.ss),
+        ("IP", regs.ip),
+        ("FLAGS", cpu.flags_image()),
+    ]
+}
+
+fn stack(cpu: &Cpu) -> Vec<u16> {
+    let mem = cpu.mem.cursor.get_ref();
+    (0..STACK_WORDS)
+        .map(|i| {
+            let at = cpu.stack_addr(cpu.regs.sp.wrapping_add(i * 2));
+            u16::from_le_bytes([mem[at as usize], mem[(at as usize + 1) & 0xfffff]])
+        })
+        .collect()
+}
+
+/// The text a caught panic carried.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(s), _) => s.to_string(),
+        (_, Some(s)) => s.clone(),
+        _ => "unknown failure".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod report_test {
+    use sixemu_core::cpu::Cpu;
+
+    use super::{Kind, Recent, Report};
+    use crate::json;
+
+    #[test]
+    fn report_json() {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&[0xb8, 0x34, 0x12, 0x40]);
+        let mut recent = Recent::default();
+        recent.push(0, 0, 0, 3);
+        recent.push(0, 3, 3, 4);
+        cpu.faults.push("mov/pop cs".to_string());
+        let report = Report::fault(Kind::Exception, "divide \"by\" zero".to_string(), (0, 4), &cpu, &recent);
+        let doc = json::parse(&report.to_json()).unwrap();
+        let error = doc.get("error").unwrap();
+        assert_eq!(error.get("kind").and_then(json::Json::as_str), Some("exception"));
+        assert_eq!(
+            error.get("message").and_then(json::Json::as_str),
+            Some("divide \"by\" zero")
+        );
+        assert_eq!(error.get("ip").and_then(json::Json::as_u32), Some(4));
+        assert_eq!(
+            error.get("recent"),
+            Some(&json::Json::Arr(vec![
+                json::Json::Str("0000:0000  b8 34 12".to_string()),
+                json::Json::Str("0000:0003  40".to_string()),
+            ]))
+        );
+        assert_eq!(
+            error.get("faults"),
+            Some(&json::Json::Arr(vec![json::Json::Str("mov/pop cs".to_string())]))
+        );
+        assert_eq!(error.get("registers").and_then(|r| r.get("SP")).and_then(json::Json::as_u32), Some(4095));
+        assert!(matches!(error.get("stack"), Some(json::Json::Arr(words)) if words.len() == 8));
+
+        let doc = json::parse(&Report::load("no such file".to_string()).to_json()).unwrap();
+        assert_eq!(doc.get("error").and_then(|e| e.get("address")), Some(&json::Json::Null));
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Write},
+    panic,
+    process::{Command, Stdio},
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use sixemu_core::cpu::Cpu;
+use sixemu_devices::{
+    cga::Registers,
+    font::CodePage,
+    keyboard::{self, Layout},
+    ppi,
+    video::{self, TEXT_COLS, TEXT_ROWS},
+};
+
+// the vector IRQ 1 is taken through
+const KEYBOARD_IRQ_VECTOR: u8 = 9;
+
+// Ctrl-], which ends the run, as it leaves telnet
+const QUIT_KEY: u8 = 0x1d;
+// Ctrl-\, which types the host clipboard; it has no key of its own on
+// the machine
+const PASTE_KEY: u8 = 0x1c;
+
+// what prints the clipboard, Wayland, X11 and macOS
+const CLIPBOARD: [&[&str]; 4] = [
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-o", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+];
+
+// `stty -g` from before raw mode, while it is on
+static SAVED: Mutex<Option<String>> = Mutex::new(None);
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(File::open("/dev/tty")?))
+        .output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// The host clipboard's text, from the first clipboard tool that runs.
+fn clipboard() -> io::Result<Vec<u8>> {
+    for command in CLIPBOARD {
+        let Ok(out) = Command::new(command[0])
+            .args(&command[1..])
+            .stderr(Stdio::null())
+            .output()
+        else {
+            continue;
+        };
+        if out.status.success() {
+            return Ok(out.stdout);
+        }
+    }
+    Err(io::Error::other(
+        "no clipboard tool (wl-paste, xclip, xsel, pbpaste) worked",
+    ))
+}
+
+/// Puts the terminal back as it was before `Terminal::open`. Safe to
+/// call more than once, and from the exit paths that skip destructors.
+pub fn restore() {
+    let Some(saved) = SAVED.lock().ok().and_then(|mut saved| saved.take()) else {
+        return;
+    };
+    let _ = stty(&[&saved]);
+    // below the screen, cursor back on, colours reset
+    print!("\x1b[{};1H\x1b[0m\x1b[?25h", TEXT_ROWS + 1);
+    let _ = io::stdout().flush();
+}
+
+/// The host terminal as the machine's screen and keyboard: raw mode,
+/// the text screen drawn on stdout in its colors, and what is typed on the tty turned
+/// into BIOS keys for `int 16h` and into scan codes for the keyboard on
+/// port 60h.
+pub struct Terminal {
+    input: Receiver<Vec<u8>>,
+    layout: Layout,
+    code_page: CodePage,
+    /// Typed keys the guest has not read, as BIOS words.
+    keys: VecDeque<u16>,
+    /// The queue of the keyboard behind the PPI.
+    scan_codes: Rc<RefCell<VecDeque<u8>>>,
+    /// Pasted keys still to be typed, one every `paste_delay` frames.
+    paste: VecDeque<u16>,
+    paste_delay: u64,
+    // frames until the next pasted key
+    paste_wait: u64,
+    /// The color adapter's mode control, for whether attribute bit 7
+    /// blinks.
+    registers: Rc<Cell<Registers>>,
+    // the least time between redraws, and when the last one was
+    frame: Duration,
+    drawn: Option<Instant>,
+    // the text screen as last written out, where it came from and
+    // whether it blinked
+    shown: Vec<u8>,
+    page: usize,
+    blink: bool,
+    // the attribute the tty is drawing in
+    attr: Option<u8>,
+    // where the tty's cursor was left showing
+    cursor: Option<(usize, usize)>,
+    // set once Ctrl-] has been typed or the tty has closed
+    quit: bool,
+}
+
+impl Terminal {
+    /// Takes over the tty, redrawing at most `refresh` times a second.
+    pub fn open(
+        layout: Layout,
+        code_page: CodePage,
+        paste_delay: u64,
+        refresh: u32,
+        registers: Rc<Cell<Registers>>,
+        scan_codes: Rc<RefCell<VecDeque<u8>>>,
+    ) -> io::Result<Self> {
+        let tty = File::open("/dev/tty")?;
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        *SAVED.lock().unwrap() = Some(saved);
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            hook(info);
+        }));
+
+        let (tx, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut tty = tty;
+            let mut buf = [0u8; 64];
+            while let Ok(n @ 1..) = tty.read(&mut buf) {
+                if tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        // clear the screen, hide the cursor
+        print!("\x1b[2J\x1b[?25l");
+        Ok(Self {
+            input,
+            layout,
+            code_page,
+            keys: VecDeque::new(),
+            scan_codes,
+            paste: VecDeque::new(),
+            paste_delay,
+            paste_wait: 0,
+            blink: registers.get().blink(),
+            registers,
+            frame: Duration::from_secs(1) / refresh,
+            drawn: None,
+            shown: Vec::new(),
+            page: video::TEXT_BASE,
+            attr: None,
+            cursor: None,
+            quit: false,
+        })
+    }
+
+    fn typed(&mut self, cpu: &mut Cpu, bytes: &[u8]) {
+        if bytes.contains(&QUIT_KEY) {
+            self.quit = true;
+            return;
+        }
+        for (i, bytes) in bytes.split(|&b| b == PASTE_KEY).enumerate() {
+            if i > 0 {
+                match clipboard() {
+                    Ok(text) => self
+                        .paste
+                        .extend(keyboard::paste_keys(&String::from_utf8_lossy(&text), self.layout)),
+                    // nowhere to say why on the guest's screen
+                    Err(_) => print!("\x07"),
+                }
+            }
+            for key in keyboard::terminal_keys(bytes, self.layout) {
+                self.press(cpu, key);
+            }
+        }
+    }
+
+    fn press(&mut self, cpu: &mut Cpu, key: u16) {
+        // hypercall programs read plain characters
+        if cpu.hypercall.is_some() && key as u8 != 0 {
+            cpu.input.push_back(key as u8);
+        }
+        // IRQ 1 would take a guest with no keyboard handler into
+        // whatever is at 0000:0000
+        if cpu.vector(KEYBOARD_IRQ_VECTOR) != (0, 0) {
+            ppi::type_codes(&self.scan_codes, &keyboard::scan_codes(key, self.layout));
+        }
+        self.keys.push_back(key);
+    }
+
+    fn next_pasted(&mut self, cpu: &mut Cpu) -> bool {
+        let Some(key) = self.paste.pop_front() else {
+            return false;
+        };
+        self.press(cpu, key);
+        self.paste_wait = self.paste_delay;
+        true
+    }
+
+    fn take_input(&mut self, cpu: &mut Cpu) {
+        while let Ok(bytes) = self.input.try_recv() {
+            self.typed(cpu, &bytes);
+        }
+    }
+
+    /// Called once a video frame: takes in what has been typed, types
+    /// the next pasted key when it is due, and redraws the screen if it
+    /// changed and a refresh is due. False once the user has asked to
+    /// stop.
+    pub fn poll(&mut self, cpu: &mut Cpu) -> bool {
+        self.take_input(cpu);
+        if self.drawn.is_none_or(|drawn| drawn.elapsed() >= self.frame) {
+            self.draw(cpu);
+        }
+        if self.paste_wait > 0 {
+            self.paste_wait -= 1;
+        } else {
+            while self.next_pasted(cpu) && self.paste_delay == 0 {}
+        }
+        !self.quit
+    }
+
+    // writes out the cells the guest has changed since the last frame,
+    // all of them when it has flipped to another page or blinking, and
+    // puts the cursor where the BIOS has it
+    fn draw(&mut self, cpu: &mut Cpu) {
+        self.drawn = Some(Instant::now());
+        let dirty = cpu.dirty.get_or_insert_with(video::text_dirty);
+        let mem = cpu.mem.cursor.get_ref();
+        let page = video::page_base(mem);
+        let blink = self.registers.get().blink();
+        if page != self.page || blink != self.blink {
+            self.page = page;
+            self.blink = blink;
+            self.shown.clear();
+            dirty.mark_all();
+        }
+        let cells = dirty.take().map_or(0..0, |span| video::dirty_cells(mem, span));
+        let cursor = video::cursor(mem);
+        if cells.is_empty() && cursor == self.cursor {
+            return;
+        }
+        let screen = &mem[page..page + TEXT_ROWS * TEXT_COLS * 2];
+        let mut out = String::new();
+        // the cell the tty's cursor is on after the last one written
+        let mut next = None;
+        for cell in cells {
+            let at = cell * 2;
+            if self.shown.get(at..at + 2) == Some(&screen[at..at + 2]) {
+                continue;
+            }
+            if next != Some(cell) || cell % TEXT_COLS == 0 {
+                out.push_str(&format!("\x1b[{};{}H", cell / TEXT_COLS + 1, cell % TEXT_COLS + 1));
+            }
+            if self.attr != Some(screen[at + 1]) {
+                self.attr = Some(screen[at + 1]);
+                out.push_str(&video::ansi_attribute(screen[at + 1], blink));
+            }
+            // box drawing and accents as the PC shows them
+            out.push(self.code_page.char(screen[at]));
+            next = Some(cell + 1);
+        }
+        self.shown = screen.to_vec();
+        if next.is_some() || cursor != self.cursor {
+            match cursor {
+                Some((row, col)) => out.push_str(&format!("\x1b[{};{}H\x1b[?25h", row + 1, col + 1)),
+                None => out.push_str("\x1b[?25l"),
+            }
+            self.cursor = cursor;
+        }
+        if out.is_empty() {
+            return;
+        }
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush());
+    }
+
+    /// Services an `int 16h`. A read with no key waiting shows the
+    /// screen and waits for one, as the BIOS does; a paste under way
+    /// types its next key at once rather than keep the guest waiting.
+    pub fn int16(&mut self, cpu: &mut Cpu) {
+        self.take_input(cpu);
+        self.draw(cpu);
+        if matches!(cpu.regs.get_ah(), 0x00 | 0x10) {
+            while self.keys.is_empty() && !self.quit && !self.next_pasted(cpu) {
+                match self.input.recv() {
+                    Ok(bytes) => self.typed(cpu, &bytes),
+                    Err(_) => self.quit = true,
+                }
+            }
+        }
+        keyboard::int16(cpu, &mut self.keys);
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
@@ -0,0 +1,438 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use sixemu_core::{cpu::Cpu, disasm::Syntax, snapshot::REGISTERS};
+
+use crate::{gzip::GzipWriter, json_str};
+
+const MAGIC: &[u8; 8] = b"6EMUTRCE";
+
+/// How `--trace` writes each executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    /// `cs:ip  bytes  opcode dest, src`, for reading.
+    #[default]
+    Text,
+    /// A header row, then one row per instruction with the registers it
+    /// left.
+    Csv,
+    /// One JSON object per line, with the same fields as the CSV.
+    JsonLines,
+    /// After an 8-byte magic, per instruction: CS and IP as
+    /// little-endian words, the instruction length and bytes, then the
+    /// registers and FLAGS it left as 14 words.
+    Binary,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "csv" => Some(Format::Csv),
+            "jsonl" => Some(Format::JsonLines),
+            "binary" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// One executed instruction: where it was, its bytes and its decoding.
+pub struct Step {
+    pub cs: u16,
+    pub ip: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Step {
+    /// The instruction at CS:IP, decoded without running it.
+    pub fn decode(cpu: &mut Cpu, syntax: Syntax) -> Option<Self> {
+        let (cs, ip) = (cpu.regs.cs, cpu.regs.ip);
+        let (i, next) = cpu.decode().ok()??;
+        Some(Step {
+            cs,
+            ip,
+            bytes: cpu.code_bytes(ip, next.wrapping_sub(ip) as u8),
+            text: syntax.format(&i),
+        })
+    }
+}
+
+fn registers(cpu: &Cpu) -> [u16; 14] {
+    let r = &cpu.regs;
+    [
+        r.ax,
+        r.bx,
+        r.cx,
+        r.dx,
+        r.si,
+        r.di,
+        r.sp,
+        r.bp,
+        r.cs,
+        r.ds,
+        r.es,
+        r.ss,
+        r.ip,
+        r.flags.to_u16(),
+    ]
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    bytes.join(" ")
+}
+
+/// Parses a size such as `4096`, `512K`, `64M` or `2G`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let (digits, unit) = match s.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => s.split_at(i),
+        None => (s, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().as_str() {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift).filter(|&n| n > 0)
+}
+
+/// Where a trace log goes.
+pub trait Sink: Write {
+    /// Starts a new file if the current one is full, returning whether
+    /// it did.
+    fn rotate(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Flushes everything written, closing off compressed output.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Sink for Vec<u8> {}
+
+// counts what reaches the file, after any compression
+struct Counted<W: Write> {
+    w: W,
+    n: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = self.w.write(data)?;
+        self.n += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+enum Output {
+    Plain(Counted<BufWriter<File>>),
+    Gzip(Box<GzipWriter<Counted<BufWriter<File>>>>),
+}
+
+impl Output {
+    fn written(&self) -> u64 {
+        match self {
+            Output::Plain(w) => w.n,
+            Output::Gzip(w) => w.get_ref().n,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut w) => w.flush(),
+            Output::Gzip(w) => w.finish().map(drop),
+        }
+    }
+}
+
+/// A trace file, gzip compressed when its name ends in `.gz`. With a
+/// limit, once it reaches that many bytes it is renamed `FILE.1`
+/// (`FILE.1.gz` for `FILE.gz`), older ones move up a number, and only
+/// `keep` of them are kept.
+pub struct TraceFile {
+    path: PathBuf,
+    gzip: bool,
+    limit: Option<u64>,
+    keep: usize,
+    out: Option<Output>,
+}
+
+impl TraceFile {
+    pub fn create(path: impl Into<PathBuf>, limit: Option<u64>, keep: usize) -> io::Result<Self> {
+        let path = path.into();
+        let gzip = path.extension().is_some_and(|ext| ext == "gz");
+        let mut file = Self {
+            path,
+            gzip,
+            limit,
+            keep,
+            out: None,
+        };
+        file.open()?;
+        Ok(file)
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let w = Counted {
+            w: BufWriter::new(File::create(&self.path)?),
+            n: 0,
+        };
+        self.out = Some(match self.gzip {
+            true => Output::Gzip(Box::new(GzipWriter::new(w)?)),
+            false => Output::Plain(w),
+        });
+        Ok(())
+    }
+
+    // the nth older file
+    fn numbered(&self, n: usize) -> PathBuf {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let name = match name.strip_suffix(".gz").filter(|_| self.gzip) {
+            Some(stem) => format!("{}.{}.gz", stem, n),
+            None => format!("{}.{}", name, n),
+        };
+        self.path.with_file_name(name)
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        match self.out.as_mut().expect("trace file open") {
+            Output::Plain(w) => w,
+            Output::Gzip(w) => w,
+        }
+    }
+}
+
+impl Write for TraceFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.output().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output().flush()
+    }
+}
+
+impl Sink for TraceFile {
+    fn rotate(&mut self) -> io::Result<bool> {
+        match (self.limit, &self.out) {
+            (Some(limit), Some(out)) if out.written() >= limit => {}
+            _ => return Ok(false),
+        }
+        self.out.take().unwrap().finish()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match fs::rename(self.numbered(n), self.numbered(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, self.numbered(1))?;
+        }
+        self.open()?;
+        Ok(true)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.out.take() {
+            Some(out) => out.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct TraceLog<W: Sink> {
+    w: W,
+    format: Format,
+}
+
+impl<W: Sink> TraceLog<W> {
+    /// Writes the CSV header or the binary magic.
+    pub fn new(w: W, format: Format) -> io::Result<Self> {
+        let mut log = Self { w, format };
+        log.header()?;
+        Ok(log)
+    }
+
+    // at the start of every file
+    fn header(&mut self) -> io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                let names: Vec<&str> = REGISTERS.iter().copied().chain(["FLAGS"]).collect();
+                writeln!(self.w, "step,cs,ip,bytes,instruction,{}", names.join(","))
+            }
+            Format::Binary => self.w.write_all(MAGIC),
+            Format::Text | Format::JsonLines => Ok(()),
+        }
+    }
+
+    /// Records `step` with the registers `cpu` holds after it, moving
+    /// on to a new file if the sink rotates.
+    pub fn record(&mut self, step: &Step, cpu: &Cpu) -> io::Result<()> {
+        self.write(step, cpu)?;
+        if self.w.rotate()? {
+            self.header()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, step: &Step, cpu: &Cpu) -> io::Result<()> {
+        let n = cpu.stats.instructions;
+        let regs = registers(cpu);
+        match self.format {
+            Format::Text => writeln!(
+                self.w,
+                "{:04x}:{:04x}  {:<12} {}",
+                step.cs,
+                step.ip,
+                hex(&step.bytes),
+                step.text
+            ),
+            Format::Csv => {
+                let regs: Vec<String> = regs.iter().map(u16::to_string).collect();
+                writeln!(
+                    self.w,
+                    "{},{},{},{},\"{}\",{}",
+                    n,
+                    step.cs,
+                    step.ip,
+                    hex(&step.bytes),
+                    step.text.replace('"', "\"\""),
+                    regs.join(",")
+                )
+            }
+            Format::JsonLines => {
+                let regs: Vec<String> = REGISTERS
+                    .iter()
+                    .chain(&["FLAGS"])
+                    .zip(regs)
+                    .map(|(name, val)| format!("\"{}\":{}", name, val))
+                    .collect();
+                writeln!(
+                    self.w,
+                    "{{\"step\":{},\"cs\":{},\"ip\":{},\"bytes\":\"{}\",\"instruction\":{},\"registers\":{{{}}}}}",
+                    n,
+                    step.cs,
+                    step.ip,
+                    hex(&step.bytes),
+                    json_str(step.text.as_bytes()),
+                    regs.join(",")
+                )
+            }
+            Format::Binary => {
+                self.w.write_all(&step.cs.to_le_bytes())?;
+                self.w.write_all(&step.ip.to_le_bytes())?;
+                self.w.write_all(&[step.bytes.len() as u8])?;
+                self.w.write_all(&step.bytes)?;
+                for word in regs {
+                    self.w.write_all(&word.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.w.finish()
+    }
+}
+
+#[cfg(test)]
+mod tracelog_test {
+    use std::fs;
+
+    use super::{parse_size, Format, Step, TraceFile, TraceLog};
+    use sixemu_core::cpu::Cpu;
+
+    fn step() -> (Step, Cpu) {
+        let mut cpu = Cpu::init();
+        cpu.regs.ax = 5;
+        cpu.stats.instructions = 1;
+        let step = Step {
+            cs: 0,
+            ip: 0x100,
+            bytes: vec![0xb8, 0x05, 0x00],
+            text: "Mov Reg16(0), Imm16(5)".to_string(),
+        };
+        (step, cpu)
+    }
+
+    fn log(format: Format) -> Vec<u8> {
+        let (step, cpu) = step();
+        let mut log = TraceLog::new(Vec::new(), format).unwrap();
+        log.record(&step, &cpu).unwrap();
+        log.w
+    }
+
+    #[test]
+    fn formats() {
+        let text = String::from_utf8(log(Format::Text)).unwrap();
+        assert_eq!(text, "0000:0100  b8 05 00     Mov Reg16(0), Imm16(5)\n");
+
+        let csv = String::from_utf8(log(Format::Csv)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "step,cs,ip,bytes,instruction,AX,BX,CX,DX,SI,DI,SP,BP,CS,DS,ES,SS,IP,FLAGS"
+        );
+        assert!(lines[1].starts_with("1,0,256,b8 05 00,\"Mov Reg16(0), Imm16(5)\",5,0,"));
+
+        let json = String::from_utf8(log(Format::JsonLines)).unwrap();
+        assert!(json.starts_with("{\"step\":1,\"cs\":0,\"ip\":256,\"bytes\":\"b8 05 00\","));
+        assert!(json.contains("\"registers\":{\"AX\":5,"));
+
+        let binary = log(Format::Binary);
+        assert_eq!(&binary[..8], b"6EMUTRCE");
+        assert_eq!(&binary[8..16], &[0, 0, 0, 1, 3, 0xb8, 0x05, 0x00]);
+        assert_eq!(binary.len(), 16 + 28);
+    }
+
+    #[test]
+    fn rotation() {
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size("64m"), Some(64 << 20));
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("0"), None);
+        assert_eq!(parse_size("1T"), None);
+
+        let dir = std::env::temp_dir().join(format!("6emu-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.csv");
+        let (step, cpu) = step();
+        // each row is over 60 bytes, so every record fills the file
+        let mut log = TraceLog::new(TraceFile::create(&path, Some(60), 2).unwrap(), Format::Csv).unwrap();
+        for _ in 0..4 {
+            log.record(&step, &cpu).unwrap();
+        }
+        log.finish().unwrap();
+        let header = "step,cs,ip,bytes,instruction,";
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.starts_with(header) && current.lines().count() == 1);
+        for n in 1..=2 {
+            let old = fs::read_to_string(dir.join(format!("trace.csv.{}", n))).unwrap();
+            assert!(old.starts_with(header) && old.lines().count() == 2);
+        }
+        assert!(!dir.join("trace.csv.3").exists());
+
+        let path = dir.join("trace.log.gz");
+        let mut log = TraceLog::new(TraceFile::create(&path, Some(1), 1).unwrap(), Format::Text).unwrap();
+        log.record(&step, &cpu).unwrap();
+        log.finish().unwrap();
+        assert!(dir.join("trace.log.1.gz").exists());
+        assert_eq!(&fs::read(&path).unwrap()[..2], b"\x1f\x8b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
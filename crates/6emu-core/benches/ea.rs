@@ -0,0 +1,95 @@
+//! Decode and effective address timings for code heavy in memory
+//! operands. Run with `cargo bench -p sixemu-core`; each line is the
+//! best of several runs, in nanoseconds per instruction.
+
+use std::{hint::black_box, time::Instant};
+
+use sixemu_core::{Cpu, Decoder};
+
+const RUNS: usize = 7;
+
+// every r/m memory form under every mod, as `mov ax, [..]` and
+// `add [..], bl`, with displacements where the mod asks for them
+fn ea_heavy_code() -> Vec<u8> {
+    let mut code = Vec::new();
+    for opcode in [0x8b, 0x00] {
+        for mode in 0..3u8 {
+            for rm in 0..8u8 {
+                let reg = if opcode == 0x8b { 0 } else { 3 };
+                code.extend([opcode, mode << 6 | reg << 3 | rm]);
+                match (mode, rm) {
+                    (0, 6) | (2, _) => code.extend([0x34, 0x12]),
+                    (1, _) => code.push(0xfe),
+                    _ => {}
+                }
+            }
+        }
+    }
+    code
+}
+
+// the best of `RUNS` timings of `f`, which handles `count` instructions
+fn time(name: &str, count: usize, mut f: impl FnMut()) {
+    let best = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    println!(
+        "{:<8} {:>8.2} ns/instruction",
+        name,
+        best.as_nanos() as f64 / count as f64
+    );
+}
+
+fn main() {
+    let code = ea_heavy_code();
+    let per_pass = 48;
+    let passes = 20_000;
+
+    time("decode", per_pass * passes, || {
+        for _ in 0..passes {
+            let mut decoder = Decoder::new(0, |at| code[at as usize]);
+            for _ in 0..per_pass {
+                black_box(decoder.decode().unwrap());
+            }
+        }
+    });
+
+    let mut decoder = Decoder::new(0, |at| code[at as usize]);
+    let decoded: Vec<_> = (0..per_pass).map(|_| decoder.decode().unwrap().operands()).collect();
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    (cpu.regs.bx, cpu.regs.bp, cpu.regs.si, cpu.regs.di) = (0x100, 0x200, 0x10, 0x20);
+    time("resolve", per_pass * passes, || {
+        for _ in 0..passes {
+            for &(dest, src) in &decoded {
+                black_box(cpu.resolve(black_box(dest)));
+                black_box(cpu.resolve(black_box(src)));
+            }
+        }
+    });
+
+    // the same forms in a loop too long for `loop`, fetched, decoded
+    // and executed: mov cx, n; l: ...; dec cx; jz done; jmp l; done: hlt
+    let iterations = 20_000u16;
+    let mut program = vec![0xb9];
+    program.extend(iterations.to_le_bytes());
+    program.extend(&code);
+    let back = -(code.len() as i16 + 6);
+    program.extend([0x49, 0x74, 0x03, 0xe9]);
+    program.extend(back.to_le_bytes());
+    program.push(0xf4);
+    time("run", (per_pass + 3) * iterations as usize, || {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.regs.cs = 0x20;
+        cpu.load_code_vec(&program);
+        (cpu.regs.bx, cpu.regs.bp, cpu.regs.si, cpu.regs.di) = (0x100, 0x200, 0x10, 0x20);
+        cpu.fire();
+        assert_eq!(black_box(cpu.regs.cx), 0);
+    });
+}
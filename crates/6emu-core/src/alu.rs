@@ -0,0 +1,100 @@
+//! The arithmetic and logic unit: the two-operand operations at byte
+//! and word width, each giving its result and the status flags it
+//! leaves. Handlers fetch and store the operands; carries, overflow and
+//! sign are worked out here, once, from the operation's own width.
+
+/// Status flag bits, as they sit in the flags register.
+pub const CF: u16 = 0x001;
+pub const PF: u16 = 0x004;
+pub const AF: u16 = 0x010;
+pub const ZF: u16 = 0x040;
+pub const SF: u16 = 0x080;
+pub const OF: u16 = 0x800;
+/// Every flag an arithmetic operation sets.
+pub const ARITH: u16 = CF | PF | AF | ZF | SF | OF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AluOp {
+    Add,
+    /// Add with the carry in.
+    Adc,
+    Sub,
+    /// Subtract with the carry in as a borrow.
+    Sbb,
+    /// The logic operations clear CF, OF and AF.
+    And,
+    Or,
+    Xor,
+}
+
+/// `a op b` on bytes, with `carry` the carry in for `Adc` and `Sbb`.
+pub fn alu8(op: AluOp, a: u8, b: u8, carry: bool) -> (u8, u16) {
+    let (result, flags) = alu(op, a as u32, b as u32, carry, 8);
+    (result as u8, flags)
+}
+
+/// `a op b` on words, with `carry` the carry in for `Adc` and `Sbb`.
+pub fn alu16(op: AluOp, a: u16, b: u16, carry: bool) -> (u16, u16) {
+    let (result, flags) = alu(op, a as u32, b as u32, carry, 16);
+    (result as u16, flags)
+}
+
+/// `alu16` when `word` is set, otherwise `alu8` on the low bytes.
+pub fn apply(op: AluOp, a: u16, b: u16, carry: bool, word: bool) -> (u16, u16) {
+    match word {
+        true => alu16(op, a, b, carry),
+        false => {
+            let (result, flags) = alu8(op, a as u8, b as u8, carry);
+            (result as u16, flags)
+        }
+    }
+}
+
+// both widths at once: the sums are taken wide enough that the carry
+// out is the bit above the top one
+fn alu(op: AluOp, a: u32, b: u32, carry: bool, bits: u32) -> (u32, u16) {
+    let (mask, sign) = ((1 << bits) - 1, 1 << (bits - 1));
+    let carry = carry as u32;
+    let (wide, mut flags) = match op {
+        AluOp::Add | AluOp::Adc => {
+            let r = a + b + if op == AluOp::Adc { carry } else { 0 };
+            let mut flags = 0;
+            if r > mask {
+                flags |= CF;
+            }
+            if (a ^ r) & (b ^ r) & sign != 0 {
+                flags |= OF;
+            }
+            (r, flags)
+        }
+        AluOp::Sub | AluOp::Sbb => {
+            let borrow = if op == AluOp::Sbb { carry } else { 0 };
+            let r = a.wrapping_sub(b).wrapping_sub(borrow);
+            let mut flags = 0;
+            if a < b + borrow {
+                flags |= CF;
+            }
+            if (a ^ b) & (a ^ r) & sign != 0 {
+                flags |= OF;
+            }
+            (r, flags)
+        }
+        AluOp::And => (a & b, 0),
+        AluOp::Or => (a | b, 0),
+        AluOp::Xor => (a ^ b, 0),
+    };
+    let result = wide & mask;
+    if !matches!(op, AluOp::And | AluOp::Or | AluOp::Xor) && (a ^ b ^ wide) & 0x10 != 0 {
+        flags |= AF;
+    }
+    if result == 0 {
+        flags |= ZF;
+    }
+    if result & sign != 0 {
+        flags |= SF;
+    }
+    if (result as u8).count_ones().is_multiple_of(2) {
+        flags |= PF;
+    }
+    (result, flags)
+}
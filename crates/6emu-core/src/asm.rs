@@ -0,0 +1,733 @@
+//! A line assembler for the 8086's integer instructions, in NASM's
+//! Intel syntax: `mov ax, 5`, `add byte [bx+si+2], al`, `jmp 0x100`.
+//! Numbers are decimal unless written `0x1f` or `1fh`; jump and call
+//! targets are offsets in the code segment. `assemble` takes a whole
+//! program, one instruction per line, with `name:` labels to jump to.
+
+use std::collections::HashMap;
+
+const REGS16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const REGS8: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const SEGS: [&str; 4] = ["es", "cs", "ss", "ds"];
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const UNARY: [&str; 6] = ["not", "neg", "mul", "imul", "div", "idiv"];
+const SHIFTS: [(&str, u8); 8] = [
+    ("rol", 0),
+    ("ror", 1),
+    ("rcl", 2),
+    ("rcr", 3),
+    ("shl", 4),
+    ("sal", 4),
+    ("shr", 5),
+    ("sar", 7),
+];
+const JCC: [(&str, u8); 30] = [
+    ("jo", 0x70),
+    ("jno", 0x71),
+    ("jb", 0x72),
+    ("jc", 0x72),
+    ("jnae", 0x72),
+    ("jnb", 0x73),
+    ("jnc", 0x73),
+    ("jae", 0x73),
+    ("jz", 0x74),
+    ("je", 0x74),
+    ("jnz", 0x75),
+    ("jne", 0x75),
+    ("jbe", 0x76),
+    ("jna", 0x76),
+    ("ja", 0x77),
+    ("jnbe", 0x77),
+    ("js", 0x78),
+    ("jns", 0x79),
+    ("jp", 0x7a),
+    ("jpe", 0x7a),
+    ("jnp", 0x7b),
+    ("jpo", 0x7b),
+    ("jl", 0x7c),
+    ("jnge", 0x7c),
+    ("jge", 0x7d),
+    ("jnl", 0x7d),
+    ("jle", 0x7e),
+    ("jng", 0x7e),
+    ("jg", 0x7f),
+    ("jnle", 0x7f),
+];
+const LOOPS: [(&str, u8); 6] = [
+    ("loopne", 0xe0),
+    ("loopnz", 0xe0),
+    ("loope", 0xe1),
+    ("loopz", 0xe1),
+    ("loop", 0xe2),
+    ("jcxz", 0xe3),
+];
+const PREFIXES: [(&str, u8); 6] = [
+    ("lock", 0xf0),
+    ("rep", 0xf3),
+    ("repe", 0xf3),
+    ("repz", 0xf3),
+    ("repne", 0xf2),
+    ("repnz", 0xf2),
+];
+const IMPLIED: [(&str, u8); 38] = [
+    ("nop", 0x90),
+    ("hlt", 0xf4),
+    ("cmc", 0xf5),
+    ("clc", 0xf8),
+    ("stc", 0xf9),
+    ("cli", 0xfa),
+    ("sti", 0xfb),
+    ("cld", 0xfc),
+    ("std", 0xfd),
+    ("pushf", 0x9c),
+    ("popf", 0x9d),
+    ("sahf", 0x9e),
+    ("lahf", 0x9f),
+    ("cbw", 0x98),
+    ("cwd", 0x99),
+    ("wait", 0x9b),
+    ("movsb", 0xa4),
+    ("movsw", 0xa5),
+    ("cmpsb", 0xa6),
+    ("cmpsw", 0xa7),
+    ("stosb", 0xaa),
+    ("stosw", 0xab),
+    ("lodsb", 0xac),
+    ("lodsw", 0xad),
+    ("scasb", 0xae),
+    ("scasw", 0xaf),
+    ("xlat", 0xd7),
+    ("xlatb", 0xd7),
+    ("daa", 0x27),
+    ("das", 0x2f),
+    ("aaa", 0x37),
+    ("aas", 0x3f),
+    ("into", 0xce),
+    ("iret", 0xcf),
+    ("ret", 0xc3),
+    ("retf", 0xcb),
+    ("aam", 0xd4),
+    ("aad", 0xd5),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hint {
+    Byte,
+    Word,
+    Short,
+    Near,
+    Far,
+}
+
+// [base+index+disp], with the r/m field it encodes as, None for a
+// direct address
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Mem {
+    rm: Option<u8>,
+    disp: i32,
+    seg: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arg {
+    Reg(u8, bool),
+    Seg(u8),
+    Imm(i32),
+    Far(u16, u16),
+    Mem(Mem),
+}
+
+use Arg::*;
+
+/// Parses `42`, `-3`, `0x2a`, `2ah` or a character in quotes.
+pub fn number(text: &str) -> Option<i32> {
+    let text = text.trim();
+    if let Some(neg) = text.strip_prefix('-') {
+        return number(neg).map(|n| -n);
+    }
+    let quoted = text
+        .strip_prefix('\'')
+        .and_then(|t| t.strip_suffix('\''))
+        .or_else(|| text.strip_prefix('"').and_then(|t| t.strip_suffix('"')));
+    if let Some(quoted) = quoted {
+        let mut chars = quoted.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii() => Some(c as i32),
+            _ => None,
+        };
+    }
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(hex) = text
+        .strip_suffix('h')
+        .filter(|t| t.starts_with(|c: char| c.is_ascii_digit()))
+    {
+        (hex, 16)
+    } else {
+        (text, 10)
+    };
+    i32::from_str_radix(digits, radix).ok().filter(|n| *n <= 0xfffff)
+}
+
+fn reg(name: &str) -> Option<Arg> {
+    if let Some(id) = REGS16.iter().position(|r| *r == name) {
+        return Some(Reg(id as u8, true));
+    }
+    if let Some(id) = REGS8.iter().position(|r| *r == name) {
+        return Some(Reg(id as u8, false));
+    }
+    SEGS.iter().position(|r| *r == name).map(|id| Seg(id as u8))
+}
+
+fn memory(inner: &str, seg: Option<u8>) -> Result<Mem, String> {
+    let (mut base, mut index, mut disp) = (None, None, 0);
+    let inner = inner.replace('-', "+-");
+    for term in inner.split('+').map(str::trim).filter(|t| !t.is_empty()) {
+        match term {
+            "bx" | "bp" if base.is_none() => base = Some(term),
+            "si" | "di" if index.is_none() => index = Some(term),
+            _ => match number(term) {
+                Some(n) => disp += n,
+                None => return Err(format!("bad address term {}", term)),
+            },
+        }
+    }
+    let rm = match (base, index) {
+        (Some("bx"), Some("si")) => Some(0),
+        (Some("bx"), Some("di")) => Some(1),
+        (Some("bp"), Some("si")) => Some(2),
+        (Some("bp"), Some("di")) => Some(3),
+        (None, Some("si")) => Some(4),
+        (None, Some("di")) => Some(5),
+        (Some("bp"), None) => Some(6),
+        (Some("bx"), None) => Some(7),
+        _ => None,
+    };
+    if !(-0x8000..=0xffff).contains(&disp) {
+        return Err(format!("displacement {} out of range", disp));
+    }
+    Ok(Mem { rm, disp, seg })
+}
+
+fn operand(text: &str) -> Result<(Arg, Option<Hint>), String> {
+    let mut text = text.trim();
+    let mut hint = None;
+    for (word, h) in [
+        ("byte", Hint::Byte),
+        ("word", Hint::Word),
+        ("short", Hint::Short),
+        ("near", Hint::Near),
+        ("far", Hint::Far),
+    ] {
+        if let Some(rest) = text.strip_prefix(word).filter(|r| r.starts_with([' ', '['])) {
+            hint = Some(h);
+            text = rest.trim_start();
+            text = text.strip_prefix("ptr").map(str::trim_start).unwrap_or(text);
+            break;
+        }
+    }
+    if let Some(arg) = reg(text) {
+        return Ok((arg, hint));
+    }
+    // es:[bx], [es:bx] or a far pointer 1234:5678
+    let (seg, rest) = match text.split_once(':') {
+        Some((seg, rest)) if !seg.contains('[') && !text.starts_with(['\'', '"']) => (Some(seg.trim()), rest.trim()),
+        _ => (None, text),
+    };
+    let inner = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']'));
+    match (seg, inner) {
+        (Some(seg), None) => match (number(seg), number(rest)) {
+            (Some(seg), Some(off)) => Ok((Far(seg as u16, off as u16), hint)),
+            _ => Err(format!("bad operand {}", text)),
+        },
+        (seg, Some(inner)) => {
+            let (seg, inner) = match (seg, inner.split_once(':')) {
+                (None, Some((seg, inner))) => (Some(seg.trim()), inner),
+                (seg, _) => (seg, inner),
+            };
+            let seg = match seg {
+                None => None,
+                Some(name) => match reg(name) {
+                    Some(Seg(id)) => Some(id),
+                    _ => return Err(format!("bad segment {}", name)),
+                },
+            };
+            Ok((Mem(memory(inner, seg)?), hint))
+        }
+        (None, None) => match number(text) {
+            Some(n) => Ok((Imm(n), hint)),
+            None => Err(format!("bad operand {}", text)),
+        },
+    }
+}
+
+// the segment override a memory operand needs, if any
+fn prefix(arg: &Arg) -> Option<u8> {
+    match arg {
+        Mem(Mem { seg: Some(seg), .. }) => Some(0x26 | seg << 3),
+        _ => None,
+    }
+}
+
+// the ModRM byte and displacement for `arg` with `reg` in the reg field
+fn modrm(reg: u8, arg: &Arg) -> Vec<u8> {
+    let reg = reg << 3;
+    match *arg {
+        Reg(r, _) => vec![0xc0 | reg | r],
+        Mem(Mem { rm: None, disp, .. }) => {
+            let [lo, hi] = (disp as u16).to_le_bytes();
+            vec![0x06 | reg, lo, hi]
+        }
+        Mem(Mem { rm: Some(rm), disp, .. }) if disp == 0 && rm != 6 => vec![reg | rm],
+        Mem(Mem { rm: Some(rm), disp, .. }) if (-128..=127).contains(&disp) => vec![0x40 | reg | rm, disp as u8],
+        Mem(Mem { rm: Some(rm), disp, .. }) => {
+            let [lo, hi] = (disp as u16).to_le_bytes();
+            vec![0x80 | reg | rm, lo, hi]
+        }
+        _ => unreachable!("modrm of a non-r/m operand"),
+    }
+}
+
+fn imm(n: i32, word: bool) -> Result<Vec<u8>, String> {
+    match word {
+        true if (-0x8000..=0xffff).contains(&n) => Ok((n as u16).to_le_bytes().to_vec()),
+        false if (-0x80..=0xff).contains(&n) => Ok(vec![n as u8]),
+        _ => Err(format!(
+            "{} does not fit in a {}",
+            n,
+            if word { "word" } else { "byte" }
+        )),
+    }
+}
+
+// the operand size an r/m operand has or is given
+fn width(arg: &Arg, hint: Option<Hint>) -> Result<bool, String> {
+    match (arg, hint) {
+        (Reg(_, word), _) => Ok(*word),
+        (_, Some(Hint::Byte)) => Ok(false),
+        (_, Some(Hint::Word)) => Ok(true),
+        _ => Err("operand size not specified (byte or word)".to_string()),
+    }
+}
+
+fn is_rm(arg: &Arg) -> bool {
+    matches!(arg, Reg(..) | Mem(_))
+}
+
+// an opcode with an r/m operand: the opcode, ModRM and displacement
+fn with_rm(op: u8, reg: u8, arg: &Arg) -> Vec<u8> {
+    let mut out = vec![op];
+    out.extend(modrm(reg, arg));
+    out
+}
+
+fn rel8(op: u8, target: i32, ip: u16) -> Result<Vec<u8>, String> {
+    let rel = target - (ip as i32 + 2);
+    match (-128..=127).contains(&rel) {
+        true => Ok(vec![op, rel as u8]),
+        false => Err(format!("{:04x} is out of short jump range", target)),
+    }
+}
+
+fn rel16(op: u8, target: i32, ip: u16) -> Vec<u8> {
+    let rel = (target - (ip as i32 + 3)) as u16;
+    let [lo, hi] = rel.to_le_bytes();
+    vec![op, lo, hi]
+}
+
+fn far(op: u8, seg: u16, off: u16) -> Vec<u8> {
+    let mut out = vec![op];
+    out.extend(off.to_le_bytes());
+    out.extend(seg.to_le_bytes());
+    out
+}
+
+// splits operands on commas outside quotes
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quote) = (0, None);
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+// db/dw: numbers, characters and strings in quotes
+fn data(operands: &[&str], word: bool) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for op in operands {
+        let string = op.strip_prefix('\'').and_then(|o| o.strip_suffix('\''));
+        let string = string.or_else(|| op.strip_prefix('"').and_then(|o| o.strip_suffix('"')));
+        match string {
+            Some(s) if !word && s.len() != 1 => out.extend(s.bytes()),
+            _ => match number(op) {
+                Some(n) => out.extend(imm(n, word)?),
+                None => return Err(format!("bad value {}", op)),
+            },
+        }
+    }
+    Ok(out)
+}
+
+// the line without its comment, lowercased outside quotes
+fn strip(line: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    for c in line.chars() {
+        match (c, quote) {
+            (';', None) => break,
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            _ => {}
+        }
+        out.push(if quote.is_some() { c } else { c.to_ascii_lowercase() });
+    }
+    out.trim().to_string()
+}
+
+/// Assembles one instruction placed at offset `ip`, which relative
+/// jumps and calls are measured from. Comments after `;` are ignored.
+pub fn assemble_line(line: &str, ip: u16) -> Result<Vec<u8>, String> {
+    let line = strip(line);
+    let mut rest = line.as_str();
+    let mut out = Vec::new();
+    loop {
+        let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        match PREFIXES.iter().find(|(name, _)| *name == word) {
+            Some(&(_, byte)) => {
+                out.push(byte);
+                rest = tail.trim_start();
+            }
+            None => break,
+        }
+    }
+    if rest.is_empty() {
+        return match out.is_empty() {
+            true => Err("nothing to assemble".to_string()),
+            false => Ok(out),
+        };
+    }
+    let (mnemonic, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let operands = split_operands(operands);
+    match mnemonic {
+        "db" | "dw" => {
+            out.extend(data(&operands, mnemonic == "dw")?);
+            return Ok(out);
+        }
+        _ => {}
+    }
+    let args: Vec<(Arg, Option<Hint>)> = operands.iter().map(|o| operand(o)).collect::<Result<_, _>>()?;
+    if let Some(seg) = args.iter().find_map(|(arg, _)| prefix(arg)) {
+        out.push(seg);
+    }
+    // the length of what precedes the instruction's own bytes, which
+    // relative targets must account for
+    let at = ip.wrapping_add(out.len() as u16);
+    let bytes = encode(mnemonic, &args, at)?;
+    out.extend(bytes);
+    Ok(out)
+}
+
+fn encode(mnemonic: &str, args: &[(Arg, Option<Hint>)], ip: u16) -> Result<Vec<u8>, String> {
+    let bad = || Err(format!("{} cannot take these operands", mnemonic));
+    let size = |hint: Option<Hint>| hint.filter(|h| matches!(h, Hint::Byte | Hint::Word));
+    if let Some(&(_, op)) = IMPLIED.iter().find(|(name, _)| *name == mnemonic) {
+        return match (mnemonic, args) {
+            (_, []) if matches!(mnemonic, "aam" | "aad") => Ok(vec![op, 0x0a]),
+            (_, []) => Ok(vec![op]),
+            ("ret" | "retf", [(Imm(n), _)]) => {
+                let mut out = vec![op - 1];
+                out.extend(imm(*n, true)?);
+                Ok(out)
+            }
+            ("aam" | "aad", [(Imm(n), _)]) => Ok(vec![op, imm(*n, false)?[0]]),
+            _ => bad(),
+        };
+    }
+    if let Some(n) = ALU.iter().position(|name| *name == mnemonic) {
+        let n = n as u8;
+        return match args {
+            [(dest, dh), (Reg(s, w), _)] if is_rm(dest) && width(dest, size(*dh)).unwrap_or(*w) == *w => {
+                Ok(with_rm(n << 3 | *w as u8, *s, dest))
+            }
+            [(Reg(d, w), _), (src @ Mem(_), sh)] if width(src, size(*sh)).unwrap_or(*w) == *w => {
+                Ok(with_rm(n << 3 | 2 | *w as u8, *d, src))
+            }
+            [(Reg(0, w), _), (Imm(i), _)] => {
+                let mut out = vec![n << 3 | 4 | *w as u8];
+                out.extend(imm(*i, *w)?);
+                Ok(out)
+            }
+            [(dest, dh), (Imm(i), _)] if is_rm(dest) => {
+                let w = width(dest, size(*dh))?;
+                let mut out = with_rm(0x80 | w as u8, n, dest);
+                out.extend(imm(*i, w)?);
+                Ok(out)
+            }
+            _ => bad(),
+        };
+    }
+    if let Some(n) = UNARY.iter().position(|name| *name == mnemonic) {
+        return match args {
+            [(arg, hint)] if is_rm(arg) => Ok(with_rm(0xf6 | width(arg, size(*hint))? as u8, n as u8 + 2, arg)),
+            _ => bad(),
+        };
+    }
+    if let Some(&(_, n)) = SHIFTS.iter().find(|(name, _)| *name == mnemonic) {
+        return match args {
+            [(arg, hint), (Imm(1), _)] if is_rm(arg) => Ok(with_rm(0xd0 | width(arg, size(*hint))? as u8, n, arg)),
+            [(arg, hint), (Reg(1, false), _)] if is_rm(arg) => {
+                Ok(with_rm(0xd2 | width(arg, size(*hint))? as u8, n, arg))
+            }
+            [(_, _), (Imm(_), _)] => Err("the 8086 only shifts by 1 or cl".to_string()),
+            _ => bad(),
+        };
+    }
+    if let Some(&(_, op)) = JCC.iter().find(|(name, _)| *name == mnemonic) {
+        return match args {
+            [(Imm(target), _)] => rel8(op, *target, ip),
+            _ => bad(),
+        };
+    }
+    if let Some(&(_, op)) = LOOPS.iter().find(|(name, _)| *name == mnemonic) {
+        return match args {
+            [(Imm(target), _)] => rel8(op, *target, ip),
+            _ => bad(),
+        };
+    }
+    match (mnemonic, args) {
+        ("mov", [(Mem(m @ Mem { rm: None, .. }), dh), (Reg(0, w), _)])
+            if width(&Mem(*m), size(*dh)).unwrap_or(*w) == *w =>
+        {
+            let mut out = vec![0xa2 | *w as u8];
+            out.extend((m.disp as u16).to_le_bytes());
+            Ok(out)
+        }
+        ("mov", [(Reg(0, w), _), (Mem(m @ Mem { rm: None, .. }), sh)])
+            if width(&Mem(*m), size(*sh)).unwrap_or(*w) == *w =>
+        {
+            let mut out = vec![0xa0 | *w as u8];
+            out.extend((m.disp as u16).to_le_bytes());
+            Ok(out)
+        }
+        ("mov", [(dest, dh), (Reg(s, w), _)]) if is_rm(dest) && width(dest, size(*dh)).unwrap_or(*w) == *w => {
+            Ok(with_rm(0x88 | *w as u8, *s, dest))
+        }
+        ("mov", [(Reg(d, w), _), (src @ Mem(_), sh)]) if width(src, size(*sh)).unwrap_or(*w) == *w => {
+            Ok(with_rm(0x8a | *w as u8, *d, src))
+        }
+        ("mov", [(Reg(d, w), _), (Imm(i), _)]) => {
+            let mut out = vec![0xb0 | (*w as u8) << 3 | d];
+            out.extend(imm(*i, *w)?);
+            Ok(out)
+        }
+        ("mov", [(dest @ Mem(_), dh), (Imm(i), _)]) => {
+            let w = width(dest, size(*dh))?;
+            let mut out = with_rm(0xc6 | w as u8, 0, dest);
+            out.extend(imm(*i, w)?);
+            Ok(out)
+        }
+        ("mov", [(dest, dh), (Seg(s), _)]) if is_rm(dest) && width(dest, size(*dh)).unwrap_or(true) => {
+            Ok(with_rm(0x8c, *s, dest))
+        }
+        ("mov", [(Seg(s), _), (src, sh)]) if is_rm(src) && width(src, size(*sh)).unwrap_or(true) => {
+            Ok(with_rm(0x8e, *s, src))
+        }
+        ("test", [(Reg(0, w), _), (Imm(i), _)]) => {
+            let mut out = vec![0xa8 | *w as u8];
+            out.extend(imm(*i, *w)?);
+            Ok(out)
+        }
+        ("test", [(dest, dh), (Imm(i), _)]) if is_rm(dest) => {
+            let w = width(dest, size(*dh))?;
+            let mut out = with_rm(0xf6 | w as u8, 0, dest);
+            out.extend(imm(*i, w)?);
+            Ok(out)
+        }
+        ("test" | "xchg", [(a, ah), (Reg(r, w), _)]) | ("test" | "xchg", [(Reg(r, w), _), (a, ah)])
+            if is_rm(a) && width(a, size(*ah)).unwrap_or(*w) == *w =>
+        {
+            match (mnemonic, a) {
+                ("xchg", Reg(0, true)) if *w => Ok(vec![0x90 | r]),
+                ("xchg", Reg(other, true)) if *r == 0 => Ok(vec![0x90 | other]),
+                ("xchg", _) => Ok(with_rm(0x86 | *w as u8, *r, a)),
+                _ => Ok(with_rm(0x84 | *w as u8, *r, a)),
+            }
+        }
+        ("inc" | "dec", [(arg, hint)]) if is_rm(arg) => {
+            let n = (mnemonic == "dec") as u8;
+            match arg {
+                Reg(r, true) => Ok(vec![0x40 | n << 3 | r]),
+                _ => Ok(with_rm(0xfe | width(arg, size(*hint))? as u8, n, arg)),
+            }
+        }
+        ("push", [(Reg(r, true), _)]) => Ok(vec![0x50 | r]),
+        ("pop", [(Reg(r, true), _)]) => Ok(vec![0x58 | r]),
+        ("push", [(Seg(s), _)]) => Ok(vec![0x06 | s << 3]),
+        ("pop", [(Seg(s), _)]) => Ok(vec![0x07 | s << 3]),
+        ("push", [(arg @ Mem(_), hint)]) if width(arg, size(*hint)).unwrap_or(true) => Ok(with_rm(0xff, 6, arg)),
+        ("pop", [(arg @ Mem(_), hint)]) if width(arg, size(*hint)).unwrap_or(true) => Ok(with_rm(0x8f, 0, arg)),
+        ("lea" | "lds" | "les", [(Reg(r, true), _), (src @ Mem(_), _)]) => {
+            let op = match mnemonic {
+                "lea" => 0x8d,
+                "lds" => 0xc5,
+                _ => 0xc4,
+            };
+            Ok(with_rm(op, *r, src))
+        }
+        ("jmp", [(Imm(target), hint)]) => match hint {
+            Some(Hint::Short) => rel8(0xeb, *target, ip),
+            Some(Hint::Near) => Ok(rel16(0xe9, *target, ip)),
+            _ => rel8(0xeb, *target, ip).or_else(|_| Ok(rel16(0xe9, *target, ip))),
+        },
+        ("call", [(Imm(target), _)]) => Ok(rel16(0xe8, *target, ip)),
+        ("jmp", [(Far(seg, off), _)]) => Ok(far(0xea, *seg, *off)),
+        ("call", [(Far(seg, off), _)]) => Ok(far(0x9a, *seg, *off)),
+        ("jmp" | "call", [(arg, hint)]) if is_rm(arg) => {
+            let n = if mnemonic == "jmp" { 4 } else { 2 };
+            match (arg, hint) {
+                (Mem(_), Some(Hint::Far)) => Ok(with_rm(0xff, n + 1, arg)),
+                (Reg(_, true), None) | (Mem(_), None | Some(Hint::Word | Hint::Near)) => Ok(with_rm(0xff, n, arg)),
+                _ => bad(),
+            }
+        }
+        ("int", [(Imm(3), _)]) => Ok(vec![0xcc]),
+        ("int", [(Imm(n), _)]) => Ok(vec![0xcd, imm(*n, false)?[0]]),
+        ("in", [(Reg(0, w), _), (Imm(port), _)]) => Ok(vec![0xe4 | *w as u8, imm(*port, false)?[0]]),
+        ("in", [(Reg(0, w), _), (Reg(2, true), _)]) => Ok(vec![0xec | *w as u8]),
+        ("out", [(Imm(port), _), (Reg(0, w), _)]) => Ok(vec![0xe6 | *w as u8, imm(*port, false)?[0]]),
+        ("out", [(Reg(2, true), _), (Reg(0, w), _)]) => Ok(vec![0xee | *w as u8]),
+        _ if [
+            "mov", "test", "xchg", "inc", "dec", "push", "pop", "lea", "lds", "les", "jmp", "call", "int", "in", "out",
+        ]
+        .contains(&mnemonic) =>
+        {
+            bad()
+        }
+        _ => Err(format!("unknown instruction {}", mnemonic)),
+    }
+}
+
+// words an operand can hold that are not labels
+const KEYWORDS: [&str; 6] = ["byte", "word", "short", "near", "far", "ptr"];
+
+// the label a line starts with, and the rest of it
+fn label(line: &str) -> (Option<&str>, &str) {
+    match line.split_once(':') {
+        Some((name, rest)) if is_name(name) && reg(name).is_none() && !KEYWORDS.contains(&name) => {
+            (Some(name), rest.trim_start())
+        }
+        _ => (None, line),
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+// where the operands start, past any prefixes and the mnemonic, as
+// labels only stand in for operands and can share a name with an
+// instruction
+fn operands_at(line: &str) -> usize {
+    let mut rest = line;
+    loop {
+        let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        rest = tail.trim_start();
+        if !PREFIXES.iter().any(|(name, _)| *name == word) {
+            return line.len() - rest.len();
+        }
+    }
+}
+
+// the line with the labels it names written as their offsets
+fn resolve(line: &str, offset: impl Fn(&str) -> Option<u16>) -> String {
+    let mut out = String::new();
+    let (mut word, mut quote) = (String::new(), None);
+    let flush = |word: &mut String, out: &mut String| {
+        match offset(word) {
+            Some(at) => out.push_str(&format!("0x{:x}", at)),
+            None => out.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c.is_ascii_alphanumeric() || c == '_' || c == '.' => {
+                word.push(c);
+                continue;
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None => {}
+        }
+        flush(&mut word, &mut out);
+        out.push(c);
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// Assembles a program loaded at offset 0: one instruction or `db`/`dw`
+/// per line, each optionally after a `name:` label that jumps, calls
+/// and other operands can use as a number.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_at(source, 0)
+}
+
+/// `assemble` for a program loaded at offset `org`, such as 0x100 for a
+/// .com file.
+pub fn assemble_at(source: &str, org: u16) -> Result<Vec<u8>, String> {
+    let lines: Vec<String> = source.lines().map(strip).collect();
+    let mut names = Vec::new();
+    for (n, line) in lines.iter().enumerate() {
+        if let (Some(name), _) = label(line) {
+            if names.contains(&name) {
+                return Err(format!("line {}: {} is already defined", n + 1, name));
+            }
+            names.push(name);
+        }
+    }
+    // a jump's length depends on where its label lands, so go over the
+    // program until the labels stop moving. Labels ahead are taken from
+    // the pass before, or as here on the first, which starts jumps short.
+    let mut labels: HashMap<String, u16> = HashMap::new();
+    for _ in 0..=lines.len() {
+        let (mut out, mut placed) = (Vec::new(), HashMap::new());
+        for (n, line) in lines.iter().enumerate() {
+            let at = org.wrapping_add(out.len() as u16);
+            let (name, rest) = label(line);
+            if let Some(name) = name {
+                placed.insert(name.to_string(), at);
+            }
+            if rest.is_empty() {
+                continue;
+            }
+            let offset = |word: &str| {
+                let ahead = names.contains(&word).then_some(at);
+                placed.get(word).or(labels.get(word)).copied().or(ahead)
+            };
+            let (head, operands) = rest.split_at(operands_at(rest));
+            match assemble_line(&format!("{}{}", head, resolve(operands, offset)), at) {
+                Ok(bytes) => out.extend(bytes),
+                Err(e) => return Err(format!("line {}: {}", n + 1, e)),
+            }
+        }
+        if placed == labels {
+            return Ok(out);
+        }
+        labels = placed;
+    }
+    Err("the labels did not settle".to_string())
+}
@@ -0,0 +1,153 @@
+//! Per-site statistics for conditional jumps: how often each went either
+//! way, how often it went against its previous outcome, and which
+//! instructions last wrote the flags it tested. A site that keeps
+//! flipping is a candidate for a rewrite without the jump; a flag that
+//! comes from somewhere unexpected points at a bug in either the guest
+//! or the emulated flags.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::{
+    alu::{ARITH, CF, OF, PF, SF, ZF},
+    cpu::Opcode,
+};
+
+/// The status flags `opcode` may change. Shifts and rotates by a zero
+/// count change nothing, but are still counted as writers.
+fn writes(opcode: Opcode) -> u16 {
+    use Opcode::*;
+    match opcode {
+        Add | Or | Adc | Sbb | And | Sub | Xor | Cmp | Test | Neg | Mul | Imul | Div | Idiv | Daa | Das | Aaa | Aas
+        | Aam | Aad | Cmpsb | Cmpsw | Scasb | Scasw | Shl | Shr | Sar | Popf | Iret => ARITH,
+        // whatever services the interrupt may hand back anything
+        Int | Into | Callback => ARITH,
+        Inc | Dec | IncAx | IncCx | IncBx | IncDx | IncSp | IncBp | IncSi | IncDi | DecAx | DecCx | DecBx | DecDx
+        | DecSp | DecBp | DecSi | DecDi => ARITH & !CF,
+        Rol | Ror | Rcl | Rcr => CF | OF,
+        Sahf => ARITH & !OF,
+        Clc | Stc | Cmc => CF,
+        _ => 0,
+    }
+}
+
+/// The status flags conditional jump `opcode` tests, or None for
+/// anything else. `loop` and `jcxz` test only CX.
+fn reads(opcode: Opcode) -> Option<u16> {
+    use Opcode::*;
+    Some(match opcode {
+        Jo | Jno => OF,
+        Jb | Jnb => CF,
+        Jz | Jnz | Loope | Loopne => ZF,
+        Jbe | Jnbe => CF | ZF,
+        Js | Jns => SF,
+        Jp | Jnp => PF,
+        Jl | Jnl => SF | OF,
+        Jle | Jnle => ZF | SF | OF,
+        Loop | Jcxz => 0,
+        _ => return None,
+    })
+}
+
+/// One conditional jump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub opcode: Opcode,
+    pub taken: u64,
+    pub not_taken: u64,
+    /// Times it went the other way from the time before. The first run
+    /// is predicted not taken.
+    pub mispredicted: u64,
+    /// How often each instruction, by address, had last written a flag
+    /// the jump tested; None for flags nothing had written yet.
+    pub producers: BTreeMap<Option<u32>, u64>,
+    last: bool,
+}
+
+/// Conditional jump sites by physical address, and the flag writers
+/// they consumed.
+#[derive(Debug, Clone, Default)]
+pub struct BranchProfile {
+    pub sites: BTreeMap<u32, Site>,
+    /// The opcode at each address that wrote a flag a jump tested.
+    pub writers: BTreeMap<u32, Opcode>,
+    // the address of the last writer of each bit of the flags register
+    last_write: [Option<(u32, Opcode)>; 16],
+}
+
+impl BranchProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes that `opcode` at `addr` ran, and for a conditional jump
+    /// whether it was taken.
+    pub fn record(&mut self, addr: u32, opcode: Opcode, taken: bool) {
+        let addr = addr & 0xfffff;
+        if let Some(tested) = reads(opcode) {
+            let site = self.sites.entry(addr).or_insert_with(|| Site {
+                opcode,
+                taken: 0,
+                not_taken: 0,
+                mispredicted: 0,
+                producers: BTreeMap::new(),
+                last: false,
+            });
+            if taken {
+                site.taken += 1;
+            } else {
+                site.not_taken += 1;
+            }
+            if taken != site.last {
+                site.mispredicted += 1;
+            }
+            site.last = taken;
+            let mut seen = Vec::new();
+            for bit in (0..16).filter(|bit| tested & 1 << bit != 0) {
+                let writer = self.last_write[bit];
+                if seen.contains(&writer) {
+                    continue;
+                }
+                seen.push(writer);
+                *site.producers.entry(writer.map(|(at, _)| at)).or_insert(0) += 1;
+                if let Some((at, op)) = writer {
+                    self.writers.insert(at, op);
+                }
+            }
+        }
+        let written = writes(opcode);
+        for bit in (0..16).filter(|bit| written & 1 << bit != 0) {
+            self.last_write[bit] = Some((addr, opcode));
+        }
+    }
+
+    /// One line per site: address, mnemonic, taken and not-taken counts,
+    /// mispredictions, then each flag writer as `address mnemonic xcount`.
+    pub fn write_report(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "site     jump    taken  not-taken  mispredicted  flags from")?;
+        for (addr, site) in &self.sites {
+            write!(
+                w,
+                "0x{:05x}  {:<6} {:>6} {:>10} {:>13} ",
+                addr,
+                site.opcode.to_string(),
+                site.taken,
+                site.not_taken,
+                site.mispredicted
+            )?;
+            if site.producers.is_empty() {
+                write!(w, " -")?;
+            }
+            for (writer, count) in &site.producers {
+                match writer {
+                    Some(at) => write!(w, " 0x{:05x} {} x{}", at, self.writers[at], count)?,
+                    None => write!(w, " reset x{}", count)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,2714 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, Read},
+    process::exit,
+};
+
+#[cfg(feature = "profile")]
+use crate::branches::BranchProfile;
+#[cfg(feature = "profile")]
+use crate::profile::Profile;
+#[cfg(feature = "trace")]
+use crate::trace::{Recording, Timeline};
+use crate::{
+    alu::{self, AluOp},
+    decode::{Decoder, Prefixes, Rep},
+    mem::{Dirty, Mem},
+    regs::Registers,
+    stats::Stats,
+};
+
+/// A memory operand as it was encoded, `seg:[base + index + disp]`, with
+/// any segment override already in `seg`. Registers are numbered as in
+/// `Operand::Reg16`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ea {
+    pub base: Option<u8>,
+    pub index: Option<u8>,
+    pub disp: u16,
+    pub seg: Segment,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    /// A word in memory: its physical address and its offset in the
+    /// segment. What an `Ea16` resolves to when the instruction executes.
+    Mem16(u32, u32),
+    Mem8(u32, u32),
+    /// A word in memory as decoded.
+    Ea16(Ea),
+    Ea8(Ea),
+    Reg8(u8),
+    Reg16(u8),
+    Imm8(u8),
+    Imm16(u16),
+    Seg(u8),
+    /// Target offset of a jump with an 8-bit displacement, already added
+    /// to the IP of the next instruction.
+    Rel8(u16),
+    /// Target offset of a jump or call with a 16-bit displacement.
+    Rel16(u16),
+}
+
+impl Operand {
+    // bits moved, for operands that hold a value
+    fn width(self) -> Option<u8> {
+        match self {
+            Operand::Mem8(..) | Operand::Ea8(_) | Operand::Reg8(_) | Operand::Imm8(_) => Some(8),
+            Operand::Mem16(..) | Operand::Ea16(_) | Operand::Reg16(_) | Operand::Imm16(_) | Operand::Seg(_) => {
+                Some(16)
+            }
+            Operand::Rel8(_) | Operand::Rel16(_) => None,
+        }
+    }
+
+    // a register or memory: what a ModR/M byte names
+    fn is_rm(self) -> bool {
+        self.is_mem() || matches!(self, Operand::Reg8(_) | Operand::Reg16(_))
+    }
+
+    fn is_rm16(self) -> bool {
+        matches!(self, Operand::Mem16(..) | Operand::Ea16(_) | Operand::Reg16(_))
+    }
+
+    pub(crate) fn is_mem(self) -> bool {
+        matches!(self, Operand::Mem8(..) | Operand::Mem16(..) | Operand::Ea8(_) | Operand::Ea16(_))
+    }
+
+    fn is_imm(self) -> bool {
+        matches!(self, Operand::Imm8(_) | Operand::Imm16(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Opcode {
+    Add,
+    PushEs,
+    PopEs,
+    Or,
+    PushCs,
+    PopCs,
+    Adc,
+    PushSs,
+    PopSs,
+    Sbb,
+    Sub,
+    Cmp,
+    PushDs,
+    PopDs,
+    And,
+    Xor,
+    Daa,
+    Aas,
+    Das,
+    Aaa,
+    IncAx,
+    IncCx,
+    IncBx,
+    IncDx,
+    IncSp,
+    IncBp,
+    IncSi,
+    IncDi,
+    DecAx,
+    DecCx,
+    DecBx,
+    DecDx,
+    DecSp,
+    DecBp,
+    DecSi,
+    DecDi,
+    PushAx,
+    PushCx,
+    PushBx,
+    PushDx,
+    PushSp,
+    PushBp,
+    PushSi,
+    PushDi,
+    PopAx,
+    PopCx,
+    PopBx,
+    PopDx,
+    PopSp,
+    PopBp,
+    PopSi,
+    PopDi,
+    Jo,
+    Jno,
+    Jb,
+    Jnb,
+    Jz,
+    Jnz,
+    Jbe,
+    Jnbe,
+    Js,
+    Jns,
+    Jp,
+    Jnp,
+    Jl,
+    Jnl,
+    Jle,
+    Jnle,
+    Test,
+    Xchg,
+    Mov,
+    Lea,
+    Pop,
+    Push,
+    Cbw,
+    Cwd,
+    CallFar,
+    Wait,
+    Pushf,
+    Popf,
+    Lahf,
+    Sahf,
+    Movsb,
+    Movsw,
+    Cmpsb,
+    Cmpsw,
+    Stosb,
+    Stosw,
+    Lodsb,
+    Lodsw,
+    Scasb,
+    Scasw,
+    Ret,
+    Retf,
+    Les,
+    Lds,
+    Int,
+    Into,
+    Iret,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Aad,
+    Aam,
+    Xlat,
+    Loop,
+    Loope,
+    Loopne,
+    Jcxz,
+    In,
+    Out,
+    Hlt,
+    Cmc,
+    CallNear,
+    JmpNear,
+    JmpFar,
+    Not,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Clc,
+    Stc,
+    Cli,
+    Sti,
+    Cld,
+    Std,
+    Inc,
+    Dec,
+    /// `FE 38 nn`, which the 8086 leaves undefined: hands service `nn` to
+    /// the host. Only HLE trampolines use it.
+    Callback,
+}
+
+#[derive(Debug)]
+pub struct Instruction {
+    pub(crate) opcode: Opcode,
+    pub(crate) dest: Operand,
+    pub(crate) src: Operand,
+    pub(crate) prefixes: Prefixes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Segment {
+    Ds,
+    Es,
+    Ss,
+    Cs,
+}
+
+impl Instruction {
+    pub(crate) fn new(opcode: Opcode, dest: Operand, src: Operand) -> Self {
+        Self {
+            opcode,
+            dest,
+            src,
+            prefixes: Prefixes::default(),
+        }
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn operands(&self) -> (Operand, Operand) {
+        (self.dest, self.src)
+    }
+
+    pub fn prefixes(&self) -> Prefixes {
+        self.prefixes
+    }
+
+    fn is_string(&self) -> bool {
+        matches!(
+            self.opcode,
+            Opcode::Movsb
+                | Opcode::Movsw
+                | Opcode::Cmpsb
+                | Opcode::Cmpsw
+                | Opcode::Stosb
+                | Opcode::Stosw
+                | Opcode::Lodsb
+                | Opcode::Lodsw
+                | Opcode::Scasb
+                | Opcode::Scasw
+        )
+    }
+
+    /// Checks the operands suit the opcode, so that execution can count
+    /// on them: nothing is written to an immediate, a segment register
+    /// only moves, both sides are the same width and at most one is in
+    /// memory, and branches have targets. Opcodes whose operands are
+    /// implied are not looked at.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let (dest, src) = (self.dest, self.src);
+        let same_width = || match dest.width() == src.width() {
+            true => Ok(()),
+            false => Err("operands of different widths"),
+        };
+        let check = |ok: bool, why: &'static str| if ok { Ok(()) } else { Err(why) };
+        match self.opcode {
+            Opcode::Add
+            | Opcode::Or
+            | Opcode::Adc
+            | Opcode::Sbb
+            | Opcode::Sub
+            | Opcode::Cmp
+            | Opcode::And
+            | Opcode::Xor
+            | Opcode::Test => {
+                check(dest.is_rm(), "destination is not a register or memory")?;
+                check(src.is_rm() || src.is_imm(), "source is not a register, memory or immediate")?;
+                check(!(dest.is_mem() && src.is_mem()), "two memory operands")?;
+                same_width()
+            }
+            Opcode::Mov => {
+                let seg = matches!(dest, Operand::Seg(_)) || matches!(src, Operand::Seg(_));
+                match seg {
+                    true => check(dest.is_rm16() || src.is_rm16(), "segment register moved to or from a non-word")?,
+                    false => {
+                        check(dest.is_rm(), "destination is not a register or memory")?;
+                        check(src.is_rm() || src.is_imm(), "source is not a register, memory or immediate")?;
+                    }
+                }
+                check(!(dest.is_mem() && src.is_mem()), "two memory operands")?;
+                same_width()
+            }
+            Opcode::Xchg => {
+                check(dest.is_rm() && src.is_rm(), "exchange with something other than a register or memory")?;
+                check(!(dest.is_mem() && src.is_mem()), "two memory operands")?;
+                same_width()
+            }
+            Opcode::Lea | Opcode::Les | Opcode::Lds => check(
+                matches!((dest, src), (Operand::Reg16(_), Operand::Mem16(..) | Operand::Ea16(_))),
+                "needs a word register and a memory operand",
+            ),
+            Opcode::Inc | Opcode::Dec | Opcode::Not | Opcode::Neg | Opcode::Mul | Opcode::Imul | Opcode::Div | Opcode::Idiv => {
+                check(dest.is_rm(), "operand is not a register or memory")
+            }
+            Opcode::Pop => check(dest.is_rm16(), "pops into something other than a word register or memory"),
+            Opcode::Push => check(dest.is_rm16(), "pushes something other than a word register or memory"),
+            Opcode::Rol | Opcode::Ror | Opcode::Rcl | Opcode::Rcr | Opcode::Shl | Opcode::Shr | Opcode::Sar => {
+                check(dest.is_rm(), "shifts something other than a register or memory")?;
+                check(matches!(src, Operand::Imm8(_) | Operand::Reg8(1)), "shift count is not 1 or CL")
+            }
+            Opcode::Jo
+            | Opcode::Jno
+            | Opcode::Jb
+            | Opcode::Jnb
+            | Opcode::Jz
+            | Opcode::Jnz
+            | Opcode::Jbe
+            | Opcode::Jnbe
+            | Opcode::Js
+            | Opcode::Jns
+            | Opcode::Jp
+            | Opcode::Jnp
+            | Opcode::Jl
+            | Opcode::Jnl
+            | Opcode::Jle
+            | Opcode::Jnle
+            | Opcode::Loop
+            | Opcode::Loope
+            | Opcode::Loopne
+            | Opcode::Jcxz => check(matches!(dest, Operand::Rel8(_)), "branch without a target"),
+            Opcode::JmpNear => check(
+                matches!(dest, Operand::Rel8(_) | Operand::Rel16(_)) || dest.is_rm16(),
+                "jump to something other than a target, word register or memory",
+            ),
+            Opcode::CallNear => check(
+                matches!(dest, Operand::Rel16(_)) || dest.is_rm16(),
+                "call to something other than a target, word register or memory",
+            ),
+            Opcode::CallFar => check(
+                matches!(
+                    (dest, src),
+                    (Operand::Imm16(_), Operand::Imm16(_)) | (Operand::Mem16(..) | Operand::Ea16(_), _)
+                ),
+                "far call through something other than a pointer in memory",
+            ),
+            Opcode::JmpFar => check(
+                matches!(
+                    (dest, src),
+                    (Operand::Imm16(_), Operand::Imm16(_)) | (Operand::Mem16(..) | Operand::Ea16(_), _)
+                ),
+                "far jump through something other than a pointer in memory",
+            ),
+            Opcode::Int => check(matches!(dest, Operand::Imm8(_)), "interrupt without a vector"),
+            Opcode::Aam | Opcode::Aad => check(dest == Operand::Imm8(10), "decimal adjust in a base other than 10"),
+            Opcode::In | Opcode::Out => {
+                check(matches!(dest, Operand::Reg8(_) | Operand::Reg16(_)), "port data is not AL or AX")?;
+                check(matches!(src, Operand::Imm8(_) | Operand::Reg16(2)), "port is not an immediate or DX")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct Cpu {
+    pub regs: Registers,
+    pub mem: Mem,
+    pub prog_size: u64,
+    /// Physical address the program image was loaded at.
+    pub load_addr: u32,
+    pub seg_override: Option<Segment>,
+    /// IP of the instruction after the one the last fetch decoded: the
+    /// return address calls push.
+    pub next_ip: u16,
+    pub halt: bool,
+    /// Bytes written by the guest through the test-harness channels
+    /// (the debug port and the hypercall interrupt).
+    pub output: Vec<u8>,
+    /// Problems the run went on past, such as CS writes under
+    /// `CsWrite::Warn`, oldest first.
+    pub faults: Vec<String>,
+    /// Keys waiting to be read through the hypercall interrupt.
+    pub input: VecDeque<u8>,
+    /// Interrupt vector serviced by the host instead of the IVT, if any.
+    pub hypercall: Option<u8>,
+    /// Vectors serviced by the embedder (e.g. disk services in the device
+    /// crate): `int` on one of them leaves the vector in `host_int` and
+    /// returns without touching the stack or the IVT.
+    pub host_ints: Vec<u8>,
+    pub host_int: Option<u8>,
+    /// Physical ranges (start, end exclusive) mapped as ROM or left
+    /// unpopulated: guest writes to them are dropped.
+    pub rom: Vec<(u32, u32)>,
+    /// Physical ranges (start, end exclusive) the guest must not touch:
+    /// reading, writing or fetching a byte there fails the instruction,
+    /// so a test can check a routine stays inside its buffers.
+    pub poison: Vec<(u32, u32)>,
+    /// What guest writes have changed in a range a front end redraws,
+    /// video memory usually; tracked only when set.
+    pub dirty: Option<Dirty>,
+    /// Devices on the I/O bus, asked in order. Ports nobody claims read
+    /// as all ones.
+    pub io: Vec<Box<dyn IoDevice>>,
+    /// Devices mapped into memory, asked in order before RAM on data
+    /// reads and writes. Instruction fetches always come from RAM.
+    pub mmio: Vec<Box<dyn MmioDevice>>,
+    pub exit_code: Option<u8>,
+    /// Which guest idioms end the run.
+    pub exits: ExitConventions,
+    /// How the guest ended the run, once `halt` is set.
+    pub exit_reason: Option<RunExit>,
+    /// What resetting or turning off the machine does.
+    pub power: PowerControl,
+    // CS and IP of the instruction the last fetch decoded
+    inst_start: (u16, u16),
+    /// Times in a row a loop may come round with interrupts disabled and
+    /// nothing changed before the run stops as a livelock; `None` lets
+    /// busy-waiting guests spin.
+    pub livelock_limit: Option<u32>,
+    // registers and activity count at the last jump backwards, and how
+    // many times in a row they were the same
+    livelock: Option<(Registers, u64, u32)>,
+    /// What `mov cs, ...` and `pop cs` do.
+    pub cs_write: CsWrite,
+    /// What a divide error does.
+    pub exceptions: Exceptions,
+    /// Why the run ended with `RunExit::FatalFault`.
+    pub fatal_fault: Option<String>,
+    /// Why the last `fetch` found no instruction at CS:IP, when the
+    /// bytes there do not encode one.
+    pub decode_fault: Option<String>,
+    // SS:SP as the last exception was taken, to tell a fault inside its
+    // handler from one after it returned
+    exception_stack: Option<(u16, u16)>,
+    /// Whether writes to code just ahead of IP are seen at once.
+    pub prefetch: Prefetch,
+    /// How the CPU answers detection tricks; an 8086's by default.
+    pub quirks: CpuQuirks,
+    // CS, IP and contents of the modelled prefetch queue
+    queue: Option<(u16, u16, [u8; QUEUE_LEN])>,
+    pub stats: Stats,
+    /// Execution counts per address, collected only when set.
+    #[cfg(feature = "profile")]
+    pub profile: Option<Profile>,
+    /// Conditional jump outcomes and the flag writers they consumed,
+    /// collected only when set.
+    #[cfg(feature = "profile")]
+    pub branches: Option<BranchProfile>,
+    #[cfg(feature = "trace")]
+    pub timeline: Option<Timeline>,
+    /// Per-step register and memory history for the HTML trace.
+    #[cfg(feature = "trace")]
+    pub recording: Option<Recording>,
+}
+
+/// Why `Cpu::run_until` returned. The ways the guest ends a run set
+/// `exit_reason`, and `exit_code` when they carry one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunExit {
+    /// The predicate held.
+    Predicate,
+    /// `hlt`.
+    Halted,
+    /// The hypercall exit, with code AL.
+    Hypercall,
+    /// `int 21h` with AH=4Ch, with code AL.
+    DosExit,
+    /// A write to the exit port, with the byte written as the code.
+    ExitPort,
+    /// A jump or taken branch to itself, e.g. `jmp $`.
+    JumpToSelf,
+    /// A loop came round `livelock_limit` times with nothing changed.
+    Livelock,
+    /// IP reached the end of the loaded program.
+    EndOfProgram,
+    /// The guest raised an interrupt the host services.
+    HostInt(u8),
+    /// The guest pulsed the reset line and `PowerControl::reboot` is off.
+    Reset,
+    /// A write to `PowerControl::shutdown_port`.
+    PowerOff,
+    /// Under `Exceptions::Deliver`, a fault whose vector cannot be used
+    /// or that came back inside its own handler; `Cpu::fatal_fault` says
+    /// which.
+    FatalFault,
+    /// The bytes at CS:IP do not encode an instruction;
+    /// `Cpu::decode_fault` says why.
+    DecodeFault,
+}
+
+impl RunExit {
+    /// The name dumps give it: `hlt`, `exit-port`, `end-of-program`...
+    pub fn name(self) -> &'static str {
+        match self {
+            RunExit::Predicate => "predicate",
+            RunExit::Halted => "hlt",
+            RunExit::Hypercall => "hypercall",
+            RunExit::DosExit => "dos-exit",
+            RunExit::ExitPort => "exit-port",
+            RunExit::JumpToSelf => "jump-to-self",
+            RunExit::Livelock => "livelock",
+            RunExit::EndOfProgram => "end-of-program",
+            RunExit::HostInt(_) => "host-int",
+            RunExit::Reset => "reset",
+            RunExit::PowerOff => "power-off",
+            RunExit::FatalFault => "fatal-fault",
+            RunExit::DecodeFault => "decode-fault",
+        }
+    }
+}
+
+/// Port the `port` exit convention usually listens on, as with QEMU's
+/// isa-debug-exit device.
+pub const EXIT_PORT: u16 = 0xf4;
+
+/// The keyboard controller's command port, and the command that pulses
+/// the CPU's reset line: how AT software reboots.
+pub const KBC_COMMAND: u16 = 0x64;
+pub const KBC_PULSE_RESET: u8 = 0xfe;
+
+/// Default for `Cpu::livelock_limit`.
+pub const LIVELOCK_LIMIT: u32 = 1000;
+
+/// Guest idioms that end a run. Only `hlt` does by default; the others
+/// suit programs written for DOS or for other emulators' test rigs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExitConventions {
+    /// `hlt` ends the run. Otherwise it only waits for the next
+    /// interrupt, which between steps means it does nothing.
+    pub hlt: bool,
+    /// `int 21h` with AH=4Ch ends the run with code AL.
+    pub dos: bool,
+    /// A jump or taken branch to itself ends the run.
+    pub jump_to_self: bool,
+    /// A write to this port ends the run with the byte written.
+    pub port: Option<u16>,
+}
+
+impl Default for ExitConventions {
+    fn default() -> Self {
+        Self {
+            hlt: true,
+            dos: false,
+            jump_to_self: false,
+            port: None,
+        }
+    }
+}
+
+/// What the guest can do to the machine's power.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PowerControl {
+    /// The reset line restarts the CPU at FFFF:0000, keeping memory,
+    /// rather than ending the run.
+    pub reboot: bool,
+    /// A write to this port turns the machine off, ending the run.
+    pub shutdown_port: Option<u16>,
+}
+
+/// CPUs whose differences detection routines look for.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CpuModel {
+    #[default]
+    I8086,
+    I80186,
+    /// In real mode.
+    I80286,
+}
+
+/// The behaviour CPU-detection tricks test, as a model shows it. Set
+/// from `CpuModel::quirks`; fields can also be changed one by one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuQuirks {
+    /// `push sp` pushes SP as it was before the push (80286) rather than
+    /// after (8086, 80186).
+    pub push_sp_before: bool,
+    /// FLAGS bits 12-15 read as ones (8086, 80186) rather than zeros
+    /// (80286 in real mode).
+    pub flags_high_set: bool,
+    /// Shift and rotate counts are cut to 5 bits (80186 on).
+    pub mask_shift_count: bool,
+}
+
+impl CpuModel {
+    pub fn quirks(self) -> CpuQuirks {
+        match self {
+            CpuModel::I8086 => CpuQuirks {
+                push_sp_before: false,
+                flags_high_set: true,
+                mask_shift_count: false,
+            },
+            CpuModel::I80186 => CpuQuirks {
+                push_sp_before: false,
+                flags_high_set: true,
+                mask_shift_count: true,
+            },
+            CpuModel::I80286 => CpuQuirks {
+                push_sp_before: true,
+                flags_high_set: false,
+                mask_shift_count: true,
+            },
+        }
+    }
+}
+
+/// Policy for `mov cs, r/m16` and `pop cs` (0Fh). The 8086 executes
+/// both, jumping to the same IP in the new segment, but outside of
+/// deliberate tricks they are guest bugs and later CPUs reject them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CsWrite {
+    /// Load CS like the 8086 does.
+    #[default]
+    Allow,
+    /// Load CS and print a warning on stderr.
+    Warn,
+    /// Stop with a panic naming the instruction.
+    Fault,
+}
+
+/// What a divide error does.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Exceptions {
+    /// Stop with a panic naming the fault.
+    #[default]
+    Stop,
+    /// Take it through its vector, as the 8086 does: INT 0 with the
+    /// address of the next instruction pushed. A vector at 0000:0000, in
+    /// poisoned memory or in a ROM hole with nothing mapped, or a fault
+    /// again inside the handler, ends the run with `RunExit::FatalFault`
+    /// instead.
+    Deliver,
+}
+
+/// The vector a divide error is taken through.
+pub const DIVIDE_ERROR: u8 = 0;
+
+/// Where `init_ivt` puts the IRET every vector points to. Offset 0
+/// keeps it below `prog_size`, where fetch runs.
+pub const IRET_STUB: (u16, u16) = (0xf000, 0x0000);
+
+/// The pseudo-instruction HLE trampolines call the host with, followed
+/// by the service number.
+pub const CALLBACK: [u8; 2] = [0xfe, 0x38];
+/// Segment of vector 0's trampoline; vector `n`'s is at
+/// `HLE_SEGMENT + n`:0000, so each one starts at offset 0.
+pub const HLE_SEGMENT: u16 = 0xf010;
+
+/// Bytes the 8086 fetches ahead of the instruction it executes.
+pub const QUEUE_LEN: usize = 6;
+
+/// How code the guest writes is seen by the instructions that follow.
+/// A jump, call, return or interrupt always starts from memory.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Prefetch {
+    /// Every byte is fetched from memory as it is decoded, so a write
+    /// takes effect from the next instruction on.
+    #[default]
+    Coherent,
+    /// Model the 8086's six-byte queue: the bytes following an
+    /// instruction are fetched when it starts, and a write to them goes
+    /// unseen until the queue is flushed by a transfer of control, e.g.
+    /// `jmp $+2`.
+    Authentic,
+}
+
+/// Writing AL to this port appends it to the output-capture buffer
+/// (same convention as the Bochs/QEMU 0xE9 debug port).
+pub const HARNESS_PORT: u16 = 0xe9;
+
+/// Clock rate of the 8088 in the PC and XT.
+pub const CPU_HZ: u64 = 4_772_727;
+/// Clocks counted per instruction. Timings are not modelled per opcode:
+/// every instruction costs the 8088's rough average.
+pub const CLOCKS_PER_INSTRUCTION: u64 = 8;
+
+/// A device answering `in` and `out` on some ports. Word accesses reach
+/// it as two byte accesses, low port first. `now` is `Cpu::cycles`, the
+/// machine's time base.
+pub trait IoDevice {
+    fn handles(&self, port: u16) -> bool;
+    fn read(&mut self, port: u16, now: u64) -> u8;
+    fn write(&mut self, port: u16, val: u8, now: u64);
+    /// Registers and queues to show in the debugger and the dump, for
+    /// devices that have any worth showing.
+    fn debug_state(&self) -> Option<DeviceState> {
+        None
+    }
+    /// The states of the devices behind this one, for one standing in
+    /// for several.
+    fn debug_states(&self) -> Vec<DeviceState> {
+        self.debug_state().into_iter().collect()
+    }
+}
+
+/// A device answering reads and writes to some physical addresses in
+/// place of memory. Word accesses reach it as two byte accesses, low
+/// address first.
+pub trait MmioDevice {
+    fn handles(&self, addr: u32) -> bool;
+    fn read(&mut self, addr: u32, now: u64) -> u8;
+    fn write(&mut self, addr: u32, val: u8, now: u64);
+}
+
+/// A device's state as a name (`com1`, `cmos`) and labelled values,
+/// formatted the way the device's documentation writes them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceState {
+    pub name: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// AH values understood by the hypercall interrupt.
+pub const HYPERCALL_PUTCHAR: u8 = 0;
+pub const HYPERCALL_PUTS: u8 = 1;
+pub const HYPERCALL_EXIT: u8 = 2;
+pub const HYPERCALL_GETCHAR: u8 = 3;
+/// Bytes `HYPERCALL_PUTS` prints before giving up on the NUL: all of DS.
+pub const HYPERCALL_PUTS_LIMIT: usize = 0x10000;
+
+impl Cpu {
+    /// A CPU as reset leaves it, at FFFF:0000 with zeroed memory.
+    pub fn init() -> Self {
+        let mut cpu = Self {
+            halt: false,
+            output: Vec::new(),
+            faults: Vec::new(),
+            input: VecDeque::new(),
+            hypercall: None,
+            host_ints: Vec::new(),
+            host_int: None,
+            rom: Vec::new(),
+            poison: Vec::new(),
+            dirty: None,
+            io: Vec::new(),
+            mmio: Vec::new(),
+            exit_code: None,
+            exits: ExitConventions::default(),
+            exit_reason: None,
+            power: PowerControl::default(),
+            inst_start: (0, 0),
+            livelock_limit: Some(LIVELOCK_LIMIT),
+            livelock: None,
+            cs_write: CsWrite::default(),
+            exceptions: Exceptions::default(),
+            fatal_fault: None,
+            decode_fault: None,
+            exception_stack: None,
+            prefetch: Prefetch::default(),
+            quirks: CpuModel::default().quirks(),
+            queue: None,
+            stats: Stats::default(),
+            #[cfg(feature = "profile")]
+            profile: None,
+            #[cfg(feature = "profile")]
+            branches: None,
+            #[cfg(feature = "trace")]
+            timeline: None,
+            #[cfg(feature = "trace")]
+            recording: None,
+            prog_size: 0,
+            load_addr: 0,
+            regs: Registers::default(),
+            mem: Mem::new(),
+            seg_override: None,
+            next_ip: 0,
+        };
+        cpu.regs.cs = 0xffff;
+        cpu.regs.flags.set_from_u16(2);
+        //println!("New Cpu: mem: {}", cpu.mem.size());
+        cpu
+    }
+
+    /// Puts the registers back as a reset leaves them, at FFFF:0000 with
+    /// interrupts off. Memory and the devices are kept, as on a warm
+    /// boot.
+    pub fn reset(&mut self) {
+        self.regs = Registers::default();
+        self.regs.cs = 0xffff;
+        self.regs.flags.set_from_u16(2);
+        self.flush_queue();
+    }
+
+    /// Starts at 0000:0000 with DS and ES zero and a stack at
+    /// 0100:0FFF, where `load_code_vec` puts a program.
+    pub fn test_mode(&mut self) {
+        self.regs.cs = 0;
+        self.regs.ds = 0;
+        self.regs.ss = 0x100;
+        self.regs.es = 0;
+        self.regs.ip = 0;
+        self.regs.sp = 4095;
+    }
+
+    /// The state of every attached device that reports one.
+    pub fn device_states(&self) -> Vec<DeviceState> {
+        self.io.iter().flat_map(|device| device.debug_states()).collect()
+    }
+
+    /// CPU clocks elapsed so far. Timers count these rather than host
+    /// time, so the guest sees the same timing on any host.
+    pub fn cycles(&self) -> u64 {
+        self.stats.instructions * CLOCKS_PER_INSTRUCTION
+    }
+
+    /// Raises a maskable hardware interrupt between instructions. It is
+    /// taken only with IF set; returns whether it was.
+    pub fn interrupt(&mut self, vector: u8) -> bool {
+        if !self.regs.flags.i_f() {
+            return false;
+        }
+        self.stats.interrupts += 1;
+        #[cfg(feature = "trace")]
+        if let Some(timeline) = &mut self.timeline {
+            timeline.interrupt(vector);
+        }
+        self.push(self.flags_image());
+        self.push(self.regs.cs);
+        self.push(self.regs.ip);
+        self.flush_queue();
+        self.regs.flags.clear_if();
+        let offt = vector as u32 * 4;
+        self.regs.ip = self.read_mem_u16(offt);
+        self.regs.cs = self.read_mem_u16(offt + 2);
+        true
+    }
+
+    /// Points interrupt vector `vector` at `seg:off`.
+    pub fn set_vector(&mut self, vector: u8, seg: u16, off: u16) {
+        let at = vector as usize * 4;
+        let mem = self.mem.cursor.get_mut();
+        mem[at..at + 2].copy_from_slice(&off.to_le_bytes());
+        mem[at + 2..at + 4].copy_from_slice(&seg.to_le_bytes());
+    }
+
+    /// The `(segment, offset)` interrupt vector `vector` holds.
+    pub fn vector(&self, vector: u8) -> (u16, u16) {
+        let at = vector as usize * 4;
+        let mem = self.mem.cursor.get_ref();
+        (
+            u16::from_le_bytes([mem[at + 2], mem[at + 3]]),
+            u16::from_le_bytes([mem[at], mem[at + 1]]),
+        )
+    }
+
+    /// Writes an IRET at `IRET_STUB` and points all 256 vectors at it, so
+    /// an `int` the program never set up returns at once instead of
+    /// running whatever is at 0000:0000.
+    pub fn init_ivt(&mut self) {
+        let (seg, off) = IRET_STUB;
+        self.mem.cursor.get_mut()[(seg as usize * 16 + off as usize) & 0xfffff] = 0xcf;
+        for vector in 0..=255 {
+            self.set_vector(vector, seg, off);
+        }
+    }
+
+    /// Points `vector` at a trampoline in ROM space that hands the
+    /// interrupt to the host through `host_int`, as `host_ints` does, and
+    /// then returns with `sti; retf 2` so the flags the service sets
+    /// reach the caller. Unlike `host_ints`, the guest can hook the
+    /// vector and chain to the saved trampoline address.
+    pub fn install_trampoline(&mut self, vector: u8) {
+        let seg = HLE_SEGMENT + vector as u16;
+        let at = seg as usize * 16;
+        let [c0, c1] = CALLBACK;
+        self.mem.cursor.get_mut()[at..at + 7].copy_from_slice(&[c0, c1, vector, 0xfb, 0xca, 0x02, 0x00]);
+        self.set_vector(vector, seg, 0);
+    }
+
+    /// Runs until the guest halts or IP runs past the program.
+    pub fn fire(&mut self) {
+        while let Some(i) = self.fetch() {
+            self.execute(&i);
+            if self.halt {
+                break;
+            }
+        }
+    }
+
+    /// Steps until `stop` holds for the state an instruction left, the
+    /// guest halts, IP runs past the program or an interrupt is left in
+    /// `host_int`, and says which. After servicing a host interrupt the
+    /// run can be resumed by calling it again.
+    pub fn run_until(&mut self, mut stop: impl FnMut(&Cpu) -> bool) -> RunExit {
+        loop {
+            let Some(i) = self.fetch() else {
+                return self.fetch_failure();
+            };
+            self.execute(&i);
+            if self.halt {
+                return self.exit_reason.unwrap_or(RunExit::Halted);
+            }
+            if let Some(vector) = self.host_int {
+                return RunExit::HostInt(vector);
+            }
+            if stop(self) {
+                return RunExit::Predicate;
+            }
+        }
+    }
+
+    pub fn get_seg_reg(&self, pos: u8) -> u16 {
+        match pos & 0b11 {
+            0 => self.regs.es,
+            1 => self.regs.cs,
+            2 => self.regs.ss,
+            3 => self.regs.ds,
+            4u8..=u8::MAX => panic!("invalid seg reg {}", pos),
+        }
+    }
+
+    pub fn set_seg_reg(&mut self, pos: u8, val: u16) {
+        match pos & 0b11 {
+            0 => self.regs.es = val,
+            1 => self.regs.cs = val,
+            2 => self.regs.ss = val,
+            3 => self.regs.ds = val,
+            4u8..=u8::MAX => panic!("invalid seg reg {}", pos),
+        };
+    }
+
+    // a write to CS by mov or pop, subject to `cs_write`
+    fn write_cs(&mut self, val: u16) {
+        match self.cs_write {
+            CsWrite::Allow => {}
+            CsWrite::Warn => {
+                let fault = format!(
+                    "mov/pop cs changed cs from {:04x} to {:04x}, ip {:04x}",
+                    self.regs.cs, val, self.regs.ip
+                );
+                eprintln!("warning: {}", fault);
+                self.faults.push(fault);
+            }
+            CsWrite::Fault => panic!("mov/pop cs ({:04x}) refused by the cs write policy", val),
+        }
+        self.regs.cs = val;
+    }
+
+    pub fn get_reg(&self, id: u8, word: bool) -> u16 {
+        if word {
+            match id {
+                0 => self.regs.get_ax(),
+                3 => self.regs.get_bx(),
+                2 => self.regs.get_dx(),
+                6 => self.regs.get_si(),
+                4 => self.regs.get_sp(),
+                5 => self.regs.get_bp(),
+                7 => self.regs.get_di(),
+                1 => self.regs.get_cx(),
+                8..=u8::MAX => panic!("invalid register: {}", id),
+            }
+        } else {
+            (match id {
+                0 => self.regs.get_al(),
+                3 => self.regs.get_bl(),
+                2 => self.regs.get_dl(),
+                6 => self.regs.get_dh(),
+                4 => self.regs.get_ah(),
+                5 => self.regs.get_ch(),
+                7 => self.regs.get_bh(),
+                1 => self.regs.get_cl(),
+                8..=u8::MAX => panic!("invalid register: {}", id),
+            }) as u16
+        }
+    }
+
+    pub fn set_reg(&mut self, id: u8, word: bool, val: u16) {
+        if word {
+            match id {
+                0 => self.regs.set_ax(val),
+                3 => self.regs.set_bx(val),
+                2 => self.regs.set_dx(val),
+                6 => self.regs.set_si(val),
+                4 => self.regs.set_sp(val),
+                5 => self.regs.set_bp(val),
+                7 => self.regs.set_di(val),
+                1 => self.regs.set_cx(val),
+                8..=u8::MAX => panic!("invalid register: {}", id),
+            };
+        } else {
+            (match id {
+                0 => self.regs.set_al(val as u8),
+                3 => self.regs.set_bl(val as u8),
+                2 => self.regs.set_dl(val as u8),
+                6 => self.regs.set_dh(val as u8),
+                4 => self.regs.set_ah(val as u8),
+                5 => self.regs.set_ch(val as u8),
+                7 => self.regs.set_bh(val as u8),
+                1 => self.regs.set_cl(val as u8),
+                8..=u8::MAX => panic!("invalid register: {}", id),
+            });
+        };
+    }
+
+    /// Physical address of SEG:OFFT, wrapping at 1 MiB like the 8086.
+    pub fn ea(&self, seg: &Segment, offt: u32) -> u32 {
+        let base = match seg {
+            Segment::Ds => self.regs.get_ds(),
+            Segment::Es => self.regs.get_es(),
+            Segment::Ss => self.regs.get_ss(),
+            Segment::Cs => self.regs.get_cs(),
+        };
+        (base + offt) & 0xfffff
+    }
+
+    pub fn get_segment_offset(&mut self, seg: Segment, offt: u32) -> u32 {
+        if let Some(ov) = &self.seg_override {
+            let res = self.ea(ov, offt);
+            //self.seg_override = None;
+            //unimplemented!("segment override");
+            res
+        } else {
+            self.ea(&seg, offt)
+        }
+    }
+
+    /// Decodes the instruction at CS:IP and moves IP past it, counting it
+    /// in the statistics and the profilers. `None` at the end of the
+    /// program, or with `decode_fault` set when the bytes there do not
+    /// decode; IP is left on them.
+    pub fn fetch(&mut self) -> Option<Instruction> {
+        let (ip, start) = (self.regs.ip, self.code_addr(self.regs.ip));
+        self.inst_start = (self.regs.cs, ip);
+        self.decode_fault = None;
+        if self.queue.is_some_and(|(cs, at, _)| (cs, at) != (self.regs.cs, ip)) {
+            self.flush_queue();
+        }
+        let (res, next_ip) = match self.decode() {
+            Ok(decoded) => decoded?,
+            Err(why) => {
+                self.decode_fault = Some(why);
+                return None;
+            }
+        };
+        self.next_ip = next_ip;
+        if self.prefetch == Prefetch::Authentic {
+            self.fill_queue(next_ip);
+        }
+        let len = next_ip.wrapping_sub(ip) as u8;
+        self.stats.instructions += 1;
+        #[cfg(feature = "profile")]
+        if let Some(profile) = &mut self.profile {
+            profile.record(start, len);
+        }
+        #[cfg(feature = "trace")]
+        if let Some(timeline) = &mut self.timeline {
+            timeline.instruction(start, len);
+        }
+        #[cfg(feature = "trace")]
+        if self.recording.is_some() {
+            let bytes = self.code_bytes(ip, len);
+            let text = res.to_string();
+            if let Some(recording) = &mut self.recording {
+                recording.step(&self.regs, &bytes, text);
+            }
+        }
+        self.regs.ip = next_ip;
+        Some(res)
+    }
+
+    /// Why the run stops after `fetch` returned `None`.
+    pub fn fetch_failure(&self) -> RunExit {
+        match self.decode_fault {
+            Some(_) => RunExit::DecodeFault,
+            None => RunExit::EndOfProgram,
+        }
+    }
+
+    /// Decodes the instruction at CS:IP without moving IP, returning it
+    /// with the offset of the instruction that follows, `None` past the
+    /// end of the program, or why the bytes there are no instruction.
+    /// Bytes are read through CS, so an instruction straddling offset
+    /// 0xffff wraps like the 8086's. Nothing changes: memory operands
+    /// are left for `execute` to resolve.
+    pub fn decode(&self) -> Result<Option<(Instruction, u16)>, String> {
+        if self.regs.ip as u64 >= self.prog_size {
+            return Ok(None);
+        }
+        let mut decoder = Decoder::new(self.regs.ip, |ip| {
+            self.check_poison(self.code_addr(ip), 1, "fetch");
+            self.code_byte(ip)
+        });
+        let inst = decoder.decode()?;
+        Ok(Some((inst, decoder.ip())))
+    }
+
+    // the byte at CS:ip, from the prefetch queue if it holds it
+    fn code_byte(&self, ip: u16) -> u8 {
+        match self.queue {
+            Some((cs, at, bytes)) if cs == self.regs.cs && (ip.wrapping_sub(at) as usize) < QUEUE_LEN => {
+                bytes[ip.wrapping_sub(at) as usize]
+            }
+            _ => self.mem.cursor.get_ref()[self.code_addr(ip) as usize],
+        }
+    }
+
+    // the queue now starts at next_ip: bytes it already held are kept,
+    // the rest come from memory as it is now
+    fn fill_queue(&mut self, next_ip: u16) {
+        let mut bytes = [0; QUEUE_LEN];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = self.code_byte(next_ip.wrapping_add(i as u16));
+        }
+        self.queue = Some((self.regs.cs, next_ip, bytes));
+    }
+
+    pub(crate) fn flush_queue(&mut self) {
+        self.queue = None;
+    }
+
+    /// The `len` bytes of code at CS:`ip`.
+    pub fn code_bytes(&self, ip: u16, len: u8) -> Vec<u8> {
+        let mem = self.mem.cursor.get_ref();
+        (0..len as u16)
+            .map(|i| mem[self.code_addr(ip.wrapping_add(i)) as usize])
+            .collect()
+    }
+
+    /// `op` with a memory operand as decoded turned into the address it
+    /// names under the current registers; anything else as it is.
+    pub fn resolve(&self, op: Operand) -> Operand {
+        let ea = match op {
+            Operand::Ea8(ea) | Operand::Ea16(ea) => ea,
+            _ => return op,
+        };
+        let reg = |r: Option<u8>| r.map_or(0, |r| self.get_reg(r, true));
+        let offt = ea.disp.wrapping_add(reg(ea.base)).wrapping_add(reg(ea.index)) as u32;
+        let addr = self.ea(&ea.seg, offt);
+        match op {
+            Operand::Ea16(_) => Operand::Mem16(addr, offt),
+            _ => Operand::Mem8(addr, offt),
+        }
+    }
+
+    fn operand_value(&mut self, op: Operand) -> u16 {
+        match op {
+            Operand::Ea8(_) | Operand::Ea16(_) => {
+                let op = self.resolve(op);
+                self.operand_value(op)
+            }
+            Operand::Mem16(i, _) => self.read_mem_u16(i),
+            Operand::Mem8(i, _) => self.read_mem_u8(i) as u16,
+            Operand::Reg8(i) => self.get_reg(i, false),
+            Operand::Reg16(i) => self.get_reg(i, true),
+            Operand::Imm8(i) => i as u16,
+            Operand::Imm16(i) => i,
+            Operand::Rel8(t) | Operand::Rel16(t) => t,
+            Operand::Seg(i) => self.get_seg_reg(i),
+        }
+    }
+
+    fn set_operand(&mut self, op: Operand, val: u16) {
+        match op {
+            Operand::Ea8(_) | Operand::Ea16(_) => self.set_operand(self.resolve(op), val),
+            Operand::Mem16(p, _) => self.write_mem_u16(p, val),
+            Operand::Mem8(p, _) => self.write_mem_u8(p, val as u8),
+            Operand::Reg8(r) => self.set_reg(r, false, val),
+            Operand::Reg16(r) => self.set_reg(r, true, val),
+            _ => unreachable!("Immediate destination"),
+        }
+    }
+
+    fn check_poison(&self, pos: u32, len: u32, access: &str) {
+        if self.poison.is_empty() {
+            return;
+        }
+        if let Some(&(start, end)) = self.poison.iter().find(|&&(start, end)| pos < end && pos + len > start) {
+            panic!("{} of poisoned memory at {:05x} (poisoned {:05x}-{:05x})", access, pos, start, end - 1);
+        }
+    }
+
+    fn in_rom(&self, pos: u32) -> bool {
+        self.rom.iter().any(|&(start, end)| (start..end).contains(&pos))
+    }
+
+    // notes a write in `dirty` if it changes memory
+    fn note_write(&mut self, pos: u32, val: u8) {
+        if let Some(dirty) = &mut self.dirty {
+            if self.mem.cursor.get_ref().get(pos as usize) != Some(&val) {
+                dirty.note(pos);
+            }
+        }
+    }
+
+    // the memory-mapped device at `pos`, if any
+    fn mmio_at(&mut self, pos: u32) -> Option<&mut Box<dyn MmioDevice>> {
+        self.mmio.iter_mut().find(|d| d.handles(pos))
+    }
+
+    fn store_u8(&mut self, pos: u32, val: u8) {
+        if self.in_rom(pos) {
+            return;
+        }
+        if !self.mmio.is_empty() {
+            let now = self.cycles();
+            if let Some(device) = self.mmio_at(pos) {
+                device.write(pos, val, now);
+                return;
+            }
+        }
+        self.note_write(pos, val);
+        #[cfg(feature = "trace")]
+        if let Some(recording) = &mut self.recording {
+            recording.write(pos, val, self.mem.cursor.get_ref());
+        }
+        let p = self.mem.pos();
+        self.mem.seek_to(pos as u64);
+        self.mem.write_u8(val);
+        self.mem.seek_to(p);
+    }
+
+    pub fn write_mem_u16(&mut self, pos: u32, val: u16) {
+        self.check_poison(pos, 2, "write");
+        self.stats.mem_writes16 += 1;
+        if !self.rom.is_empty() && (self.in_rom(pos) || self.in_rom(pos + 1)) || !self.mmio.is_empty() {
+            let [lo, hi] = val.to_le_bytes();
+            self.store_u8(pos, lo);
+            self.store_u8(pos + 1, hi);
+            return;
+        }
+        #[cfg(feature = "trace")]
+        if let Some(recording) = &mut self.recording {
+            let [lo, hi] = val.to_le_bytes();
+            recording.write(pos, lo, self.mem.cursor.get_ref());
+            recording.write(pos + 1, hi, self.mem.cursor.get_ref());
+        }
+        if self.dirty.is_some() {
+            let [lo, hi] = val.to_le_bytes();
+            self.note_write(pos, lo);
+            self.note_write(pos + 1, hi);
+        }
+        let p = self.mem.pos();
+        self.mem.seek_to(pos as u64);
+        self.mem.write_u16(val);
+        self.mem.seek_to(p);
+    }
+
+    pub fn write_mem_u8(&mut self, pos: u32, val: u8) {
+        self.check_poison(pos, 1, "write");
+        self.stats.mem_writes8 += 1;
+        self.store_u8(pos, val);
+    }
+
+    pub fn read_mem_u16(&mut self, pos: u32) -> u16 {
+        self.check_poison(pos, 2, "read");
+        self.stats.mem_reads16 += 1;
+        if !self.mmio.is_empty() {
+            return u16::from_le_bytes([self.load_u8(pos), self.load_u8(pos + 1)]);
+        }
+        let p = self.mem.pos();
+        self.mem.seek_to(pos as u64);
+        let res = self.mem.read_u16();
+        self.mem.seek_to(p);
+        res
+    }
+
+    pub fn read_mem_u8(&mut self, pos: u32) -> u8 {
+        self.check_poison(pos, 1, "read");
+        self.stats.mem_reads8 += 1;
+        self.load_u8(pos)
+    }
+
+    fn load_u8(&mut self, pos: u32) -> u8 {
+        if !self.mmio.is_empty() {
+            let now = self.cycles();
+            if let Some(device) = self.mmio_at(pos) {
+                return device.read(pos, now);
+            }
+        }
+        let p = self.mem.pos();
+        self.mem.seek_to(pos as u64);
+        let res = self.mem.read_u8();
+        self.mem.seek_to(p);
+        res
+    }
+
+    //pub fn add()
+
+    /// Runs `op` on the destination and source at the destination's
+    /// width and sets the flags in `written` from it, storing the result
+    /// unless `store` is off, as for `cmp` and `test`.
+    fn alu(&mut self, d: Operand, s: Operand, op: AluOp, written: u16, store: bool) {
+        let (dest, src) = (self.operand_value(d), self.operand_value(s));
+        let (result, flags) = alu::apply(op, dest, src, self.regs.flags.cf(), d.width() == Some(16));
+        self.regs.flags.set_masked(flags, written);
+        if store {
+            self.set_operand(d, result);
+        }
+    }
+
+    // inc and dec leave CF alone
+    fn inc(&mut self, d: Operand) {
+        self.alu(d, Operand::Imm16(1), AluOp::Add, alu::ARITH & !alu::CF, true);
+    }
+
+    fn dec(&mut self, d: Operand) {
+        self.alu(d, Operand::Imm16(1), AluOp::Sub, alu::ARITH & !alu::CF, true);
+    }
+
+    // the adjustments follow the Intel pseudocode: the high digit is
+    // corrected from the value al had before the low one was
+    fn daa(&mut self) {
+        let (old_al, old_cf) = (self.regs.get_al(), self.regs.flags.cf());
+        let mut al = old_al;
+        self.regs.flags.clear_cf();
+        if al & 0b1111 > 9 || self.regs.flags.af() {
+            let (sum, carry) = al.overflowing_add(6);
+            al = sum;
+            if old_cf || carry {
+                self.regs.flags.set_cf();
+            }
+            self.regs.flags.set_af();
+        } else {
+            self.regs.flags.clear_af();
+        }
+
+        if old_al > 0x99 || old_cf {
+            al = al.wrapping_add(0x60);
+            self.regs.flags.set_cf();
+        } else {
+            self.regs.flags.clear_cf();
+        }
+
+        self.regs.flags.set_szp8(al);
+        self.regs.set_al(al);
+    }
+
+    fn aaa(&mut self) {
+        let mut al = self.regs.get_al();
+        let mut ah = self.regs.get_ah();
+        if al & 0b1111 > 9 || self.regs.flags.af() {
+            al = al.wrapping_add(6);
+            ah = ah.wrapping_add(1);
+            self.regs.flags.set_af();
+            self.regs.flags.set_cf();
+        } else {
+            self.regs.flags.clear_af();
+            self.regs.flags.clear_cf();
+        }
+        self.regs.set_al(al & 0b1111);
+        self.regs.set_ah(ah);
+    }
+
+    fn das(&mut self) {
+        let (old_al, old_cf) = (self.regs.get_al(), self.regs.flags.cf());
+        let mut al = old_al;
+        self.regs.flags.clear_cf();
+        if al & 0b1111 > 9 || self.regs.flags.af() {
+            let (diff, borrow) = al.overflowing_sub(6);
+            al = diff;
+            if old_cf || borrow {
+                self.regs.flags.set_cf();
+            }
+            self.regs.flags.set_af();
+        } else {
+            self.regs.flags.clear_af();
+        }
+
+        if old_al > 0x99 || old_cf {
+            al = al.wrapping_sub(0x60);
+            self.regs.flags.set_cf();
+        }
+
+        self.regs.flags.set_szp8(al);
+        self.regs.set_al(al);
+    }
+
+    fn aas(&mut self) {
+        let mut al = self.regs.get_al();
+        let mut ah = self.regs.get_ah();
+        if al & 0b1111 > 9 || self.regs.flags.af() {
+            al = al.wrapping_sub(6);
+            ah = ah.wrapping_sub(1);
+            self.regs.flags.set_af();
+            self.regs.flags.set_cf();
+        } else {
+            self.regs.flags.clear_af();
+            self.regs.flags.clear_cf();
+        }
+        self.regs.set_al(al & 0b1111);
+        self.regs.set_ah(ah);
+    }
+
+    fn push(&mut self, val: u16) {
+        self.regs.sp = self.regs.sp.wrapping_sub(2);
+        self.write_mem_u16(self.stack_addr(self.regs.sp), val);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let v = self.read_mem_u16(self.stack_addr(self.regs.sp));
+        self.regs.sp = self.regs.sp.wrapping_add(2);
+        v
+    }
+
+    /// FLAGS as `pushf` and interrupts store it: bits 12-15 read as ones
+    /// on the 8086 and 80186 and as zeros on the 80286 in real mode.
+    pub fn flags_image(&self) -> u16 {
+        let flags = self.regs.flags.to_u16() & 0x0fff;
+        if self.quirks.flags_high_set {
+            flags | 0xf000
+        } else {
+            flags
+        }
+    }
+
+    fn pushf(&mut self) {
+        self.push(self.flags_image());
+    }
+
+    fn popf(&mut self) {
+        let v = self.read_mem_u16(self.stack_addr(self.regs.sp));
+        // bits 12-15 are not kept; flags_image supplies them
+        self.regs.flags.set_from_u16(v & 0x0fff);
+        self.regs.sp = self.regs.sp.wrapping_add(2);
+    }
+
+    fn pop2(&mut self, inst: &Instruction) {
+        let val = self.read_mem_u16(self.stack_addr(self.regs.sp));
+        self.regs.sp = self.regs.sp.wrapping_add(2);
+
+        self.set_operand(inst.dest, val);
+    }
+
+    fn jump_short_if(&mut self, inst: &Instruction, cond: bool) {
+        if cond {
+            self.stats.branches_taken += 1;
+            if let Operand::Rel8(target) = inst.dest {
+                self.regs.ip = target;
+                self.flush_queue();
+            } else {
+                unreachable!()
+            }
+        } else {
+            self.stats.branches_not_taken += 1;
+        }
+    }
+
+    fn exchg(&mut self, inst: &Instruction) {
+        let d = self.operand_value(inst.dest);
+        let s = self.operand_value(inst.src);
+        self.set_operand(inst.src, d);
+        self.set_operand(inst.dest, s);
+    }
+
+    fn mov(&mut self, inst: &Instruction) {
+        let val = self.operand_value(inst.src);
+        match inst.dest {
+            Operand::Seg(r) if r & 0b11 == 1 => self.write_cs(val),
+            Operand::Seg(r) => self.set_seg_reg(r, val),
+            dest => self.set_operand(dest, val),
+        }
+    }
+
+    fn lea(&mut self, inst: &Instruction) {
+        let Operand::Mem16(_, m) = inst.src else {
+            unreachable!("lea validated with a memory source");
+        };
+        self.set_operand(inst.dest, m as u16);
+    }
+
+    fn sign_extend8(val: u8) -> u16 {
+        val as i8 as u16
+    }
+
+    fn sign_extend16(val: u16) -> u32 {
+        val as i16 as u32
+    }
+
+    fn cbw(&mut self) {
+        self.regs.ax = Self::sign_extend8(self.regs.get_al());
+    }
+
+    // dx takes the upper half of ax sign-extended to 32 bits
+    fn cwd(&mut self) {
+        self.regs.dx = (Self::sign_extend16(self.regs.ax) >> 16) as u16;
+    }
+
+    fn lahf(&mut self) {
+        let v = self.regs.flags.to_u16();
+        self.regs.set_ah(v as u8);
+    }
+
+    fn sahf(&mut self) {
+        let mut v = self.regs.flags.to_u16() & 0xff00;
+        v |= self.regs.get_ah() as u16;
+        self.regs.flags.set_from_u16(v);
+    }
+
+    /// Moves SI and/or DI past the element a string instruction just
+    /// used: by one or two bytes, backwards when DF is set.
+    fn advance_string(&mut self, si: bool, di: bool, word: bool) {
+        let step: u16 = if word { 2 } else { 1 };
+        let step = if self.regs.flags.df() { step.wrapping_neg() } else { step };
+        if si {
+            self.regs.si = self.regs.si.wrapping_add(step);
+        }
+        if di {
+            self.regs.di = self.regs.di.wrapping_add(step);
+        }
+    }
+
+    // DS:SI, where string instructions read from; unlike ES:DI a
+    // segment override can move it
+    fn source_addr(&self) -> u32 {
+        self.ea(&self.seg_override.unwrap_or(Segment::Ds), self.regs.si as u32)
+    }
+
+    fn movsb(&mut self) {
+        let mut dest = self.extra_addr(self.regs.di);
+        let mut src = self.source_addr();
+        let val = self.read_mem_u8(src);
+        self.write_mem_u8(dest, val);
+        self.advance_string(true, true, false);
+    }
+
+    fn movsw(&mut self) {
+        let mut dest = self.extra_addr(self.regs.di);
+        let mut src = self.source_addr();
+        let val = self.read_mem_u16(src);
+        self.write_mem_u16(dest, val);
+        self.advance_string(true, true, true);
+    }
+
+    // flags for [ds:si] - [es:di]
+    fn cmps(&mut self, word: bool) {
+        let src = self.source_addr();
+        let dest = self.extra_addr(self.regs.di);
+        let (a, b) = match word {
+            true => (self.read_mem_u16(src), self.read_mem_u16(dest)),
+            false => (self.read_mem_u8(src) as u16, self.read_mem_u8(dest) as u16),
+        };
+        let (_, flags) = alu::apply(AluOp::Sub, a, b, false, word);
+        self.regs.flags.set_masked(flags, alu::ARITH);
+        self.advance_string(true, true, word);
+    }
+
+    // flags for al/ax - [es:di]
+    fn scas(&mut self, word: bool) {
+        let dest = self.extra_addr(self.regs.di);
+        let (a, b) = match word {
+            true => (self.regs.get_ax(), self.read_mem_u16(dest)),
+            false => (self.regs.get_al() as u16, self.read_mem_u8(dest) as u16),
+        };
+        let (_, flags) = alu::apply(AluOp::Sub, a, b, false, word);
+        self.regs.flags.set_masked(flags, alu::ARITH);
+        self.advance_string(false, true, word);
+    }
+
+    fn stosb(&mut self) {
+        let mut destt = self.extra_addr(self.regs.di);
+        self.write_mem_u8(destt, self.regs.get_al());
+
+        self.advance_string(false, true, false);
+    }
+
+    fn stosw(&mut self) {
+        let mut destt = self.extra_addr(self.regs.di);
+        self.write_mem_u16(destt, self.regs.get_ax());
+
+        self.advance_string(false, true, true);
+    }
+
+    fn lodsb(&mut self) {
+        let mut src = self.source_addr();
+        let val = self.read_mem_u8(src);
+        self.regs.set_al(val);
+        self.advance_string(true, false, false);
+    }
+
+    fn lodsw(&mut self) {
+        let mut src = self.source_addr();
+        let val = self.read_mem_u16(src);
+        self.regs.set_ax(val);
+        self.advance_string(true, false, true);
+    }
+
+    fn ret(&mut self, inst: &Instruction) {
+        self.flush_queue();
+        self.regs.ip = self.pop();
+        if let Operand::Imm16(im) = inst.dest {
+            self.regs.sp = self.regs.sp.wrapping_add(im);
+        }
+    }
+
+    fn retf(&mut self, inst: &Instruction) {
+        self.flush_queue();
+        self.regs.ip = self.pop();
+        self.regs.cs = self.pop();
+        if let Operand::Imm16(im) = inst.dest {
+            self.regs.sp = self.regs.sp.wrapping_add(im);
+        }
+    }
+
+    // loads the offset of the far pointer at the source into the
+    // destination register and returns its segment
+    fn load_far(&mut self, inst: &Instruction) -> u16 {
+        let Operand::Mem16(m, _) = inst.src else {
+            unreachable!("{:?} validated with a memory source", inst.opcode);
+        };
+        let offset = self.read_mem_u16(m);
+        self.set_operand(inst.dest, offset);
+        self.read_mem_u16(m.wrapping_add(2))
+    }
+
+    fn les(&mut self, inst: &Instruction) {
+        self.regs.es = self.load_far(inst);
+    }
+
+    fn lds(&mut self, inst: &Instruction) {
+        self.regs.ds = self.load_far(inst);
+    }
+
+    fn rot8(&mut self, dest: u8, times: u8, left: bool) -> u8 {
+        let mut rn = 0u8;
+        let res = if left {
+            rn = (dest).rotate_left(times as u32);
+            if times > 0 && (rn & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn
+        } else {
+            rn = (dest).rotate_right(times as u32);
+            if times > 0 && (rn & 128) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn
+        };
+
+        if res & !0b01111111 != dest & !0b01111111 {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn rot16(&mut self, dest: u16, times: u8, left: bool) -> u16 {
+        let mut rn = 0u16;
+        let res = if left {
+            rn = (dest).rotate_left(times as u32);
+            if times > 0 && (rn & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn
+        } else {
+            rn = (dest).rotate_right(times as u32);
+            if times > 0 && (rn & !0b01111111_11111111) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn
+        };
+
+        if res & !0b01111111_11111111 != dest & !0b01111111_11111111 {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    // the count of a shift or rotate: 1 or CL, cut to 5 bits from the
+    // 80186 on
+    fn shift_count(&self, src: Operand) -> u8 {
+        let times = match src {
+            Operand::Imm8(imm) => imm,
+            Operand::Reg8(1) => self.regs.get_cl(),
+            _ => unreachable!("shift: invalid count operand"),
+        };
+        if self.quirks.mask_shift_count {
+            times & 0x1f
+        } else {
+            times
+        }
+    }
+
+    fn rotate(&mut self, inst: &Instruction, left: bool) {
+        let times = self.shift_count(inst.src);
+        // a count of 0, also after masking, changes nothing
+        if times == 0 {
+            return;
+        }
+
+        let dest = self.operand_value(inst.dest);
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+        match inst.dest {
+            Operand::Reg16(id) => {
+                let val = self.rot16(dest, times, left);
+                self.set_reg(id, true, val);
+            }
+            Operand::Mem16(pos, _) => {
+                let val = self.rot16(dest, times, left);
+                self.write_mem_u16(pos, val);
+            }
+            Operand::Reg8(id) => {
+                let val = self.rot8(dest as u8, times, left);
+                self.set_reg(id, false, val as u16);
+            }
+            Operand::Mem8(pos, _) => {
+                let val = self.rot8(dest as u8, times, left);
+                self.write_mem_u8(pos, val);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn rotcf8(&mut self, dest: u8, times: u8, left: bool) -> u8 {
+        let oldcf = self.regs.flags.cf();
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+        let mut rn = 0u8;
+        let res = if left {
+            rn = (dest).rotate_left(times as u32);
+            if times > 0 && (rn & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+
+            rn &= !1;
+            rn |= oldcf as u8;
+
+            rn
+        } else {
+            rn = (dest).rotate_right(times as u32);
+            if times > 0 && (rn & 128) > 0 {
+                self.regs.flags.set_cf();
+            }
+
+            rn &= !128;
+            rn |= (oldcf as u8) << 7;
+            rn
+        };
+
+        if res & !0b01111111 != dest & !0b01111111 {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn rotcf16(&mut self, dest: u16, times: u8, left: bool) -> u16 {
+        let oldcf = self.regs.flags.cf();
+
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+
+        let mut rn = 0u16;
+        let res = if left {
+            rn = (dest).rotate_left(times as u32);
+            if times > 0 && (rn & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn &= !1;
+            rn |= oldcf as u16;
+
+            rn
+        } else {
+            rn = (dest).rotate_right(times as u32);
+            if times > 0 && (rn & !0b01111111_11111111) > 0 {
+                self.regs.flags.set_cf();
+            }
+            rn &= 0x7fff;
+            rn |= (oldcf as u16) << 15;
+            rn
+        };
+
+        if res & !0b01111111_11111111 != dest & !0b01111111_11111111 {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn rotate_cf(&mut self, inst: &Instruction, left: bool) {
+        let times = self.shift_count(inst.src);
+        let word = matches!(inst.dest, Operand::Reg16(_) | Operand::Mem16(..));
+        let mut val = self.operand_value(inst.dest);
+        // one bit at a time through CF
+        for _ in 0..times {
+            val = if word {
+                self.rotcf16(val, 1, left)
+            } else {
+                self.rotcf8(val as u8, 1, left) as u16
+            };
+        }
+        if times > 0 {
+            self.set_operand(inst.dest, val);
+        }
+    }
+
+    fn sh8(&mut self, val: u8, left: bool) -> u8 {
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+
+        let res = if left {
+            if (val & 128) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shl(1)
+        } else {
+            if (val & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shr(1)
+        };
+        if (val & 128) != (res & 128) {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn sh16(&mut self, val: u16, left: bool) -> u16 {
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+
+        let res = if left {
+            if (val & 0x8000) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shl(1)
+        } else {
+            if (val & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shr(1)
+        };
+        if (val & 0x8000) != (res & 0x8000) {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn shift(&mut self, inst: &Instruction, left: bool) {
+        let times = self.shift_count(inst.src);
+        let word = matches!(inst.dest, Operand::Reg16(_) | Operand::Mem16(..));
+        let mut val = self.operand_value(inst.dest);
+        for _ in 0..times {
+            val = if word {
+                self.sh16(val, left)
+            } else {
+                self.sh8(val as u8, left) as u16
+            };
+        }
+        if times > 0 {
+            self.set_operand(inst.dest, val);
+        }
+    }
+
+    fn shal8(&mut self, val: u8) -> u8 {
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+
+        let mut res = {
+            if (val & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shr(1)
+        };
+
+        res |= (val & 128);
+
+        if (val & 128) != (res & 128) {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn shal16(&mut self, val: u16) -> u16 {
+        self.regs.flags.clear_cf();
+        self.regs.flags.clear_of();
+
+        let mut res = {
+            if (val & 1) > 0 {
+                self.regs.flags.set_cf();
+            }
+            val.wrapping_shr(1)
+        };
+
+        res |= (val & 0x8000);
+
+        if (val & 0x8000) != (res & 0x8000) {
+            self.regs.flags.set_of();
+        }
+        res
+    }
+
+    fn shalr(&mut self, inst: &Instruction) {
+        let times = self.shift_count(inst.src);
+        let word = matches!(inst.dest, Operand::Reg16(_) | Operand::Mem16(..));
+        let mut val = self.operand_value(inst.dest);
+        for _ in 0..times {
+            val = if word { self.shal16(val) } else { self.shal8(val as u8) as u16 };
+        }
+        if times > 0 {
+            self.set_operand(inst.dest, val);
+        }
+    }
+
+    fn aad(&mut self) {
+        let al = self
+            .regs
+            .get_ah()
+            .wrapping_mul(10)
+            .wrapping_add(self.regs.get_al());
+        self.regs.flags.set_szp8(al);
+        self.regs.set_al(al);
+        self.regs.set_ah(0);
+    }
+
+    fn aam(&mut self) {
+        let ah = self.regs.get_al() / 10;
+        let al = self.regs.get_al() % 10;
+        self.regs.flags.set_szp8(al);
+        self.regs.set_al(al);
+        self.regs.set_ah(ah);
+    }
+
+    // al = [ds:bx+al], the offset wrapping within the segment
+    fn xlat(&mut self) {
+        let offt = self.regs.bx.wrapping_add(self.regs.get_al() as u16);
+        let addr = self.get_segment_offset(Segment::Ds, offt as u32);
+        let byte = self.read_mem_u8(addr);
+        self.regs.set_al(byte);
+    }
+
+    fn loopp(&mut self, inst: &Instruction) {
+        self.regs.cx = self.regs.cx.wrapping_sub(1);
+        self.jump_short_if(inst, self.regs.cx != 0);
+    }
+
+    fn loope(&mut self, inst: &Instruction) {
+        self.regs.cx = self.regs.cx.wrapping_sub(1);
+        self.jump_short_if(inst, self.regs.cx != 0 && self.regs.flags.zf());
+    }
+
+    fn loopne(&mut self, inst: &Instruction) {
+        self.regs.cx = self.regs.cx.wrapping_sub(1);
+        self.jump_short_if(inst, self.regs.cx != 0 && !self.regs.flags.zf());
+    }
+
+    fn jcxz(&mut self, inst: &Instruction) {
+        self.jump_short_if(inst, self.regs.cx == 0);
+    }
+
+    fn jmp_near(&mut self, inst: &Instruction) {
+        self.flush_queue();
+        match inst.dest {
+            Operand::Rel8(target) | Operand::Rel16(target) => {
+                self.regs.ip = target;
+            }
+            Operand::Reg16(r) => {
+                self.regs.ip = self.get_reg(r, true);
+            }
+            Operand::Mem16(pos, _) => {
+                self.regs.ip = self.read_mem_u16(pos);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the target is read before anything is pushed, so a pointer on the
+    // stack is not overwritten first
+    fn call_near(&mut self, inst: &Instruction) {
+        let target = match inst.dest {
+            Operand::Rel16(target) => target,
+            Operand::Mem16(pos, _) => self.read_mem_u16(pos),
+            Operand::Reg16(r) => self.get_reg(r, true),
+            _ => unreachable!(),
+        };
+        self.push(self.next_ip);
+        self.regs.ip = target;
+        self.flush_queue();
+    }
+
+    fn call_far(&mut self, inst: &Instruction) {
+        let (ip, cs) = match (inst.dest, inst.src) {
+            (Operand::Imm16(ip), Operand::Imm16(seg)) => (ip, seg),
+            (Operand::Mem16(pos, _), _) => (self.read_mem_u16(pos), self.read_mem_u16(pos.wrapping_add(2))),
+            _ => unreachable!(),
+        };
+        self.push(self.regs.cs);
+        self.push(self.next_ip);
+        self.regs.ip = ip;
+        self.regs.cs = cs;
+        self.flush_queue();
+    }
+
+    fn jmp_far(&mut self, inst: &Instruction) {
+        self.flush_queue();
+        match (inst.dest, inst.src) {
+            (Operand::Imm16(ip), Operand::Imm16(cs)) => {
+                self.regs.ip = ip;
+                self.regs.cs = cs;
+            }
+            (Operand::Mem16(pos, _), _) => {
+                self.regs.ip = self.read_mem_u16(pos);
+                self.regs.cs = self.read_mem_u16(pos.wrapping_add(2))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the string instruction runs once for each count in CX; compares
+    // and scans also stop once ZF no longer matches the prefix, set for
+    // rep(e) and clear for repne
+    fn repeat(&mut self, inst: &Instruction, rep: Rep) {
+        let compares = matches!(inst.opcode, Opcode::Cmpsb | Opcode::Cmpsw | Opcode::Scasb | Opcode::Scasw);
+        while self.regs.cx != 0 {
+            self.operate(inst);
+            self.regs.cx = self.regs.cx.wrapping_sub(1);
+            if compares && self.regs.flags.zf() != (rep == Rep::Rep) {
+                break;
+            }
+        }
+    }
+
+    fn push_mem(&mut self, inst: &Instruction) {
+        let val = self.operand_value(inst.dest);
+        self.push(val);
+    }
+
+    // mul/imul: al * r/m8 into ax, or ax * r/m16 into dx:ax. CF and OF
+    // are set when the upper half is more than the extension of the lower
+    // half, zero for mul and its sign for imul.
+    fn multiply(&mut self, inst: &Instruction, signed: bool) {
+        let word = matches!(inst.dest, Operand::Reg16(_) | Operand::Mem16(_, _));
+        let op = self.operand_value(inst.dest);
+        let (product, fits) = match (word, signed) {
+            (false, false) => {
+                let p = self.regs.get_al() as u32 * op as u32;
+                (p, p <= 0xff)
+            }
+            (false, true) => {
+                let p = self.regs.get_al() as i8 as i32 * op as u8 as i8 as i32;
+                (p as u32, p == p as i8 as i32)
+            }
+            (true, false) => {
+                let p = self.regs.ax as u32 * op as u32;
+                (p, p <= 0xffff)
+            }
+            (true, true) => {
+                let p = self.regs.ax as i16 as i32 * op as i16 as i32;
+                (p as u32, p == p as i16 as i32)
+            }
+        };
+        self.regs.ax = product as u16;
+        if word {
+            self.regs.dx = (product >> 16) as u16;
+        }
+        if fits {
+            self.regs.flags.clear_of();
+            self.regs.flags.clear_cf();
+        } else {
+            self.regs.flags.set_of();
+            self.regs.flags.set_cf();
+        }
+    }
+
+    // div/idiv: ax by r/m8 into al rem ah, or dx:ax by r/m16 into ax rem
+    // dx. A zero divisor or a quotient too wide for the lower half faults.
+    fn divide(&mut self, inst: &Instruction, signed: bool) {
+        let word = matches!(inst.dest, Operand::Reg16(_) | Operand::Mem16(_, _));
+        let op = self.operand_value(inst.dest);
+        let wide = (self.regs.dx as u32) << 16 | self.regs.ax as u32;
+        let (n, d) = match (word, signed) {
+            (false, false) => (self.regs.ax as i64, op as i64),
+            (false, true) => (self.regs.ax as i16 as i64, Self::sign_extend8(op as u8) as i16 as i64),
+            (true, false) => (wide as i64, op as i64),
+            (true, true) => (wide as i32 as i64, Self::sign_extend16(op) as i32 as i64),
+        };
+        if d == 0 {
+            return self.fault(DIVIDE_ERROR, "attempt to divide by zero".to_string());
+        }
+        // both truncate toward zero, the remainder taking the dividend's sign
+        let (q, r) = (n / d, n % d);
+        let fits = match (word, signed) {
+            (false, false) => q <= 0xff,
+            (false, true) => q == q as i8 as i64,
+            (true, false) => q <= 0xffff,
+            (true, true) => q == q as i16 as i64,
+        };
+        if !fits {
+            return self.fault(DIVIDE_ERROR, format!("divide overflow: quotient {:#x} does not fit", q));
+        }
+        if word {
+            self.regs.ax = q as u16;
+            self.regs.dx = r as u16;
+        } else {
+            self.regs.set_al(q as u8);
+            self.regs.set_ah(r as u8);
+        }
+    }
+
+    // leaves the flags alone
+    fn not(&mut self, inst: &Instruction) {
+        let d = self.operand_value(inst.dest);
+        self.set_operand(inst.dest, !d);
+    }
+
+    // 0 - dest, flagged like the subtraction
+    fn neg(&mut self, inst: &Instruction) {
+        let d = self.operand_value(inst.dest);
+        let (result, flags) = alu::apply(AluOp::Sub, 0, d, false, inst.dest.width() == Some(16));
+        self.regs.flags.set_masked(flags, alu::ARITH);
+        self.set_operand(inst.dest, result);
+    }
+
+    fn int(&mut self, inst: &Instruction) {
+        if let Operand::Imm8(imm) = inst.dest {
+            self.stats.interrupts += 1;
+            #[cfg(feature = "trace")]
+            if let Some(timeline) = &mut self.timeline {
+                timeline.interrupt(imm);
+            }
+            if self.hypercall == Some(imm) {
+                self.hypercall();
+                return;
+            }
+            if self.exits.dos && imm == 0x21 && self.regs.get_ah() == 0x4c {
+                self.exit(RunExit::DosExit, Some(self.regs.get_al()));
+                return;
+            }
+            if self.host_ints.contains(&imm) {
+                self.host_int = Some(imm);
+                return;
+            }
+        }
+
+        self.push(self.flags_image());
+        self.push(self.regs.cs);
+        self.push(self.next_ip);
+        self.flush_queue();
+
+        self.regs.flags.clear_if();
+
+        match inst.dest {
+            Operand::Imm8(imm) => {
+                let offt = (imm as u32).wrapping_mul(4);
+                self.regs.ip = self.read_mem_u16(offt);
+                self.regs.cs = self.read_mem_u16(offt.wrapping_add(2));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // a fault the instruction raised, `why` naming it
+    fn fault(&mut self, vector: u8, why: String) {
+        if self.exceptions == Exceptions::Stop {
+            panic!("{}", why);
+        }
+        let (seg, off) = self.vector(vector);
+        let at = ((seg as u32) << 4).wrapping_add(off as u32) & 0xfffff;
+        let within = |ranges: &[(u32, u32)]| ranges.iter().any(|&(start, end)| (start..end).contains(&at));
+        let mem = self.mem.cursor.get_ref();
+        let unusable = if (seg, off) == (0, 0) {
+            Some("was never set")
+        } else if within(&self.poison) {
+            Some("is in poisoned memory")
+        } else if within(&self.rom) && mem[at as usize] == 0 && mem[(at as usize + 1) & 0xfffff] == 0 {
+            Some("is in a ROM hole with nothing mapped")
+        } else {
+            None
+        };
+        let stack = (self.regs.ss, self.regs.sp);
+        let nested = matches!(self.exception_stack, Some((ss, sp)) if ss == stack.0 && stack.1 < sp);
+        let fatal = match unusable {
+            Some(problem) => Some(format!(
+                "{}, and int {:02x}h's handler at {:04x}:{:04x} {}",
+                why, vector, seg, off, problem
+            )),
+            None if nested => Some(format!("{} inside int {:02x}h's handler for the last one", why, vector)),
+            None => None,
+        };
+        if let Some(message) = fatal {
+            self.fatal_fault = Some(message);
+            self.exit(RunExit::FatalFault, None);
+            return;
+        }
+        self.exception_stack = Some(stack);
+        self.stats.interrupts += 1;
+        #[cfg(feature = "trace")]
+        if let Some(timeline) = &mut self.timeline {
+            timeline.interrupt(vector);
+        }
+        self.push(self.flags_image());
+        self.push(self.regs.cs);
+        self.push(self.next_ip);
+        self.flush_queue();
+        self.regs.flags.clear_if();
+        self.regs.flags.clear_tf();
+        (self.regs.cs, self.regs.ip) = (seg, off);
+    }
+
+    fn into(&mut self, inst: &Instruction) {
+        if self.regs.flags.of() {
+            self.push(self.flags_image());
+            self.push(self.regs.cs);
+            self.push(self.next_ip);
+            self.flush_queue();
+            self.stats.interrupts += 1;
+            #[cfg(feature = "trace")]
+            if let Some(timeline) = &mut self.timeline {
+                timeline.interrupt(4);
+            }
+            self.regs.flags.clear_if();
+            let offt = (4u32).wrapping_mul(4);
+            self.regs.ip = self.read_mem_u16(offt);
+            self.regs.cs = self.read_mem_u16(offt.wrapping_add(2));
+        }
+    }
+
+    fn iret(&mut self, inst: &Instruction) {
+        self.flush_queue();
+        self.regs.ip = self.pop();
+        self.regs.cs = self.pop();
+        let f = self.pop();
+        self.regs.flags.set_from_u16(f & 0x0fff);
+        // back out of the handler of the last exception, so a fault
+        // deeper in the stack later on is a fresh one
+        if self.exception_stack.is_some_and(|(ss, sp)| ss != self.regs.ss || self.regs.sp >= sp) {
+            self.exception_stack = None;
+        }
+    }
+
+    // AH=0: print AL, AH=1: print the NUL-terminated string at DS:DX,
+    // at most a segment of it, AH=2: stop with exit code AL
+    fn hypercall(&mut self) {
+        match self.regs.get_ah() {
+            HYPERCALL_PUTCHAR => self.output.push(self.regs.get_al()),
+            HYPERCALL_PUTS => {
+                let (mut offt, mut terminated) = (self.regs.dx, false);
+                for _ in 0..HYPERCALL_PUTS_LIMIT {
+                    let c = self.read_mem_u8(self.data_addr(offt));
+                    if c == 0 {
+                        terminated = true;
+                        break;
+                    }
+                    self.output.push(c);
+                    offt = offt.wrapping_add(1);
+                }
+                if !terminated {
+                    self.faults.push(format!(
+                        "hypercall puts: no NUL in the {:#x} bytes from {:04x}:{:04x}",
+                        HYPERCALL_PUTS_LIMIT, self.regs.ds, self.regs.dx
+                    ));
+                }
+            }
+            HYPERCALL_EXIT => self.exit(RunExit::Hypercall, Some(self.regs.get_al())),
+            HYPERCALL_GETCHAR => {
+                let key = self.input.pop_front().unwrap_or(0);
+                self.regs.set_al(key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads a byte from the device on `port` as `in` would, for host
+    /// services that program hardware, without counting it in `stats`.
+    pub fn io_read(&mut self, port: u16) -> u8 {
+        let now = self.cycles();
+        match self.io.iter_mut().find(|d| d.handles(port)) {
+            Some(device) => device.read(port, now),
+            None => 0xff,
+        }
+    }
+
+    /// Writes a byte to the device on `port` as `out` would, without
+    /// counting it in `stats`.
+    pub fn io_write(&mut self, port: u16, val: u8) {
+        let now = self.cycles();
+        if let Some(device) = self.io.iter_mut().find(|d| d.handles(port)) {
+            device.write(port, val, now);
+        }
+    }
+
+    fn port_in(&mut self, port: u16, word: bool) -> u16 {
+        self.stats.port_reads += 1;
+        let lo = self.io_read(port) as u16;
+        let val = if word {
+            lo | ((self.io_read(port.wrapping_add(1)) as u16) << 8)
+        } else {
+            lo
+        };
+        #[cfg(feature = "trace")]
+        if let Some(timeline) = &mut self.timeline {
+            timeline.port(port, val, false);
+        }
+        val
+    }
+
+    fn port_out(&mut self, port: u16, val: u16, word: bool) {
+        self.stats.port_writes += 1;
+        #[cfg(feature = "trace")]
+        if let Some(timeline) = &mut self.timeline {
+            timeline.port(port, val, true);
+        }
+        if port == HARNESS_PORT {
+            self.output.push(val as u8);
+        }
+        if self.exits.port == Some(port) {
+            self.exit(RunExit::ExitPort, Some(val as u8));
+        }
+        if port == KBC_COMMAND && val as u8 == KBC_PULSE_RESET {
+            if self.power.reboot {
+                self.reset();
+                return;
+            }
+            self.exit(RunExit::Reset, None);
+        }
+        if self.power.shutdown_port == Some(port) {
+            self.exit(RunExit::PowerOff, None);
+        }
+        self.io_write(port, val as u8);
+        if word {
+            self.io_write(port.wrapping_add(1), (val >> 8) as u8);
+        }
+    }
+
+    fn port_of(&self, op: Operand) -> u16 {
+        match op {
+            Operand::Imm8(p) => p as u16,
+            Operand::Reg16(2) => self.regs.dx,
+            _ => unreachable!("invalid port operand"),
+        }
+    }
+
+    fn inn(&mut self, inst: &Instruction) {
+        let port = self.port_of(inst.src);
+        match inst.dest {
+            Operand::Reg8(_) => {
+                let v = self.port_in(port, false);
+                self.regs.set_al(v as u8);
+            }
+            Operand::Reg16(_) => self.regs.ax = self.port_in(port, true),
+            _ => unreachable!(),
+        }
+    }
+
+    fn out(&mut self, inst: &Instruction) {
+        let port = self.port_of(inst.src);
+        match inst.dest {
+            Operand::Reg8(_) => self.port_out(port, self.regs.get_al() as u16, false),
+            Operand::Reg16(_) => self.port_out(port, self.regs.ax, true),
+            _ => unreachable!(),
+        }
+    }
+
+    fn hlt(&mut self) {
+        if self.exits.hlt {
+            self.exit(RunExit::Halted, None);
+        }
+    }
+
+    // At a jump backwards with IF clear, compares the registers and the
+    // count of memory writes, port accesses and interrupts with those at
+    // the last one. Any write counts as a change, even of the same value.
+    fn watch_livelock(&mut self, limit: u32) {
+        let (cs, ip) = self.inst_start;
+        if self.regs.cs != cs || self.regs.ip > ip {
+            return;
+        }
+        if self.regs.flags.i_f() {
+            self.livelock = None;
+            return;
+        }
+        let s = &self.stats;
+        let activity = s.mem_writes8 + s.mem_writes16 + s.port_reads + s.port_writes + s.interrupts;
+        let repeats = match &self.livelock {
+            Some((regs, seen, n)) if *regs == self.regs && *seen == activity => n + 1,
+            _ => 0,
+        };
+        if repeats >= limit {
+            self.exit(RunExit::Livelock, None);
+        }
+        self.livelock = Some((self.regs.clone(), activity, repeats));
+    }
+
+    // ends the run the way `why` says
+    fn exit(&mut self, why: RunExit, code: Option<u8>) {
+        self.halt = true;
+        self.exit_reason = Some(why);
+        if code.is_some() {
+            self.exit_code = code;
+        }
+    }
+
+    /// Runs an instruction `fetch` returned, with its prefixes.
+    pub fn execute(&mut self, inst: &Instruction) {
+        self.seg_override = inst.prefixes.segment;
+        // addresses are worked out once, before anything changes
+        let inst = &Instruction {
+            dest: self.resolve(inst.dest),
+            src: self.resolve(inst.src),
+            ..*inst
+        };
+        match inst.prefixes.rep {
+            Some(rep) if inst.is_string() => self.repeat(inst, rep),
+            _ => self.operate(inst),
+        }
+        #[cfg(feature = "profile")]
+        if let Some(branches) = &mut self.branches {
+            let (cs, ip) = self.inst_start;
+            // a jump is taken when it lands anywhere but the next instruction
+            let taken = (self.regs.cs, self.regs.ip) != (cs, self.next_ip);
+            branches.record(((cs as u32) << 4) + ip as u32, inst.opcode, taken);
+        }
+        if self.exits.jump_to_self
+            && (self.regs.cs, self.regs.ip) == self.inst_start
+            && matches!(
+                inst.opcode,
+                Opcode::JmpNear
+                    | Opcode::JmpFar
+                    | Opcode::Jcxz
+                    | Opcode::Jo
+                    | Opcode::Jno
+                    | Opcode::Jb
+                    | Opcode::Jnb
+                    | Opcode::Jz
+                    | Opcode::Jnz
+                    | Opcode::Jbe
+                    | Opcode::Jnbe
+                    | Opcode::Js
+                    | Opcode::Jns
+                    | Opcode::Jp
+                    | Opcode::Jnp
+                    | Opcode::Jl
+                    | Opcode::Jnl
+                    | Opcode::Jle
+                    | Opcode::Jnle
+            )
+        {
+            self.exit(RunExit::JumpToSelf, None);
+        }
+        if let Some(limit) = self.livelock_limit {
+            self.watch_livelock(limit);
+        }
+        self.seg_override = None;
+    }
+
+    // one run of the instruction, without its prefixes
+    fn operate(&mut self, inst: &Instruction) {
+        if inst.is_string() {
+            self.stats.string_iterations += 1;
+        }
+        match inst.opcode {
+            Opcode::Or => self.alu(inst.dest, inst.src, AluOp::Or, alu::ARITH, true),
+            Opcode::Add => self.alu(inst.dest, inst.src, AluOp::Add, alu::ARITH, true),
+            Opcode::Adc => self.alu(inst.dest, inst.src, AluOp::Adc, alu::ARITH, true),
+            Opcode::Sbb => self.alu(inst.dest, inst.src, AluOp::Sbb, alu::ARITH, true),
+            Opcode::Sub => self.alu(inst.dest, inst.src, AluOp::Sub, alu::ARITH, true),
+            Opcode::Cmp => self.alu(inst.dest, inst.src, AluOp::Sub, alu::ARITH, false),
+            Opcode::PushEs => {
+                self.push(self.regs.es);
+            }
+            Opcode::PushCs => {
+                self.push(self.regs.cs);
+            }
+            Opcode::PopCs => {
+                let val = self.pop();
+                self.write_cs(val);
+            }
+            Opcode::PopEs => {
+                self.regs.es = self.pop();
+            }
+            Opcode::PushSs => {
+                self.push(self.regs.ss);
+            }
+            Opcode::PopSs => {
+                self.regs.ss = self.pop();
+            }
+            Opcode::PushDs => {
+                self.push(self.regs.ds);
+            }
+            Opcode::PopDs => {
+                self.regs.ds = self.pop();
+            }
+            Opcode::And => self.alu(inst.dest, inst.src, AluOp::And, alu::ARITH, true),
+            Opcode::Xor => self.alu(inst.dest, inst.src, AluOp::Xor, alu::ARITH, true),
+            Opcode::Daa => self.daa(),
+            Opcode::Aaa => self.aaa(),
+            Opcode::Das => self.das(),
+            Opcode::Aas => self.aas(),
+            Opcode::IncAx => self.inc(Operand::Reg16(0)),
+            Opcode::IncCx => self.inc(Operand::Reg16(1)),
+            Opcode::IncBx => self.inc(Operand::Reg16(3)),
+            Opcode::IncDx => self.inc(Operand::Reg16(2)),
+            Opcode::IncSp => self.inc(Operand::Reg16(4)),
+            Opcode::IncBp => self.inc(Operand::Reg16(5)),
+            Opcode::IncSi => self.inc(Operand::Reg16(6)),
+            Opcode::IncDi => self.inc(Operand::Reg16(7)),
+            Opcode::DecAx => self.dec(Operand::Reg16(0)),
+            Opcode::DecCx => self.dec(Operand::Reg16(1)),
+            Opcode::DecBx => self.dec(Operand::Reg16(3)),
+            Opcode::DecDx => self.dec(Operand::Reg16(2)),
+            Opcode::DecSp => self.dec(Operand::Reg16(4)),
+            Opcode::DecBp => self.dec(Operand::Reg16(5)),
+            Opcode::DecSi => self.dec(Operand::Reg16(6)),
+            Opcode::DecDi => self.dec(Operand::Reg16(7)),
+            Opcode::PushAx => self.push(self.regs.ax),
+            Opcode::PushCx => self.push(self.regs.cx),
+            Opcode::PushBx => self.push(self.regs.bx),
+            Opcode::PushDx => self.push(self.regs.dx),
+            // the 8086 pushes SP as the push leaves it, the 80286 as it was
+            Opcode::PushSp if self.quirks.push_sp_before => self.push(self.regs.sp),
+            Opcode::PushSp => self.push(self.regs.sp.wrapping_sub(2)),
+            Opcode::PushBp => self.push(self.regs.bp),
+            Opcode::PushSi => self.push(self.regs.si),
+            Opcode::PushDi => self.push(self.regs.di),
+            Opcode::PopAx => self.regs.ax = self.pop(),
+            Opcode::PopCx => self.regs.cx = self.pop(),
+            Opcode::PopBx => self.regs.bx = self.pop(),
+            Opcode::PopDx => self.regs.dx = self.pop(),
+            Opcode::PopSp => self.regs.sp = self.pop(),
+            Opcode::PopBp => self.regs.bp = self.pop(),
+            Opcode::PopSi => self.regs.si = self.pop(),
+            Opcode::PopDi => self.regs.di = self.pop(),
+            Opcode::Jo => self.jump_short_if(inst, self.regs.flags.of()),
+            Opcode::Jno => self.jump_short_if(inst, !self.regs.flags.of()),
+            Opcode::Jb => self.jump_short_if(inst, self.regs.flags.cf()),
+            Opcode::Jnb => self.jump_short_if(inst, !self.regs.flags.cf()),
+            Opcode::Jz => self.jump_short_if(inst, self.regs.flags.zf()),
+            Opcode::Jnz => self.jump_short_if(inst, !self.regs.flags.zf()),
+            Opcode::Jbe => self.jump_short_if(inst, self.regs.flags.cf() || self.regs.flags.zf()),
+            Opcode::Jnbe => {
+                self.jump_short_if(inst, !self.regs.flags.cf() && !self.regs.flags.zf())
+            }
+            Opcode::Js => self.jump_short_if(inst, self.regs.flags.sf()),
+            Opcode::Jns => self.jump_short_if(inst, !self.regs.flags.sf()),
+            Opcode::Jp => self.jump_short_if(inst, self.regs.flags.pf()),
+            Opcode::Jnp => self.jump_short_if(inst, !self.regs.flags.pf()),
+            Opcode::Jl => self.jump_short_if(inst, self.regs.flags.sf() != self.regs.flags.of()),
+            Opcode::Jnl => self.jump_short_if(inst, self.regs.flags.sf() == self.regs.flags.of()),
+            Opcode::Jle => self.jump_short_if(
+                inst,
+                (self.regs.flags.sf() != self.regs.flags.of()) || self.regs.flags.zf(),
+            ),
+            Opcode::Jnle => self.jump_short_if(
+                inst,
+                (self.regs.flags.sf() == self.regs.flags.of()) && !self.regs.flags.zf(),
+            ),
+            Opcode::Test => self.alu(inst.dest, inst.src, AluOp::And, alu::ARITH, false),
+            Opcode::Xchg => self.exchg(inst),
+            Opcode::Mov => self.mov(inst),
+            Opcode::Lea => self.lea(inst),
+            Opcode::Pop => self.pop2(inst),
+            Opcode::Push => self.push_mem(inst),
+            Opcode::Cbw => self.cbw(),
+            Opcode::Cwd => self.cwd(),
+            Opcode::CallFar => self.call_far(inst),
+            Opcode::Pushf => self.pushf(),
+            Opcode::Popf => self.popf(),
+            Opcode::Lahf => self.lahf(),
+            Opcode::Sahf => self.sahf(),
+            Opcode::Movsb => self.movsb(),
+            Opcode::Movsw => self.movsw(),
+            Opcode::Cmpsw => self.cmps(true),
+            Opcode::Cmpsb => self.cmps(false),
+            Opcode::Stosb => self.stosb(),
+            Opcode::Lodsb => self.lodsb(),
+            Opcode::Scasb => self.scas(false),
+            Opcode::Stosw => self.stosw(),
+            Opcode::Lodsw => self.lodsw(),
+            Opcode::Scasw => self.scas(true),
+            Opcode::Ret => self.ret(inst),
+            Opcode::Retf => self.retf(inst),
+            Opcode::Les => self.les(inst),
+            Opcode::Lds => self.lds(inst),
+            Opcode::Int => self.int(inst),
+            Opcode::Into => self.into(inst),
+            Opcode::Iret => self.iret(inst),
+            Opcode::Rol => self.rotate(inst, true),
+            Opcode::Ror => self.rotate(inst, false),
+            Opcode::Rcl => self.rotate_cf(inst, true),
+            Opcode::Rcr => self.rotate_cf(inst, false),
+            Opcode::Shl => self.shift(inst, true),
+            Opcode::Shr => self.shift(inst, false),
+            Opcode::Sar => self.shalr(inst),
+            Opcode::Aad => self.aad(),
+            Opcode::Aam => self.aam(),
+            Opcode::Xlat => self.xlat(),
+            Opcode::Loop => self.loopp(inst),
+            Opcode::Loope => self.loope(inst),
+            Opcode::Loopne => self.loopne(inst),
+            Opcode::Jcxz => self.jcxz(inst),
+            Opcode::Cmc => {
+                if self.regs.flags.cf() {
+                    self.regs.flags.clear_cf();
+                } else {
+                    self.regs.flags.set_cf();
+                }
+            }
+            // no coprocessor holds TEST high, and the bus has no one else
+            // to lock it against
+            Opcode::Wait => {}
+            Opcode::In => self.inn(inst),
+            Opcode::Out => self.out(inst),
+            Opcode::Hlt => self.hlt(),
+            Opcode::CallNear => self.call_near(inst),
+            Opcode::JmpNear => self.jmp_near(inst),
+            Opcode::JmpFar => self.jmp_far(inst),
+            Opcode::Not => self.not(inst),
+            Opcode::Neg => self.neg(inst),
+            Opcode::Mul => self.multiply(inst, false),
+            Opcode::Imul => self.multiply(inst, true),
+            Opcode::Div => self.divide(inst, false),
+            Opcode::Idiv => self.divide(inst, true),
+            Opcode::Clc => self.regs.flags.clear_cf(),
+            Opcode::Stc => self.regs.flags.set_cf(),
+            Opcode::Cli => self.regs.flags.clear_if(),
+            Opcode::Sti => self.regs.flags.set_if(),
+            Opcode::Cld => self.regs.flags.clear_df(),
+            Opcode::Std => self.regs.flags.set_df(),
+            Opcode::Inc => self.inc(inst.dest),
+            Opcode::Dec => self.dec(inst.dest),
+            Opcode::Callback => {
+                if let Operand::Imm8(service) = inst.dest {
+                    self.host_int = Some(service);
+                }
+            }
+        }
+    }
+
+    // program will be cut
+    pub fn load_code(&mut self, path: &str) {
+        if let Ok(mut file) = File::open(path) {
+            self.load_addr = self.code_addr(0);
+            self.mem.seek_to(self.load_addr as u64);
+            while self.mem.pos() < 1024 {
+                let mut buf = [0u8];
+                if let Ok(0) = file.read(&mut buf) {
+                    break;
+                }
+                self.mem.write_u8(buf[0]);
+            }
+            self.prog_size = self.mem.pos();
+        } else {
+            println!("Failed to open file: {}", path);
+            exit(1);
+        }
+    }
+
+    /// Copies up to 1 KiB of code to CS:0000; the program ends after it.
+    pub fn load_code_vec(&mut self, vec: &[u8]) {
+        self.load_addr = self.code_addr(0);
+        self.mem.seek_to(self.load_addr as u64);
+        let mut it = vec.iter();
+        while self.mem.pos() < 1024 {
+            if let Some(v) = it.next() {
+                self.mem.write_u8(*v);
+            } else {
+                break;
+            }
+        }
+        self.prog_size = self.mem.pos();
+    }
+
+    pub fn load_code_stdin(&mut self) {
+        self.load_addr = self.code_addr(0);
+        self.mem.seek_to(self.load_addr as u64);
+        let mut it = BufReader::new(std::io::stdin()).bytes();
+        while self.mem.pos() < 1024 {
+            if let Some(rs) = it.next() {
+                match rs {
+                    Ok(v) => self.mem.write_u8(v),
+                    Err(_) => panic!("error reading stdin"),
+                }
+            } else {
+                break;
+            }
+        }
+        self.prog_size = self.mem.pos();
+    }
+
+    pub fn code_addr(&self, offset: u16) -> u32 {
+        ((self.regs.get_cs() + offset as u32) & 0xfffff)
+    }
+
+    pub fn stack_addr(&self, offset: u16) -> u32 {
+        ((self.regs.get_ss() + offset as u32) & 0xfffff)
+    }
+
+    pub fn extra_addr(&self, offset: u16) -> u32 {
+        ((self.regs.get_es() + offset as u32) & 0xfffff)
+    }
+
+    pub fn data_addr(&self, offset: u16) -> u32 {
+        ((self.regs.get_ds() + offset as u32) & 0xfffff)
+    }
+}
+#[cfg(test)]
+mod cpu_test {
+
+    use super::{Cpu, Instruction};
+    use crate::{
+        cpu::{self, Opcode, Operand},
+        decode::OpByte,
+    };
+}
@@ -0,0 +1,164 @@
+//! Pieces of an interactive debugger: a tracker of the calls the guest
+//! is inside and the stepping commands built on it.
+
+use crate::{
+    asm,
+    cpu::{Cpu, Instruction, RunExit},
+    decode::{ModRm, Prefixes},
+};
+
+/// How a frame was entered, which decides how much it pushed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Near,
+    Far,
+    Interrupt,
+}
+
+impl FrameKind {
+    fn pushed(self) -> u16 {
+        match self {
+            FrameKind::Near => 2,
+            FrameKind::Far => 4,
+            FrameKind::Interrupt => 6,
+        }
+    }
+}
+
+/// A call or interrupt the guest has not returned from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// CS:IP of the call.
+    pub call: (u16, u16),
+    /// CS:IP it returns to.
+    pub ret: (u16, u16),
+    /// SP once the return address was pushed; the frame is gone when SP
+    /// rises above it.
+    pub sp: u16,
+}
+
+/// Follows calls and returns between `before_step` and `after_step`,
+/// which wrap every executed instruction.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    /// Innermost last.
+    pub frames: Vec<Frame>,
+    // the call about to run, with CS:IP and SP before it
+    pending: Option<(FrameKind, (u16, u16), u16)>,
+}
+
+// what the instruction at CS:IP would push, judged from its bytes
+fn call_kind(cpu: &Cpu) -> Option<FrameKind> {
+    let bytes = cpu.code_bytes(cpu.regs.ip, 8);
+    let mut bytes = bytes.into_iter();
+    // eight prefixes in a row read on as nops
+    let (_, opcode) = Prefixes::parse(|| bytes.next().unwrap_or(0x90));
+    match (opcode, bytes.next().map(|b| ModRm::new(b).reg)) {
+        (0xe8, _) | (0xff, Some(2)) => Some(FrameKind::Near),
+        (0x9a, _) | (0xff, Some(3)) => Some(FrameKind::Far),
+        (0xcc..=0xce, _) => Some(FrameKind::Interrupt),
+        _ => None,
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn before_step(&mut self, cpu: &Cpu) {
+        self.pending = call_kind(cpu).map(|kind| (kind, (cpu.regs.cs, cpu.regs.ip), cpu.regs.sp));
+    }
+
+    /// Drops the frames the stack has unwound past and pushes one for a
+    /// call or interrupt that was taken. An `int` the host serviced
+    /// pushes nothing and gets no frame.
+    pub fn after_step(&mut self, cpu: &Cpu) {
+        while self.frames.last().is_some_and(|f| f.sp < cpu.regs.sp) {
+            self.frames.pop();
+        }
+        if let Some((kind, call, sp)) = self.pending.take() {
+            if cpu.regs.sp == sp.wrapping_sub(kind.pushed()) {
+                let word = |offt: u16| {
+                    let at = cpu.stack_addr(cpu.regs.sp.wrapping_add(offt)) as usize;
+                    let mem = cpu.mem.cursor.get_ref();
+                    u16::from_le_bytes([mem[at], mem[at + 1]])
+                };
+                let ret = match kind {
+                    FrameKind::Near => (call.0, word(0)),
+                    _ => (word(2), word(0)),
+                };
+                self.frames.push(Frame {
+                    kind,
+                    call,
+                    ret,
+                    sp: cpu.regs.sp,
+                });
+            }
+        }
+    }
+
+    /// Executes one instruction, following calls; `None` at the end of
+    /// the program or bytes that do not decode.
+    pub fn step(&mut self, cpu: &mut Cpu) -> Option<Instruction> {
+        self.before_step(cpu);
+        let inst = cpu.fetch()?;
+        cpu.execute(&inst);
+        self.after_step(cpu);
+        Some(inst)
+    }
+
+    /// Runs until the innermost call returns, handing interrupts left in
+    /// `host_int` to `host`. Returns `None` once it has, or why the run
+    /// stopped first.
+    pub fn finish(&mut self, cpu: &mut Cpu, mut host: impl FnMut(&mut Cpu, u8)) -> Result<Option<RunExit>, String> {
+        let depth = self.frames.len();
+        if depth == 0 {
+            return Err("not inside a call".to_string());
+        }
+        loop {
+            if self.step(cpu).is_none() {
+                return Ok(Some(cpu.fetch_failure()));
+            }
+            if let Some(vector) = cpu.host_int.take() {
+                host(cpu, vector);
+            }
+            if cpu.halt {
+                return Ok(Some(cpu.exit_reason.unwrap_or(RunExit::Halted)));
+            }
+            if self.frames.len() < depth {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Moves IP past the instruction at CS:IP without executing it and
+    /// returns the new IP, or `None` at the end of the program or bytes
+    /// that do not decode.
+    pub fn skip(&mut self, cpu: &mut Cpu) -> Option<u16> {
+        let (_, next_ip) = cpu.decode().ok()??;
+        cpu.regs.ip = next_ip;
+        Some(next_ip)
+    }
+
+    /// Assembles `line` at `seg:off` and writes it there, past any ROM
+    /// or poisoned range, returning the bytes written. Code patched past
+    /// the end of the program in CS extends it so it runs.
+    pub fn assemble(&mut self, cpu: &mut Cpu, (seg, off): (u16, u16), line: &str) -> Result<Vec<u8>, String> {
+        let bytes = asm::assemble_line(line, off)?;
+        let mem = cpu.mem.cursor.get_mut();
+        for (i, byte) in bytes.iter().enumerate() {
+            let at = ((seg as u32) << 4).wrapping_add(off.wrapping_add(i as u16) as u32) & 0xfffff;
+            if let Some(b) = mem.get_mut(at as usize) {
+                *b = *byte;
+            }
+        }
+        let end = off as u64 + bytes.len() as u64;
+        if seg == cpu.regs.cs && end > cpu.prog_size {
+            cpu.prog_size = end;
+        }
+        cpu.flush_queue();
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,645 @@
+//! The bytes around an opcode: the prefixes in front of it, its own
+//! direction and width bits, and the ModR/M byte after it; the table of
+//! what follows each opcode byte; and the `Decoder` driven by it.
+
+use crate::cpu::{Ea, Instruction, Opcode, Operand, Segment, CALLBACK};
+
+/// The first byte of an instruction, read as the 8086's regular
+/// encodings use its low bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpByte(u8);
+
+impl OpByte {
+    pub fn new(byte: u8) -> Self {
+        Self(byte)
+    }
+
+    pub fn byte(self) -> u8 {
+        self.0
+    }
+
+    /// Bit 0: the operands are words rather than bytes.
+    pub fn word(self) -> bool {
+        self.0 & 1 > 0
+    }
+
+    pub fn set_word(&mut self) {
+        self.0 |= 1;
+    }
+
+    /// Bit 1: the ModR/M reg field is the destination.
+    pub fn reg_is_dest(self) -> bool {
+        self.0 & 0b10 > 0
+    }
+
+    /// Bits 2-7, which pick the family of instructions.
+    pub fn group(self) -> u8 {
+        self.0 >> 2
+    }
+}
+
+/// A ModR/M byte. `mode` 3 makes `rm` a register; otherwise `rm` and
+/// `mode` pick a memory address and its displacement. `reg` is a
+/// register, or for group opcodes which operation it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModRm {
+    pub mode: u8,
+    pub reg: u8,
+    pub rm: u8,
+}
+
+impl ModRm {
+    pub fn new(byte: u8) -> Self {
+        Self {
+            mode: byte >> 6,
+            reg: (byte >> 3) & 0b111,
+            rm: byte & 0b111,
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self.mode << 6 | self.reg << 3 | self.rm
+    }
+
+    /// Whether `rm` is a register rather than memory.
+    pub fn is_reg(self) -> bool {
+        self.mode == 3
+    }
+
+    /// How many displacement bytes follow: one for mode 1, two for mode
+    /// 2 and for the bare address of mode 0 with `rm` 6.
+    pub fn displacement_len(self) -> u8 {
+        match (self.mode, self.rm) {
+            (0, 6) | (2, _) => 2,
+            (1, _) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Which repeat prefix an instruction carries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rep {
+    /// F3: `rep`, or `repe` in front of a compare or scan.
+    Rep,
+    /// F2: `repne`.
+    Repne,
+}
+
+/// The prefixes in front of an instruction. They come in any order and
+/// each kind may repeat, the last of a kind counting.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Prefixes {
+    pub segment: Option<Segment>,
+    pub rep: Option<Rep>,
+    pub lock: bool,
+}
+
+impl Prefixes {
+    /// Takes in `byte` if it is a prefix, returning whether it was.
+    pub fn add(&mut self, byte: u8) -> bool {
+        match byte {
+            0x26 => self.segment = Some(Segment::Es),
+            0x2e => self.segment = Some(Segment::Cs),
+            0x36 => self.segment = Some(Segment::Ss),
+            0x3e => self.segment = Some(Segment::Ds),
+            0xf0 => self.lock = true,
+            0xf2 => self.rep = Some(Rep::Repne),
+            0xf3 => self.rep = Some(Rep::Rep),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Reads bytes through `next` up to the first that is not a prefix,
+    /// returning the prefixes and that byte, the opcode.
+    pub fn parse(mut next: impl FnMut() -> u8) -> (Self, u8) {
+        let mut prefixes = Self::default();
+        loop {
+            let byte = next();
+            if !prefixes.add(byte) {
+                return (prefixes, byte);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What the decoder leaves in an operand slot the instruction does not
+/// use.
+pub(crate) const UNUSED: Operand = Operand::Mem16(0, 0);
+
+/// How the operands of an opcode are encoded after it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Form {
+    /// Nothing follows: the operands are implied by the opcode.
+    Fixed(Operand, Operand),
+    /// A ModR/M byte with a general register in reg; the direction bit
+    /// says whether it is the destination.
+    RegRm,
+    /// A ModR/M byte with a segment register in reg. Always words.
+    SegRm,
+    /// A ModR/M byte with a word register in reg, always the
+    /// destination, and memory in r/m (lea, les, lds).
+    RegMem,
+    /// The accumulator and an immediate.
+    AccImm,
+    /// A register picked by bits 0-2 and sized by bit 3, and an immediate.
+    RegImm,
+    /// The accumulator and a bare address, stored to when the direction
+    /// bit is set.
+    AccMem,
+    /// The accumulator and an immediate port.
+    PortImm,
+    Rel8,
+    Rel16,
+    /// An offset and then a segment.
+    Far,
+    Imm8,
+    Imm16,
+}
+
+/// What follows the r/m operand of a group opcode, as its source.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Src {
+    None,
+    /// An immediate as wide as the operation.
+    Imm,
+    /// A byte immediate sign-extended to a word.
+    Imm8Sx,
+    One,
+    Cl,
+    /// An immediate for `test` (reg field 0), nothing for the rest.
+    TestImm,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Entry {
+    Undefined,
+    Op(Opcode, Form),
+    /// A ModR/M byte follows whose reg field picks the operation, `None`
+    /// where the 8086 leaves it undefined; r/m is the destination.
+    Group(&'static [Option<Opcode>; 8], Src),
+}
+
+const IMPLIED: Form = Form::Fixed(UNUSED, UNUSED);
+
+const ALU: [Opcode; 8] = [
+    Opcode::Add,
+    Opcode::Or,
+    Opcode::Adc,
+    Opcode::Sbb,
+    Opcode::And,
+    Opcode::Sub,
+    Opcode::Xor,
+    Opcode::Cmp,
+];
+
+static ALU_GROUP: [Option<Opcode>; 8] = [
+    Some(Opcode::Add),
+    Some(Opcode::Or),
+    Some(Opcode::Adc),
+    Some(Opcode::Sbb),
+    Some(Opcode::And),
+    Some(Opcode::Sub),
+    Some(Opcode::Xor),
+    Some(Opcode::Cmp),
+];
+
+static SHIFTS: [Option<Opcode>; 8] = [
+    Some(Opcode::Rol),
+    Some(Opcode::Ror),
+    Some(Opcode::Rcl),
+    Some(Opcode::Rcr),
+    Some(Opcode::Shl),
+    Some(Opcode::Shr),
+    None,
+    Some(Opcode::Sar),
+];
+
+static UNARY: [Option<Opcode>; 8] = [
+    Some(Opcode::Test),
+    None,
+    Some(Opcode::Not),
+    Some(Opcode::Neg),
+    Some(Opcode::Mul),
+    Some(Opcode::Imul),
+    Some(Opcode::Div),
+    Some(Opcode::Idiv),
+];
+
+static INC_DEC: [Option<Opcode>; 8] = [Some(Opcode::Inc), Some(Opcode::Dec), None, None, None, None, None, None];
+
+static INDIRECT: [Option<Opcode>; 8] = [
+    Some(Opcode::Inc),
+    Some(Opcode::Dec),
+    Some(Opcode::CallNear),
+    Some(Opcode::CallFar),
+    Some(Opcode::JmpNear),
+    Some(Opcode::JmpFar),
+    Some(Opcode::Push),
+    None,
+];
+
+static MOV: [Option<Opcode>; 8] = [Some(Opcode::Mov), None, None, None, None, None, None, None];
+
+static POP: [Option<Opcode>; 8] = [Some(Opcode::Pop), None, None, None, None, None, None, None];
+
+/// What follows each opcode byte. Prefixes are taken before the opcode
+/// and so are left undefined here, as are the bytes the 8086 does not
+/// define.
+pub(crate) static TABLE: [Entry; 256] = table();
+
+const fn table() -> [Entry; 256] {
+    use Entry::{Group, Op};
+    use Form::*;
+    use Opcode::*;
+
+    let mut t = [Entry::Undefined; 256];
+    // 00-3F: each row of eight is an ALU op on r/m and a register both
+    // ways round, then on the accumulator and an immediate
+    let mut row = 0;
+    while row < 8 {
+        let b = row * 8;
+        t[b] = Op(ALU[row], RegRm);
+        t[b + 1] = Op(ALU[row], RegRm);
+        t[b + 2] = Op(ALU[row], RegRm);
+        t[b + 3] = Op(ALU[row], RegRm);
+        t[b + 4] = Op(ALU[row], AccImm);
+        t[b + 5] = Op(ALU[row], AccImm);
+        row += 1;
+    }
+    t[0x06] = Op(PushEs, IMPLIED);
+    t[0x07] = Op(PopEs, IMPLIED);
+    t[0x0e] = Op(PushCs, IMPLIED);
+    t[0x0f] = Op(PopCs, IMPLIED);
+    t[0x16] = Op(PushSs, IMPLIED);
+    t[0x17] = Op(PopSs, IMPLIED);
+    t[0x1e] = Op(PushDs, IMPLIED);
+    t[0x1f] = Op(PopDs, IMPLIED);
+    t[0x27] = Op(Daa, IMPLIED);
+    t[0x2f] = Op(Das, IMPLIED);
+    t[0x37] = Op(Aaa, IMPLIED);
+    t[0x3f] = Op(Aas, IMPLIED);
+
+    // 40-5F: a register in the low three bits
+    let inc = [IncAx, IncCx, IncDx, IncBx, IncSp, IncBp, IncSi, IncDi];
+    let dec = [DecAx, DecCx, DecDx, DecBx, DecSp, DecBp, DecSi, DecDi];
+    let push = [PushAx, PushCx, PushDx, PushBx, PushSp, PushBp, PushSi, PushDi];
+    let pop = [PopAx, PopCx, PopDx, PopBx, PopSp, PopBp, PopSi, PopDi];
+    let mut r = 0;
+    while r < 8 {
+        t[0x40 + r] = Op(inc[r], IMPLIED);
+        t[0x48 + r] = Op(dec[r], IMPLIED);
+        t[0x50 + r] = Op(push[r], IMPLIED);
+        t[0x58 + r] = Op(pop[r], IMPLIED);
+        t[0x90 + r] = Op(Xchg, Fixed(Operand::Reg16(0), Operand::Reg16(r as u8)));
+        r += 1;
+    }
+
+    let jcc = [
+        Jo, Jno, Jb, Jnb, Jz, Jnz, Jbe, Jnbe, Js, Jns, Jp, Jnp, Jl, Jnl, Jle, Jnle,
+    ];
+    let mut c = 0;
+    while c < 16 {
+        t[0x70 + c] = Op(jcc[c], Rel8);
+        t[0xb0 + c] = Op(Mov, RegImm);
+        c += 1;
+    }
+
+    t[0x80] = Group(&ALU_GROUP, Src::Imm);
+    t[0x81] = Group(&ALU_GROUP, Src::Imm);
+    t[0x82] = Group(&ALU_GROUP, Src::Imm);
+    t[0x83] = Group(&ALU_GROUP, Src::Imm8Sx);
+    t[0x84] = Op(Test, RegRm);
+    t[0x85] = Op(Test, RegRm);
+    t[0x86] = Op(Xchg, RegRm);
+    t[0x87] = Op(Xchg, RegRm);
+    t[0x88] = Op(Mov, RegRm);
+    t[0x89] = Op(Mov, RegRm);
+    t[0x8a] = Op(Mov, RegRm);
+    t[0x8b] = Op(Mov, RegRm);
+    t[0x8c] = Op(Mov, SegRm);
+    t[0x8d] = Op(Lea, RegMem);
+    t[0x8e] = Op(Mov, SegRm);
+    t[0x8f] = Group(&POP, Src::None);
+
+    t[0x98] = Op(Cbw, IMPLIED);
+    t[0x99] = Op(Cwd, IMPLIED);
+    t[0x9a] = Op(CallFar, Far);
+    t[0x9b] = Op(Wait, IMPLIED);
+    t[0x9c] = Op(Pushf, IMPLIED);
+    t[0x9d] = Op(Popf, IMPLIED);
+    t[0x9e] = Op(Sahf, IMPLIED);
+    t[0x9f] = Op(Lahf, IMPLIED);
+
+    t[0xa0] = Op(Mov, AccMem);
+    t[0xa1] = Op(Mov, AccMem);
+    t[0xa2] = Op(Mov, AccMem);
+    t[0xa3] = Op(Mov, AccMem);
+    t[0xa4] = Op(Movsb, IMPLIED);
+    t[0xa5] = Op(Movsw, IMPLIED);
+    t[0xa6] = Op(Cmpsb, IMPLIED);
+    t[0xa7] = Op(Cmpsw, IMPLIED);
+    t[0xa8] = Op(Test, AccImm);
+    t[0xa9] = Op(Test, AccImm);
+    t[0xaa] = Op(Stosb, IMPLIED);
+    t[0xab] = Op(Stosw, IMPLIED);
+    t[0xac] = Op(Lodsb, IMPLIED);
+    t[0xad] = Op(Lodsw, IMPLIED);
+    t[0xae] = Op(Scasb, IMPLIED);
+    t[0xaf] = Op(Scasw, IMPLIED);
+
+    t[0xc2] = Op(Ret, Imm16);
+    t[0xc3] = Op(Ret, IMPLIED);
+    t[0xc4] = Op(Les, RegMem);
+    t[0xc5] = Op(Lds, RegMem);
+    t[0xc6] = Group(&MOV, Src::Imm);
+    t[0xc7] = Group(&MOV, Src::Imm);
+    t[0xca] = Op(Retf, Imm16);
+    t[0xcb] = Op(Retf, IMPLIED);
+    t[0xcc] = Op(Int, Fixed(Operand::Imm8(3), UNUSED));
+    t[0xcd] = Op(Int, Imm8);
+    t[0xce] = Op(Into, IMPLIED);
+    t[0xcf] = Op(Iret, IMPLIED);
+
+    t[0xd0] = Group(&SHIFTS, Src::One);
+    t[0xd1] = Group(&SHIFTS, Src::One);
+    t[0xd2] = Group(&SHIFTS, Src::Cl);
+    t[0xd3] = Group(&SHIFTS, Src::Cl);
+    // the base the adjust works in
+    t[0xd4] = Op(Aam, Imm8);
+    t[0xd5] = Op(Aad, Imm8);
+    t[0xd7] = Op(Xlat, IMPLIED);
+
+    t[0xe0] = Op(Loopne, Rel8);
+    t[0xe1] = Op(Loope, Rel8);
+    t[0xe2] = Op(Loop, Rel8);
+    t[0xe3] = Op(Jcxz, Rel8);
+    t[0xe4] = Op(In, PortImm);
+    t[0xe5] = Op(In, PortImm);
+    t[0xe6] = Op(Out, PortImm);
+    t[0xe7] = Op(Out, PortImm);
+    t[0xe8] = Op(CallNear, Rel16);
+    t[0xe9] = Op(JmpNear, Rel16);
+    t[0xea] = Op(JmpFar, Far);
+    t[0xeb] = Op(JmpNear, Rel8);
+    t[0xec] = Op(In, Fixed(Operand::Reg8(0), Operand::Reg16(2)));
+    t[0xed] = Op(In, Fixed(Operand::Reg16(0), Operand::Reg16(2)));
+    t[0xee] = Op(Out, Fixed(Operand::Reg8(0), Operand::Reg16(2)));
+    t[0xef] = Op(Out, Fixed(Operand::Reg16(0), Operand::Reg16(2)));
+
+    t[0xf4] = Op(Hlt, IMPLIED);
+    t[0xf5] = Op(Cmc, IMPLIED);
+    t[0xf6] = Group(&UNARY, Src::TestImm);
+    t[0xf7] = Group(&UNARY, Src::TestImm);
+    t[0xf8] = Op(Clc, IMPLIED);
+    t[0xf9] = Op(Stc, IMPLIED);
+    t[0xfa] = Op(Cli, IMPLIED);
+    t[0xfb] = Op(Sti, IMPLIED);
+    t[0xfc] = Op(Cld, IMPLIED);
+    t[0xfd] = Op(Std, IMPLIED);
+    t[0xfe] = Group(&INC_DEC, Src::None);
+    t[0xff] = Group(&INDIRECT, Src::None);
+    t
+}
+
+// Reg16 numbers of the registers an r/m field adds up
+const BX: u8 = 3;
+const BP: u8 = 5;
+const SI: u8 = 6;
+const DI: u8 = 7;
+
+// the base, index and default segment of each r/m field's memory
+// operand; r/m 6 is [bp] only with a displacement
+const RM_EA: [(Option<u8>, Option<u8>, Segment); 8] = [
+    (Some(BX), Some(SI), Segment::Ds),
+    (Some(BX), Some(DI), Segment::Ds),
+    (Some(BP), Some(SI), Segment::Ss),
+    (Some(BP), Some(DI), Segment::Ss),
+    (None, Some(SI), Segment::Ds),
+    (None, Some(DI), Segment::Ds),
+    (Some(BP), None, Segment::Ss),
+    (Some(BX), None, Segment::Ds),
+];
+
+/// Turns instruction bytes into `Instruction`s. It sees nothing but what
+/// `fetch` returns for each offset, so decoding leaves the CPU alone:
+/// memory operands come out symbolic, as `Ea`s, and only become addresses
+/// when the instruction executes.
+pub struct Decoder<F> {
+    fetch: F,
+    start: u16,
+    ip: u16,
+    segment: Option<Segment>,
+}
+
+impl<F: FnMut(u16) -> u8> Decoder<F> {
+    /// A decoder reading the instruction at offset `ip` first.
+    pub fn new(ip: u16, fetch: F) -> Self {
+        Self {
+            fetch,
+            start: ip,
+            ip,
+            segment: None,
+        }
+    }
+
+    /// Offset of the next byte to read: after `decode`, that of the
+    /// instruction that follows.
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
+
+    /// Decodes the instruction at `ip` and moves past it. Bytes the 8086
+    /// leaves undefined, and operands that do not suit the opcode, are an
+    /// error naming the bytes at fault.
+    pub fn decode(&mut self) -> Result<Instruction, String> {
+        self.start = self.ip;
+        // prefixes come in any order and number; the segment one applies
+        // to the memory operands decoded below
+        let mut prefixes = Prefixes::default();
+        let mut byte = self.u8();
+        while prefixes.add(byte) {
+            if self.ip == self.start {
+                return Err("invalid encoding: a segment of nothing but prefixes".to_string());
+            }
+            byte = self.u8();
+        }
+        self.segment = prefixes.segment;
+
+        let b1 = OpByte::new(byte);
+        let inst = match TABLE[byte as usize] {
+            Entry::Undefined => return Err(format!("invalid encoding {:02x}: undefined opcode", byte)),
+            Entry::Op(opcode, form) => {
+                let (dest, src) = self.operands(b1, form)?;
+                Instruction::new(opcode, dest, src)
+            }
+            Entry::Group(ops, src) => self.group(b1, ops, src)?,
+        };
+        if let Err(why) = inst.validate() {
+            let len = self.ip.wrapping_sub(self.start);
+            let bytes: Vec<String> = (0..len)
+                .map(|i| format!("{:02x}", (self.fetch)(self.start.wrapping_add(i))))
+                .collect();
+            return Err(format!("invalid encoding {}: {}", bytes.join(" "), why));
+        }
+        Ok(Instruction { prefixes, ..inst })
+    }
+
+    fn u8(&mut self) -> u8 {
+        let b = (self.fetch)(self.ip);
+        self.ip = self.ip.wrapping_add(1);
+        b
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    // a displacement as the target it reaches: the displacement is the
+    // last thing decoded, so ip is already the base
+    fn rel8(&mut self) -> u16 {
+        let disp = self.u8() as i8 as u16;
+        self.ip.wrapping_add(disp)
+    }
+
+    fn rel16(&mut self) -> u16 {
+        let disp = self.u16();
+        self.ip.wrapping_add(disp)
+    }
+
+    fn immediate(&mut self, word: bool) -> Operand {
+        match word {
+            true => Operand::Imm16(self.u16()),
+            false => Operand::Imm8(self.u8()),
+        }
+    }
+
+    // an encoding the 8086 leaves undefined
+    fn invalid(b1: OpByte, b2: ModRm, why: &str) -> String {
+        format!("invalid encoding {:02x} {:02x}: {}", b1.byte(), b2.byte(), why)
+    }
+
+    fn memory(&self, word: bool, ea: Ea) -> Operand {
+        let ea = Ea {
+            seg: self.segment.unwrap_or(ea.seg),
+            ..ea
+        };
+        match word {
+            true => Operand::Ea16(ea),
+            false => Operand::Ea8(ea),
+        }
+    }
+
+    // a bare 16-bit address, as in `mov ax, [1234h]`
+    fn direct(&mut self, word: bool) -> Operand {
+        let disp = self.u16();
+        let ea = Ea {
+            base: None,
+            index: None,
+            disp,
+            seg: Segment::Ds,
+        };
+        self.memory(word, ea)
+    }
+
+    // the register or memory the r/m field names
+    fn rm(&mut self, b1: OpByte, b2: ModRm) -> Operand {
+        if b2.is_reg() {
+            return match b1.word() {
+                true => Operand::Reg16(b2.rm),
+                false => Operand::Reg8(b2.rm),
+            };
+        }
+        // mod=00 rm=110 is a bare 16-bit address instead of [bp]
+        if b2.mode == 0 && b2.rm == 6 {
+            return self.direct(b1.word());
+        }
+        let (base, index, seg) = RM_EA[b2.rm as usize];
+        let disp = match b2.mode {
+            0 => 0,
+            // the 8-bit displacement is signed
+            1 => self.u8() as i8 as u16,
+            _ => self.u16(),
+        };
+        self.memory(b1.word(), Ea { base, index, disp, seg })
+    }
+
+    // the operands of a non-group opcode, laid out as its form says
+    fn operands(&mut self, mut b1: OpByte, form: Form) -> Result<(Operand, Operand), String> {
+        let reg = |word, r| match word {
+            true => Operand::Reg16(r),
+            false => Operand::Reg8(r),
+        };
+        let acc = reg(b1.word(), 0);
+        Ok(match form {
+            Form::Fixed(dest, src) => (dest, src),
+            Form::RegRm | Form::SegRm => {
+                let b2 = ModRm::new(self.u8());
+                let r = match form {
+                    Form::SegRm if b2.reg > 3 => return Err(Self::invalid(b1, b2, "segment register field above 3")),
+                    Form::SegRm => {
+                        b1.set_word();
+                        Operand::Seg(b2.reg)
+                    }
+                    _ => reg(b1.word(), b2.reg),
+                };
+                let rm = self.rm(b1, b2);
+                match b1.reg_is_dest() {
+                    true => (r, rm),
+                    false => (rm, r),
+                }
+            }
+            Form::RegMem => {
+                let b2 = ModRm::new(self.u8());
+                b1.set_word();
+                (Operand::Reg16(b2.reg), self.rm(b1, b2))
+            }
+            Form::AccImm => (acc, self.immediate(b1.word())),
+            Form::RegImm => {
+                let word = b1.byte() & 0b1000 > 0;
+                (reg(word, b1.byte() & 0b111), self.immediate(word))
+            }
+            // A2/A3 store the accumulator, A0/A1 load it
+            Form::AccMem => {
+                let mem = self.direct(b1.word());
+                match b1.reg_is_dest() {
+                    true => (mem, acc),
+                    false => (acc, mem),
+                }
+            }
+            Form::PortImm => (acc, Operand::Imm8(self.u8())),
+            Form::Rel8 => (Operand::Rel8(self.rel8()), UNUSED),
+            Form::Rel16 => (Operand::Rel16(self.rel16()), UNUSED),
+            Form::Far => (Operand::Imm16(self.u16()), Operand::Imm16(self.u16())),
+            Form::Imm8 => (Operand::Imm8(self.u8()), UNUSED),
+            Form::Imm16 => (Operand::Imm16(self.u16()), UNUSED),
+        })
+    }
+
+    // a group opcode: the ModR/M reg field picks the operation from
+    // `ops`, r/m is the destination and `src` says what follows it
+    fn group(&mut self, b1: OpByte, ops: &[Option<Opcode>; 8], src: Src) -> Result<Instruction, String> {
+        let b2 = ModRm::new(self.u8());
+        if [b1.byte(), b2.byte()] == CALLBACK {
+            return Ok(Instruction::new(Opcode::Callback, Operand::Imm8(self.u8()), UNUSED));
+        }
+        let Some(opcode) = ops[b2.reg as usize] else {
+            return Err(Self::invalid(b1, b2, "undefined reg field"));
+        };
+        let dest = self.rm(b1, b2);
+        let src = match src {
+            Src::Imm => self.immediate(b1.word()),
+            Src::TestImm if opcode == Opcode::Test => self.immediate(b1.word()),
+            Src::Imm8Sx => Operand::Imm16(self.u8() as i8 as u16),
+            Src::One => Operand::Imm8(1),
+            Src::Cl => Operand::Reg8(1),
+            Src::TestImm | Src::None => UNUSED,
+        };
+        Ok(Instruction::new(opcode, dest, src))
+    }
+}
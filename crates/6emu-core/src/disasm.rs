@@ -0,0 +1,330 @@
+//! Instructions as assembly text, in the NASM flavour of Intel syntax
+//! that `asm` reads: `mov ax, [bx+si+4]`, `add byte [es:di], 0x7f`,
+//! `rep movsb`, or in AT&T syntax through `Att`. Numbers below 10 are
+//! decimal, the rest hex.
+
+use std::fmt;
+
+use crate::{
+    cpu::{Ea, Instruction, Opcode, Operand, Segment},
+    decode::{Decoder, Rep, UNUSED},
+};
+
+const REG16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const REG8: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const SEGS: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+fn number(n: u16) -> String {
+    match n {
+        0..=9 => n.to_string(),
+        _ => format!("0x{:x}", n),
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// `[bx+si+4]`, `[bp-2]`, `[0x1234]`, with the segment named only when
+/// it is not the one the registers imply: `[es:di]`.
+impl fmt::Display for Ea {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let implied = match self.base {
+            Some(5) => Segment::Ss,
+            _ => Segment::Ds,
+        };
+        write!(f, "[")?;
+        if self.seg != implied {
+            write!(f, "{}:", self.seg)?;
+        }
+        let regs: Vec<&str> = [self.base, self.index]
+            .into_iter()
+            .flatten()
+            .map(|r| REG16[r as usize & 7])
+            .collect();
+        write!(f, "{}", regs.join("+"))?;
+        match (regs.is_empty(), self.disp as i16) {
+            (true, _) => write!(f, "{}", number(self.disp))?,
+            (false, 0) => {}
+            (false, d) if d < 0 => write!(f, "-{}", number(d.unsigned_abs()))?,
+            (false, _) => write!(f, "+{}", number(self.disp))?,
+        }
+        write!(f, "]")
+    }
+}
+
+/// Registers by name, immediates and branch targets as numbers, memory
+/// in brackets. A resolved memory operand shows its offset.
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Ea8(ea) | Operand::Ea16(ea) => write!(f, "{}", ea),
+            Operand::Mem8(_, offt) | Operand::Mem16(_, offt) => write!(f, "[{}]", number(offt as u16)),
+            Operand::Reg8(r) => f.write_str(REG8[r as usize & 7]),
+            Operand::Reg16(r) => f.write_str(REG16[r as usize & 7]),
+            Operand::Seg(s) => f.write_str(SEGS[s as usize & 3]),
+            Operand::Imm8(n) => f.write_str(&number(n as u16)),
+            Operand::Imm16(n) | Operand::Rel8(n) | Operand::Rel16(n) => f.write_str(&number(n)),
+        }
+    }
+}
+
+/// The mnemonic, with the register of the opcodes that name one
+/// themselves: `inc ax`, `push es`.
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Opcode::CallNear | Opcode::CallFar => return f.write_str("call"),
+            Opcode::JmpNear | Opcode::JmpFar => return f.write_str("jmp"),
+            _ => format!("{:?}", self).to_lowercase(),
+        };
+        // IncAx, PushEs: the register is the last two letters
+        let (op, reg) = name.split_at(name.len().saturating_sub(2));
+        match op {
+            "inc" | "dec" | "push" | "pop" if REG16.contains(&reg) || SEGS.contains(&reg) => {
+                write!(f, "{} {}", op, reg)
+            }
+            _ => f.write_str(&name),
+        }
+    }
+}
+
+/// Which assembler's notation instructions are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Syntax {
+    /// NASM's, as `asm` reads: `mov ax, [bx+4]`.
+    #[default]
+    Intel,
+    /// GNU as's: `mov 0x4(%bx),%ax`, source first.
+    Att,
+}
+
+impl Syntax {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "intel" => Some(Syntax::Intel),
+            "att" => Some(Syntax::Att),
+            _ => None,
+        }
+    }
+
+    pub fn format(self, inst: &Instruction) -> String {
+        match self {
+            Syntax::Intel => inst.to_string(),
+            Syntax::Att => Att(inst).to_string(),
+        }
+    }
+}
+
+impl Instruction {
+    // whether a register operand gives the size, so that a memory one
+    // needs none written; a shift count does not
+    fn sized(&self) -> bool {
+        let reg = |op| matches!(op, Operand::Reg8(_) | Operand::Reg16(_) | Operand::Seg(_));
+        let shift = matches!(
+            self.opcode,
+            Opcode::Rol | Opcode::Ror | Opcode::Rcl | Opcode::Rcr | Opcode::Shl | Opcode::Shr | Opcode::Sar
+        );
+        (reg(self.dest) || reg(self.src) && !shift) && !matches!(self.opcode, Opcode::Push | Opcode::Pop)
+    }
+
+    // lock, rep and, on string instructions, the segment override, each
+    // followed by a space
+    fn prefix_words(&self) -> String {
+        let prefixes = self.prefixes();
+        let mut words = String::new();
+        if prefixes.lock {
+            words.push_str("lock ");
+        }
+        let written = |op: Operand| op != UNUSED && op.is_mem();
+        if let Some(seg) = prefixes.segment.filter(|_| !written(self.dest) && !written(self.src)) {
+            words.push_str(&format!("{} ", seg));
+        }
+        let compares = matches!(
+            self.opcode,
+            Opcode::Cmpsb | Opcode::Cmpsw | Opcode::Scasb | Opcode::Scasw
+        );
+        match prefixes.rep {
+            Some(Rep::Rep) if compares => words.push_str("repe "),
+            Some(Rep::Rep) => words.push_str("rep "),
+            Some(Rep::Repne) => words.push_str("repne "),
+            None => {}
+        }
+        words
+    }
+
+    fn is_nop(&self) -> bool {
+        (self.opcode, self.dest, self.src) == (Opcode::Xchg, Operand::Reg16(0), Operand::Reg16(0))
+    }
+
+    // the operands as written: none for implied ones, Intel order for
+    // out, and a size where nothing else gives it
+    fn written_operands(&self) -> Vec<String> {
+        let (dest, src) = (self.dest, self.src);
+        let sized = self.sized();
+        let show = |op: Operand| match op {
+            Operand::Ea16(_) | Operand::Mem16(..) if matches!(self.opcode, Opcode::CallFar | Opcode::JmpFar) => {
+                format!("far {}", op)
+            }
+            Operand::Ea16(_) | Operand::Mem16(..) if !sized => format!("word {}", op),
+            Operand::Ea8(_) | Operand::Mem8(..) if !sized => format!("byte {}", op),
+            _ => op.to_string(),
+        };
+        match (self.opcode, dest, src) {
+            (Opcode::CallFar | Opcode::JmpFar, Operand::Imm16(off), Operand::Imm16(seg)) => {
+                vec![format!("{}:{}", number(seg), number(off))]
+            }
+            (Opcode::Out, data, port) => vec![show(port), show(data)],
+            (Opcode::Aam | Opcode::Aad, Operand::Imm8(10), _) => vec![],
+            _ => [dest, src].into_iter().filter(|&op| op != UNUSED).map(show).collect(),
+        }
+    }
+}
+
+/// `lock`, `rep`/`repe`/`repne` and, on string instructions, the
+/// segment override go in front; other overrides show in the memory
+/// operand.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.prefix_words())?;
+        if self.is_nop() {
+            return write!(f, "nop");
+        }
+        write!(f, "{}", self.opcode)?;
+        let operands = self.written_operands();
+        if !operands.is_empty() {
+            write!(f, " {}", operands.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// An instruction in AT&T syntax: `%` on registers, `$` on immediates,
+/// `disp(base,index)` memory, source before destination, and a `b` or
+/// `w` on the mnemonic where no register gives the size.
+pub struct Att<'a>(pub &'a Instruction);
+
+fn att_operand(op: Operand) -> String {
+    match op {
+        Operand::Ea8(ea) | Operand::Ea16(ea) => {
+            let implied = match ea.base {
+                Some(5) => Segment::Ss,
+                _ => Segment::Ds,
+            };
+            let seg = if ea.seg != implied {
+                format!("%{}:", ea.seg)
+            } else {
+                String::new()
+            };
+            let regs: Vec<String> = [ea.base, ea.index]
+                .into_iter()
+                .flatten()
+                .map(|r| format!("%{}", REG16[r as usize & 7]))
+                .collect();
+            let disp = match (regs.is_empty(), ea.disp as i16) {
+                (true, _) => number(ea.disp),
+                (false, 0) => String::new(),
+                (false, d) if d < 0 => format!("-{}", number(d.unsigned_abs())),
+                (false, _) => number(ea.disp),
+            };
+            match regs.is_empty() {
+                true => format!("{}{}", seg, disp),
+                false => format!("{}{}({})", seg, disp, regs.join(",")),
+            }
+        }
+        Operand::Mem8(_, offt) | Operand::Mem16(_, offt) => number(offt as u16),
+        Operand::Reg8(_) | Operand::Reg16(_) | Operand::Seg(_) => format!("%{}", op),
+        Operand::Imm8(_) | Operand::Imm16(_) => format!("${}", op),
+        Operand::Rel8(_) | Operand::Rel16(_) => op.to_string(),
+    }
+}
+
+impl fmt::Display for Att<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inst = self.0;
+        write!(f, "{}", inst.prefix_words())?;
+        if inst.is_nop() {
+            return write!(f, "nop");
+        }
+        let (dest, src) = (inst.dest, inst.src);
+        let mnemonic = match inst.opcode {
+            Opcode::CallFar => "lcall".to_string(),
+            Opcode::JmpFar => "ljmp".to_string(),
+            Opcode::Retf => "lret".to_string(),
+            Opcode::Cbw => "cbtw".to_string(),
+            Opcode::Cwd => "cwtd".to_string(),
+            // inc ax, push es
+            op => match op.to_string().split_once(' ') {
+                Some((op, reg)) => return write!(f, "{} %{}", op, reg),
+                None => op.to_string(),
+            },
+        };
+        let indirect = matches!(
+            inst.opcode,
+            Opcode::CallNear | Opcode::JmpNear | Opcode::CallFar | Opcode::JmpFar
+        );
+        let memory = [dest, src].into_iter().find(|&op| op != UNUSED && op.is_mem());
+        let suffix = match memory {
+            _ if inst.sized() || indirect => "",
+            Some(Operand::Ea8(_) | Operand::Mem8(..)) => "b",
+            Some(_) => "w",
+            None => "",
+        };
+        let show = |op: Operand| match op {
+            Operand::Reg16(2) if matches!(inst.opcode, Opcode::In | Opcode::Out) => "(%dx)".to_string(),
+            Operand::Rel8(_) | Operand::Rel16(_) => att_operand(op),
+            _ if indirect => format!("*{}", att_operand(op)),
+            _ => att_operand(op),
+        };
+        let operands: Vec<String> = match (inst.opcode, dest, src) {
+            (Opcode::CallFar | Opcode::JmpFar, Operand::Imm16(off), Operand::Imm16(seg)) => {
+                vec![format!("${},${}", number(seg), number(off))]
+            }
+            (Opcode::Out, data, port) => vec![show(data), show(port)],
+            (Opcode::Aam | Opcode::Aad, Operand::Imm8(10), _) => vec![],
+            _ => [src, dest].into_iter().filter(|&op| op != UNUSED).map(show).collect(),
+        };
+        write!(f, "{}{}", mnemonic, suffix)?;
+        if !operands.is_empty() {
+            write!(f, " {}", operands.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// One instruction of a listing.
+#[derive(Debug, PartialEq)]
+pub struct Line<'a> {
+    pub addr: u16,
+    pub bytes: &'a [u8],
+    pub text: String,
+}
+
+/// Decodes `code` front to back as if loaded at offset `org`, without
+/// following jumps. Bytes that do not decode, or an instruction cut off
+/// by the end of `code`, come out one at a time as `db`.
+pub fn disassemble(code: &[u8], org: u16, syntax: Syntax) -> Vec<Line<'_>> {
+    // what fits in the segment above org
+    let code = &code[..code.len().min(0x10000 - org as usize)];
+    let mut lines = Vec::new();
+    let mut at = 0;
+    while at < code.len() {
+        let addr = org.wrapping_add(at as u16);
+        let mut decoder = Decoder::new(addr, |ip| code.get(ip.wrapping_sub(org) as usize).copied().unwrap_or(0));
+        let decoded = decoder.decode();
+        let len = decoder.ip().wrapping_sub(addr) as usize;
+        let (len, text) = match decoded {
+            Ok(inst) if at + len <= code.len() => (len, syntax.format(&inst)),
+            _ => (1, format!("db {}", number(code[at] as u16))),
+        };
+        lines.push(Line {
+            addr,
+            bytes: &code[at..at + len],
+            text,
+        });
+        at += len;
+    }
+    lines
+}
@@ -0,0 +1,202 @@
+use std::fmt::Write;
+
+use crate::{
+    cpu::{Cpu, Ea, Operand},
+    decode::UNUSED,
+    regs::{Flags, Registers},
+};
+
+const REG16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const REG8: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const SEGS: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+fn reg_by_name(regs: &Registers, name: &str) -> u16 {
+    match name {
+        "bx" => regs.bx,
+        "bp" => regs.bp,
+        "si" => regs.si,
+        _ => regs.di,
+    }
+}
+
+fn operand_name(op: Operand) -> String {
+    match op {
+        Operand::Mem16(addr, _) => format!("word [{:05x}]", addr),
+        Operand::Mem8(addr, _) => format!("byte [{:05x}]", addr),
+        Operand::Reg16(r) => REG16[r as usize & 7].to_string(),
+        Operand::Reg8(r) => REG8[r as usize & 7].to_string(),
+        Operand::Imm8(_) | Operand::Imm16(_) => "imm".to_string(),
+        Operand::Rel8(_) | Operand::Rel16(_) => "target".to_string(),
+        Operand::Seg(s) => SEGS[s as usize & 3].to_string(),
+        // operands are resolved before they are named
+        Operand::Ea16(_) | Operand::Ea8(_) => unreachable!(),
+    }
+}
+
+/// Reads an operand without touching the statistics counters.
+fn peek(cpu: &Cpu, op: Operand) -> u16 {
+    let mem = cpu.mem.cursor.get_ref();
+    match op {
+        Operand::Mem16(addr, _) => {
+            let addr = addr as usize;
+            u16::from_le_bytes([mem[addr], mem[addr + 1]])
+        }
+        Operand::Mem8(addr, _) => mem[addr as usize] as u16,
+        Operand::Reg16(r) => cpu.get_reg(r, true),
+        Operand::Reg8(r) => cpu.get_reg(r, false),
+        Operand::Imm8(i) => i as u16,
+        Operand::Imm16(i) => i,
+        Operand::Rel8(t) | Operand::Rel16(t) => t,
+        Operand::Seg(s) => cpu.get_seg_reg(s),
+        Operand::Ea16(_) | Operand::Ea8(_) => peek(cpu, cpu.resolve(op)),
+    }
+}
+
+/// `ea: ds*16 + bx + disp = 0000*16 + 0010 + 0004 = 00014`
+fn describe_ea(before: &Registers, ea: Ea, addr: u32, offt: u32) -> String {
+    let regs: Vec<&str> = [ea.base, ea.index]
+        .into_iter()
+        .flatten()
+        .map(|r| REG16[r as usize & 7])
+        .collect();
+    let seg = format!("{:?}", ea.seg).to_lowercase();
+    let mut names = vec![format!("{}*16", seg)];
+    let mut vals = vec![format!("{:04x}*16", (addr - offt) >> 4)];
+    for r in &regs {
+        names.push(r.to_string());
+        vals.push(format!("{:04x}", reg_by_name(before, r)));
+    }
+    if regs.is_empty() || ea.disp != 0 {
+        names.push("disp".to_string());
+        vals.push(format!("{:04x}", ea.disp));
+    }
+    format!(
+        "ea: {} = {} = {:05x}",
+        names.join(" + "),
+        vals.join(" + "),
+        addr
+    )
+}
+
+/// Why a status flag ended up with its new value.
+fn flag_reason(name: &str, set: bool) -> &'static str {
+    match (name, set) {
+        ("CF", true) => "carry/borrow out of the top bit",
+        ("CF", false) => "no carry/borrow out of the top bit",
+        ("PF", true) => "low byte of the result has an even number of 1 bits",
+        ("PF", false) => "low byte of the result has an odd number of 1 bits",
+        ("AF", true) => "carry/borrow out of bit 3",
+        ("AF", false) => "no carry/borrow out of bit 3",
+        ("ZF", true) => "result is zero",
+        ("ZF", false) => "result is not zero",
+        ("SF", true) => "top bit of the result is 1",
+        ("SF", false) => "top bit of the result is 0",
+        ("OF", true) => "signed result does not fit the operand size",
+        ("OF", false) => "signed result fits the operand size",
+        (_, true) => "set by the instruction",
+        (_, false) => "cleared by the instruction",
+    }
+}
+
+fn flag_list(flags: &Flags) -> [(&'static str, bool); 9] {
+    [
+        ("CF", flags.cf()),
+        ("PF", flags.pf()),
+        ("AF", flags.af()),
+        ("ZF", flags.zf()),
+        ("SF", flags.sf()),
+        ("TF", flags.tf()),
+        ("IF", flags.i_f()),
+        ("DF", flags.df()),
+        ("OF", flags.of()),
+    ]
+}
+
+/// Fetches and executes one instruction and narrates what it did: the
+/// effective-address arithmetic of memory operands, operand values before
+/// and after, and each flag that changed. Returns `None` once the program
+/// ends, or with `Cpu::decode_fault` set at bytes that do not decode.
+pub fn step(cpu: &mut Cpu) -> Option<String> {
+    let before = cpu.regs.clone();
+    let start = cpu.code_addr(cpu.regs.ip);
+    let inst = cpu.fetch()?;
+    let end = cpu.code_addr(cpu.regs.ip);
+    let (dest, src) = inst.operands();
+
+    let mut text = String::new();
+    let bytes: Vec<String> = cpu.mem.cursor.get_ref()[start as usize..end.max(start) as usize]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let _ = writeln!(
+        text,
+        "{:04x}:{:04x}  {:<12} {}",
+        before.cs,
+        before.ip,
+        bytes.join(" "),
+        inst
+    );
+
+    let mut operands = Vec::new();
+    for (role, op) in [("dest", dest), ("src", src)] {
+        if op == UNUSED {
+            continue;
+        }
+        let resolved = cpu.resolve(op);
+        if let (Operand::Ea16(ea) | Operand::Ea8(ea), Operand::Mem16(addr, offt) | Operand::Mem8(addr, offt)) =
+            (op, resolved)
+        {
+            if operands.iter().all(|(_, o)| *o != resolved) {
+                let _ = writeln!(text, "  {}", describe_ea(&before, ea, addr, offt));
+            }
+        }
+        operands.push((role, resolved));
+    }
+    let values: Vec<u16> = operands.iter().map(|(_, op)| peek(cpu, *op)).collect();
+
+    cpu.execute(&inst);
+
+    // opcodes with implied operands fill the unused slots with
+    // placeholder registers; only name those when they changed
+    let explicit = dest.is_mem() || src.is_mem();
+    for ((role, op), old) in operands.iter().zip(values) {
+        let new = peek(cpu, *op);
+        let _ = match op {
+            Operand::Imm8(_) | Operand::Imm16(_) => {
+                writeln!(text, "  {} imm = {:04x}", role, old)
+            }
+            Operand::Rel8(_) | Operand::Rel16(_) => {
+                writeln!(text, "  {} target = {:04x}", role, old)
+            }
+            Operand::Reg8(_) | Operand::Reg16(_) | Operand::Seg(_) if old == new && !explicit => {
+                Ok(())
+            }
+            _ if old == new => writeln!(text, "  {} {} = {:04x}", role, operand_name(*op), old),
+            _ => writeln!(
+                text,
+                "  {} {}: {:04x} -> {:04x}",
+                role,
+                operand_name(*op),
+                old,
+                new
+            ),
+        };
+    }
+
+    for ((name, old), (_, new)) in flag_list(&before.flags)
+        .into_iter()
+        .zip(flag_list(&cpu.regs.flags))
+    {
+        if old != new {
+            let _ = writeln!(
+                text,
+                "  {} {} -> {}: {}",
+                name,
+                old as u8,
+                new as u8,
+                flag_reason(name, new)
+            );
+        }
+    }
+    Some(text)
+}
@@ -0,0 +1,129 @@
+//! Runs arbitrary bytes as a program on a machine that depends on
+//! nothing but its input: memory and registers come from a fixed seed,
+//! there are no devices and no host time, so a failing input fails the
+//! same way every time.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::OnceLock,
+};
+
+use crate::cpu::{Cpu, RunExit};
+
+/// Instructions an input may run before it is cut off.
+pub const LIMIT: u64 = 10_000;
+
+const SEED: u64 = 0x6e6d_7538_3038_3621;
+
+// what Rust's own checks panic with; anything else was raised on
+// purpose for the guest, like a divide error
+const BUGS: [&str; 6] = [
+    "index out of bounds",
+    "with overflow",
+    "range end index",
+    "range start index",
+    "slice index starts",
+    "called `Option::unwrap()`",
+];
+// how execution turns down instructions it has no semantics for
+const HOLES: [&str; 2] = ["entered unreachable code", "not implemented:"];
+
+/// A xorshift generator, so runs are the same on every host.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// How a fuzzed program ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The guest ended the run the way `exit_reason` says.
+    Exit(RunExit),
+    /// IP ran past the program.
+    EndOfProgram,
+    /// The limit was reached.
+    Limit,
+    /// The bytes at CS:IP do not decode, with the message.
+    Decode(String),
+    /// The guest did something the CPU faults on, with the message.
+    Fault(String),
+}
+
+/// The machine every input starts on.
+pub fn machine() -> Cpu {
+    // filled once; copying it is much quicker than drawing it again
+    static MEMORY: OnceLock<(Vec<u8>, [u8; 16])> = OnceLock::new();
+    let mut cpu = Cpu::init();
+    let (mem, words) = MEMORY.get_or_init(|| {
+        let mut rng = Rng::new(SEED);
+        let mut mem = cpu.mem.cursor.get_ref().clone();
+        let mut words = [0u8; 16];
+        rng.fill(&mut mem);
+        rng.fill(&mut words);
+        (mem, words)
+    });
+    cpu.mem.cursor.get_mut().copy_from_slice(mem);
+    let word = |i: usize| u16::from_le_bytes([words[2 * i], words[2 * i + 1]]);
+    let r = &mut cpu.regs;
+    (r.ax, r.bx, r.cx, r.dx, r.si, r.di, r.bp) = (word(0), word(1), word(2), word(3), word(4), word(5), word(6));
+    cpu.test_mode();
+    cpu
+}
+
+/// Runs `program` for at most `limit` instructions. A panic that is
+/// not one the CPU raises for the guest is a bug in the emulator and is
+/// passed on, so a fuzzer sees it as a crash.
+pub fn run(program: &[u8], limit: u64) -> Outcome {
+    let mut cpu = machine();
+    cpu.load_code_vec(program);
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        while cpu.stats.instructions < limit {
+            let Some(i) = cpu.fetch() else {
+                return match cpu.decode_fault.take() {
+                    Some(why) => Outcome::Decode(why),
+                    None => Outcome::EndOfProgram,
+                };
+            };
+            cpu.execute(&i);
+            if cpu.halt {
+                return Outcome::Exit(cpu.exit_reason.unwrap_or(RunExit::Halted));
+            }
+        }
+        Outcome::Limit
+    }));
+    match res {
+        Ok(outcome) => outcome,
+        Err(payload) => {
+            let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+                (Some(s), _) => s.to_string(),
+                (_, Some(s)) => s.clone(),
+                _ => String::new(),
+            };
+            let is = |messages: &[&str]| messages.iter().any(|m| message.contains(m));
+            if message.is_empty() || is(&BUGS) || message.contains("not yet implemented") {
+                panic::resume_unwind(payload);
+            }
+            match is(&HOLES) {
+                true => Outcome::Decode(message),
+                false => Outcome::Fault(message),
+            }
+        }
+    }
+}
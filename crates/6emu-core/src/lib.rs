@@ -0,0 +1,69 @@
+//! An 8086 CPU, its megabyte of memory and the decoder, for embedding in
+//! other tools. The `emu8086` binary is a command line over this crate.
+//!
+//! A [`Cpu`] owns its [`Registers`] and [`Mem`]. Load a program, then
+//! either run it to the end or step it an [`Instruction`] at a time:
+//!
+//! ```
+//! use sixemu_core::Cpu;
+//!
+//! let mut cpu = Cpu::init();
+//! cpu.test_mode();
+//! // mov ax, 5; inc ax; hlt
+//! cpu.load_code_vec(&[0xb8, 0x05, 0x00, 0x40, 0xf4]);
+//! let inst = cpu.fetch().unwrap();
+//! cpu.execute(&inst);
+//! assert_eq!(cpu.regs.ax, 5);
+//! cpu.fire();
+//! assert_eq!((cpu.regs.ax, cpu.halt), (6, true));
+//! ```
+//!
+//! Guest faults such as a divide error panic, unless `Cpu::exceptions`
+//! has them taken through their vectors; catch them with
+//! `std::panic::catch_unwind` around `fetch` (the bytes could not be
+//! decoded) and `execute` (the instruction failed). Devices attach
+//! through [`cpu::IoDevice`]; the `sixemu-devices` crate has the PC's.
+//! A [`Machine`] wires devices that also map memory or raise IRQs to a
+//! `Cpu` and ticks them as it runs.
+//!
+//! A [`Decoder`] turns bytes into instructions without a `Cpu` at all:
+//! memory operands come out as [`Ea`]s, which `execute` resolves against
+//! the registers of the moment.
+
+pub mod alu;
+pub mod asm;
+#[cfg(feature = "profile")]
+pub mod branches;
+#[allow(unused)]
+pub mod cpu;
+#[cfg(feature = "debug")]
+pub mod debugger;
+pub mod decode;
+pub mod disasm;
+#[cfg(feature = "explain")]
+pub mod explain;
+pub mod fuzz;
+pub mod machine;
+#[allow(unused)]
+pub mod mem;
+pub mod opcodes;
+#[cfg(feature = "png")]
+pub mod png;
+#[cfg(feature = "profile")]
+pub mod profile;
+#[allow(unused)]
+pub mod regs;
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "trace")]
+pub mod trace;
+
+pub use cpu::{Cpu, Ea, Instruction, Opcode, Operand, RunExit, Segment};
+pub use decode::Decoder;
+pub use machine::Machine;
+pub use mem::Mem;
+pub use regs::{Flags, Registers};
+pub use stats::Stats;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,360 @@
+//! A [`Machine`]: a [`Cpu`] and the devices wired to it. Devices
+//! declare the I/O ports, memory ranges and IRQ lines they use when
+//! they are attached, and the machine ticks them after every
+//! instruction and turns the lines they raise into interrupts.
+//!
+//! The devices live on a [`Bus`] that can also be driven on its own, by
+//! a run loop that steps the `Cpu` itself and calls [`Bus::tick`]
+//! between instructions.
+
+use std::{cell::RefCell, ops::RangeInclusive, rc::Rc};
+
+use crate::cpu::{Cpu, DeviceState, IoDevice, MmioDevice, RunExit};
+
+/// Interrupt vector IRQ 0 is taken through: the PC BIOS programs the
+/// 8259 for IRQs 0-7 on vectors 8-15.
+pub const IRQ_BASE: u8 = 8;
+/// IRQ lines on the PC's single 8259.
+pub const IRQ_LINES: u8 = 8;
+
+/// A device on a [`Bus`]. Everything but `name` has a default, so a
+/// device implements just the parts it has.
+pub trait Device {
+    /// A short name for messages and the debugger, such as `pit`.
+    fn name(&self) -> &str;
+    /// The I/O ports the device answers.
+    fn ports(&self) -> Vec<RangeInclusive<u16>> {
+        Vec::new()
+    }
+    /// The physical ranges (start, end exclusive) the device answers in
+    /// place of memory.
+    fn mmio(&self) -> Vec<(u32, u32)> {
+        Vec::new()
+    }
+    /// The IRQ lines the device may raise.
+    fn irqs(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn port_read(&mut self, _port: u16, _now: u64, _irq: &mut Irq) -> u8 {
+        0xff
+    }
+    fn port_write(&mut self, _port: u16, _val: u8, _now: u64, _irq: &mut Irq) {}
+    fn mem_read(&mut self, _addr: u32, _now: u64) -> u8 {
+        0xff
+    }
+    fn mem_write(&mut self, _addr: u32, _val: u8, _now: u64) {}
+    /// Called between instructions with the cycle count, `Cpu::cycles`.
+    fn tick(&mut self, _now: u64, _irq: &mut Irq) {}
+    fn debug_state(&self) -> Option<DeviceState> {
+        None
+    }
+}
+
+/// The IRQ lines as a device sees them: it may raise and lower only the
+/// ones it declared.
+pub struct Irq<'a> {
+    name: &'a str,
+    allowed: u16,
+    pending: &'a mut u16,
+}
+
+impl Irq<'_> {
+    /// Asks for the interrupt on `line`; it stays pending until the CPU
+    /// takes it or the device lowers the line.
+    pub fn raise(&mut self, line: u8) {
+        self.check(line);
+        *self.pending |= 1 << line;
+    }
+
+    pub fn lower(&mut self, line: u8) {
+        self.check(line);
+        *self.pending &= !(1 << line);
+    }
+
+    fn check(&self, line: u8) {
+        assert!(
+            line < 16 && self.allowed & 1 << line != 0,
+            "{} used IRQ {} it did not declare",
+            self.name,
+            line
+        );
+    }
+}
+
+struct Slot {
+    name: String,
+    device: Box<dyn Device>,
+    ports: Vec<RangeInclusive<u16>>,
+    mmio: Vec<(u32, u32)>,
+    irqs: u16,
+}
+
+impl Slot {
+    fn irq<'a>(&'a mut self, pending: &'a mut u16) -> (&'a mut dyn Device, Irq<'a>) {
+        let irq = Irq {
+            name: &self.name,
+            allowed: self.irqs,
+            pending,
+        };
+        (&mut *self.device, irq)
+    }
+}
+
+#[derive(Default)]
+struct Devices {
+    slots: Vec<Slot>,
+    pending: u16,
+}
+
+impl Devices {
+    fn by_addr(&mut self, addr: u32) -> Option<&mut Slot> {
+        self.slots
+            .iter_mut()
+            .find(|slot| slot.mmio.iter().any(|&(start, end)| (start..end).contains(&addr)))
+    }
+}
+
+// what the bus puts on the cpu's port and memory lists
+struct Ports(Rc<RefCell<Devices>>);
+struct Memory(Rc<RefCell<Devices>>);
+
+impl IoDevice for Ports {
+    fn debug_states(&self) -> Vec<DeviceState> {
+        self.0
+            .borrow()
+            .slots
+            .iter()
+            .filter_map(|slot| slot.device.debug_state())
+            .collect()
+    }
+
+    fn handles(&self, port: u16) -> bool {
+        self.0
+            .borrow()
+            .slots
+            .iter()
+            .any(|slot| slot.ports.iter().any(|r| r.contains(&port)))
+    }
+
+    fn read(&mut self, port: u16, now: u64) -> u8 {
+        let devices = &mut *self.0.borrow_mut();
+        let slot = devices
+            .slots
+            .iter_mut()
+            .find(|slot| slot.ports.iter().any(|r| r.contains(&port)));
+        match slot {
+            Some(slot) => {
+                let (device, mut irq) = slot.irq(&mut devices.pending);
+                device.port_read(port, now, &mut irq)
+            }
+            None => 0xff,
+        }
+    }
+
+    fn write(&mut self, port: u16, val: u8, now: u64) {
+        let devices = &mut *self.0.borrow_mut();
+        let slot = devices
+            .slots
+            .iter_mut()
+            .find(|slot| slot.ports.iter().any(|r| r.contains(&port)));
+        if let Some(slot) = slot {
+            let (device, mut irq) = slot.irq(&mut devices.pending);
+            device.port_write(port, val, now, &mut irq);
+        }
+    }
+}
+
+impl MmioDevice for Memory {
+    fn handles(&self, addr: u32) -> bool {
+        self.0
+            .borrow()
+            .slots
+            .iter()
+            .any(|slot| slot.mmio.iter().any(|&(start, end)| (start..end).contains(&addr)))
+    }
+
+    fn read(&mut self, addr: u32, now: u64) -> u8 {
+        self.0
+            .borrow_mut()
+            .by_addr(addr)
+            .map_or(0xff, |slot| slot.device.mem_read(addr, now))
+    }
+
+    fn write(&mut self, addr: u32, val: u8, now: u64) {
+        if let Some(slot) = self.0.borrow_mut().by_addr(addr) {
+            slot.device.mem_write(addr, val, now);
+        }
+    }
+}
+
+/// The devices attached to a `Cpu`, with the ports, memory and IRQ
+/// lines each has claimed.
+pub struct Bus {
+    devices: Rc<RefCell<Devices>>,
+    // whether the bus is on the cpu's port and memory lists yet
+    on_ports: bool,
+    on_memory: bool,
+    /// The vector IRQ 0 is taken through, `IRQ_BASE` by default.
+    pub irq_base: u8,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self {
+            devices: Rc::default(),
+            on_ports: false,
+            on_memory: false,
+            irq_base: IRQ_BASE,
+        }
+    }
+}
+
+impl Bus {
+    /// Adds `device` to `cpu`, or says which device already has one of
+    /// the ports, addresses or IRQ lines it asks for. The bus is asked
+    /// ahead of the devices on `cpu.io` and `cpu.mmio`, and goes on
+    /// `cpu.mmio` only once a device maps memory, so memory accesses
+    /// cost nothing extra until then.
+    pub fn attach(&mut self, cpu: &mut Cpu, device: Box<dyn Device>) -> Result<(), String> {
+        let (ports, mmio, lines) = (device.ports(), device.mmio(), device.irqs());
+        let mut irqs = 0u16;
+        for line in lines {
+            if line >= IRQ_LINES {
+                return Err(format!(
+                    "{} asks for IRQ {}, but there are only {}",
+                    device.name(),
+                    line,
+                    IRQ_LINES
+                ));
+            }
+            irqs |= 1 << line;
+        }
+        let devices = &mut *self.devices.borrow_mut();
+        for slot in &devices.slots {
+            let clash = if ports
+                .iter()
+                .any(|p| slot.ports.iter().any(|q| p.start() <= q.end() && q.start() <= p.end()))
+            {
+                "I/O ports"
+            } else if mmio.iter().any(|m| slot.mmio.iter().any(|n| m.0 < n.1 && n.0 < m.1)) {
+                "memory"
+            } else if irqs & slot.irqs != 0 {
+                "IRQ lines"
+            } else {
+                continue;
+            };
+            return Err(format!("{} wants {} {} already has", device.name(), clash, slot.name));
+        }
+        if !ports.is_empty() && !self.on_ports {
+            cpu.io.insert(0, Box::new(Ports(self.devices.clone())));
+            self.on_ports = true;
+        }
+        if !mmio.is_empty() && !self.on_memory {
+            cpu.mmio.insert(0, Box::new(Memory(self.devices.clone())));
+            self.on_memory = true;
+        }
+        devices.slots.push(Slot {
+            name: device.name().to_string(),
+            device,
+            ports,
+            mmio,
+            irqs,
+        });
+        Ok(())
+    }
+
+    /// The names of the attached devices, in the order they were
+    /// attached.
+    pub fn names(&self) -> Vec<String> {
+        self.devices
+            .borrow()
+            .slots
+            .iter()
+            .map(|slot| slot.name.clone())
+            .collect()
+    }
+
+    /// The state of every attached device that reports one.
+    pub fn device_states(&self) -> Vec<DeviceState> {
+        self.devices
+            .borrow()
+            .slots
+            .iter()
+            .filter_map(|slot| slot.device.debug_state())
+            .collect()
+    }
+
+    /// The IRQ lines raised and not yet taken, bit 0 for IRQ 0.
+    pub fn pending(&self) -> u16 {
+        self.devices.borrow().pending
+    }
+
+    /// Ticks every device, then takes the lowest pending IRQ if the
+    /// CPU has interrupts enabled; call between instructions.
+    pub fn tick(&mut self, cpu: &mut Cpu) {
+        let now = cpu.cycles();
+        let pending = {
+            let devices = &mut *self.devices.borrow_mut();
+            for slot in &mut devices.slots {
+                let (device, mut irq) = slot.irq(&mut devices.pending);
+                device.tick(now, &mut irq);
+            }
+            devices.pending
+        };
+        if pending == 0 {
+            return;
+        }
+        // the stack the interrupt is pushed on may be device memory
+        let line = pending.trailing_zeros() as u8;
+        if cpu.interrupt(self.irq_base + line) {
+            self.devices.borrow_mut().pending &= !(1 << line);
+        }
+    }
+}
+
+/// A `Cpu` and the `Bus` of devices attached to it.
+pub struct Machine {
+    pub cpu: Cpu,
+    pub bus: Bus,
+}
+
+impl Machine {
+    pub fn new(cpu: Cpu) -> Self {
+        Self {
+            cpu,
+            bus: Bus::default(),
+        }
+    }
+
+    /// Adds a device; see `Bus::attach`.
+    pub fn attach(&mut self, device: Box<dyn Device>) -> Result<(), String> {
+        self.bus.attach(&mut self.cpu, device)
+    }
+
+    /// Runs one instruction and then the devices. None until the run
+    /// ends as `Cpu::run_until` says, with a pending host interrupt
+    /// left in `cpu.host_int` for the caller to service.
+    pub fn step(&mut self) -> Option<RunExit> {
+        let Some(i) = self.cpu.fetch() else {
+            return Some(self.cpu.fetch_failure());
+        };
+        self.cpu.execute(&i);
+        if self.cpu.halt {
+            return Some(self.cpu.exit_reason.unwrap_or(RunExit::Halted));
+        }
+        self.bus.tick(&mut self.cpu);
+        self.cpu.host_int.map(RunExit::HostInt)
+    }
+
+    /// Steps until the run ends or `stop` holds after an instruction.
+    pub fn run_until(&mut self, mut stop: impl FnMut(&Cpu) -> bool) -> RunExit {
+        loop {
+            if let Some(exit) = self.step() {
+                return exit;
+            }
+            if stop(&self.cpu) {
+                return RunExit::Predicate;
+            }
+        }
+    }
+}
@@ -1,70 +1,20 @@
 use std::{
     io::{Cursor, Read, Seek, Write},
     mem::MaybeUninit,
+    ops::Range,
 };
 
-#[derive(Clone, Copy)]
-pub struct Byte1 {
-    bp: u8,
-}
-
-impl Byte1 {
-    pub fn new(bp: u8) -> Self {
-        Self { bp }
-    }
-
-    pub fn word(&self) -> bool {
-        self.bp & 0b1 > 0
-    }
-
-    pub fn set_word(&mut self) {
-        self.bp |= 1;
-    }
-
-    pub fn reg_is_dest(&self) -> bool {
-        self.bp & 0b10 > 0
-    }
-
-    pub fn opcode(&self) -> u8 {
-        self.bp >> 2
-    }
-
-    pub fn to_u8(&self) -> u8 {
-        self.bp
-    }
-}
-
-pub struct Byte2 {
-    bp: u8,
-}
-
-impl Byte2 {
-    pub fn new(bp: u8) -> Self {
-        Self { bp }
-    }
-
-    pub fn to_u8(&self) -> u8 {
-        self.bp
-    }
-
-    pub fn modd(&self) -> u8 {
-        self.bp >> 6
-    }
-
-    pub fn rm(&self) -> u8 {
-        self.bp & 0b111
-    }
-
-    pub fn reg(&self) -> u8 {
-        (self.bp >> 3) & 0b111
-    }
-}
-
 pub struct Mem {
     pub cursor: Cursor<Vec<u8>>,
     pub size: u64,
 }
 
+impl Default for Mem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Mem {
     fn zero(&mut self) {
         let vec = self.cursor.get_mut();
@@ -156,6 +106,63 @@ impl Mem {
     pub fn pos(&self) -> u64 {
         self.cursor.position()
     }
+
+    /// Physical addresses where `needle` starts and ends within `start`
+    /// to `end`, both inclusive, in order.
+    pub fn find(&self, start: u32, end: u32, needle: &[u8]) -> Vec<u32> {
+        let mem = self.cursor.get_ref();
+        let end = (end as usize + 1).min(mem.len());
+        let start = start as usize;
+        if needle.is_empty() || start >= end {
+            return Vec::new();
+        }
+        mem[start..end]
+            .windows(needle.len())
+            .enumerate()
+            .filter(|(_, window)| *window == needle)
+            .map(|(i, _)| (start + i) as u32)
+            .collect()
+    }
+}
+
+/// The part of a physical range that guest writes have changed, for a
+/// renderer that redraws only that. Writing the value already there
+/// does not count, so a guest rewriting the same screen every frame
+/// costs nothing to show.
+#[derive(Debug, Clone)]
+pub struct Dirty {
+    range: Range<u32>,
+    // lowest and highest changed address since the last `take`
+    changed: Option<(u32, u32)>,
+}
+
+impl Dirty {
+    /// Watches `range`, all of it changed to begin with so the first
+    /// frame is drawn whole.
+    pub fn new(range: Range<u32>) -> Self {
+        let changed = (!range.is_empty()).then(|| (range.start, range.end - 1));
+        Self { range, changed }
+    }
+
+    pub(crate) fn note(&mut self, pos: u32) {
+        if self.range.contains(&pos) {
+            self.changed = Some(match self.changed {
+                Some((lo, hi)) => (lo.min(pos), hi.max(pos)),
+                None => (pos, pos),
+            });
+        }
+    }
+
+    /// Marks the whole range changed, for memory written behind the
+    /// guest's back, such as a restored snapshot.
+    pub fn mark_all(&mut self) {
+        *self = Self::new(self.range.clone());
+    }
+
+    /// The span changed since the last call, if any.
+    pub fn take(&mut self) -> Option<Range<u32>> {
+        self.changed.take().map(|(lo, hi)| lo..hi + 1)
+    }
 }
 
 #[cfg(test)]
@@ -175,4 +182,16 @@ mod mem_test {
         m.seek_by(-2);
         assert_eq!(m.read_i16(), 6000);
     }
+
+    #[test]
+    fn find() {
+        let mut m = Mem::new();
+        m.seek_to(0x100);
+        m.cursor.write_all(b"abcabca").unwrap();
+        assert_eq!(m.find(0, 0xfffff, b"abc"), [0x100, 0x103]);
+        // a match has to end inside the range
+        assert_eq!(m.find(0x101, 0x105, b"abc"), [0x103]);
+        assert_eq!(m.find(0x101, 0x104, b"abc"), []);
+        assert_eq!(m.find(0x200, 0x100, b"a"), []);
+    }
 }
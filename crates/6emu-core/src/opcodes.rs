@@ -0,0 +1,378 @@
+//! Encodings of every 8086 opcode, for tools that want to reason about
+//! instructions without decoding them: disassemblers, documentation
+//! generators, coverage reports.
+//!
+//! Operand templates follow the Intel manual's notation: `r8`/`r16` is
+//! the ModR/M reg field, `r/m8`/`r/m16` the r/m field, `m` a memory-only
+//! r/m, `imm8`/`imm16` immediates, `rel8`/`rel16` branch displacements,
+//! `moffs` a direct address, `ptr16:16` and `m16:16` far pointers and
+//! `Sreg` a segment register. Bytes the 8086 leaves undefined (60h-6Fh,
+//! C0h, C1h, C8h, C9h, D6h, F1h) have no entry.
+
+use crate::decode::ModRm;
+
+/// One row of the opcode table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    /// For group opcodes, the ModR/M reg field that selects the
+    /// operation; `None` when the byte alone decides.
+    pub reg: Option<u8>,
+    pub mnemonic: &'static str,
+    /// Operands in Intel order, empty when there are none.
+    pub operands: &'static str,
+}
+
+impl OpcodeInfo {
+    /// Whether the opcode is followed by a ModR/M byte.
+    pub fn has_modrm(&self) -> bool {
+        self.reg.is_some()
+            || self
+                .operands
+                .split(", ")
+                .any(|o| o.starts_with("r/m") || o == "Sreg" || o.starts_with('m') && !o.starts_with("moffs"))
+    }
+}
+
+const fn op(opcode: u8, mnemonic: &'static str, operands: &'static str) -> OpcodeInfo {
+    OpcodeInfo {
+        opcode,
+        reg: None,
+        mnemonic,
+        operands,
+    }
+}
+
+const fn group(opcode: u8, reg: u8, mnemonic: &'static str, operands: &'static str) -> OpcodeInfo {
+    OpcodeInfo {
+        opcode,
+        reg: Some(reg),
+        mnemonic,
+        operands,
+    }
+}
+
+/// Every defined encoding, sorted by opcode and then reg field. Prefixes
+/// (segment overrides, `lock`, `rep`, `repne`) are listed as opcodes.
+pub static OPCODES: &[OpcodeInfo] = &[
+    op(0x00, "add", "r/m8, r8"),
+    op(0x01, "add", "r/m16, r16"),
+    op(0x02, "add", "r8, r/m8"),
+    op(0x03, "add", "r16, r/m16"),
+    op(0x04, "add", "AL, imm8"),
+    op(0x05, "add", "AX, imm16"),
+    op(0x06, "push", "ES"),
+    op(0x07, "pop", "ES"),
+    op(0x08, "or", "r/m8, r8"),
+    op(0x09, "or", "r/m16, r16"),
+    op(0x0a, "or", "r8, r/m8"),
+    op(0x0b, "or", "r16, r/m16"),
+    op(0x0c, "or", "AL, imm8"),
+    op(0x0d, "or", "AX, imm16"),
+    op(0x0e, "push", "CS"),
+    op(0x0f, "pop", "CS"),
+    op(0x10, "adc", "r/m8, r8"),
+    op(0x11, "adc", "r/m16, r16"),
+    op(0x12, "adc", "r8, r/m8"),
+    op(0x13, "adc", "r16, r/m16"),
+    op(0x14, "adc", "AL, imm8"),
+    op(0x15, "adc", "AX, imm16"),
+    op(0x16, "push", "SS"),
+    op(0x17, "pop", "SS"),
+    op(0x18, "sbb", "r/m8, r8"),
+    op(0x19, "sbb", "r/m16, r16"),
+    op(0x1a, "sbb", "r8, r/m8"),
+    op(0x1b, "sbb", "r16, r/m16"),
+    op(0x1c, "sbb", "AL, imm8"),
+    op(0x1d, "sbb", "AX, imm16"),
+    op(0x1e, "push", "DS"),
+    op(0x1f, "pop", "DS"),
+    op(0x20, "and", "r/m8, r8"),
+    op(0x21, "and", "r/m16, r16"),
+    op(0x22, "and", "r8, r/m8"),
+    op(0x23, "and", "r16, r/m16"),
+    op(0x24, "and", "AL, imm8"),
+    op(0x25, "and", "AX, imm16"),
+    op(0x26, "es:", ""),
+    op(0x27, "daa", ""),
+    op(0x28, "sub", "r/m8, r8"),
+    op(0x29, "sub", "r/m16, r16"),
+    op(0x2a, "sub", "r8, r/m8"),
+    op(0x2b, "sub", "r16, r/m16"),
+    op(0x2c, "sub", "AL, imm8"),
+    op(0x2d, "sub", "AX, imm16"),
+    op(0x2e, "cs:", ""),
+    op(0x2f, "das", ""),
+    op(0x30, "xor", "r/m8, r8"),
+    op(0x31, "xor", "r/m16, r16"),
+    op(0x32, "xor", "r8, r/m8"),
+    op(0x33, "xor", "r16, r/m16"),
+    op(0x34, "xor", "AL, imm8"),
+    op(0x35, "xor", "AX, imm16"),
+    op(0x36, "ss:", ""),
+    op(0x37, "aaa", ""),
+    op(0x38, "cmp", "r/m8, r8"),
+    op(0x39, "cmp", "r/m16, r16"),
+    op(0x3a, "cmp", "r8, r/m8"),
+    op(0x3b, "cmp", "r16, r/m16"),
+    op(0x3c, "cmp", "AL, imm8"),
+    op(0x3d, "cmp", "AX, imm16"),
+    op(0x3e, "ds:", ""),
+    op(0x3f, "aas", ""),
+    op(0x40, "inc", "AX"),
+    op(0x41, "inc", "CX"),
+    op(0x42, "inc", "DX"),
+    op(0x43, "inc", "BX"),
+    op(0x44, "inc", "SP"),
+    op(0x45, "inc", "BP"),
+    op(0x46, "inc", "SI"),
+    op(0x47, "inc", "DI"),
+    op(0x48, "dec", "AX"),
+    op(0x49, "dec", "CX"),
+    op(0x4a, "dec", "DX"),
+    op(0x4b, "dec", "BX"),
+    op(0x4c, "dec", "SP"),
+    op(0x4d, "dec", "BP"),
+    op(0x4e, "dec", "SI"),
+    op(0x4f, "dec", "DI"),
+    op(0x50, "push", "AX"),
+    op(0x51, "push", "CX"),
+    op(0x52, "push", "DX"),
+    op(0x53, "push", "BX"),
+    op(0x54, "push", "SP"),
+    op(0x55, "push", "BP"),
+    op(0x56, "push", "SI"),
+    op(0x57, "push", "DI"),
+    op(0x58, "pop", "AX"),
+    op(0x59, "pop", "CX"),
+    op(0x5a, "pop", "DX"),
+    op(0x5b, "pop", "BX"),
+    op(0x5c, "pop", "SP"),
+    op(0x5d, "pop", "BP"),
+    op(0x5e, "pop", "SI"),
+    op(0x5f, "pop", "DI"),
+    op(0x70, "jo", "rel8"),
+    op(0x71, "jno", "rel8"),
+    op(0x72, "jb", "rel8"),
+    op(0x73, "jnb", "rel8"),
+    op(0x74, "jz", "rel8"),
+    op(0x75, "jnz", "rel8"),
+    op(0x76, "jbe", "rel8"),
+    op(0x77, "jnbe", "rel8"),
+    op(0x78, "js", "rel8"),
+    op(0x79, "jns", "rel8"),
+    op(0x7a, "jp", "rel8"),
+    op(0x7b, "jnp", "rel8"),
+    op(0x7c, "jl", "rel8"),
+    op(0x7d, "jnl", "rel8"),
+    op(0x7e, "jle", "rel8"),
+    op(0x7f, "jnle", "rel8"),
+    group(0x80, 0, "add", "r/m8, imm8"),
+    group(0x80, 1, "or", "r/m8, imm8"),
+    group(0x80, 2, "adc", "r/m8, imm8"),
+    group(0x80, 3, "sbb", "r/m8, imm8"),
+    group(0x80, 4, "and", "r/m8, imm8"),
+    group(0x80, 5, "sub", "r/m8, imm8"),
+    group(0x80, 6, "xor", "r/m8, imm8"),
+    group(0x80, 7, "cmp", "r/m8, imm8"),
+    group(0x81, 0, "add", "r/m16, imm16"),
+    group(0x81, 1, "or", "r/m16, imm16"),
+    group(0x81, 2, "adc", "r/m16, imm16"),
+    group(0x81, 3, "sbb", "r/m16, imm16"),
+    group(0x81, 4, "and", "r/m16, imm16"),
+    group(0x81, 5, "sub", "r/m16, imm16"),
+    group(0x81, 6, "xor", "r/m16, imm16"),
+    group(0x81, 7, "cmp", "r/m16, imm16"),
+    group(0x82, 0, "add", "r/m8, imm8"),
+    group(0x82, 1, "or", "r/m8, imm8"),
+    group(0x82, 2, "adc", "r/m8, imm8"),
+    group(0x82, 3, "sbb", "r/m8, imm8"),
+    group(0x82, 4, "and", "r/m8, imm8"),
+    group(0x82, 5, "sub", "r/m8, imm8"),
+    group(0x82, 6, "xor", "r/m8, imm8"),
+    group(0x82, 7, "cmp", "r/m8, imm8"),
+    group(0x83, 0, "add", "r/m16, imm8"),
+    group(0x83, 1, "or", "r/m16, imm8"),
+    group(0x83, 2, "adc", "r/m16, imm8"),
+    group(0x83, 3, "sbb", "r/m16, imm8"),
+    group(0x83, 4, "and", "r/m16, imm8"),
+    group(0x83, 5, "sub", "r/m16, imm8"),
+    group(0x83, 6, "xor", "r/m16, imm8"),
+    group(0x83, 7, "cmp", "r/m16, imm8"),
+    op(0x84, "test", "r/m8, r8"),
+    op(0x85, "test", "r/m16, r16"),
+    op(0x86, "xchg", "r8, r/m8"),
+    op(0x87, "xchg", "r16, r/m16"),
+    op(0x88, "mov", "r/m8, r8"),
+    op(0x89, "mov", "r/m16, r16"),
+    op(0x8a, "mov", "r8, r/m8"),
+    op(0x8b, "mov", "r16, r/m16"),
+    op(0x8c, "mov", "r/m16, Sreg"),
+    op(0x8d, "lea", "r16, m"),
+    op(0x8e, "mov", "Sreg, r/m16"),
+    group(0x8f, 0, "pop", "r/m16"),
+    op(0x90, "nop", ""),
+    op(0x91, "xchg", "AX, CX"),
+    op(0x92, "xchg", "AX, DX"),
+    op(0x93, "xchg", "AX, BX"),
+    op(0x94, "xchg", "AX, SP"),
+    op(0x95, "xchg", "AX, BP"),
+    op(0x96, "xchg", "AX, SI"),
+    op(0x97, "xchg", "AX, DI"),
+    op(0x98, "cbw", ""),
+    op(0x99, "cwd", ""),
+    op(0x9a, "call", "ptr16:16"),
+    op(0x9b, "wait", ""),
+    op(0x9c, "pushf", ""),
+    op(0x9d, "popf", ""),
+    op(0x9e, "sahf", ""),
+    op(0x9f, "lahf", ""),
+    op(0xa0, "mov", "AL, moffs8"),
+    op(0xa1, "mov", "AX, moffs16"),
+    op(0xa2, "mov", "moffs8, AL"),
+    op(0xa3, "mov", "moffs16, AX"),
+    op(0xa4, "movsb", ""),
+    op(0xa5, "movsw", ""),
+    op(0xa6, "cmpsb", ""),
+    op(0xa7, "cmpsw", ""),
+    op(0xa8, "test", "AL, imm8"),
+    op(0xa9, "test", "AX, imm16"),
+    op(0xaa, "stosb", ""),
+    op(0xab, "stosw", ""),
+    op(0xac, "lodsb", ""),
+    op(0xad, "lodsw", ""),
+    op(0xae, "scasb", ""),
+    op(0xaf, "scasw", ""),
+    op(0xb0, "mov", "AL, imm8"),
+    op(0xb1, "mov", "CL, imm8"),
+    op(0xb2, "mov", "DL, imm8"),
+    op(0xb3, "mov", "BL, imm8"),
+    op(0xb4, "mov", "AH, imm8"),
+    op(0xb5, "mov", "CH, imm8"),
+    op(0xb6, "mov", "DH, imm8"),
+    op(0xb7, "mov", "BH, imm8"),
+    op(0xb8, "mov", "AX, imm16"),
+    op(0xb9, "mov", "CX, imm16"),
+    op(0xba, "mov", "DX, imm16"),
+    op(0xbb, "mov", "BX, imm16"),
+    op(0xbc, "mov", "SP, imm16"),
+    op(0xbd, "mov", "BP, imm16"),
+    op(0xbe, "mov", "SI, imm16"),
+    op(0xbf, "mov", "DI, imm16"),
+    op(0xc2, "ret", "imm16"),
+    op(0xc3, "ret", ""),
+    op(0xc4, "les", "r16, m16:16"),
+    op(0xc5, "lds", "r16, m16:16"),
+    group(0xc6, 0, "mov", "r/m8, imm8"),
+    group(0xc7, 0, "mov", "r/m16, imm16"),
+    op(0xca, "retf", "imm16"),
+    op(0xcb, "retf", ""),
+    op(0xcc, "int", "3"),
+    op(0xcd, "int", "imm8"),
+    op(0xce, "into", ""),
+    op(0xcf, "iret", ""),
+    group(0xd0, 0, "rol", "r/m8, 1"),
+    group(0xd0, 1, "ror", "r/m8, 1"),
+    group(0xd0, 2, "rcl", "r/m8, 1"),
+    group(0xd0, 3, "rcr", "r/m8, 1"),
+    group(0xd0, 4, "shl", "r/m8, 1"),
+    group(0xd0, 5, "shr", "r/m8, 1"),
+    group(0xd0, 7, "sar", "r/m8, 1"),
+    group(0xd1, 0, "rol", "r/m16, 1"),
+    group(0xd1, 1, "ror", "r/m16, 1"),
+    group(0xd1, 2, "rcl", "r/m16, 1"),
+    group(0xd1, 3, "rcr", "r/m16, 1"),
+    group(0xd1, 4, "shl", "r/m16, 1"),
+    group(0xd1, 5, "shr", "r/m16, 1"),
+    group(0xd1, 7, "sar", "r/m16, 1"),
+    group(0xd2, 0, "rol", "r/m8, CL"),
+    group(0xd2, 1, "ror", "r/m8, CL"),
+    group(0xd2, 2, "rcl", "r/m8, CL"),
+    group(0xd2, 3, "rcr", "r/m8, CL"),
+    group(0xd2, 4, "shl", "r/m8, CL"),
+    group(0xd2, 5, "shr", "r/m8, CL"),
+    group(0xd2, 7, "sar", "r/m8, CL"),
+    group(0xd3, 0, "rol", "r/m16, CL"),
+    group(0xd3, 1, "ror", "r/m16, CL"),
+    group(0xd3, 2, "rcl", "r/m16, CL"),
+    group(0xd3, 3, "rcr", "r/m16, CL"),
+    group(0xd3, 4, "shl", "r/m16, CL"),
+    group(0xd3, 5, "shr", "r/m16, CL"),
+    group(0xd3, 7, "sar", "r/m16, CL"),
+    op(0xd4, "aam", "imm8"),
+    op(0xd5, "aad", "imm8"),
+    op(0xd7, "xlat", ""),
+    op(0xd8, "esc", "r/m"),
+    op(0xd9, "esc", "r/m"),
+    op(0xda, "esc", "r/m"),
+    op(0xdb, "esc", "r/m"),
+    op(0xdc, "esc", "r/m"),
+    op(0xdd, "esc", "r/m"),
+    op(0xde, "esc", "r/m"),
+    op(0xdf, "esc", "r/m"),
+    op(0xe0, "loopne", "rel8"),
+    op(0xe1, "loope", "rel8"),
+    op(0xe2, "loop", "rel8"),
+    op(0xe3, "jcxz", "rel8"),
+    op(0xe4, "in", "AL, imm8"),
+    op(0xe5, "in", "AX, imm8"),
+    op(0xe6, "out", "imm8, AL"),
+    op(0xe7, "out", "imm8, AX"),
+    op(0xe8, "call", "rel16"),
+    op(0xe9, "jmp", "rel16"),
+    op(0xea, "jmp", "ptr16:16"),
+    op(0xeb, "jmp", "rel8"),
+    op(0xec, "in", "AL, DX"),
+    op(0xed, "in", "AX, DX"),
+    op(0xee, "out", "DX, AL"),
+    op(0xef, "out", "DX, AX"),
+    op(0xf0, "lock", ""),
+    op(0xf2, "repne", ""),
+    op(0xf3, "rep", ""),
+    op(0xf4, "hlt", ""),
+    op(0xf5, "cmc", ""),
+    group(0xf6, 0, "test", "r/m8, imm8"),
+    group(0xf6, 2, "not", "r/m8"),
+    group(0xf6, 3, "neg", "r/m8"),
+    group(0xf6, 4, "mul", "r/m8"),
+    group(0xf6, 5, "imul", "r/m8"),
+    group(0xf6, 6, "div", "r/m8"),
+    group(0xf6, 7, "idiv", "r/m8"),
+    group(0xf7, 0, "test", "r/m16, imm16"),
+    group(0xf7, 2, "not", "r/m16"),
+    group(0xf7, 3, "neg", "r/m16"),
+    group(0xf7, 4, "mul", "r/m16"),
+    group(0xf7, 5, "imul", "r/m16"),
+    group(0xf7, 6, "div", "r/m16"),
+    group(0xf7, 7, "idiv", "r/m16"),
+    op(0xf8, "clc", ""),
+    op(0xf9, "stc", ""),
+    op(0xfa, "cli", ""),
+    op(0xfb, "sti", ""),
+    op(0xfc, "cld", ""),
+    op(0xfd, "std", ""),
+    group(0xfe, 0, "inc", "r/m8"),
+    group(0xfe, 1, "dec", "r/m8"),
+    group(0xff, 0, "inc", "r/m16"),
+    group(0xff, 1, "dec", "r/m16"),
+    group(0xff, 2, "call", "r/m16"),
+    group(0xff, 3, "call", "m16:16"),
+    group(0xff, 4, "jmp", "r/m16"),
+    group(0xff, 5, "jmp", "m16:16"),
+    group(0xff, 6, "push", "r/m16"),
+];
+
+/// Finds the row for an opcode byte; `modrm` is needed for group
+/// opcodes (80h-83h, 8Fh, C6h, C7h, D0h-D3h, F6h, F7h, FEh, FFh).
+pub fn lookup(opcode: u8, modrm: Option<u8>) -> Option<&'static OpcodeInfo> {
+    let start = OPCODES.partition_point(|info| info.opcode < opcode);
+    let rows = &OPCODES[start..];
+    let rows = &rows[..rows.iter().take_while(|info| info.opcode == opcode).count()];
+    match rows.first()?.reg {
+        None => rows.first(),
+        Some(_) => {
+            let reg = ModRm::new(modrm?).reg;
+            rows.iter().find(|info| info.reg == Some(reg))
+        }
+    }
+}
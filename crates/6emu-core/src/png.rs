@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+
+/// Runs the CRC-32 of PNG, zlib and gzip over `data` from `crc`. A
+/// checksum starts at `0xffffffff` and is inverted once all is in.
+pub fn crc32(data: &[u8], mut crc: u32) -> u32 {
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 > 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(w: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = !crc32(data, crc32(kind, 0xffffffff));
+    w.write_all(&crc.to_be_bytes())
+}
+
+// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(raw: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(raw).to_be_bytes());
+    out
+}
+
+/// Writes an 8-bit RGB image; `pixels` holds `width * height` RGB triples.
+pub fn write_rgb(w: &mut impl Write, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height * 3) as usize);
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    chunk(w, b"IHDR", &ihdr)?;
+
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks((width * 3) as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    chunk(w, b"IDAT", &zlib_stored(&raw))?;
+    chunk(w, b"IEND", &[])
+}
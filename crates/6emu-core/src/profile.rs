@@ -0,0 +1,241 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, Write},
+};
+
+use crate::png;
+
+/// Per physical address execution counts and the control flow observed
+/// between executed instructions.
+pub struct Profile {
+    pub exec_counts: Vec<u32>,
+    /// Length in bytes of every executed instruction, by address.
+    pub lengths: BTreeMap<u32, u8>,
+    /// How often execution went from one instruction to the next.
+    pub transitions: HashMap<(u32, u32), u64>,
+    entry: Option<u32>,
+    last: Option<u32>,
+}
+
+/// Straight-line run of executed instructions, `start..end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: u32,
+    pub end: u32,
+    pub instructions: usize,
+    pub count: u64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            exec_counts: vec![0; 1 << 20],
+            lengths: BTreeMap::new(),
+            transitions: HashMap::new(),
+            entry: None,
+            last: None,
+        }
+    }
+
+    pub fn record(&mut self, addr: u32, len: u8) {
+        let addr = addr & 0xfffff;
+        let c = &mut self.exec_counts[addr as usize];
+        *c = c.saturating_add(1);
+        self.lengths.insert(addr, len);
+
+        match self.last {
+            Some(prev) => *self.transitions.entry((prev, addr)).or_insert(0) += 1,
+            None => self.entry = Some(addr),
+        }
+        self.last = Some(addr);
+    }
+
+    /// `address,count` rows for every executed address.
+    pub fn write_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "address,count")?;
+        for (addr, count) in self.exec_counts.iter().enumerate() {
+            if *count > 0 {
+                writeln!(w, "0x{:05x},{}", addr, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 1024x1024 image, one pixel per address (row = address / 1024),
+    /// black for never executed through red and yellow to white on a
+    /// log scale.
+    pub fn write_heatmap_png(&self, w: &mut impl Write) -> io::Result<()> {
+        let max = self.exec_counts.iter().copied().max().unwrap_or(0);
+        let scale = ((max as f64) + 1.0).ln().max(f64::MIN_POSITIVE);
+
+        let mut pixels = Vec::with_capacity(self.exec_counts.len() * 3);
+        for &count in &self.exec_counts {
+            let heat = if count == 0 {
+                0.0
+            } else {
+                ((count as f64) + 1.0).ln() / scale
+            };
+            pixels.extend_from_slice(&Self::hot(heat));
+        }
+        png::write_rgb(w, 1024, 1024, &pixels)
+    }
+
+    fn hot(heat: f64) -> [u8; 3] {
+        if heat <= 0.0 {
+            return [0, 0, 0];
+        }
+        let v = heat * 3.0;
+        let ch = |x: f64| (x.clamp(0.0, 1.0) * 255.0) as u8;
+        // keep the coldest executed address visible against the background
+        [ch(v).max(64), ch(v - 1.0), ch(v - 2.0)]
+    }
+
+    fn next_addr(&self, addr: u32) -> u32 {
+        addr + self.lengths[&addr] as u32
+    }
+
+    // instructions that transferred control somewhere other than the
+    // following instruction at least once
+    fn jump_sources(&self) -> BTreeSet<u32> {
+        self.transitions
+            .keys()
+            .filter(|(from, to)| self.next_addr(*from) != *to)
+            .map(|(from, _)| *from)
+            .collect()
+    }
+
+    pub fn basic_blocks(&self) -> Vec<BasicBlock> {
+        let sources = self.jump_sources();
+        let mut leaders: BTreeSet<u32> = self.entry.into_iter().collect();
+        for (from, to) in self.transitions.keys() {
+            if self.next_addr(*from) != *to {
+                leaders.insert(*to);
+            }
+        }
+        for s in &sources {
+            leaders.insert(self.next_addr(*s));
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut open = false;
+        for (&addr, &len) in &self.lengths {
+            match blocks.last_mut() {
+                Some(b) if open && b.end == addr && !leaders.contains(&addr) => {
+                    b.end += len as u32;
+                    b.instructions += 1;
+                }
+                _ => blocks.push(BasicBlock {
+                    start: addr,
+                    end: addr + len as u32,
+                    instructions: 1,
+                    count: self.exec_counts[addr as usize] as u64,
+                }),
+            }
+            open = !sources.contains(&addr);
+        }
+        blocks
+    }
+
+    /// Edges between basic blocks with the number of times each was taken.
+    pub fn block_edges(&self, blocks: &[BasicBlock]) -> BTreeMap<(u32, u32), u64> {
+        let mut last_of = HashMap::new();
+        let mut addr = 0;
+        for b in blocks {
+            for (&a, _) in self.lengths.range(b.start..b.end) {
+                addr = a;
+            }
+            last_of.insert(addr, b.start);
+        }
+
+        let mut edges = BTreeMap::new();
+        for ((from, to), count) in &self.transitions {
+            if let Some(start) = last_of.get(from) {
+                *edges.entry((*start, *to)).or_insert(0) += count;
+            }
+        }
+        edges
+    }
+
+    // executed instruction starts inside the image as (offset, length, count)
+    fn image_coverage(&self, base: u32, size: u32) -> Vec<(u32, u8, u32)> {
+        self.lengths
+            .range(base..base + size)
+            .map(|(&a, &len)| (a - base, len, self.exec_counts[a as usize]))
+            .collect()
+    }
+
+    /// Execution counts of the instructions inside the loaded image
+    /// `base..base + size`, keyed by file offset.
+    pub fn write_coverage_json(&self, w: &mut impl Write, base: u32, size: u32) -> io::Result<()> {
+        let cov = self.image_coverage(base, size);
+        let covered: u32 = cov.iter().map(|(_, len, _)| *len as u32).sum();
+        writeln!(w, "{{")?;
+        writeln!(w, "\"size\":{},", size)?;
+        writeln!(w, "\"covered_bytes\":{},", covered.min(size))?;
+        writeln!(w, "\"offsets\":{{")?;
+        for (i, (offt, len, count)) in cov.iter().enumerate() {
+            let sep = if i + 1 < cov.len() { "," } else { "" };
+            writeln!(
+                w,
+                "\"{}\":{{\"length\":{},\"count\":{}}}{}",
+                offt, len, count, sep
+            )?;
+        }
+        writeln!(w, "}}")?;
+        writeln!(w, "}}")
+    }
+
+    /// lcov tracefile with one `DA` record per byte of the image, using
+    /// `offset + 1` as the line number so lcov/genhtml can consume it.
+    /// Bytes of an executed instruction carry its count, the rest 0.
+    pub fn write_lcov(&self, w: &mut impl Write, name: &str, base: u32, size: u32) -> io::Result<()> {
+        let mut bytes = vec![0u32; size as usize];
+        for (offt, len, count) in self.image_coverage(base, size) {
+            for b in offt..(offt + len as u32).min(size) {
+                bytes[b as usize] = count;
+            }
+        }
+        writeln!(w, "SF:{}", name)?;
+        for (offt, count) in bytes.iter().enumerate() {
+            writeln!(w, "DA:{},{}", offt + 1, count)?;
+        }
+        writeln!(w, "LH:{}", bytes.iter().filter(|c| **c > 0).count())?;
+        writeln!(w, "LF:{}", size)?;
+        writeln!(w, "end_of_record")
+    }
+
+    /// Graphviz description of the observed control-flow graph.
+    pub fn write_cfg_dot(&self, w: &mut impl Write, counts: bool) -> io::Result<()> {
+        let blocks = self.basic_blocks();
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box fontname=\"monospace\"];")?;
+        for b in &blocks {
+            write!(
+                w,
+                "    \"{:05x}\" [label=\"{:05x}-{:05x}\\n{} instructions",
+                b.start,
+                b.start,
+                b.end - 1,
+                b.instructions
+            )?;
+            if counts {
+                write!(w, "\\n{}x", b.count)?;
+            }
+            writeln!(w, "\"];")?;
+        }
+        for ((from, to), count) in self.block_edges(&blocks) {
+            write!(w, "    \"{:05x}\" -> \"{:05x}\"", from, to)?;
+            if counts {
+                write!(w, " [label=\"{}\"]", count)?;
+            }
+            writeln!(w, ";")?;
+        }
+        writeln!(w, "}}")
+    }
+}
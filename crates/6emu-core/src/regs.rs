@@ -6,6 +6,7 @@ use std::fmt::Display;
 //}
 use ::paste::paste;
 
+#[derive(Clone, PartialEq)]
 pub struct Flags {
     pub bi: u16,
 }
@@ -30,6 +31,29 @@ impl Flags {
         self.clear_pf();
     }
 
+    /// Sets ZF, SF and PF from a byte result and clears the ones it does
+    /// not call for.
+    pub fn set_szp8(&mut self, val: u8) {
+        self.clear_zf();
+        self.clear_sf();
+        self.clear_pf();
+        if val == 0 {
+            self.set_zf();
+        }
+        if val & 0x80 != 0 {
+            self.set_sf();
+        }
+        if val.count_ones().is_multiple_of(2) {
+            self.set_pf();
+        }
+    }
+
+    /// Replaces the flags `mask` selects with those in `flags`, as an
+    /// ALU result leaves them.
+    pub fn set_masked(&mut self, flags: u16, mask: u16) {
+        self.bi = self.bi & !mask | flags & mask;
+    }
+
     pub fn to_u16(&self) -> u16 {
         self.bi
     }
@@ -171,6 +195,7 @@ impl Display for Flags {
     }
 }
 
+#[derive(Default, Clone, PartialEq)]
 pub struct Registers {
     pub ax: u16,
     pub bx: u16,
@@ -223,6 +248,37 @@ macro_rules! getsetreg {
     };
 }
 
+// a segment register's linear base, and setters taking either the
+// segment itself or a base it must be able to hold
+macro_rules! segreg {
+    ($seg:ident) => {
+        paste! {
+        #[inline(always)]
+        pub fn [<get_ $seg>](&self) -> u32 {
+            (self.$seg as u32) << 4
+        }
+        #[inline(always)]
+        pub fn [<set_ $seg _segment>](&mut self, val: u16) {
+            self.$seg = val;
+        }
+        /// Points the register at linear address `val`, which must be
+        /// a paragraph boundary in the first megabyte.
+        pub fn [<set_ $seg _linear>](&mut self, val: u32) -> Result<(), String> {
+            self.$seg = segment_of(val)?;
+            Ok(())
+        }
+        }
+    };
+}
+
+/// The segment whose base is linear address `linear`.
+pub fn segment_of(linear: u32) -> Result<u16, String> {
+    if !linear.is_multiple_of(16) {
+        return Err(format!("0x{:x} is not on a paragraph boundary", linear));
+    }
+    u16::try_from(linear >> 4).map_err(|_| format!("0x{:x} is past the last segment", linear))
+}
+
 impl Registers {
     getsetreg!(ax, al, ah);
     getsetreg!(bx, bl, bh);
@@ -262,64 +318,9 @@ impl Registers {
         self.sp = val;
     }
 
-    #[inline(always)]
-    pub fn get_ss(&self) -> u32 {
-        (self.ss as u32) << 4
-    }
-    #[inline(always)]
-    pub fn set_ss(&mut self, val: u32) {
-        assert!(val % 16 == 0);
-        self.ss = (val >> 4) as u16;
-    }
-
-    #[inline(always)]
-    pub fn get_cs(&self) -> u32 {
-        (self.cs as u32) << 4
-    }
-    #[inline(always)]
-    pub fn set_cs(&mut self, val: u32) {
-        assert!(val % 16 == 0);
-        self.cs = (val >> 4) as u16;
-    }
-
-    #[inline(always)]
-    pub fn get_ds(&self) -> u32 {
-        (self.ds as u32) << 4
-    }
-    #[inline(always)]
-    pub fn set_ds(&mut self, val: u32) {
-        assert!(val % 16 == 0);
-        self.ds = (val >> 4) as u16;
-    }
-
-    #[inline(always)]
-    pub fn get_es(&self) -> u32 {
-        (self.es as u32) << 4
-    }
-    #[inline(always)]
-    pub fn set_es(&mut self, val: u32) {
-        assert!(val % 16 == 0);
-        self.es = (val >> 4) as u16;
-    }
+    segreg!(cs);
+    segreg!(ds);
+    segreg!(ss);
+    segreg!(es);
 }
 
-impl Default for Registers {
-    fn default() -> Self {
-        Self {
-            ip: Default::default(),
-            ax: Default::default(),
-            bx: Default::default(),
-            cx: Default::default(),
-            dx: Default::default(),
-            si: Default::default(),
-            sp: Default::default(),
-            di: Default::default(),
-            bp: Default::default(),
-            ss: Default::default(),
-            ds: Default::default(),
-            es: Default::default(),
-            cs: Default::default(),
-            flags: Flags::default(),
-        }
-    }
-}
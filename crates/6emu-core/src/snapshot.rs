@@ -0,0 +1,294 @@
+//! Saved machine state, and what changed between two saves.
+
+use std::io::{self, Read, Write};
+
+use crate::{cpu::Cpu, regs::Registers, stats::Stats};
+
+const MAGIC: &[u8; 8] = b"6EMUSNAP";
+
+/// Register names in the order they are saved and compared.
+pub const REGISTERS: [&str; 13] = [
+    "AX", "BX", "CX", "DX", "SI", "DI", "SP", "BP", "CS", "DS", "ES", "SS", "IP",
+];
+
+/// Flag names, as the dump prints them, with their bit in FLAGS.
+pub const FLAGS: [(&str, u16); 9] = [
+    ("Carry", 0),
+    ("Parity", 2),
+    ("Aux", 4),
+    ("Zero", 6),
+    ("Sign", 7),
+    ("Trap", 8),
+    ("Interrupt", 9),
+    ("Direction", 10),
+    ("Overflow", 11),
+];
+
+/// Unchanged bytes a changed memory range may span before it is split
+/// in two.
+pub const GAP: usize = 8;
+/// Bytes of each changed range shown in reports.
+pub const EXCERPT: usize = 16;
+
+/// Registers and memory at one point of a run. Devices are not saved.
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub regs: Registers,
+    pub mem: Vec<u8>,
+}
+
+/// A snapshot taken partway through a run, with what the CPU had
+/// counted and printed by then, so the run can be taken up again from
+/// it.
+#[derive(Clone)]
+pub struct Checkpoint {
+    pub snapshot: Snapshot,
+    pub stats: Stats,
+    output: usize,
+    faults: usize,
+}
+
+impl Checkpoint {
+    pub fn of(cpu: &Cpu) -> Self {
+        Self {
+            snapshot: Snapshot::of(cpu),
+            stats: cpu.stats.clone(),
+            output: cpu.output.len(),
+            faults: cpu.faults.len(),
+        }
+    }
+
+    /// Rewinds `cpu` to the checkpoint, dropping the output written and
+    /// faults met since. Devices are left as they are.
+    pub fn restore(&self, cpu: &mut Cpu) {
+        self.snapshot.restore(cpu);
+        cpu.stats = self.stats.clone();
+        cpu.output.truncate(self.output);
+        cpu.faults.truncate(self.faults);
+        cpu.halt = false;
+        cpu.exit_reason = None;
+        cpu.host_int = None;
+        cpu.flush_queue();
+    }
+}
+
+/// A run of memory that differs, from `start` (physical).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemChange {
+    pub start: u32,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+/// What differs between two snapshots, as `(name, before, after)`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Diff {
+    pub registers: Vec<(&'static str, u16, u16)>,
+    pub flags: Vec<(&'static str, bool, bool)>,
+    pub memory: Vec<MemChange>,
+}
+
+fn words(regs: &Registers) -> [u16; 14] {
+    [
+        regs.ax,
+        regs.bx,
+        regs.cx,
+        regs.dx,
+        regs.si,
+        regs.di,
+        regs.sp,
+        regs.bp,
+        regs.cs,
+        regs.ds,
+        regs.es,
+        regs.ss,
+        regs.ip,
+        regs.flags.to_u16(),
+    ]
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let excerpt: Vec<String> = bytes.iter().take(EXCERPT).map(|b| format!("{:02x}", b)).collect();
+    let more = if bytes.len() > EXCERPT { " .." } else { "" };
+    format!("{}{}", excerpt.join(" "), more)
+}
+
+impl Snapshot {
+    pub fn of(cpu: &Cpu) -> Self {
+        Self {
+            regs: cpu.regs.clone(),
+            mem: cpu.mem.cursor.get_ref().clone(),
+        }
+    }
+
+    /// Puts the registers and memory back as they were saved.
+    pub fn restore(&self, cpu: &mut Cpu) {
+        cpu.regs = self.regs.clone();
+        cpu.mem.cursor.get_mut().copy_from_slice(&self.mem);
+        if let Some(dirty) = &mut cpu.dirty {
+            dirty.mark_all();
+        }
+    }
+
+    /// The magic, the registers and FLAGS as little-endian words, the
+    /// memory size as a 32-bit word and the memory.
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        for word in words(&self.regs) {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        w.write_all(&(self.mem.len() as u32).to_le_bytes())?;
+        w.write_all(&self.mem)
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        match r.read_exact(&mut magic) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(invalid("not a snapshot")),
+            res => res?,
+        }
+        if &magic != MAGIC {
+            return Err(invalid("not a snapshot"));
+        }
+        let mut words = [0u16; 14];
+        for word in words.iter_mut() {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf)?;
+            *word = u16::from_le_bytes(buf);
+        }
+        let mut len = [0; 4];
+        r.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > 1 << 20 {
+            return Err(invalid("memory larger than 1 MiB"));
+        }
+        let mut mem = vec![0; len];
+        r.read_exact(&mut mem)?;
+
+        let [ax, bx, cx, dx, si, di, sp, bp, cs, ds, es, ss, ip, flags] = words;
+        let mut regs = Registers {
+            ax,
+            bx,
+            cx,
+            dx,
+            si,
+            di,
+            sp,
+            bp,
+            es,
+            ds,
+            cs,
+            ss,
+            ip,
+            ..Registers::default()
+        };
+        regs.flags.set_from_u16(flags);
+        Ok(Self { regs, mem })
+    }
+
+    /// What changed from `self` to `after`. Changed bytes closer than
+    /// `GAP` are reported as one range.
+    pub fn diff(&self, after: &Snapshot) -> Diff {
+        let (old, new) = (words(&self.regs), words(&after.regs));
+        let registers = REGISTERS
+            .iter()
+            .zip(old.iter().zip(new))
+            .filter(|(_, (a, b))| *a != b)
+            .map(|(&name, (&a, b))| (name, a, b))
+            .collect();
+        let flags = FLAGS
+            .iter()
+            .map(|&(name, bit)| (name, old[13] & 1 << bit != 0, new[13] & 1 << bit != 0))
+            .filter(|(_, a, b)| a != b)
+            .collect();
+
+        let byte = |mem: &[u8], at: usize| mem.get(at).copied().unwrap_or(0);
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for at in (0..self.mem.len().max(after.mem.len())).filter(|&at| byte(&self.mem, at) != byte(&after.mem, at)) {
+            match ranges.last_mut() {
+                Some((_, end)) if at - *end <= GAP => *end = at + 1,
+                _ => ranges.push((at, at + 1)),
+            }
+        }
+        let memory = ranges
+            .into_iter()
+            .map(|(start, end)| MemChange {
+                start: start as u32,
+                before: (start..end).map(|at| byte(&self.mem, at)).collect(),
+                after: (start..end).map(|at| byte(&after.mem, at)).collect(),
+            })
+            .collect();
+        Diff {
+            registers,
+            flags,
+            memory,
+        }
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.flags.is_empty() && self.memory.is_empty()
+    }
+
+    /// One line per change: `AX 0001 -> 0002`, `Carry false -> true`,
+    /// `00100+5 01 02 .. -> 03 04 ..`.
+    pub fn write_text(&self, w: &mut impl Write) -> io::Result<()> {
+        for (name, before, after) in &self.registers {
+            writeln!(w, "{:<5} {:04x} -> {:04x}", name, before, after)?;
+        }
+        for (name, before, after) in &self.flags {
+            writeln!(w, "{:<5} {} -> {}", name, before, after)?;
+        }
+        for change in &self.memory {
+            writeln!(
+                w,
+                "{:05x}+{} {} -> {}",
+                change.start,
+                change.before.len(),
+                hex(&change.before),
+                hex(&change.after)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// `{"registers": {"AX": [1, 2]}, "flags": {"Carry": [false, true]},
+    /// "memory": [{"at": 256, "length": 5, "before": "01 02 ..", ...}]}`.
+    pub fn write_json(&self, w: &mut impl Write) -> io::Result<()> {
+        let registers: Vec<String> = self
+            .registers
+            .iter()
+            .map(|(name, before, after)| format!("\"{}\":[{},{}]", name, before, after))
+            .collect();
+        let flags: Vec<String> = self
+            .flags
+            .iter()
+            .map(|(name, before, after)| format!("\"{}\":[{},{}]", name, before, after))
+            .collect();
+        let memory: Vec<String> = self
+            .memory
+            .iter()
+            .map(|change| {
+                format!(
+                    "{{\"at\":{},\"length\":{},\"before\":\"{}\",\"after\":\"{}\"}}",
+                    change.start,
+                    change.before.len(),
+                    hex(&change.before),
+                    hex(&change.after)
+                )
+            })
+            .collect();
+        writeln!(
+            w,
+            "{{\"registers\":{{{}}},\"flags\":{{{}}},\"memory\":[{}]}}",
+            registers.join(","),
+            flags.join(","),
+            memory.join(",")
+        )
+    }
+}
@@ -0,0 +1,34 @@
+/// Event counters collected while the cpu runs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub instructions: u64,
+    pub mem_reads8: u64,
+    pub mem_reads16: u64,
+    pub mem_writes8: u64,
+    pub mem_writes16: u64,
+    pub port_reads: u64,
+    pub port_writes: u64,
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    pub interrupts: u64,
+    pub string_iterations: u64,
+}
+
+impl Stats {
+    /// Counter names paired with their values, in a stable order.
+    pub fn fields(&self) -> [(&'static str, u64); 11] {
+        [
+            ("instructions", self.instructions),
+            ("mem_reads8", self.mem_reads8),
+            ("mem_reads16", self.mem_reads16),
+            ("mem_writes8", self.mem_writes8),
+            ("mem_writes16", self.mem_writes16),
+            ("port_reads", self.port_reads),
+            ("port_writes", self.port_writes),
+            ("branches_taken", self.branches_taken),
+            ("branches_not_taken", self.branches_not_taken),
+            ("interrupts", self.interrupts),
+            ("string_iterations", self.string_iterations),
+        ]
+    }
+}
@@ -0,0 +1,2666 @@
+#[cfg(feature = "explain")]
+use crate::explain;
+#[cfg(feature = "profile")]
+use crate::{branches::BranchProfile, profile::Profile};
+#[cfg(feature = "trace")]
+use crate::trace::{Recording, Timeline, TimelineEvent};
+use crate::{
+    asm::assemble,
+    cpu::{self, Cpu, Instruction, Opcode, Operand},
+    decode,
+    machine::{Device, Irq, Machine},
+    regs::{segment_of, Flags, Registers},
+};
+
+#[test]
+fn flow1() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov ax, 0
+        mov cx, 1
+        cmp ax, cx
+        ja above
+        jmp done
+    above:
+        mov ax, 69
+    done:",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 0);
+}
+
+#[test]
+fn loopy() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov cx, 20
+        xor ax, ax
+        mov [si], ax
+    again:
+        inc ax
+        inc word [si]
+        loop again
+        add ax, [si]",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 40);
+}
+
+#[test]
+fn flow0() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov ax, 1
+        mov cx, 0
+        cmp ax, cx
+        ja above
+        jmp done
+    above:
+        mov ax, 69
+    done:",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 69);
+}
+
+#[test]
+fn stack() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&vec![
+        184, 70, 0, 185, 45, 0, 186, 89, 0, 187, 132, 3, 83, 82, 81, 80, 91, 90, 89, 88,
+    ]);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 900);
+    assert_eq!(cpu.regs.cx, 89);
+    assert_eq!(cpu.regs.dx, 45);
+    assert_eq!(cpu.regs.bx, 70);
+}
+
+#[test]
+fn inc_dec_bx_dx() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&assemble("mov bx, 10\nmov dx, 20\ninc bx\ninc bx\ndec dx").unwrap());
+    cpu.fire();
+    assert_eq!(cpu.regs.bx, 12);
+    assert_eq!(cpu.regs.dx, 19);
+}
+
+#[test]
+fn memstuff() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[198, 4, 0, 198, 68, 1, 1, 139, 4]);
+    cpu.fire();
+    assert_eq!(cpu.regs.get_ax(), 256);
+}
+
+#[test]
+fn addmem16() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[199, 4, 0, 0, 131, 192, 70, 1, 4, 139, 4]);
+    cpu.fire();
+    assert_eq!(cpu.regs.get_ax(), 70);
+}
+
+#[test]
+fn subreg8() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&vec![
+        128, 236, 67, 40, 224, 40, 197, 40, 233, 40, 207, 40, 251, 40, 222, 40, 242,
+    ]);
+    cpu.fire();
+    assert!(cpu.regs.get_dl() == 67);
+}
+
+#[test]
+fn addreg8() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&vec![
+        128, 196, 67, 0, 224, 0, 197, 0, 233, 0, 207, 0, 251, 0, 222, 0, 242,
+    ]);
+    cpu.fire();
+    assert!(cpu.regs.get_dl() == 67);
+}
+
+#[test]
+fn addreg16() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[131, 192, 67, 1, 200, 1, 195, 1, 218]);
+    cpu.fire();
+    assert!(cpu.regs.dx == 67);
+}
+
+#[test]
+fn addregimm16() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&vec![
+        131, 192, 67, 129, 193, 207, 7, 131, 195, 120, 129, 194, 0, 3,
+    ]);
+    cpu.fire();
+    assert!(cpu.regs.ax == 67);
+    assert!(cpu.regs.cx == 1999);
+    assert!(cpu.regs.bx == 120);
+    assert!(cpu.regs.dx == 768);
+}
+
+#[test]
+fn addregimm8() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&vec![
+        4, 1, 128, 196, 1, 128, 193, 1, 128, 197, 1, 128, 195, 1, 128, 199, 1, 128, 194, 1, 128,
+        198, 1,
+    ]);
+    cpu.fire();
+    assert!(cpu.regs.ax == 0x101);
+    assert!(cpu.regs.bx == 0x101);
+    assert!(cpu.regs.cx == 0x101);
+    assert!(cpu.regs.dx == 0x101);
+}
+
+#[test]
+fn test_mode() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[140, 209, 137, 224]);
+    cpu.fire();
+    assert_eq!(cpu.regs.sp, 4095);
+    assert_eq!(cpu.regs.get_ss(), 4096)
+}
+
+#[test]
+pub fn test1() {
+    let mut regs = Registers::default();
+    regs.set_ax(30000);
+    assert_eq!(regs.get_ax(), 30000);
+    assert_eq!(regs.get_al() as u16, 30000 & 0xff);
+    assert_eq!(regs.get_ah() as u16, (30000 >> 8));
+    regs.set_ah(40);
+    assert_eq!(regs.get_ah(), 40);
+    assert_eq!(regs.get_al() as u16, 30000 & 0xff);
+    regs.set_al(69);
+    assert_eq!(regs.get_al(), 69);
+    assert_eq!(regs.get_ah(), 40);
+
+    regs.set_bx(30000);
+    assert_eq!(regs.get_bx(), 30000);
+    assert_eq!(regs.get_bl() as u16, 30000 & 0xff);
+    assert_eq!(regs.get_bh() as u16, (30000 >> 8));
+    regs.set_bh(40);
+    assert_eq!(regs.get_bh(), 40);
+    assert_eq!(regs.get_bl() as u16, 30000 & 0xff);
+    regs.set_bl(69);
+    assert_eq!(regs.get_bl(), 69);
+    assert_eq!(regs.get_bh(), 40);
+}
+
+#[test]
+pub fn test2() {
+    let mut f: Flags = Flags::default();
+    assert_eq!(f.bi, 2);
+    f.set_cf();
+    assert!(f.bi == 3);
+    assert!(f.cf());
+    f.set_af();
+    assert!(f.af());
+    assert!(f.cf());
+    f.set_df();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    f.set_if();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    f.set_of();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    assert!(f.of());
+    f.set_pf();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    assert!(f.of());
+    assert!(f.pf());
+    f.set_sf();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    assert!(f.of());
+    assert!(f.pf());
+    assert!(f.sf());
+    f.set_tf();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    assert!(f.of());
+    assert!(f.pf());
+    assert!(f.sf());
+    assert!(f.tf());
+    f.set_zf();
+    assert!(f.af());
+    assert!(f.cf());
+    assert!(f.df());
+    assert!(f.i_f());
+    assert!(f.of());
+    assert!(f.pf());
+    assert!(f.sf());
+    assert!(f.sf());
+    assert!(f.zf());
+
+    f.clear_cf();
+    assert!(!f.cf());
+    f.clear_af();
+    assert!(!f.af());
+    assert!(!f.cf());
+    f.clear_df();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    f.clear_if();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    f.clear_of();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    assert!(!f.of());
+    f.clear_pf();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    assert!(!f.of());
+    assert!(!f.pf());
+    f.clear_sf();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    assert!(!f.of());
+    assert!(!f.pf());
+    assert!(!f.sf());
+    f.clear_tf();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    assert!(!f.of());
+    assert!(!f.pf());
+    assert!(!f.tf());
+    assert!(!f.sf());
+    f.clear_zf();
+    assert!(!f.af());
+    assert!(!f.cf());
+    assert!(!f.df());
+    assert!(!f.i_f());
+    assert!(!f.of());
+    assert!(!f.pf());
+    assert!(!f.sf());
+    assert!(!f.sf());
+    assert!(!f.zf());
+}
+
+#[test]
+fn cmp() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0;
+    cpu.execute(&Instruction::new(Opcode::Cmp, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert!(cpu.regs.flags.zf());
+    assert!(cpu.regs.flags.zf());
+
+    cpu.regs.ax = 1;
+    cpu.regs.cx = 2;
+    cpu.execute(&Instruction::new(Opcode::Cmp, Operand::Reg8(0), Operand::Reg8(1)));
+
+    assert!(cpu.regs.flags.cf());
+    assert!(cpu.regs.flags.sf());
+}
+
+#[test]
+fn aas() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0x2ff;
+    cpu.execute(&Instruction::new(Opcode::Aas, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.get_ah(), 1);
+    assert_eq!(cpu.regs.get_al(), 9);
+}
+
+#[test]
+fn aaa() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0xf;
+    cpu.execute(&Instruction::new(Opcode::Aaa, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.get_ah(), 1);
+    assert_eq!(cpu.regs.get_al(), 5);
+}
+
+#[test]
+fn das() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0xff;
+    cpu.execute(&Instruction::new(Opcode::Das, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.get_al(), 0x99);
+    assert!(cpu.regs.flags.cf())
+}
+
+#[test]
+fn daa() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0xf;
+    cpu.execute(&Instruction::new(Opcode::Daa, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.get_al(), 0x15)
+}
+
+#[test]
+fn ov_ss() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // ss: mov ax, [bx]
+    cpu.load_code_vec(&[0x36, 0x8b, 0x07]);
+    cpu.regs.set_ds_segment(0x40);
+    cpu.regs.set_ss_segment(0x100);
+    cpu.regs.bx = 0x10;
+    let inst = cpu.fetch().unwrap();
+    assert_eq!(inst.prefixes().segment, Some(cpu::Segment::Ss));
+    let ea = cpu::Ea {
+        base: Some(3),
+        index: None,
+        disp: 0,
+        seg: cpu::Segment::Ss,
+    };
+    assert_eq!(inst.operands(), (Operand::Reg16(0), Operand::Ea16(ea)));
+    assert_eq!(cpu.resolve(inst.operands().1), Operand::Mem16(0x1010, 0x10));
+    // the override went with the instruction
+    assert_eq!((cpu.seg_override, cpu.regs.ip), (None, 3));
+}
+
+#[test]
+fn and() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 255;
+    cpu.execute(&Instruction::new(Opcode::And, Operand::Reg8(0), Operand::Reg8(2)));
+    assert_eq!(cpu.regs.ax, 0);
+    assert!(cpu.regs.flags.zf());
+    assert!(cpu.regs.flags.pf());
+}
+
+#[test]
+fn or2() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 255;
+    cpu.execute(&Instruction::new(Opcode::Or, Operand::Reg8(0), Operand::Reg8(1)));
+    assert_eq!(cpu.regs.ax, 255);
+
+    assert!(!cpu.regs.flags.zf());
+    assert!(cpu.regs.flags.pf());
+}
+
+#[test]
+fn xor() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 255;
+    cpu.execute(&Instruction::new(Opcode::Xor, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.ax, 0);
+    assert!(cpu.regs.flags.zf());
+    assert!(cpu.regs.flags.pf());
+}
+
+#[test]
+fn push_pop_ds() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0);
+    cpu.regs.set_ss_segment(0x100);
+    cpu.regs.set_es_segment(0x2);
+    cpu.regs.sp = 64;
+    cpu.regs.ds = 128;
+    cpu.execute(&Instruction::new(Opcode::PushDs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.sp, 62);
+    assert_eq!(cpu.read_mem_u16(cpu.stack_addr(cpu.regs.sp)), 128);
+    cpu.write_mem_u16(cpu.stack_addr(cpu.regs.sp), 64);
+    let sp = cpu.regs.sp;
+    cpu.execute(&Instruction::new(Opcode::PopDs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.ds, 64);
+    assert_eq!(cpu.regs.sp - sp, 2);
+}
+
+#[test]
+fn sbb() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_ss_segment(0x100);
+
+    cpu.regs.flags.set_cf();
+    assert!(cpu.regs.flags.cf());
+
+    cpu.execute(&Instruction::new(Opcode::Sbb, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.ax as i8, -1);
+}
+
+#[test]
+fn push_pop_ss() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0);
+    cpu.regs.set_ss_segment(0x100);
+    cpu.regs.set_es_segment(0x2);
+    cpu.regs.sp = 64;
+    cpu.regs.ss = 128;
+    cpu.execute(&Instruction::new(Opcode::PushSs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.sp, 62);
+    assert_eq!(cpu.read_mem_u16(cpu.stack_addr(cpu.regs.sp)), 128);
+    cpu.write_mem_u16(cpu.stack_addr(cpu.regs.sp), 64);
+    let sp = cpu.regs.sp;
+    cpu.execute(&Instruction::new(Opcode::PopSs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.ss, 64);
+    assert_eq!(cpu.regs.sp - sp, 2);
+}
+
+#[test]
+fn wa() {
+    let mut a: i32 = 90;
+    a = a.wrapping_add(1);
+    assert!(a == 91);
+}
+
+#[test]
+fn a() {
+    let mut cpu = Cpu::init();
+    let cs = cpu.regs.cs;
+    let err = |why: &str| Err(why.to_string());
+    assert_eq!(cpu.regs.set_cs_linear(3), err("0x3 is not on a paragraph boundary"));
+    assert_eq!(cpu.regs.set_cs_linear(0x100000), err("0x100000 is past the last segment"));
+    assert_eq!(cpu.regs.set_ds_linear(1024 * 64), Ok(()));
+    assert_eq!(cpu.regs.set_ss_linear(1024 * 128), Ok(()));
+    assert_eq!(cpu.regs.set_es_linear(1024 * 196), Ok(()));
+    // a refused base leaves the register as it was
+    assert_eq!((cpu.regs.cs, cpu.regs.ds, cpu.regs.ss, cpu.regs.es), (cs, 0x1000, 0x2000, 0x3100));
+    assert_eq!(cpu.regs.set_ds_linear(0x10008), err("0x10008 is not on a paragraph boundary"));
+    assert_eq!(cpu.regs.set_ss_linear(0x100010), err("0x100010 is past the last segment"));
+    assert_eq!(cpu.regs.set_es_linear(0xfffff), err("0xfffff is not on a paragraph boundary"));
+    assert_eq!((cpu.regs.ds, cpu.regs.ss, cpu.regs.es), (0x1000, 0x2000, 0x3100));
+
+    let err = |why: &str| Err(why.to_string());
+    assert_eq!(segment_of(0), Ok(0));
+    assert_eq!(segment_of(0xffff0), Ok(0xffff));
+    assert_eq!(segment_of(0x100000), err("0x100000 is past the last segment"));
+    assert_eq!(segment_of(0xffffffff), err("0xffffffff is not on a paragraph boundary"));
+    assert_eq!(segment_of(0xfffffff0), err("0xfffffff0 is past the last segment"));
+}
+#[test]
+fn b() {
+    let mut cpu = Cpu::init();
+    cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0x1000);
+    cpu.regs.set_ss_segment(0x2000);
+    cpu.regs.set_es_segment(0x3100);
+
+    assert!(cpu.code_addr(0) == 0);
+    assert!(cpu.code_addr(5) == 5);
+    assert!(cpu.code_addr(0xffff) == 0xffff);
+
+    cpu.regs.set_cs_linear(0xffff + 1).unwrap();
+    assert!(cpu.code_addr(0) == 0xffff + 1_u32);
+
+    cpu.regs.set_cs_segment(0xffff);
+    assert!(cpu.code_addr(0x10) == 0);
+}
+
+#[test]
+fn c() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0x2, 0x2e, 0x50, 0x0, 0x0, 0xc0, 0x0, 0xc9, 0x0, 0xe4, 0x0, 0xdb, 0x0, 0xff, 0x0, 0xed,
+        0x0, 0xc9, 0x1, 0xc0, 0x1, 0xdb, 0x1, 0xc9, 0x1, 0xd2, 0x1, 0x6, 0x5a, 0x0, 0x3, 0x4, 0x1,
+        0xf6, 0x1, 0xff, 0x1, 0xed, 0x1, 0xe4, 0x1, 0x4, 0x1, 0x1d,
+    ]);
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+
+    //cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0);
+    cpu.regs.set_ss_segment(0);
+    cpu.regs.set_es_segment(0);
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    //assert_eq!(b1.operands(), (Operand::Reg8(5), Operand::Mem8(80)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(0), Operand::Reg8(0)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(1), Operand::Reg8(1)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(4), Operand::Reg8(4)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(3), Operand::Reg8(3)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(7), Operand::Reg8(7)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(5), Operand::Reg8(5)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg8(1), Operand::Reg8(1)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(0), Operand::Reg16(0)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(3), Operand::Reg16(3)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(1), Operand::Reg16(1)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(2), Operand::Reg16(2)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    //assert_eq!(b1.operands(), (Operand::Mem16(90), Operand::Reg16(0)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    //assert_eq!(b1.operands(), (Operand::Reg16(0), Operand::Mem16(0)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(6), Operand::Reg16(6)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(7), Operand::Reg16(7)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(5), Operand::Reg16(5)));
+
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    assert_eq!(b1.operands(), (Operand::Reg16(4), Operand::Reg16(4)));
+
+    cpu.regs.set_si(90);
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    //assert_eq!(b1.operands(), (Operand::Mem16(90), Operand::Reg16(0)));
+
+    cpu.regs.set_di(90);
+    let b1 = cpu.fetch().unwrap();
+    assert!(b1.opcode() == Opcode::Add);
+    //assert_eq!(b1.operands(), (Operand::Mem16(90), Operand::Reg16(3)));
+}
+
+#[test]
+fn add() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+
+    cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0);
+    cpu.regs.set_ss_segment(0);
+    cpu.regs.set_es_segment(0);
+
+    cpu.execute(&Instruction::new(Opcode::Add, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert!(cpu.regs.flags.zf());
+
+    cpu.regs.set_ax(255);
+    cpu.execute(&Instruction::new(Opcode::Add, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert!(cpu.regs.flags.af());
+    assert!(cpu.regs.flags.cf());
+    assert!(!cpu.regs.flags.of());
+    assert!(cpu.regs.flags.sf());
+
+    cpu.regs.set_ax(70);
+    cpu.execute(&Instruction::new(Opcode::Add, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert!(cpu.regs.flags.of());
+
+    let a = -127i8;
+
+    assert!(a.overflowing_add(a).1);
+
+    cpu.regs.set_ax(a as u16);
+    cpu.execute(&Instruction::new(Opcode::Add, Operand::Reg8(0), Operand::Reg8(0)));
+    assert!(cpu.regs.flags.of());
+}
+
+#[test]
+fn push_pop_es() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_cs_segment(0);
+    cpu.regs.set_ds_segment(0);
+    cpu.regs.set_ss_segment(0x100);
+    cpu.regs.set_es_segment(0x2);
+    cpu.regs.sp = 64;
+    cpu.execute(&Instruction::new(Opcode::PushEs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.sp, 62);
+    assert_eq!(cpu.read_mem_u16(cpu.stack_addr(cpu.regs.sp)), 2);
+    cpu.write_mem_u16(cpu.stack_addr(cpu.regs.sp), 64);
+    let sp = cpu.regs.sp;
+    cpu.execute(&Instruction::new(Opcode::PopEs, Operand::Reg8(0), Operand::Reg8(0)));
+    assert_eq!(cpu.regs.es, 64);
+    assert_eq!(cpu.regs.sp - sp, 2);
+}
+
+#[test]
+fn or() {
+    let mut cpu = Cpu::init();
+    cpu.regs.ax = 0b11;
+    cpu.regs.cx = 0b1100;
+
+    cpu.execute(&Instruction::new(Opcode::Or, Operand::Reg8(0), Operand::Reg8(1)));
+
+    assert_eq!(cpu.regs.ax, 0b1111);
+    assert!(cpu.regs.flags.pf());
+    assert!(!cpu.regs.flags.zf());
+
+    cpu.regs.ax = 0b00;
+    cpu.regs.cx = 0b00;
+    cpu.execute(&Instruction::new(Opcode::Or, Operand::Reg8(0), Operand::Reg8(1)));
+
+    assert_eq!(cpu.regs.ax, 0b0);
+    assert!(cpu.regs.flags.pf());
+    assert!(cpu.regs.flags.zf());
+}
+
+#[test]
+fn push_cs() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_ss_segment(0x100);
+    cpu.regs.cs = 90;
+    cpu.execute(&Instruction::new(Opcode::PushCs, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.read_mem_u16(cpu.stack_addr(cpu.regs.sp)), 90);
+}
+
+#[test]
+fn adc() {
+    let mut cpu = Cpu::init();
+    cpu.mem.seek_to(cpu.code_addr(0) as u64);
+    cpu.regs.set_ss_segment(0x100);
+    //cpu.regs.cs = 90;
+    cpu.regs.ax = 255;
+    cpu.execute(&Instruction::new(Opcode::Add, Operand::Reg8(0), Operand::Reg8(0)));
+
+    cpu.regs.ax = 0;
+
+    cpu.execute(&Instruction::new(Opcode::Adc, Operand::Reg8(0), Operand::Reg8(0)));
+
+    assert_eq!(cpu.regs.ax, 1);
+}
+
+#[test]
+fn harness_output() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.hypercall = Some(0xff);
+    // mov al,'h'; out 0xe9,al; mov ah,0; mov al,'i'; int 0xff;
+    // mov ax,0x0203; int 0xff; mov al,'x'; out 0xe9,al
+    cpu.load_code_vec(&[
+        0xb0, 0x68, 0xe6, 0xe9, 0xb4, 0x00, 0xb0, 0x69, 0xcd, 0xff, 0xb8, 0x03, 0x02, 0xcd, 0xff,
+        0xb0, 0x78, 0xe6, 0xe9,
+    ]);
+    cpu.fire();
+    assert_eq!(cpu.output, b"hi");
+    assert_eq!(cpu.exit_code, Some(3));
+    assert!(cpu.halt);
+}
+
+#[test]
+fn hypercall_puts() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.hypercall = Some(0xff);
+    for (i, c) in b"hello\0".iter().enumerate() {
+        cpu.write_mem_u8(0x200 + i as u32, *c);
+    }
+    // mov dx,0x200; mov ah,1; int 0xff
+    cpu.load_code_vec(&[0xba, 0x00, 0x02, 0xb4, 0x01, 0xcd, 0xff]);
+    cpu.fire();
+    assert_eq!(cpu.output, b"hello");
+    assert_eq!(cpu.exit_code, None);
+
+    // with no NUL in DS it stops after going round it once
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.hypercall = Some(0xff);
+    cpu.regs.ds = 0x2000;
+    cpu.mem.cursor.get_mut()[0x20000..0x30000].fill(b'x');
+    cpu.load_code_vec(&[0xba, 0x00, 0x02, 0xb4, 0x01, 0xcd, 0xff, 0xf4]);
+    cpu.fire();
+    assert!(cpu.halt);
+    assert_eq!(cpu.output.len(), cpu::HYPERCALL_PUTS_LIMIT);
+    assert!(cpu.output.iter().all(|&c| c == b'x'));
+    assert_eq!(cpu.faults, ["hypercall puts: no NUL in the 0x10000 bytes from 2000:0200"]);
+}
+
+#[test]
+fn hypercall_getchar() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.hypercall = Some(0xff);
+    cpu.input.extend(b"k");
+    // mov ah,3; int 0xff; mov bl,al; int 0xff
+    cpu.load_code_vec(&[0xb4, 0x03, 0xcd, 0xff, 0x88, 0xc3, 0xcd, 0xff]);
+    cpu.fire();
+    assert_eq!(cpu.regs.get_bl(), b'k');
+    // nothing left to read
+    assert_eq!(cpu.regs.get_al(), 0);
+}
+
+#[test]
+fn host_interrupt() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(0x13);
+    // int 0x13
+    cpu.load_code_vec(&[0xcd, 0x13]);
+    let sp = cpu.regs.sp;
+    let i = cpu.fetch().unwrap();
+    cpu.execute(&i);
+    assert_eq!(cpu.host_int, Some(0x13));
+    assert_eq!(cpu.regs.sp, sp);
+    assert_eq!(cpu.regs.ip, 2);
+}
+
+// raises IRQ 0 the given number of instructions after a write to port
+// 0x40, and keeps the last byte written to D000:0000-000F
+struct Alarm {
+    due: Option<u64>,
+    latch: u8,
+}
+
+impl Device for Alarm {
+    fn name(&self) -> &str {
+        "alarm"
+    }
+
+    fn ports(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x40..=0x40]
+    }
+
+    fn mmio(&self) -> Vec<(u32, u32)> {
+        vec![(0xd0000, 0xd0010)]
+    }
+
+    fn irqs(&self) -> Vec<u8> {
+        vec![0]
+    }
+
+    fn port_write(&mut self, _port: u16, val: u8, now: u64, _irq: &mut Irq) {
+        self.due = Some(now + val as u64 * cpu::CLOCKS_PER_INSTRUCTION);
+    }
+
+    fn mem_read(&mut self, addr: u32, _now: u64) -> u8 {
+        self.latch ^ addr as u8
+    }
+
+    fn mem_write(&mut self, _addr: u32, val: u8, _now: u64) {
+        self.latch = val;
+    }
+
+    fn tick(&mut self, now: u64, irq: &mut Irq) {
+        if self.due.is_some_and(|due| now >= due) {
+            self.due = None;
+            irq.raise(0);
+        }
+    }
+}
+
+#[test]
+fn machine_devices() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov word [0x100], tick
+        mov word [0x102], 0
+        mov ax, 0xd000
+        mov es, ax
+        mov word [es:4], 0x1234
+        mov dx, [es:1]
+        mov al, 3
+        out 0x40, al
+        sti
+    idle:
+        cmp bx, 0
+        je idle
+        hlt
+    tick:
+        inc bx
+        iret",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    let mut machine = Machine::new(cpu);
+    // the program sits where IRQ 0's usual vector is
+    machine.bus.irq_base = 0x40;
+    machine.attach(Box::new(Alarm { due: None, latch: 0 })).unwrap();
+    assert_eq!(machine.run_until(|_| false), cpu::RunExit::Halted);
+    // words reach the device a byte at a time, low byte first
+    assert_eq!(machine.cpu.regs.dx, 0x1013);
+    assert_eq!(machine.cpu.regs.bx, 1);
+    assert_eq!(machine.bus.pending(), 0);
+    assert_eq!(machine.cpu.read_mem_u8(0xd0004), 0x12 ^ 4);
+
+    // a second device cannot claim what the first has
+    let clash = machine.attach(Box::new(Alarm { due: None, latch: 0 }));
+    assert_eq!(clash, Err("alarm wants I/O ports alarm already has".to_string()));
+    assert_eq!(machine.bus.names(), ["alarm"]);
+}
+
+#[test]
+fn stats_counters() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov cx,3; mov di,0x200; mov al,7; rep stosb; out 0xe9,al;
+    // jcxz +0; inc cx; jcxz +0
+    cpu.load_code_vec(&[
+        0xb9, 0x03, 0x00, 0xbf, 0x00, 0x02, 0xb0, 0x07, 0xf3, 0xaa, 0xe6, 0xe9, 0xe3, 0x00, 0x41,
+        0xe3, 0x00,
+    ]);
+    cpu.fire();
+    assert_eq!(cpu.stats.string_iterations, 3);
+    assert_eq!(cpu.stats.mem_writes8, 3);
+    assert_eq!(cpu.stats.port_writes, 1);
+    assert_eq!(cpu.stats.branches_taken, 1);
+    assert_eq!(cpu.stats.branches_not_taken, 1);
+    assert_eq!(cpu.read_mem_u8(0x202), 7);
+}
+
+#[cfg(feature = "profile")]
+#[test]
+fn profile_heatmap() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.profile = Some(Profile::new());
+    // mov cx,3; l: loop l
+    cpu.load_code_vec(&[0xb9, 0x03, 0x00, 0xe2, 0xfe]);
+    cpu.fire();
+    let profile = cpu.profile.as_ref().unwrap();
+    assert_eq!(profile.exec_counts[0], 1);
+    assert_eq!(profile.exec_counts[3], 3);
+
+    let mut csv = Vec::new();
+    profile.write_csv(&mut csv).unwrap();
+    assert_eq!(csv, b"address,count\n0x00000,1\n0x00003,3\n");
+
+    let mut png = Vec::new();
+    profile.write_heatmap_png(&mut png).unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+}
+
+#[cfg(feature = "profile")]
+#[test]
+fn profile_cfg() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.profile = Some(Profile::new());
+    // mov cx,3; l: dec cx; jnz l; hlt
+    cpu.load_code_vec(&[0xb9, 0x03, 0x00, 0x49, 0x75, 0xfd, 0xf4]);
+    cpu.fire();
+    let profile = cpu.profile.as_ref().unwrap();
+
+    let blocks = profile.basic_blocks();
+    let spans: Vec<(u32, u32, u64)> = blocks.iter().map(|b| (b.start, b.end, b.count)).collect();
+    assert_eq!(spans, vec![(0, 3, 1), (3, 6, 3), (6, 7, 1)]);
+
+    let edges: Vec<((u32, u32), u64)> = profile.block_edges(&blocks).into_iter().collect();
+    assert_eq!(edges, vec![((0, 3), 1), ((3, 3), 2), ((3, 6), 1)]);
+
+    let mut dot = Vec::new();
+    profile.write_cfg_dot(&mut dot, true).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.contains("\"00003\" -> \"00003\" [label=\"2\"];"));
+}
+
+#[cfg(feature = "profile")]
+#[test]
+fn branch_profile() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.branches = Some(BranchProfile::new());
+    // mov cx,3; l: dec cx; jnz l; stc; jbe over; nop; over: hlt
+    cpu.load_code_vec(&[0xb9, 0x03, 0x00, 0x49, 0x75, 0xfd, 0xf9, 0x76, 0x01, 0x90, 0xf4]);
+    cpu.fire();
+    let branches = cpu.branches.as_ref().unwrap();
+    let jnz = &branches.sites[&4];
+    assert_eq!((jnz.taken, jnz.not_taken, jnz.mispredicted), (2, 1, 2));
+    assert_eq!(jnz.producers.iter().collect::<Vec<_>>(), [(&Some(3), &3)]);
+    // carry from stc, zero from dec
+    let jbe = &branches.sites[&7];
+    assert_eq!((jbe.taken, jbe.not_taken), (1, 0));
+    assert_eq!(jbe.producers.keys().collect::<Vec<_>>(), [&Some(3), &Some(6)]);
+
+    let mut report = Vec::new();
+    branches.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("0x00004  jnz"), "{}", lines[1]);
+    assert!(lines[2].ends_with("0x00003 dec cx x1 0x00006 stc x1"), "{}", lines[2]);
+}
+
+#[cfg(feature = "profile")]
+#[test]
+fn profile_coverage() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.profile = Some(Profile::new());
+    // mov cx,2; l: loop l; jmp +1; hlt; hlt
+    cpu.load_code_vec(&[0xb9, 0x02, 0x00, 0xe2, 0xfe, 0xeb, 0x01, 0xf4, 0xf4]);
+    cpu.fire();
+    let profile = cpu.profile.as_ref().unwrap();
+
+    let mut json = Vec::new();
+    profile.write_coverage_json(&mut json, 0, 9).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains("\"covered_bytes\":8,"));
+    assert!(json.contains("\"3\":{\"length\":2,\"count\":2},"));
+    assert!(!json.contains("\"7\""));
+
+    let mut lcov = Vec::new();
+    profile.write_lcov(&mut lcov, "t.bin", 0, 9).unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "SF:t.bin\nDA:1,1\nDA:2,1\nDA:3,1\nDA:4,2\nDA:5,2\nDA:6,1\nDA:7,1\nDA:8,0\nDA:9,1\n\
+         LH:8\nLF:9\nend_of_record\n"
+    );
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn chrome_trace() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.timeline = Some(Timeline::new());
+    // mov al,'A'; out 0xe9,al; mov cx,2; l: loop l; hlt
+    cpu.load_code_vec(&[0xb0, 0x41, 0xe6, 0xe9, 0xb9, 0x02, 0x00, 0xe2, 0xfe, 0xf4]);
+    cpu.fire();
+    let timeline = cpu.timeline.as_mut().unwrap();
+    timeline.finish();
+    assert_eq!(
+        timeline.events,
+        vec![
+            TimelineEvent::Port {
+                port: 0xe9,
+                val: 0x41,
+                write: true,
+                ts: 1
+            },
+            TimelineEvent::Range {
+                addr: 0,
+                ts: 0,
+                dur: 4
+            },
+            TimelineEvent::Range {
+                addr: 7,
+                ts: 4,
+                dur: 2
+            },
+        ]
+    );
+
+    let mut json = Vec::new();
+    timeline.write_chrome_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains("{\"name\":\"00007\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":4,\"dur\":2}"));
+    assert!(json.contains("\"name\":\"out 00e9h\""));
+    assert!(json.trim_end().ends_with("]}"));
+}
+
+#[cfg(feature = "explain")]
+#[test]
+fn explain_step() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov bx,0x10; mov word [bx+4],5; add ax,[bx+4]; sub ax,10; hlt
+    cpu.load_code_vec(&[
+        0xbb, 0x10, 0x00, 0xc7, 0x47, 0x04, 0x05, 0x00, 0x03, 0x47, 0x04, 0x2d, 0x0a, 0x00, 0xf4,
+    ]);
+    explain::step(&mut cpu).unwrap();
+    explain::step(&mut cpu).unwrap();
+
+    let add = explain::step(&mut cpu).unwrap();
+    assert!(add.starts_with("0000:0008  03 47 04"));
+    assert!(add.contains("  ea: ds*16 + bx + disp = 0000*16 + 0010 + 0004 = 00014\n"));
+    assert!(add.contains("  dest ax: 0000 -> 0005\n"));
+    assert!(add.contains("  src word [00014] = 0005\n"));
+
+    let sub = explain::step(&mut cpu).unwrap();
+    assert!(sub.contains("  dest ax: 0005 -> fffb\n"));
+    assert!(sub.contains("  CF 0 -> 1: carry/borrow out of the top bit\n"));
+    assert!(sub.contains("  SF 0 -> 1: top bit of the result is 1\n"));
+    assert!(!sub.contains("ZF"));
+
+    let hlt = explain::step(&mut cpu).unwrap();
+    assert_eq!(hlt.lines().count(), 1);
+    assert!(cpu.halt);
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn html_trace() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.recording = Some(Recording::new());
+    // mov bx,0x10; mov word [bx+4],5; hlt
+    cpu.load_code_vec(&[0xbb, 0x10, 0x00, 0xc7, 0x47, 0x04, 0x05, 0x00, 0xf4]);
+    cpu.fire();
+    let recording = cpu.recording.as_ref().unwrap();
+    assert_eq!(recording.steps.len(), 3);
+    assert_eq!(recording.steps[1].ip, 3);
+    assert_eq!(recording.steps[1].bytes, vec![0xc7, 0x47, 0x04, 0x05, 0x00]);
+    assert_eq!(recording.steps[1].regs[1], 0x10);
+    assert_eq!(recording.steps[1].writes, vec![(0x14, 5), (0x15, 0)]);
+
+    let mut html = Vec::new();
+    recording
+        .write_html(&mut html, cpu.mem.cursor.get_ref(), (0, 9), &cpu.regs)
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(!html.contains("/*TRACE*/"));
+    assert!(html.contains("\"at\":\"0000:0003\",\"bytes\":\"c7 47 04 05 00\""));
+    assert!(html.contains("\"writes\":[[20, 5], [21, 0]]"));
+    // the written line is shown as it was before the store
+    assert!(html.contains("\"16\":[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]"));
+}
+
+#[test]
+fn opcode_table() {
+    use crate::opcodes::{lookup, OPCODES};
+
+    for pair in OPCODES.windows(2) {
+        assert!((pair[0].opcode, pair[0].reg) < (pair[1].opcode, pair[1].reg), "{:?}", pair);
+    }
+    let defined = (0..=255u8).filter(|&b| lookup(b, Some(0)).is_some()).count();
+    assert_eq!(defined, 256 - 16 - 6);
+
+    let cmp = lookup(0x83, Some(0b11_111_000)).unwrap();
+    assert_eq!((cmp.mnemonic, cmp.operands), ("cmp", "r/m16, imm8"));
+    assert_eq!(lookup(0x8f, Some(0b00_001_000)), None);
+    assert_eq!(lookup(0xff, None), None);
+    assert_eq!(lookup(0xb8, None).unwrap().operands, "AX, imm16");
+    assert!(lookup(0x8d, None).unwrap().has_modrm());
+    assert!(lookup(0xd8, None).unwrap().has_modrm());
+    assert!(!lookup(0xa1, None).unwrap().has_modrm());
+    assert!(!lookup(0x05, None).unwrap().has_modrm());
+}
+
+#[test]
+fn cs_write_policy() {
+    // mov ax,0x50; push ax; pop cs  and  mov ax,0x50; mov cs,ax
+    for code in [[0xb8, 0x50, 0x00, 0x50, 0x0f], [0xb8, 0x50, 0x00, 0x8e, 0xc8]] {
+        let steps = if code[3] == 0x50 { 3 } else { 2 };
+        let run = |policy| {
+            let mut cpu = Cpu::init();
+            cpu.test_mode();
+            cpu.cs_write = policy;
+            cpu.load_code_vec(&code);
+            for _ in 0..steps {
+                let i = cpu.fetch().unwrap();
+                cpu.execute(&i);
+            }
+            (cpu.regs.cs, cpu.regs.ip, cpu.faults.len())
+        };
+        assert_eq!(run(cpu::CsWrite::Allow), (0x50, 5, 0));
+        // warn goes on, keeping the fault for the dump
+        assert_eq!(run(cpu::CsWrite::Warn), (0x50, 5, 1));
+        assert!(std::panic::catch_unwind(|| run(cpu::CsWrite::Fault)).is_err());
+    }
+}
+
+#[test]
+fn mov_sreg_lea_pop_decode() {
+    let decode = |code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        let i = cpu.fetch().unwrap();
+        (i.opcode(), i.operands(), cpu.regs.ip)
+    };
+    assert_eq!(decode(&[0x8c, 0xd8]), (Opcode::Mov, (Operand::Reg16(0), Operand::Seg(3)), 2));
+    assert!(matches!(
+        decode(&[0x8c, 0x06, 0x00, 0x20]),
+        (Opcode::Mov, (Operand::Ea16(..), Operand::Seg(0)), 4)
+    ));
+    assert_eq!(decode(&[0x8e, 0xd8]), (Opcode::Mov, (Operand::Seg(3), Operand::Reg16(0)), 2));
+    assert!(matches!(
+        decode(&[0x8d, 0x47, 0x02]),
+        (Opcode::Lea, (Operand::Reg16(0), Operand::Ea16(..)), 3)
+    ));
+    assert!(matches!(decode(&[0x8f, 0x06, 0x00, 0x20]), (Opcode::Pop, (Operand::Ea16(..), _), 4)));
+    assert_eq!(decode(&[0x8f, 0xc1]).1 .0, Operand::Reg16(1));
+
+    // sreg fields 4-7, lea of a register and pop with reg != 0 stop the
+    // run on the instruction
+    for (code, why) in [
+        ([0x8c, 0xe0], "8c e0: segment register field above 3"),
+        ([0x8e, 0xf8], "8e f8: segment register field above 3"),
+        ([0x8d, 0xc0], "8d c0: needs a word register and a memory operand"),
+        ([0x8f, 0xc8], "8f c8: undefined reg field"),
+    ] {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&code);
+        assert!(cpu.fetch().is_none());
+        assert_eq!(cpu.decode_fault, Some(format!("invalid encoding {}", why)));
+        assert_eq!((cpu.regs.ip, cpu.stats.instructions), (0, 0));
+        assert_eq!(cpu.run_until(|_| false), cpu::RunExit::DecodeFault);
+    }
+    // with a memory operand too, before anything is read or written:
+    // mov ax,0x1234; mov [0x2000],ax; mov [0x2000],sreg4 / mov sreg7,[0x2000]
+    for sreg in [[0x8c, 0x26], [0x8e, 0x3e]] {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&[0xb8, 0x34, 0x12, 0xa3, 0x00, 0x20, sreg[0], sreg[1], 0x00, 0x20, 0xf4]);
+        let before = cpu.regs.clone();
+        assert_eq!(cpu.run_until(|_| false), cpu::RunExit::DecodeFault);
+        let why = format!("invalid encoding {:02x} {:02x}: segment register field above 3", sreg[0], sreg[1]);
+        assert_eq!(cpu.decode_fault, Some(why));
+        assert_eq!((cpu.regs.ip, cpu.stats.instructions), (6, 2));
+        assert_eq!((cpu.regs.ds, cpu.regs.es, cpu.regs.ss), (before.ds, before.es, before.ss));
+        assert_eq!(cpu.read_mem_u16(cpu.ea(&cpu::Segment::Ds, 0x2000)), 0x1234);
+    }
+}
+
+#[test]
+fn direct_address_override() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xb8, 0x00, 0x01, // mov ax,0x100
+        0x8e, 0xc0, // mov es,ax
+        0x26, 0xa1, 0x34, 0x12, // mov ax,[es:0x1234]
+        0x26, 0x8b, 0x1e, 0x34, 0x12, // mov bx,[es:0x1234]
+        0x8b, 0x0e, 0x34, 0x12, // mov cx,[0x1234]
+        0x26, 0xa2, 0x00, 0x20, // mov [es:0x2000],al
+        0xbd, 0x20, 0x00, // mov bp,0x20
+        0x8b, 0x96, 0x10, 0x00, // mov dx,[bp+0x10]
+        0x8b, 0x76, 0xfe, // mov si,[bp-2]
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x1234..0x1236].copy_from_slice(&[0x11, 0x11]);
+    mem[0x2234..0x2236].copy_from_slice(&[0xef, 0xbe]);
+    // ss is 0x100 in test mode
+    mem[0x1030..0x1032].copy_from_slice(&[0x34, 0x12]);
+    mem[0x101e..0x1020].copy_from_slice(&[0x78, 0x56]);
+    cpu.fire();
+    assert_eq!((cpu.regs.ax, cpu.regs.bx, cpu.regs.cx), (0xbeef, 0xbeef, 0x1111));
+    assert_eq!(cpu.mem.cursor.get_ref()[0x3000], 0xef);
+    assert_eq!((cpu.regs.dx, cpu.regs.si), (0x1234, 0x5678));
+    assert!(cpu.halt);
+}
+
+#[test]
+fn low_opcode_sweep() {
+    use Opcode::*;
+    let alu = [Add, Or, Adc, Sbb, And, Sub, Xor, Cmp];
+    for b in 0u8..0x40 {
+        let (row, low) = ((b >> 3) as usize, b & 0b111);
+        if low == 6 && row >= 4 {
+            // 26/2E/36/3E are segment overrides, see prefixed_string
+            continue;
+        }
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        // modrm 0xc1: register operands, reg field 0 (al/ax), r/m 1 (cl/cx)
+        cpu.load_code_vec(&[b, 0xc1, 0x12, 0x90]);
+        let i = cpu.fetch().unwrap();
+        let (opcode, operands, len) = match low {
+            0 => (alu[row], (Operand::Reg8(1), Operand::Reg8(0)), 2),
+            1 => (alu[row], (Operand::Reg16(1), Operand::Reg16(0)), 2),
+            2 => (alu[row], (Operand::Reg8(0), Operand::Reg8(1)), 2),
+            3 => (alu[row], (Operand::Reg16(0), Operand::Reg16(1)), 2),
+            4 => (alu[row], (Operand::Reg8(0), Operand::Imm8(0xc1)), 2),
+            5 => (alu[row], (Operand::Reg16(0), Operand::Imm16(0x12c1)), 3),
+            6 if row < 4 => ([PushEs, PushCs, PushSs, PushDs][row], i.operands(), 1),
+            7 if row < 4 => ([PopEs, PopCs, PopSs, PopDs][row], i.operands(), 1),
+            _ => ([Daa, Das, Aaa, Aas][row - 4], i.operands(), 1),
+        };
+        assert_eq!((i.opcode(), i.operands(), cpu.regs.ip), (opcode, operands, len), "opcode {:02x}", b);
+    }
+}
+
+#[test]
+fn prefixed_string() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xb8, 0x00, 0x02, // mov ax,0x200
+        0x8e, 0xc0, // mov es,ax
+        0xbe, 0x10, 0x00, // mov si,0x10
+        0xbf, 0x20, 0x00, // mov di,0x20
+        0xb9, 0x03, 0x00, // mov cx,3
+        0xf0, 0x26, 0xf3, 0xa4, // lock es: rep movsb
+        0xf4,
+    ]);
+    cpu.mem.cursor.get_mut()[0x2010..0x2013].copy_from_slice(&[7, 8, 9]);
+    cpu.fire();
+    // the source came through ES, and the whole run was one instruction
+    assert_eq!(cpu.mem.cursor.get_ref()[0x2020..0x2024], [7, 8, 9, 0]);
+    assert_eq!((cpu.regs.cx, cpu.stats.instructions, cpu.stats.string_iterations), (0, 7, 3));
+
+    // repne stops at the first match, rep(e) at the first difference
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xbf, 0x00, 0x20, // mov di,0x2000
+        0xb9, 0x08, 0x00, // mov cx,8
+        0xb0, 0x03, // mov al,3
+        0xf2, 0xae, // repne scasb
+        0x89, 0xcb, // mov bx,cx
+        0xbf, 0x00, 0x20, // mov di,0x2000
+        0xb9, 0x08, 0x00, // mov cx,8
+        0xb0, 0x01, // mov al,1
+        0xf3, 0xae, // repe scasb
+        0xf4,
+    ]);
+    cpu.mem.cursor.get_mut()[0x2000..0x2008].copy_from_slice(&[1, 1, 3, 1, 1, 1, 1, 1]);
+    cpu.fire();
+    assert_eq!((cpu.regs.bx, cpu.regs.cx), (5, 5));
+}
+
+#[test]
+fn rep_movs() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xbe, 0x00, 0x20, // mov si,0x2000
+        0xbf, 0x00, 0x30, // mov di,0x3000
+        0xb9, 0x04, 0x00, // mov cx,4
+        0xf3, 0xa4, // rep movsb
+        0xb9, 0x02, 0x00, // mov cx,2
+        0xf3, 0xa5, // rep movsw
+        0xf4,
+    ]);
+    cpu.mem.cursor.get_mut()[0x2000..0x2008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    cpu.fire();
+    assert_eq!(cpu.mem.cursor.get_ref()[0x3000..0x3009], [1, 2, 3, 4, 5, 6, 7, 8, 0]);
+    assert_eq!((cpu.regs.si, cpu.regs.di, cpu.regs.cx), (0x2008, 0x3008, 0));
+
+    // backwards, overlapping: shift the buffer up by one
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xfd, // std
+        0xbe, 0x06, 0x20, // mov si,0x2006
+        0xbf, 0x07, 0x20, // mov di,0x2007
+        0xb9, 0x07, 0x00, // mov cx,7
+        0xf3, 0xa4, // rep movsb
+        0xbe, 0x02, 0x30, // mov si,0x3002
+        0xbf, 0x02, 0x40, // mov di,0x4002
+        0xb9, 0x02, 0x00, // mov cx,2
+        0xf3, 0xa5, // rep movsw
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x2000..0x2008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    mem[0x3000..0x3004].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+    cpu.fire();
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(mem[0x2000..0x2008], [1, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(mem[0x4000..0x4004], [0x11, 0x22, 0x33, 0x44]);
+    assert_eq!((cpu.regs.si, cpu.regs.di), (0x2ffe, 0x3ffe));
+}
+
+#[test]
+fn repe_cmps() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xbe, 0x00, 0x20, // mov si,0x2000
+        0xbf, 0x00, 0x30, // mov di,0x3000
+        0xb9, 0x08, 0x00, // mov cx,8
+        0xf3, 0xa6, // repe cmpsb
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x2000..0x2008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    mem[0x3000..0x3008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    cpu.fire();
+    assert!(cpu.regs.flags.zf());
+    assert_eq!((cpu.regs.si, cpu.regs.di, cpu.regs.cx), (0x2008, 0x3008, 0));
+
+    // stops one element past the first mismatch, the third word
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xbe, 0x00, 0x20, // mov si,0x2000
+        0xbf, 0x00, 0x30, // mov di,0x3000
+        0xb9, 0x04, 0x00, // mov cx,4
+        0xf3, 0xa7, // repe cmpsw
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x2000..0x2008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    mem[0x3000..0x3008].copy_from_slice(&[1, 2, 3, 4, 5, 0, 7, 8]);
+    cpu.fire();
+    assert!(!cpu.regs.flags.zf());
+    assert_eq!((cpu.regs.si, cpu.regs.di, cpu.regs.cx), (0x2006, 0x3006, 1));
+
+    // backwards from the last byte
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xfd, // std
+        0xbe, 0x03, 0x20, // mov si,0x2003
+        0xbf, 0x03, 0x30, // mov di,0x3003
+        0xb9, 0x04, 0x00, // mov cx,4
+        0xf3, 0xa6, // repe cmpsb
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x2000..0x2004].copy_from_slice(&[9, 2, 3, 4]);
+    mem[0x3000..0x3004].copy_from_slice(&[1, 2, 3, 4]);
+    cpu.fire();
+    assert!(!cpu.regs.flags.zf() && !cpu.regs.flags.cf());
+    assert_eq!((cpu.regs.si, cpu.regs.di, cpu.regs.cx), (0x1fff, 0x2fff, 0));
+}
+
+#[test]
+fn repne_scas() {
+    let find = |al: u8, code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        cpu.regs.set_al(al);
+        cpu.regs.ax |= 0xff00;
+        cpu.mem.cursor.get_mut()[0x2000..0x200c].copy_from_slice(b"hello, world");
+        cpu.fire();
+        cpu
+    };
+    let code = [
+        0xbf, 0x00, 0x20, // mov di,0x2000
+        0xb9, 0x0c, 0x00, // mov cx,12
+        0xf2, 0xae, // repne scasb
+        0xf4,
+    ];
+    let cpu = find(b',', &code);
+    assert!(cpu.regs.flags.zf());
+    assert_eq!((cpu.regs.di, cpu.regs.cx), (0x2006, 6));
+
+    let cpu = find(b'z', &code);
+    assert!(!cpu.regs.flags.zf());
+    assert_eq!((cpu.regs.di, cpu.regs.cx), (0x200c, 0));
+    // 'z' - 'd', no borrow
+    assert!(!cpu.regs.flags.cf() && !cpu.regs.flags.sf());
+
+    // ah must not take part: al 'h' matches the first byte
+    let cpu = find(b'h', &code);
+    assert_eq!((cpu.regs.di, cpu.regs.cx), (0x2001, 11));
+
+    // word-wide: ", " sits at an odd offset and is skipped, " w" is found
+    let code = [
+        0xbf, 0x00, 0x20, // mov di,0x2000
+        0xb9, 0x06, 0x00, // mov cx,6
+        0xb8, 0x2c, 0x20, // mov ax,", "
+        0xf2, 0xaf, // repne scasw
+        0xb8, 0x20, 0x77, // mov ax," w"
+        0xbf, 0x00, 0x20, // mov di,0x2000
+        0xb9, 0x06, 0x00, // mov cx,6
+        0xf2, 0xaf, // repne scasw
+        0xf4,
+    ];
+    let cpu = find(0, &code);
+    assert!(cpu.regs.flags.zf());
+    assert_eq!((cpu.regs.di, cpu.regs.cx), (0x2008, 2));
+
+    // a single scasb subtracts memory from al
+    let cpu = find(b'a', &[0xbf, 0x00, 0x20, 0xae, 0xf4]);
+    assert!(!cpu.regs.flags.zf() && cpu.regs.flags.cf() && cpu.regs.flags.sf());
+    assert_eq!(cpu.regs.di, 0x2001);
+}
+
+#[test]
+fn xlat_table() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xb8, 0x00, 0x01, // mov ax,0x100
+        0x8e, 0xd8, // mov ds,ax
+        0xb8, 0x00, 0x02, // mov ax,0x200
+        0x8e, 0xc0, // mov es,ax
+        0xbb, 0x00, 0xff, // mov bx,0xff00
+        0xb0, 0x20, // mov al,0x20
+        0xd7, // xlat
+        0x88, 0xc2, // mov dl,al
+        0xbb, 0xc0, 0xff, // mov bx,0xffc0
+        0xb0, 0x50, // mov al,0x50
+        0xd7, // xlat
+        0x88, 0xc6, // mov dh,al
+        0xbb, 0x00, 0xff, // mov bx,0xff00
+        0xb0, 0x01, // mov al,1
+        0x26, 0xd7, // xlat es:
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    // the table fills the last 256 bytes of ds
+    for i in 0..0x100 {
+        mem[0x10f00 + i] = !(i as u8);
+    }
+    // bx+al = 0x10010 wraps to ds:0010
+    mem[0x1010] = 0x5a;
+    mem[0x11f01] = 0x77;
+    cpu.fire();
+    assert_eq!((cpu.regs.get_dl(), cpu.regs.get_dh(), cpu.regs.get_al()), (0xdf, 0x5a, 0x77));
+}
+
+#[test]
+fn mul_imul_flags() {
+    // (modrm byte for f6/f7 with bl/bx, word, ax, bx) -> (ax, dx, cf and of)
+    const MUL: u8 = 0xe3;
+    const IMUL: u8 = 0xeb;
+    let cases: &[(u8, bool, u16, u16, u16, u16, bool)] = &[
+        (MUL, false, 0x5510, 0x0f, 0x00f0, 0, false),
+        (MUL, false, 0x5510, 0x10, 0x0100, 0, true),
+        (MUL, false, 0x55ff, 0xff, 0xfe01, 0, true),
+        (IMUL, false, 0x5502, 0x03, 0x0006, 0, false),
+        (IMUL, false, 0x55ff, 0xff, 0x0001, 0, false),
+        (IMUL, false, 0x55fe, 0x03, 0xfffa, 0, false),
+        (IMUL, false, 0x55c0, 0x02, 0xff80, 0, false),
+        (IMUL, false, 0x5540, 0x02, 0x0080, 0, true),
+        (IMUL, false, 0x5510, 0x10, 0x0100, 0, true),
+        (MUL, true, 0x0100, 0x00ff, 0xff00, 0, false),
+        (MUL, true, 0x0100, 0x0100, 0x0000, 1, true),
+        (MUL, true, 0xffff, 0xffff, 0x0001, 0xfffe, true),
+        (IMUL, true, 0xffff, 0xffff, 0x0001, 0, false),
+        (IMUL, true, 0xfffe, 0x0003, 0xfffa, 0xffff, false),
+        (IMUL, true, 0xc000, 0x0002, 0x8000, 0xffff, false),
+        (IMUL, true, 0x4000, 0x0002, 0x8000, 0, true),
+        (IMUL, true, 0x0100, 0x0100, 0x0000, 1, true),
+    ];
+    for &(modrm, word, ax, bx, want_ax, want_dx, overflow) in cases {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&[0xf6 | word as u8, modrm]);
+        cpu.regs.ax = ax;
+        cpu.regs.bx = bx;
+        cpu.regs.dx = 0;
+        let i = cpu.fetch().unwrap();
+        cpu.execute(&i);
+        let got = (cpu.regs.ax, cpu.regs.dx, cpu.regs.flags.cf(), cpu.regs.flags.of());
+        assert_eq!(
+            got,
+            (want_ax, want_dx, overflow, overflow),
+            "{} {:04x} * {:04x}",
+            if modrm == MUL { "mul" } else { "imul" },
+            ax,
+            bx
+        );
+    }
+}
+
+#[test]
+fn bcd_adjust_flags() {
+    const CF: u16 = 1;
+    const PF: u16 = 1 << 2;
+    const AF: u16 = 1 << 4;
+    const ZF: u16 = 1 << 6;
+    const SF: u16 = 1 << 7;
+    const SZP: u16 = SF | ZF | PF;
+    // (code, ax, flags before) -> (ax, flags after, flags compared)
+    type Case = (&'static [u8], u16, u16, u16, u16, u16);
+    let cases: &[Case] = &[
+        // 0x79 + 0x35
+        (&[0x27], 0x00ae, 0, 0x0014, CF | AF | PF, CF | AF | SZP),
+        (&[0x27], 0x009a, 0, 0x0000, CF | AF | ZF | PF, CF | AF | SZP),
+        (&[0x27], 0x0012, SZP, 0x0012, PF, CF | AF | SZP),
+        // 0x35 - 0x47
+        (&[0x2f], 0x00ee, CF | AF, 0x0088, CF | AF | SF | PF, CF | AF | SZP),
+        (&[0x2f], 0x0045, SZP, 0x0045, 0, CF | AF | SZP),
+        (&[0x37], 0x000d, 0, 0x0103, CF | AF, CF | AF),
+        (&[0x37], 0x0004, CF, 0x0004, 0, CF | AF),
+        (&[0x3f], 0x02ff, AF, 0x0109, CF | AF, CF | AF),
+        (&[0xd4, 0x0a], 0x003f, SZP, 0x0603, PF, SZP),
+        (&[0xd4, 0x0a], 0x0050, 0, 0x0800, ZF | PF, SZP),
+        (&[0xd5, 0x0a], 0x0705, SZP, 0x004b, PF, SZP),
+        (&[0xd5, 0x0a], 0x0d00, 0, 0x0082, SF | PF, SZP),
+    ];
+    for &(code, ax, before, want_ax, want, mask) in cases {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        cpu.regs.ax = ax;
+        cpu.regs.flags.set_from_u16(cpu.regs.flags.to_u16() | before);
+        let i = cpu.fetch().unwrap();
+        cpu.execute(&i);
+        assert_eq!(
+            (cpu.regs.ax, cpu.regs.flags.to_u16() & mask),
+            (want_ax, want),
+            "{:02x?} with ax {:04x}",
+            code,
+            ax
+        );
+    }
+}
+
+#[test]
+fn neg_not_flags() {
+    const CF: u16 = 1;
+    const PF: u16 = 1 << 2;
+    const AF: u16 = 1 << 4;
+    const ZF: u16 = 1 << 6;
+    const SF: u16 = 1 << 7;
+    const OF: u16 = 1 << 11;
+    const ARITH: u16 = CF | PF | AF | ZF | SF | OF;
+    // (neg al or neg ax, ax) -> (ax, flags)
+    let cases: &[(u8, u16, u16, u16)] = &[
+        (0xf6, 0x1200, 0x1200, ZF | PF),
+        (0xf6, 0x0001, 0x00ff, CF | AF | SF | PF),
+        (0xf6, 0x0080, 0x0080, CF | OF | SF),
+        (0xf6, 0x007f, 0x0081, CF | AF | SF | PF),
+        (0xf6, 0x0010, 0x00f0, CF | SF | PF),
+        (0xf7, 0x0000, 0x0000, ZF | PF),
+        (0xf7, 0x8000, 0x8000, CF | OF | SF | PF),
+        (0xf7, 0x7fff, 0x8001, CF | AF | SF),
+        (0xf7, 0xffff, 0x0001, CF | AF),
+    ];
+    for &(op, ax, want_ax, want) in cases {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&[op, 0xd8]);
+        cpu.regs.ax = ax;
+        cpu.regs.flags.set_from_u16(cpu.regs.flags.to_u16() | ARITH);
+        let i = cpu.fetch().unwrap();
+        cpu.execute(&i);
+        assert_eq!((cpu.regs.ax, cpu.regs.flags.to_u16() & ARITH), (want_ax, want), "neg {:04x}", ax);
+    }
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xf6, 0x1e, 0x00, 0x20, // neg byte [0x2000]
+        0xf7, 0xd0, // not ax
+        0xf4,
+    ]);
+    cpu.mem.cursor.get_mut()[0x2000] = 0x80;
+    cpu.regs.ax = 0x00ff;
+    cpu.fire();
+    assert_eq!((cpu.mem.cursor.get_ref()[0x2000], cpu.regs.ax), (0x80, 0xff00));
+    // not keeps what neg left
+    assert_eq!(cpu.regs.flags.to_u16() & ARITH, CF | OF | SF);
+}
+
+#[test]
+fn alu_flags_at_byte_width() {
+    use crate::alu::{AF, ARITH, CF, OF, PF, SF, ZF};
+    // (instruction, al, flags before) -> (al, flags)
+    let cases: &[(&[u8], u8, u16, u8, u16)] = &[
+        (&[0x04, 0x01], 0xff, 0, 0x00, CF | ZF | AF | PF),
+        // inc carries nothing out and leaves CF as it was
+        (&[0xfe, 0xc0], 0xff, CF, 0x00, CF | ZF | AF | PF),
+        (&[0xfe, 0xc0], 0x7f, 0, 0x80, OF | SF | AF),
+        (&[0xfe, 0xc8], 0x80, 0, 0x7f, OF | AF),
+        (&[0x14, 0x00], 0x7f, CF, 0x80, OF | SF | AF),
+        (&[0x1c, 0x00], 0x00, CF, 0xff, CF | SF | AF | PF),
+        (&[0x24, 0x0f], 0xf0, ARITH, 0x00, ZF | PF),
+    ];
+    for &(code, al, before, want_al, want) in cases {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        cpu.regs.ax = 0x1200 | al as u16;
+        cpu.regs.flags.set_masked(before, ARITH);
+        let i = cpu.fetch().unwrap();
+        cpu.execute(&i);
+        assert_eq!(cpu.regs.ax >> 8, 0x12, "{:02x?}", code);
+        assert_eq!((cpu.regs.get_al(), cpu.regs.flags.to_u16() & ARITH), (want_al, want), "{:02x?}", code);
+    }
+}
+
+#[test]
+fn cbw_cwd_idiv() {
+    for (al, ax) in [(0x00, 0x0000), (0x7f, 0x007f), (0x80, 0xff80), (0xff, 0xffff)] {
+        let mut cpu = Cpu::init();
+        cpu.regs.ax = 0x5500 | al;
+        cpu.execute(&Instruction::new(Opcode::Cbw, Operand::Reg8(0), Operand::Reg8(0)));
+        assert_eq!(cpu.regs.ax, ax, "cbw {:02x}", al);
+    }
+    for (ax, dx) in [(0x0000, 0x0000), (0x7fff, 0x0000), (0x8000, 0xffff), (0x00ff, 0x0000)] {
+        let mut cpu = Cpu::init();
+        cpu.regs.ax = ax;
+        cpu.regs.dx = 0x1234;
+        cpu.execute(&Instruction::new(Opcode::Cwd, Operand::Reg8(0), Operand::Reg8(0)));
+        assert_eq!((cpu.regs.ax, cpu.regs.dx), (ax, dx), "cwd {:04x}", ax);
+    }
+
+    let run = |code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(code);
+        cpu.fire();
+        (cpu.regs.ax, cpu.regs.dx)
+    };
+    // mov ax,-7; cwd; mov bx,2; idiv bx
+    assert_eq!(run(&[0xb8, 0xf9, 0xff, 0x99, 0xbb, 0x02, 0x00, 0xf7, 0xfb, 0xf4]), (0xfffd, 0xffff));
+    // mov ax,1000; cwd; mov cx,-7; idiv cx
+    assert_eq!(run(&[0xb8, 0xe8, 0x03, 0x99, 0xb9, 0xf9, 0xff, 0xf7, 0xf9, 0xf4]), ((-142i16) as u16, 6));
+    // mov al,-100; cbw; mov bl,7; idiv bl: -14 rem -2
+    assert_eq!(run(&[0xb0, 0x9c, 0x98, 0xb3, 0x07, 0xf6, 0xfb, 0xf4]).0, 0xfef2);
+    // mov dx,1; xor ax,ax; mov bx,0x10; div bx
+    assert_eq!(run(&[0xba, 0x01, 0x00, 0x31, 0xc0, 0xbb, 0x10, 0x00, 0xf7, 0xf3, 0xf4]), (0x1000, 0));
+
+    // mov ax,0x8000; cwd; mov bx,-1; idiv bx overflows, and so does a zero divisor
+    for code in [
+        [0xb8, 0x00, 0x80, 0x99, 0xbb, 0xff, 0xff, 0xf7, 0xfb, 0xf4],
+        [0xb8, 0x00, 0x80, 0x99, 0xbb, 0x00, 0x00, 0xf7, 0xfb, 0xf4],
+    ] {
+        assert!(std::panic::catch_unwind(|| run(&code)).is_err(), "{:02x?}", code);
+    }
+}
+
+#[test]
+fn les_lds() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xc4, 0x3e, 0x00, 0x20, // les di,[0x2000]
+        0xbb, 0x10, 0x00, // mov bx,0x10
+        0x26, 0xc5, 0x77, 0x02, // lds si,[es:bx+2]
+        0x8b, 0x04, // mov ax,[si]
+        0xf4,
+    ]);
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0x2000..0x2004].copy_from_slice(&[0x34, 0x12, 0x00, 0x01]);
+    // es:0x12 is 0x1012
+    mem[0x1012..0x1016].copy_from_slice(&[0x04, 0x00, 0x00, 0x03]);
+    mem[0x3004..0x3006].copy_from_slice(&[0xef, 0xbe]);
+    cpu.fire();
+    assert_eq!((cpu.regs.di, cpu.regs.es), (0x1234, 0x0100));
+    assert_eq!((cpu.regs.si, cpu.regs.ds), (0x0004, 0x0300));
+    assert_eq!(cpu.regs.ax, 0xbeef);
+
+    for (code, why) in [([0xc4, 0xc0], "c4 c0"), ([0xc5, 0xd9], "c5 d9")] {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&code);
+        assert!(cpu.fetch().is_none());
+        let want = format!("invalid encoding {}: needs a word register and a memory operand", why);
+        assert_eq!(cpu.decode_fault, Some(want));
+    }
+}
+
+#[test]
+fn indirect_call_jmp() {
+    let mut code = vec![0x90; 0x72];
+    let mut put = |at: usize, bytes: &[u8]| code[at..at + bytes.len()].copy_from_slice(bytes);
+    put(
+        0,
+        &[
+            0xbb, 0x40, 0x00, // mov bx,0x40
+            0xff, 0xd3, // call bx
+            0xc7, 0x06, 0x00, 0x20, 0x48, 0x00, // mov word [0x2000],0x48
+            0xff, 0x16, 0x00, 0x20, // call [0x2000]
+            0xc7, 0x06, 0x04, 0x20, 0x50, 0x00, // mov word [0x2004],0x50
+            0xc7, 0x06, 0x06, 0x20, 0x00, 0x00, // mov word [0x2006],0
+            0xff, 0x1e, 0x04, 0x20, // call far [0x2004]
+            0xc7, 0x06, 0x08, 0x20, 0x58, 0x00, // mov word [0x2008],0x58
+            0xff, 0x26, 0x08, 0x20, // jmp [0x2008]
+            0xf4,
+        ],
+    );
+    // mov bp,sp; mov di,[bp+0]; inc cx; ret
+    put(0x40, &[0x89, 0xe5, 0x8b, 0x7e, 0x00, 0x41, 0xc3]);
+    // inc dx; ret
+    put(0x48, &[0x42, 0xc3]);
+    // inc si; retf
+    put(0x50, &[0x46, 0xcb]);
+    // mov bx,0x60; jmp bx
+    put(0x58, &[0xbb, 0x60, 0x00, 0xff, 0xe3]);
+    put(
+        0x60,
+        &[
+            0xc7, 0x06, 0x0a, 0x20, 0x00, 0x00, // mov word [0x200a],0
+            0xc7, 0x06, 0x0c, 0x20, 0x07, 0x00, // mov word [0x200c],7
+            0xff, 0x2e, 0x0a, 0x20, // jmp far [0x200a]
+        ],
+    );
+    // 0007:0000: inc di; hlt
+    put(0x70, &[0x47, 0xf4]);
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    assert!(cpu.halt);
+    // di is the return address call bx pushed, plus one
+    assert_eq!((cpu.regs.cx, cpu.regs.dx, cpu.regs.si, cpu.regs.di), (1, 1, 1, 6));
+    assert_eq!((cpu.regs.cs, cpu.regs.ip, cpu.regs.sp), (7, 2, 4095));
+    // the far call left cs:ip of the instruction after it
+    assert_eq!(cpu.mem.cursor.get_ref()[0x1ffb..0x1fff], [31, 0, 0, 0]);
+
+    // far call and far jmp through a register
+    for (code, why) in [
+        ([0xff, 0xd8], "ff d8: far call through something other than a pointer in memory"),
+        ([0xff, 0xe8], "ff e8: far jump through something other than a pointer in memory"),
+    ] {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(&code);
+        assert!(cpu.fetch().is_none());
+        assert_eq!(cpu.decode_fault, Some(format!("invalid encoding {}", why)));
+    }
+}
+
+#[test]
+fn decode_next_ip() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[0x12, 0xce, 0xeb, 0xfa]);
+    cpu.prog_size = 0x10000;
+    // mov ax,0x1234 straddling the end of the segment
+    let mem = cpu.mem.cursor.get_mut();
+    mem[0xfffe] = 0xb8;
+    mem[0xffff] = 0x34;
+    cpu.regs.ip = 0xfffe;
+    let (i, next_ip) = cpu.decode().unwrap().unwrap();
+    assert_eq!((i.opcode(), next_ip, cpu.regs.ip), (Opcode::Mov, 1, 0xfffe));
+    let i = cpu.fetch().unwrap();
+    cpu.execute(&i);
+    assert_eq!((cpu.regs.ax, cpu.regs.ip), (0x1234, 1));
+
+    // into with OF clear falls through without touching the stack
+    let i = cpu.fetch().unwrap();
+    cpu.execute(&i);
+    assert_eq!((cpu.regs.ip, cpu.regs.sp), (2, 4095));
+
+    // jmp short -6 lands back on the mov, across the wrap
+    let i = cpu.fetch().unwrap();
+    cpu.execute(&i);
+    assert_eq!(cpu.regs.ip, 0xfffe);
+
+    // int pushes the offset after its two bytes
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[0x90, 0xcd, 0x21]);
+    cpu.mem.cursor.get_mut()[0x84..0x88].copy_from_slice(&[0x00, 0x02, 0x00, 0x00]);
+    cpu.fire();
+    let sp = cpu.stack_addr(cpu.regs.sp) as usize;
+    assert_eq!((cpu.regs.ip, cpu.mem.cursor.get_ref()[sp]), (0x200, 3));
+}
+
+#[test]
+fn relative_targets() {
+    let decode = |code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        let mut image = vec![0x90; 0x10];
+        image.extend_from_slice(code);
+        cpu.load_code_vec(&image);
+        cpu.regs.ip = 0x10;
+        let (i, _) = cpu.decode().unwrap().unwrap();
+        (i.opcode(), i.operands())
+    };
+    let target = |code: &[u8]| match decode(code).1 {
+        (Operand::Rel8(t), _) | (_, Operand::Rel8(t)) => Operand::Rel8(t),
+        (Operand::Rel16(t), _) | (_, Operand::Rel16(t)) => Operand::Rel16(t),
+        ops => panic!("{:02x?}: no target in {:?}", code, ops),
+    };
+    assert_eq!(target(&[0x75, 0xfe]), Operand::Rel8(0x10));
+    assert_eq!(target(&[0x7f, 0x10]), Operand::Rel8(0x22));
+    assert_eq!(target(&[0xe2, 0x80]), Operand::Rel8(0xff92));
+    assert_eq!(target(&[0xe3, 0x00]), Operand::Rel8(0x12));
+    assert_eq!(target(&[0xeb, 0x7f]), Operand::Rel8(0x91));
+    assert_eq!(target(&[0xe8, 0x00, 0x01]), Operand::Rel16(0x113));
+    assert_eq!(target(&[0xe9, 0xfd, 0xff]), Operand::Rel16(0x10));
+    assert_eq!(decode(&[0xe0, 0x00]).0, Opcode::Loopne);
+
+    // every jcc against every combination of the flags it reads
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let (cf, pf, zf, sf, of) = (1u16, 1u16 << 2, 1u16 << 6, 1u16 << 7, 1u16 << 11);
+    for bits in 0..32u16 {
+        let flags = [cf, pf, zf, sf, of]
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| bits & 1 << n != 0)
+            .fold(0, |acc, (_, f)| acc | f);
+        let (c, p, z, s, o) = (flags & cf != 0, flags & pf != 0, flags & zf != 0, flags & sf != 0, flags & of != 0);
+        let taken = [o, !o, c, !c, z, !z, c || z, !c && !z, s, !s, p, !p, s != o, s == o, z || s != o, !z && s == o];
+        for (n, &want) in taken.iter().enumerate() {
+            cpu.load_code_vec(&[0x70 + n as u8, 0x02, 0x90, 0x90, 0x90]);
+            cpu.regs.ip = 0;
+            cpu.regs.flags.set_from_u16(flags | 2);
+            let i = cpu.fetch().unwrap();
+            cpu.execute(&i);
+            assert_eq!(cpu.regs.ip, if want { 4 } else { 2 }, "{:02x} with flags {:04x}", 0x70 + n, flags);
+        }
+    }
+}
+
+#[test]
+fn self_modifying_code() {
+    let run = |prefetch, code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.prefetch = prefetch;
+        cpu.load_code_vec(code);
+        cpu.fire();
+        (cpu.regs.ax, cpu.regs.bx)
+    };
+    // mov byte [5], 0x40 (inc ax) over the dec ax that follows
+    let patch = [0xc6, 0x06, 0x05, 0x00, 0x40, 0x48, 0xf4];
+    assert_eq!(run(cpu::Prefetch::Coherent, &patch).0, 1);
+    assert_eq!(run(cpu::Prefetch::Authentic, &patch).0, 0xffff);
+
+    // jmp $+2 flushes the queue
+    let flushed = [0xc6, 0x06, 0x07, 0x00, 0x40, 0xeb, 0x00, 0x48, 0xf4];
+    assert_eq!(run(cpu::Prefetch::Coherent, &flushed).0, 1);
+    assert_eq!(run(cpu::Prefetch::Authentic, &flushed).0, 1);
+
+    // mov di, 12; mov cx, 6; mov al, 0x43 (inc bx); rep stosb over six dec bx
+    let mut stos = vec![0xbf, 0x0c, 0x00, 0xb9, 0x06, 0x00, 0xb0, 0x43, 0xf3, 0xaa, 0x90, 0x90];
+    stos.extend([0x4b; 6]);
+    stos.push(0xf4);
+    assert_eq!(run(cpu::Prefetch::Coherent, &stos).1, 6);
+    // the queue already held 12..15 when the nops ran
+    assert_eq!(run(cpu::Prefetch::Authentic, &stos).1, 0xfffe);
+}
+
+#[test]
+fn ivt_init() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.init_ivt();
+    assert_eq!(cpu.vector(0x21), cpu::IRET_STUB);
+    assert_eq!(cpu.vector(0xff), cpu::IRET_STUB);
+    cpu.set_vector(0x60, 0x1234, 0x5678);
+    assert_eq!(cpu.vector(0x60), (0x1234, 0x5678));
+
+    // the program lives past the IVT: mov ax,1; int 0x21; inc ax; hlt
+    cpu.regs.cs = 0x20;
+    cpu.load_code_vec(&[0xb8, 0x01, 0x00, 0xcd, 0x21, 0x40, 0xf4]);
+    cpu.prog_size = 7;
+    cpu.fire();
+    assert_eq!((cpu.regs.cs, cpu.regs.ax, cpu.regs.sp), (0x20, 2, 4095));
+}
+
+#[test]
+fn hle_trampoline_chain() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.install_trampoline(0x13);
+    assert_eq!(cpu.vector(0x13), (cpu::HLE_SEGMENT + 0x13, 0));
+
+    // hook int 0x13, keeping the old vector at 0000:0100, and call it;
+    // the hook counts in bx and chains with pushf; call far [0x100]
+    cpu.regs.cs = 0x20;
+    cpu.load_code_vec(&[
+        0xa1, 0x4c, 0x00, // mov ax,[0x4c]
+        0xa3, 0x00, 0x01, // mov [0x100],ax
+        0xa1, 0x4e, 0x00, // mov ax,[0x4e]
+        0xa3, 0x02, 0x01, // mov [0x102],ax
+        0xc7, 0x06, 0x4c, 0x00, 0x1b, 0x00, // mov word [0x4c],0x1b
+        0xc7, 0x06, 0x4e, 0x00, 0x20, 0x00, // mov word [0x4e],0x20
+        0xcd, 0x13, // int 0x13
+        0xf4, // hlt
+        0x43, // hook: inc bx
+        0x9c, // pushf
+        0xff, 0x1e, 0x00, 0x01, // call far [0x100]
+        0xca, 0x02, 0x00, // retf 2
+    ]);
+    let mut served = 0;
+    while let Some(i) = cpu.fetch() {
+        cpu.execute(&i);
+        // the host service reports an error in CF
+        if cpu.host_int.take() == Some(0x13) {
+            served += 1;
+            cpu.regs.flags.set_cf();
+        }
+        if cpu.halt {
+            break;
+        }
+    }
+    assert_eq!((served, cpu.regs.bx, cpu.regs.ip), (1, 1, 0x1b));
+    assert_eq!((cpu.regs.cs, cpu.regs.sp), (0x20, 4095));
+    assert!(cpu.regs.flags.cf());
+}
+
+#[test]
+fn run_until_reasons() {
+    use cpu::RunExit;
+    // mov ax,0x4c00; inc cx; int 0x13; inc dx; hlt
+    let code = [0xb8, 0x00, 0x4c, 0x41, 0xcd, 0x13, 0x42, 0xf4];
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(0x13);
+    cpu.load_code_vec(&code);
+    assert_eq!(cpu.run_until(|cpu| cpu.regs.ax == 0x4c00), RunExit::Predicate);
+    assert_eq!(cpu.regs.ip, 3);
+    assert_eq!(cpu.run_until(|cpu| (0x10..0x20).contains(&cpu.regs.ip)), RunExit::HostInt(0x13));
+    cpu.host_int = None;
+    assert_eq!(cpu.run_until(|_| false), RunExit::Halted);
+    assert_eq!((cpu.regs.cx, cpu.regs.dx), (1, 1));
+
+    // without the hlt the program runs off its end
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&code[..4]);
+    assert_eq!(cpu.run_until(|_| false), RunExit::EndOfProgram);
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn debugger_finish_and_skip() {
+    use crate::debugger::{Debugger, FrameKind};
+    // call 7; inc cx; hlt; nop; nop
+    // 7: call 0c; inc dx; ret
+    // c: inc bx; inc bx; ret
+    let code = [
+        0xe8, 0x04, 0x00, 0x41, 0xf4, 0x90, 0x90, 0xe8, 0x02, 0x00, 0x42, 0xc3, 0x43, 0x43, 0xc3,
+    ];
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&code);
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.finish(&mut cpu, |_, _| {}), Err("not inside a call".to_string()));
+    debugger.step(&mut cpu);
+    debugger.step(&mut cpu);
+    let frames: Vec<_> = debugger.frames.iter().map(|f| (f.kind, f.call, f.ret)).collect();
+    assert_eq!(
+        frames,
+        [(FrameKind::Near, (0, 0), (0, 3)), (FrameKind::Near, (0, 7), (0, 0x0a))]
+    );
+
+    assert_eq!(debugger.finish(&mut cpu, |_, _| {}), Ok(None));
+    assert_eq!((cpu.regs.ip, cpu.regs.bx, debugger.frames.len()), (0x0a, 2, 1));
+    // inc dx never runs
+    assert_eq!(debugger.skip(&mut cpu), Some(0x0b));
+    assert_eq!(debugger.finish(&mut cpu, |_, _| {}), Ok(None));
+    assert_eq!((cpu.regs.ip, cpu.regs.dx, cpu.regs.sp), (3, 0, 4095));
+    assert!(debugger.frames.is_empty());
+
+    // an interrupt the host services pushes no frame; finish stops at hlt
+    let code = [0xe8, 0x00, 0x00, 0xcd, 0x13, 0xf4];
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(0x13);
+    cpu.load_code_vec(&code);
+    let mut debugger = Debugger::new();
+    debugger.step(&mut cpu);
+    let mut serviced = Vec::new();
+    let res = debugger.finish(&mut cpu, |_, vector| serviced.push(vector));
+    assert_eq!(res, Ok(Some(cpu::RunExit::Halted)));
+    assert_eq!(serviced, [0x13]);
+}
+
+#[test]
+fn exit_conventions() {
+    use cpu::RunExit;
+    let run = |exits: cpu::ExitConventions, code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.exits = exits;
+        cpu.load_code_vec(code);
+        (cpu.run_until(|_| false), cpu.exit_code, cpu.regs.bx)
+    };
+    let all = cpu::ExitConventions {
+        hlt: true,
+        dos: true,
+        jump_to_self: true,
+        port: Some(cpu::EXIT_PORT),
+    };
+    // mov ax,0x4c03; int 0x21; inc bx; hlt
+    let dos = [0xb8, 0x03, 0x4c, 0xcd, 0x21, 0x43, 0xf4];
+    assert_eq!(run(all, &dos), (RunExit::DosExit, Some(3), 0));
+
+    // mov al,5; out 0xf4,al; inc bx; hlt
+    let port = [0xb0, 0x05, 0xe6, 0xf4, 0x43, 0xf4];
+    assert_eq!(run(all, &port), (RunExit::ExitPort, Some(5), 0));
+    assert_eq!(run(cpu::ExitConventions::default(), &port), (RunExit::Halted, None, 1));
+
+    // inc bx; jmp $  and  stc; jb $
+    assert_eq!(run(all, &[0x43, 0xeb, 0xfe]), (RunExit::JumpToSelf, None, 1));
+    assert_eq!(run(all, &[0xf9, 0x72, 0xfe]).0, RunExit::JumpToSelf);
+
+    // hlt; inc bx: without the hlt convention hlt does not end the run
+    let no_hlt = cpu::ExitConventions { hlt: false, ..all };
+    assert_eq!(run(no_hlt, &[0xf4, 0x43]), (RunExit::EndOfProgram, None, 1));
+}
+
+#[test]
+fn power_control() {
+    use cpu::RunExit;
+    let run = |power: cpu::PowerControl, code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.power = power;
+        cpu.load_code_vec(code);
+        let exit = cpu.run_until(|cpu| cpu.regs.cs == 0xffff);
+        (exit, cpu.regs.bx, cpu.regs.cs, cpu.regs.ip)
+    };
+    // inc bx; mov al,0xfe; out 0x64,al; inc bx; hlt
+    let reset = [0x43, 0xb0, 0xfe, 0xe6, 0x64, 0x43, 0xf4];
+    assert_eq!(run(cpu::PowerControl::default(), &reset), (RunExit::Reset, 1, 0, 5));
+    // a reboot starts over at ffff:0000, with the registers cleared
+    let reboot = cpu::PowerControl {
+        reboot: true,
+        ..Default::default()
+    };
+    assert_eq!(run(reboot, &reset), (RunExit::Predicate, 0, 0xffff, 0));
+    // other commands to the controller do nothing
+    let command = [0x43, 0xb0, 0xd1, 0xe6, 0x64, 0x43, 0xf4];
+    assert_eq!(run(cpu::PowerControl::default(), &command), (RunExit::Halted, 2, 0, 7));
+
+    // inc bx; mov dx,0x8900; out dx,al; inc bx; hlt
+    let shutdown = [0x43, 0xba, 0x00, 0x89, 0xee, 0x43, 0xf4];
+    let power = cpu::PowerControl {
+        shutdown_port: Some(0x8900),
+        ..Default::default()
+    };
+    assert_eq!(run(power, &shutdown), (RunExit::PowerOff, 1, 0, 5));
+    assert_eq!(run(cpu::PowerControl::default(), &shutdown).0, RunExit::Halted);
+}
+
+#[test]
+fn exceptions_deliver() {
+    use cpu::RunExit;
+    let run = |vector: Option<(u16, u16)>, handler: &str| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.exceptions = cpu::Exceptions::Deliver;
+        cpu.rom.push((0xf0000, 0x100000));
+        if let Some((seg, off)) = vector {
+            cpu.set_vector(cpu::DIVIDE_ERROR, seg, off);
+        }
+        // the program lives past the IVT, its handler at 0020:0007
+        cpu.regs.cs = 0x20;
+        let code = assemble(&format!(
+            "xor ax, ax
+            div al
+            div al
+            hlt
+        handler:
+            {}",
+            handler
+        ))
+        .unwrap();
+        cpu.load_code_vec(&code);
+        let exit = cpu.run_until(|_| false);
+        (exit, cpu.regs.bx, cpu.regs.sp, cpu.fatal_fault.unwrap_or_default())
+    };
+    // taken through int 0 and returned from past the div, twice
+    let (exit, bx, sp, _) = run(Some((0x20, 7)), "inc bx\n iret");
+    assert_eq!((exit, bx, sp), (RunExit::Halted, 2, 4095));
+
+    // a vector never set, one into a hole in ROM, and a handler that
+    // faults again before it returns all stop the run
+    let (exit, bx, _, why) = run(None, "iret");
+    assert_eq!((exit, bx), (RunExit::FatalFault, 0));
+    assert!(why.starts_with("attempt to divide by zero, and int 00h's handler at 0000:0000 was never set"), "{}", why);
+    let (exit, _, _, why) = run(Some((0xf000, 0x100)), "iret");
+    assert_eq!(exit, RunExit::FatalFault);
+    assert!(why.ends_with("at f000:0100 is in a ROM hole with nothing mapped"), "{}", why);
+    let (exit, bx, sp, why) = run(Some((0x20, 7)), "inc bx\n div al\n iret");
+    assert_eq!((exit, bx, sp), (RunExit::FatalFault, 1, 4089));
+    assert!(why.ends_with("inside int 00h's handler for the last one"), "{}", why);
+}
+
+#[test]
+fn exceptions_deliver_after_return() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.exceptions = cpu::Exceptions::Deliver;
+    cpu.regs.cs = 0x20;
+    // a divide error handled and returned from, then another one deeper
+    // in the stack, inside a subroutine
+    let code = assemble(
+        "jmp start
+    handler:
+        inc bx
+        iret
+    start:
+        xor ax, ax
+        div al
+        call sub
+        hlt
+    sub:
+        div al
+        ret",
+    )
+    .unwrap();
+    cpu.set_vector(cpu::DIVIDE_ERROR, 0x20, 2);
+    cpu.load_code_vec(&code);
+    let exit = cpu.run_until(|_| false);
+    assert_eq!(cpu.fatal_fault, None);
+    assert_eq!((exit, cpu.regs.bx, cpu.regs.sp), (cpu::RunExit::Halted, 2, 4095));
+}
+
+#[test]
+fn livelock_detector() {
+    use cpu::RunExit;
+    let run = |code: &[u8], limit| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.livelock_limit = limit;
+        cpu.load_code_vec(code);
+        (cpu.run_until(|cpu| cpu.stats.instructions >= 10_000), cpu.regs.ip)
+    };
+    // cli; l: nop; jmp l
+    let spin = [0xfa, 0x90, 0xeb, 0xfd];
+    assert_eq!(run(&spin, Some(cpu::LIVELOCK_LIMIT)), (RunExit::Livelock, 1));
+    assert_eq!(run(&spin, None).0, RunExit::Predicate);
+    // with interrupts enabled one could still end it
+    assert_eq!(run(&[0xfb, 0x90, 0xeb, 0xfd], Some(10)).0, RunExit::Predicate);
+    // cli; mov cx,2000; l: loop l; hlt counts down
+    assert_eq!(run(&[0xfa, 0xb9, 0xd0, 0x07, 0xe2, 0xfe, 0xf4], Some(10)).0, RunExit::Halted);
+    // cli; l: in al,0x60; test al,al; jnz l  waits on a device
+    assert_eq!(run(&[0xfa, 0xe4, 0x60, 0x84, 0xc0, 0x75, 0xfa], Some(10)).0, RunExit::Predicate);
+    // cli; l: mov al,[0x100]; test al,al; jz l  waits on memory nothing writes
+    assert_eq!(run(&[0xfa, 0xa0, 0x00, 0x01, 0x84, 0xc0, 0x74, 0xf9], Some(10)), (RunExit::Livelock, 1));
+}
+
+#[test]
+fn snapshot_diff() {
+    use crate::snapshot::{MemChange, Snapshot};
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov di,0x200; mov cx,3; mov al,7; rep stosb; mov [0x20a],al; stc; hlt
+    cpu.load_code_vec(&[
+        0xbf, 0x00, 0x02, 0xb9, 0x03, 0x00, 0xb0, 0x07, 0xf3, 0xaa, 0xa2, 0x0a, 0x02, 0xf9, 0xf4,
+    ]);
+    let before = Snapshot::of(&cpu);
+    cpu.fire();
+    let after = Snapshot::of(&cpu);
+
+    let mut saved = Vec::new();
+    after.write(&mut saved).unwrap();
+    assert!(Snapshot::read(&mut saved.as_slice()).unwrap() == after);
+    assert!(Snapshot::read(&mut &saved[1..]).is_err());
+    assert!(before.diff(&before).is_empty());
+
+    let diff = before.diff(&after);
+    let names: Vec<&str> = diff.registers.iter().map(|r| r.0).collect();
+    assert_eq!(names, ["AX", "DI", "IP"]);
+    assert!(diff.registers.contains(&("DI", 0, 0x203)));
+    assert_eq!(diff.flags, [("Carry", false, true)]);
+    // the gap of six untouched bytes is bridged
+    assert_eq!(
+        diff.memory,
+        [MemChange {
+            start: 0x200,
+            before: vec![0; 11],
+            after: vec![7, 7, 7, 0, 0, 0, 0, 0, 0, 0, 7],
+        }]
+    );
+
+    let mut text = Vec::new();
+    diff.write_text(&mut text).unwrap();
+    assert!(String::from_utf8(text).unwrap().contains("00200+11 00 00"));
+
+    before.restore(&mut cpu);
+    assert!(Snapshot::of(&cpu) == before);
+}
+
+#[test]
+fn checkpoint_resume() {
+    use crate::snapshot::{Checkpoint, Snapshot};
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov cx,5; l: add ax,cx; mov [0x200],ax; loop l; hlt
+    cpu.load_code_vec(&[0xb9, 0x05, 0x00, 0x01, 0xc8, 0xa3, 0x00, 0x02, 0xe2, 0xf9, 0xf4]);
+    for _ in 0..4 {
+        let i = cpu.fetch().unwrap();
+        cpu.execute(&i);
+    }
+    let checkpoint = Checkpoint::of(&cpu);
+    cpu.fire();
+    let end = (Snapshot::of(&cpu), cpu.stats.instructions);
+    assert_eq!(cpu.regs.ax, 15);
+
+    checkpoint.restore(&mut cpu);
+    assert_eq!((cpu.stats.instructions, cpu.regs.ax), (4, 5));
+    assert!(!cpu.halt);
+    cpu.fire();
+    assert!((Snapshot::of(&cpu), cpu.stats.instructions) == end);
+}
+
+#[test]
+fn operand_validation() {
+    use cpu::Operand::*;
+    let inst = Instruction::new;
+    assert!(inst(Opcode::Mov, Reg16(0), Imm16(5)).validate().is_ok());
+    assert!(inst(Opcode::Mov, Seg(3), Mem16(0x10, 0x10)).validate().is_ok());
+    assert!(inst(Opcode::Mov, Imm16(5), Reg16(0)).validate().is_err());
+    assert!(inst(Opcode::Mov, Seg(0), Seg(3)).validate().is_err());
+    assert!(inst(Opcode::Mov, Reg8(0), Reg16(0)).validate().is_err());
+    assert!(inst(Opcode::Add, Mem8(0, 0), Mem8(1, 1)).validate().is_err());
+    assert!(inst(Opcode::Xchg, Reg16(0), Imm16(1)).validate().is_err());
+    assert!(inst(Opcode::Add, Seg(3), Reg16(0)).validate().is_err());
+    assert!(inst(Opcode::Shl, Reg8(0), Reg8(2)).validate().is_err());
+    assert!(inst(Opcode::Jz, Reg8(0), Reg8(0)).validate().is_err());
+    assert!(inst(Opcode::Lds, Reg16(0), Reg16(1)).validate().is_err());
+    assert_eq!(
+        inst(Opcode::Pop, Reg8(0), Reg8(0)).validate(),
+        Err("pops into something other than a word register or memory")
+    );
+
+    // mov ax,0x1234; push ax (ff /6); pop bx; mov [0x200],bx; mov es,[0x200]; mov bl,7; xchg ax,bx; hlt
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xb8, 0x34, 0x12, 0xff, 0xf0, 0x5b, 0x89, 0x1e, 0x00, 0x02, 0x8e, 0x06, 0x00, 0x02, 0xb3, 0x07, 0x93, 0xf4,
+    ]);
+    cpu.fire();
+    assert_eq!((cpu.regs.ax, cpu.regs.bx, cpu.regs.es), (0x1207, 0x1234, 0x1234));
+}
+
+#[test]
+fn fuzz_programs() {
+    use crate::fuzz::{self, Outcome, Rng};
+    assert_eq!(fuzz::run(&[0xf4], fuzz::LIMIT), Outcome::Exit(cpu::RunExit::Halted));
+    assert_eq!(fuzz::run(&[0xeb, 0xfe], 100), Outcome::Limit);
+    assert!(matches!(fuzz::run(&[0x31, 0xc0, 0xf6, 0xf0], 100), Outcome::Fault(_)));
+    assert_eq!(
+        fuzz::run(&[0x90, 0x8c, 0xf0], 100),
+        Outcome::Decode("invalid encoding 8c f0: segment register field above 3".to_string())
+    );
+
+    // the emulator's own bugs pass through as panics
+    let mut rng = Rng::new(1);
+    let mut program = [0u8; 64];
+    for _ in 0..500 {
+        rng.fill(&mut program);
+        let res = std::panic::catch_unwind(|| fuzz::run(&program, 1000));
+        assert!(res.is_ok(), "{:02x?}", program);
+    }
+}
+
+#[test]
+fn poisoned_memory() {
+    let run = |code: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        // guard bytes around a 16-byte buffer at 0x300
+        cpu.poison = vec![(0x2ff, 0x300), (0x310, 0x311)];
+        cpu.load_code_vec(code);
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cpu.fire();
+            cpu.regs.di
+        }))
+        .map_err(|e| e.downcast::<String>().map(|s| *s).unwrap_or_default())
+    };
+    // mov di,0x300; mov cx,N; mov al,1; rep stosb; hlt
+    let fill = |n: u8| [0xbf, 0x00, 0x03, 0xb9, n, 0x00, 0xb0, 0x01, 0xf3, 0xaa, 0xf4];
+    assert_eq!(run(&fill(16)), Ok(0x310));
+    assert_eq!(
+        run(&fill(17)),
+        Err("write of poisoned memory at 00310 (poisoned 00310-00310)".to_string())
+    );
+    // mov ax,[0x2fe] reads one byte of each
+    assert!(run(&[0xa1, 0xfe, 0x02, 0xf4]).unwrap_err().starts_with("read of poisoned memory at 002fe"));
+}
+
+#[test]
+fn cpu_detection_quirks() {
+    use cpu::CpuModel;
+    let detect = |model: CpuModel| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.quirks = model.quirks();
+        cpu.load_code_vec(&[
+            0x9c, 0x58, // pushf; pop ax
+            0x25, 0xff, 0x0f, // and ax,0x0fff
+            0x50, 0x9d, 0x9c, 0x5b, // push ax; popf; pushf; pop bx
+            0x54, 0x5f, // push sp; pop di
+            0xb1, 0x21, 0xba, 0x01, 0x00, 0xd3, 0xe2, // mov cl,33; mov dx,1; shl dx,cl
+            0xf4,
+        ]);
+        cpu.fire();
+        (cpu.regs.bx & 0xf000, cpu.regs.di, cpu.regs.dx)
+    };
+    // FLAGS bits 12-15, what push sp pushed (SP is 4095), shl by 33
+    assert_eq!(detect(CpuModel::I8086), (0xf000, 4093, 0));
+    assert_eq!(detect(CpuModel::I80186), (0xf000, 4093, 2));
+    assert_eq!(detect(CpuModel::I80286), (0, 4095, 2));
+
+    // stc; mov ax,0x8000; mov cl,32; rol ax,cl: the 8086 rotates 32
+    // times, the 80186 masks the count to 0 and leaves CF and AX be
+    let rol = |model: CpuModel| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.quirks = model.quirks();
+        cpu.load_code_vec(&[0xf9, 0xb8, 0x00, 0x80, 0xb1, 0x20, 0xd3, 0xc0, 0xf4]);
+        cpu.fire();
+        (cpu.regs.ax, cpu.regs.flags.cf())
+    };
+    assert_eq!(rol(CpuModel::I8086), (0x8000, false));
+    assert_eq!(rol(CpuModel::I80186), (0x8000, true));
+}
+
+#[test]
+fn assemble_program() {
+    use crate::asm::assemble_at;
+    // the same bytes the raw vectors of flow1 spelled out
+    let code = assemble("mov ax, 0\nmov cx, 1\ncmp ax, cx\nja up\njmp end\nup: mov ax, 69\nend:").unwrap();
+    assert_eq!(code, [184, 0, 0, 185, 1, 0, 57, 200, 119, 2, 235, 3, 184, 69, 0]);
+
+    // a jump that only fits once its label is known grows to near
+    let mut source = "jmp far_off\nloop: loop loop\n".to_string();
+    source.push_str(&"nop\n".repeat(200));
+    source.push_str("far_off: mov si, msg ; labels as numbers\nmsg: db 'hi'");
+    let code = assemble_at(&source, 0x100).unwrap();
+    assert_eq!(code[..5], [0xe9, 0xca, 0x00, 0xe2, 0xfe]);
+    assert_eq!(code[205..], [0xbe, 0xd0, 0x01, b'h', b'i']);
+
+    assert_eq!(assemble("nop\njz nowhere").unwrap_err(), "line 2: bad operand nowhere");
+    assert_eq!(assemble("a: nop\na: nop").unwrap_err(), "line 2: a is already defined");
+}
+
+#[test]
+fn assemble_lines() {
+    use crate::asm::assemble_line;
+    let cases: [(&str, u16, &[u8]); 30] = [
+        ("mov ax, 5", 0, &[0xb8, 0x05, 0x00]),
+        ("MOV AL,'A'", 0, &[0xb0, 0x41]),
+        ("add ax, bx", 0, &[0x01, 0xd8]),
+        ("add byte [bx+si+2], al", 0, &[0x00, 0x40, 0x02]),
+        ("mov [0x200], ax", 0, &[0xa3, 0x00, 0x02]),
+        ("mov word ptr [bp], 7", 0, &[0xc7, 0x46, 0x00, 0x07, 0x00]),
+        ("sub cx, 1", 0, &[0x81, 0xe9, 0x01, 0x00]),
+        ("cmp al, 10", 0, &[0x3c, 0x0a]),
+        ("xchg dx, ax", 0, &[0x92]),
+        ("inc si", 0, &[0x46]),
+        ("dec byte [di]", 0, &[0xfe, 0x0d]),
+        ("push es", 0, &[0x06]),
+        ("mov ds, ax", 0, &[0x8e, 0xd8]),
+        ("shl dx, cl", 0, &[0xd3, 0xe2]),
+        ("shr al, 1", 0, &[0xd0, 0xe8]),
+        ("jmp 100h", 0x100, &[0xeb, 0xfe]),
+        ("jz 0x110", 0x100, &[0x74, 0x0e]),
+        ("jmp near 0x102", 0x100, &[0xe9, 0xff, 0xff]),
+        ("call 0x200", 0x100, &[0xe8, 0xfd, 0x00]),
+        ("jmp 0xf000:0", 0, &[0xea, 0x00, 0x00, 0x00, 0xf0]),
+        ("int 0x21", 0, &[0xcd, 0x21]),
+        ("int 3", 0, &[0xcc]),
+        ("rep movsb", 0, &[0xf3, 0xa4]),
+        ("mov al, es:[di]", 0, &[0x26, 0x8a, 0x05]),
+        ("in al, dx ; poll", 0, &[0xec]),
+        ("out 0xe9, al", 0, &[0xe6, 0xe9]),
+        ("db 'hi', 0", 0, &[0x68, 0x69, 0x00]),
+        ("lea si, [bx+0x1234]", 0, &[0x8d, 0xb7, 0x34, 0x12]),
+        ("call far [bx]", 0, &[0xff, 0x1f]),
+        ("ret 4", 0, &[0xc2, 0x04, 0x00]),
+    ];
+    for (line, ip, bytes) in cases {
+        assert_eq!(assemble_line(line, ip).as_deref(), Ok(bytes), "{}", line);
+    }
+    for line in ["mov [bx], 5", "jz 0x200", "shl ax, 2", "mov al, 300", "mov ax, bl", "frob ax"] {
+        assert!(assemble_line(line, 0).is_err(), "{}", line);
+    }
+
+    // what it assembles runs as written
+    let program = [
+        "mov bx, 0x300",
+        "mov cx, 3",
+        "add word [bx], cx",
+        "loop 6",
+        "mov ax, [0x300]",
+        "hlt",
+    ];
+    let mut code = Vec::new();
+    for line in program {
+        code.extend(assemble_line(line, code.len() as u16).unwrap());
+    }
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 6);
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn debugger_assemble() {
+    use crate::debugger::Debugger;
+    // mov ax,1; hlt
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[0xb8, 0x01, 0x00, 0xf4]);
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.assemble(&mut cpu, (0, 0), "mov ax, 5"),
+        Ok(vec![0xb8, 0x05, 0x00])
+    );
+    // patched past the end of the program, which grows to run it
+    assert_eq!(debugger.assemble(&mut cpu, (0, 3), "jmp 0x10"), Ok(vec![0xeb, 0x0b]));
+    assert_eq!(debugger.assemble(&mut cpu, (0, 0x10), "inc ax"), Ok(vec![0x40]));
+    debugger.assemble(&mut cpu, (0, 0x11), "hlt").unwrap();
+    assert!(debugger.assemble(&mut cpu, (0, 0x12), "mov ax").is_err());
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 6);
+}
+
+#[test]
+fn prefixes_and_modrm() {
+    use crate::decode::{ModRm, Prefixes, Rep};
+    let parse = |bytes: &[u8]| {
+        let mut bytes = bytes.iter().copied();
+        Prefixes::parse(|| bytes.next().unwrap())
+    };
+    let (prefixes, opcode) = parse(&[0x26, 0xf3, 0xa4]);
+    assert_eq!((prefixes.segment, prefixes.rep, prefixes.lock), (Some(cpu::Segment::Es), Some(Rep::Rep), false));
+    assert_eq!(opcode, 0xa4);
+    // any order, the last of a kind counting
+    let (prefixes, opcode) = parse(&[0xf2, 0x2e, 0xf0, 0xf3, 0x36, 0xa6]);
+    assert_eq!((prefixes.segment, prefixes.rep, prefixes.lock), (Some(cpu::Segment::Ss), Some(Rep::Rep), true));
+    assert_eq!(opcode, 0xa6);
+    assert!(parse(&[0x90]).0.is_empty());
+
+    let modrm = ModRm::new(0b10_011_110);
+    assert_eq!((modrm.mode, modrm.reg, modrm.rm), (2, 3, 6));
+    assert_eq!(modrm.byte(), 0b10_011_110);
+    assert!(!modrm.is_reg());
+    assert!(ModRm::new(0xc0).is_reg());
+    assert_eq!(
+        [0x06, 0x07, 0x47, 0x87, 0xc6].map(|b| ModRm::new(b).displacement_len()),
+        [2, 0, 1, 2, 0]
+    );
+}
+
+#[test]
+fn decode_table() {
+    use crate::decode::{Entry, Prefixes, TABLE};
+    use crate::opcodes::OPCODES;
+    // everything the opcode listing has decodes, bar the prefixes and the
+    // coprocessor escapes
+    for info in OPCODES.iter().filter(|i| !Prefixes::default().add(i.opcode) && !(0xd8..=0xdf).contains(&i.opcode)) {
+        let defined = match (TABLE[info.opcode as usize], info.reg) {
+            (Entry::Op(..), None) => true,
+            (Entry::Group(ops, _), Some(reg)) => ops[reg as usize].is_some(),
+            _ => false,
+        };
+        assert!(defined, "{:02x} /{:?} {}", info.opcode, info.reg, info.mnemonic);
+    }
+
+    let decode = |bytes: &[u8]| {
+        let mut cpu = Cpu::init();
+        cpu.test_mode();
+        cpu.load_code_vec(bytes);
+        let i = cpu.fetch().unwrap();
+        (i.opcode(), i.operands(), cpu.regs.ip)
+    };
+    use Operand::*;
+    // xchg bx,ax; dec al; or cx,-128; xor al,1 through 82; jmp bx
+    assert_eq!(decode(&[0x87, 0xd8]), (Opcode::Xchg, (Reg16(3), Reg16(0)), 2));
+    assert_eq!(decode(&[0xfe, 0xc8]), (Opcode::Dec, (Reg8(0), decode::UNUSED), 2));
+    assert_eq!(decode(&[0x83, 0xc9, 0x80]), (Opcode::Or, (Reg16(1), Imm16(0xff80)), 3));
+    assert_eq!(decode(&[0x82, 0xf0, 0x01]), (Opcode::Xor, (Reg8(0), Imm8(1)), 3));
+    assert_eq!(decode(&[0xff, 0xe3]), (Opcode::JmpNear, (Reg16(3), decode::UNUSED), 2));
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[
+        0xb8, 0x05, 0x00, // mov ax,5
+        0xbb, 0x02, 0x01, // mov bx,0x102
+        0x87, 0xd8, // xchg bx,ax
+        0xfe, 0xc8, // dec al
+        0xff, 0xcb, // dec bx
+        0xf4,
+    ]);
+    cpu.fire();
+    assert_eq!((cpu.regs.ax, cpu.regs.bx), (0x101, 4));
+}
+
+#[test]
+fn pure_decoder() {
+    use crate::decode::Decoder;
+    // mov ax,[bp+si+4]; jmp short 0; a word of ds:[1234h]
+    let code = [0x8b, 0x42, 0x04, 0xeb, 0xfb, 0xa1, 0x34, 0x12];
+    let mut decoder = Decoder::new(0, |ip| code[ip as usize]);
+    let ea = |base, index, disp, seg| cpu::Ea { base, index, disp, seg };
+    let i = decoder.decode().unwrap();
+    assert_eq!(
+        i.operands(),
+        (Operand::Reg16(0), Operand::Ea16(ea(Some(5), Some(6), 4, cpu::Segment::Ss)))
+    );
+    let i = decoder.decode().unwrap();
+    assert_eq!((i.opcode(), i.operands().0), (Opcode::JmpNear, Operand::Rel8(0)));
+    let i = decoder.decode().unwrap();
+    assert_eq!(i.operands().1, Operand::Ea16(ea(None, None, 0x1234, cpu::Segment::Ds)));
+    assert_eq!(decoder.ip(), 8);
+    let err = Decoder::new(0, |_| 0x60).decode().unwrap_err();
+    assert_eq!(err, "invalid encoding 60: undefined opcode");
+
+    // the address is worked out when the instruction runs, from the
+    // registers as they are then
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.load_code_vec(&[0x8b, 0x07, 0xf4]); // mov ax,[bx]
+    let (inst, next) = cpu.decode().unwrap().unwrap();
+    assert_eq!((cpu.regs.ip, next), (0, 2));
+    cpu.write_mem_u16(0x20, 0xbeef);
+    cpu.regs.bx = 0x20;
+    cpu.execute(&inst);
+    assert_eq!(cpu.regs.ax, 0xbeef);
+}
+
+#[test]
+fn display_intel() {
+    use crate::{asm::assemble_line, decode::Decoder};
+    let text = |code: &[u8]| {
+        let mut decoder = Decoder::new(0, |ip| code[ip as usize]);
+        decoder.decode().unwrap().to_string()
+    };
+    let cases: [(&[u8], &str); 14] = [
+        (&[0x8b, 0x40, 0x04], "mov ax, [bx+si+4]"),
+        (&[0x8b, 0x46, 0xfe], "mov ax, [bp-2]"),
+        (&[0x26, 0x88, 0x05], "mov [es:di], al"),
+        (&[0x3e, 0x8b, 0x46, 0x00], "mov ax, [ds:bp]"),
+        (&[0x80, 0x07, 0x7f], "add byte [bx], 0x7f"),
+        (&[0xc7, 0x06, 0x34, 0x12, 0x05, 0x00], "mov word [0x1234], 5"),
+        (&[0xd1, 0x27], "shl word [bx], 1"),
+        (&[0xd2, 0xe0], "shl al, cl"),
+        (&[0xee], "out dx, al"),
+        (&[0xe6, 0x20], "out 0x20, al"),
+        (&[0xf3, 0xa4], "rep movsb"),
+        (&[0xf3, 0xa6], "repe cmpsb"),
+        (&[0x26, 0xac], "es lodsb"),
+        (&[0x40], "inc ax"),
+    ];
+    for (code, want) in cases {
+        assert_eq!(text(code), want);
+    }
+    assert_eq!(text(&[0x1e]), "push ds");
+    assert_eq!(text(&[0x90]), "nop");
+    assert_eq!(text(&[0xea, 0x00, 0x01, 0x00, 0xf0]), "jmp 0xf000:0x100");
+    assert_eq!(text(&[0xff, 0x1f]), "call far [bx]");
+    assert_eq!(text(&[0xd4, 0x0a]), "aam");
+    assert_eq!(text(&[0xf0, 0xfe, 0x07]), "lock inc byte [bx]");
+    // what the trace shows assembles back to the same bytes
+    for code in [&[0x8b, 0x40, 0x04][..], &[0x80, 0x07, 0x7f], &[0xe6, 0x20], &[0xd1, 0x27], &[0x26, 0x88, 0x05]] {
+        assert_eq!(assemble_line(&text(code), 0).as_deref(), Ok(code));
+    }
+}
+
+#[test]
+fn disassemble_listing() {
+    use crate::disasm::{disassemble, Line, Syntax};
+    // mov ax,0x201; jmp short to itself; an undefined opcode; int cut short
+    let code = [0xb8, 0x01, 0x02, 0xeb, 0xfe, 0x60, 0xcd];
+    let lines = disassemble(&code, 0x100, Syntax::Intel);
+    let line = |addr, bytes, text: &str| Line { addr, bytes, text: text.to_string() };
+    assert_eq!(
+        lines,
+        [
+            line(0x100, &code[..3], "mov ax, 0x201"),
+            line(0x103, &code[3..5], "jmp 0x103"),
+            line(0x105, &code[5..6], "db 0x60"),
+            line(0x106, &code[6..], "db 0xcd"),
+        ]
+    );
+}
+
+#[test]
+fn display_att() {
+    use crate::disasm::{disassemble, Syntax};
+    let text = |code: &[u8]| disassemble(code, 0, Syntax::Att).remove(0).text;
+    assert_eq!(text(&[0x8b, 0x40, 0x04]), "mov 4(%bx,%si),%ax");
+    assert_eq!(text(&[0x8b, 0x46, 0xfe]), "mov -2(%bp),%ax");
+    assert_eq!(text(&[0x26, 0x88, 0x05]), "mov %al,%es:(%di)");
+    assert_eq!(text(&[0x80, 0x07, 0x7f]), "addb $0x7f,(%bx)");
+    assert_eq!(text(&[0xa1, 0x34, 0x12]), "mov 0x1234,%ax");
+    assert_eq!(text(&[0xee]), "out %al,(%dx)");
+    assert_eq!(text(&[0xe4, 0x20]), "in $0x20,%al");
+    assert_eq!(text(&[0xff, 0xe3]), "jmp *%bx");
+    assert_eq!(text(&[0xff, 0x1f]), "lcall *(%bx)");
+    assert_eq!(text(&[0xea, 0x00, 0x01, 0x00, 0xf0]), "ljmp $0xf000,$0x100");
+    assert_eq!(text(&[0xf3, 0xa4]), "rep movsb");
+    assert_eq!(text(&[0x1e]), "push %ds");
+    assert_eq!(text(&[0x98]), "cbtw");
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::regs::Registers;
+
+/// Something that happened at a point on the timeline. Timestamps are
+/// instruction counts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelineEvent {
+    /// Straight-line run of instructions starting at `addr`.
+    Range {
+        addr: u32,
+        ts: u64,
+        dur: u64,
+    },
+    Interrupt {
+        vector: u8,
+        ts: u64,
+    },
+    Port {
+        port: u16,
+        val: u16,
+        write: bool,
+        ts: u64,
+    },
+}
+
+/// Records execution as a timeline that can be exported in the Chrome
+/// trace event format (chrome://tracing, ui.perfetto.dev).
+#[derive(Default)]
+pub struct Timeline {
+    pub events: Vec<TimelineEvent>,
+    // start address and timestamp of the run being built
+    run: Option<(u32, u64)>,
+    next_addr: u32,
+    now: u64,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn close_run(&mut self) {
+        if let Some((addr, ts)) = self.run.take() {
+            self.events.push(TimelineEvent::Range {
+                addr,
+                ts,
+                dur: self.now - ts,
+            });
+        }
+    }
+
+    pub fn instruction(&mut self, addr: u32, len: u8) {
+        if self.run.is_some() && addr != self.next_addr {
+            self.close_run();
+        }
+        if self.run.is_none() {
+            self.run = Some((addr, self.now));
+        }
+        self.next_addr = addr + len as u32;
+        self.now += 1;
+    }
+
+    // events fire while executing the instruction fetched last
+    fn current(&self) -> u64 {
+        self.now.saturating_sub(1)
+    }
+
+    pub fn interrupt(&mut self, vector: u8) {
+        self.events.push(TimelineEvent::Interrupt {
+            vector,
+            ts: self.current(),
+        });
+    }
+
+    pub fn port(&mut self, port: u16, val: u16, write: bool) {
+        self.events.push(TimelineEvent::Port {
+            port,
+            val,
+            write,
+            ts: self.current(),
+        });
+    }
+
+    /// Closes the run in progress; call once execution stopped.
+    pub fn finish(&mut self) {
+        self.close_run();
+    }
+
+    pub fn write_chrome_json(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        for (tid, name) in [(1, "cpu"), (2, "interrupts"), (3, "ports")] {
+            writeln!(
+                w,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}},",
+                tid, name
+            )?;
+        }
+        for (i, e) in self.events.iter().enumerate() {
+            match e {
+                TimelineEvent::Range { addr, ts, dur } => write!(
+                    w,
+                    "{{\"name\":\"{:05x}\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":{},\"dur\":{}}}",
+                    addr, ts, dur
+                )?,
+                TimelineEvent::Interrupt { vector, ts } => write!(
+                    w,
+                    "{{\"name\":\"int {:02x}h\",\"ph\":\"i\",\"s\":\"t\",\"pid\":1,\"tid\":2,\"ts\":{}}}",
+                    vector, ts
+                )?,
+                TimelineEvent::Port {
+                    port,
+                    val,
+                    write,
+                    ts,
+                } => write!(
+                    w,
+                    "{{\"name\":\"{} {:04x}h\",\"ph\":\"i\",\"s\":\"t\",\"pid\":1,\"tid\":3,\"ts\":{},\"args\":{{\"value\":{}}}}}",
+                    if *write { "out" } else { "in" },
+                    port,
+                    ts,
+                    val
+                )?,
+            }
+            writeln!(w, "{}", if i + 1 < self.events.len() { "," } else { "" })?;
+        }
+        writeln!(w, "]}}")
+    }
+}
+
+/// Steps kept by a [`Recording`]; later ones are dropped.
+pub const MAX_RECORDED_STEPS: usize = 100_000;
+
+const REG_NAMES: [&str; 14] = [
+    "AX", "BX", "CX", "DX", "SI", "DI", "SP", "BP", "CS", "DS", "ES", "SS", "IP", "FLAGS",
+];
+
+fn reg_array(regs: &Registers) -> [u16; 14] {
+    [
+        regs.ax,
+        regs.bx,
+        regs.cx,
+        regs.dx,
+        regs.si,
+        regs.di,
+        regs.sp,
+        regs.bp,
+        regs.cs,
+        regs.ds,
+        regs.es,
+        regs.ss,
+        regs.ip,
+        regs.flags.to_u16(),
+    ]
+}
+
+/// One executed instruction and the registers before it ran.
+pub struct TraceStep {
+    pub cs: u16,
+    pub ip: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    pub regs: [u16; 14],
+    /// Bytes stored while it ran, in order.
+    pub writes: Vec<(u32, u8)>,
+}
+
+/// Step-by-step record of a run, replayable in the HTML viewer.
+#[derive(Default)]
+pub struct Recording {
+    pub steps: Vec<TraceStep>,
+    pub truncated: bool,
+    // 16-byte memory lines as they were before their first write
+    original: BTreeMap<u32, [u8; 16]>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(&mut self, regs: &Registers, bytes: &[u8], text: String) {
+        if self.steps.len() == MAX_RECORDED_STEPS {
+            self.truncated = true;
+            return;
+        }
+        self.steps.push(TraceStep {
+            cs: regs.cs,
+            ip: regs.ip,
+            bytes: bytes.to_vec(),
+            text,
+            regs: reg_array(regs),
+            writes: Vec::new(),
+        });
+    }
+
+    /// Called before `val` is stored at `addr`; `mem` is still unchanged.
+    pub fn write(&mut self, addr: u32, val: u8, mem: &[u8]) {
+        if self.truncated {
+            return;
+        }
+        let line = addr & !15;
+        self.original
+            .entry(line)
+            .or_insert_with(|| line_of(mem, line));
+        if let Some(step) = self.steps.last_mut() {
+            step.writes.push((addr, val));
+        }
+    }
+
+    /// Writes a single HTML page that steps through the recording. `mem`
+    /// is memory at the end of the run; the lines of `image` are shown
+    /// next to every line that was written.
+    pub fn write_html(
+        &self,
+        w: &mut impl Write,
+        mem: &[u8],
+        image: (u32, u32),
+        last: &Registers,
+    ) -> io::Result<()> {
+        let (head, tail) = HTML_TEMPLATE
+            .split_once("/*TRACE*/")
+            .expect("template placeholder");
+        write!(w, "{}", head)?;
+
+        write!(w, "{{\"names\":[")?;
+        for (i, name) in REG_NAMES.iter().enumerate() {
+            write!(w, "{}\"{}\"", if i > 0 { "," } else { "" }, name)?;
+        }
+        write!(w, "],\"truncated\":{},\"steps\":[", self.truncated)?;
+        for (i, step) in self.steps.iter().enumerate() {
+            let bytes: Vec<String> = step.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            write!(
+                w,
+                "{}\n{{\"at\":\"{:04x}:{:04x}\",\"bytes\":\"{}\",\"text\":{},\"regs\":{:?},\"writes\":{:?}}}",
+                if i > 0 { "," } else { "" },
+                step.cs,
+                step.ip,
+                bytes.join(" "),
+                js_str(&step.text),
+                step.regs,
+                step.writes.iter().map(|&(a, v)| [a, v as u32]).collect::<Vec<_>>()
+            )?;
+        }
+        write!(w, "],\n\"last\":{:?},\"mem\":{{", reg_array(last))?;
+
+        let mut lines = self.original.clone();
+        let mut line = image.0 & !15;
+        while line < image.1 {
+            lines.entry(line).or_insert_with(|| line_of(mem, line));
+            line += 16;
+        }
+        for (i, (addr, bytes)) in lines.iter().enumerate() {
+            write!(
+                w,
+                "{}\n\"{}\":{:?}",
+                if i > 0 { "," } else { "" },
+                addr,
+                bytes
+            )?;
+        }
+        write!(w, "}}}}")?;
+        write!(w, "{}", tail)
+    }
+}
+
+fn line_of(mem: &[u8], line: u32) -> [u8; 16] {
+    let mut buf = [0u8; 16];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = mem.get(line as usize + i).copied().unwrap_or(0);
+    }
+    buf
+}
+
+/// JSON string that is also safe inside a `<script>` element.
+fn js_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '<' => out.push_str("\\u003c"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>emu8086 trace</title>
+<style>
+body { font-family: monospace; margin: 0; display: flex; height: 100vh; }
+#list { overflow-y: auto; width: 45%; border-right: 1px solid #ccc; }
+#list div { padding: 1px 6px; cursor: pointer; white-space: pre; }
+#list div.cur { background: #ffd54f; }
+#side { padding: 8px; overflow-y: auto; flex: 1; }
+table { border-collapse: collapse; }
+td { padding: 1px 6px; }
+.changed { color: #c62828; font-weight: bold; }
+.written { background: #ffcdd2; }
+</style>
+</head>
+<body>
+<div id="list"></div>
+<div id="side">
+<div><button id="prev">&lt; prev</button> <button id="next">next &gt;</button>
+<span id="pos"></span></div>
+<h3>Registers after the step</h3>
+<table id="regs"></table>
+<h3>Memory</h3>
+<table id="mem"></table>
+</div>
+<script>
+const trace = /*TRACE*/;
+const hex = (v, n) => v.toString(16).padStart(n, "0");
+const list = document.getElementById("list");
+trace.steps.forEach((s, i) => {
+  const d = document.createElement("div");
+  d.textContent = s.at + "  " + s.bytes.padEnd(18) + s.text;
+  d.onclick = () => show(i);
+  list.appendChild(d);
+});
+if (trace.truncated) {
+  const d = document.createElement("div");
+  d.textContent = "... trace truncated";
+  list.appendChild(d);
+}
+let cur = 0;
+function regsAfter(i) {
+  return i + 1 < trace.steps.length ? trace.steps[i + 1].regs : trace.last;
+}
+function show(i) {
+  if (i < 0 || i >= trace.steps.length) return;
+  list.children[cur].className = "";
+  cur = i;
+  list.children[cur].className = "cur";
+  list.children[cur].scrollIntoView({ block: "nearest" });
+  document.getElementById("pos").textContent = "step " + (i + 1) + " / " + trace.steps.length;
+
+  const before = trace.steps[i].regs, after = regsAfter(i);
+  let rows = "";
+  trace.names.forEach((n, r) => {
+    const cls = before[r] !== after[r] ? " class=changed" : "";
+    rows += "<tr><td>" + n + "</td><td" + cls + ">" + hex(after[r], 4) + "</td></tr>";
+  });
+  document.getElementById("regs").innerHTML = rows;
+
+  const mem = {};
+  for (const line in trace.mem) mem[line] = trace.mem[line].slice();
+  for (let s = 0; s <= i; s++) {
+    for (const [a, v] of trace.steps[s].writes) mem[a & ~15][a & 15] = v;
+  }
+  const fresh = new Set(trace.steps[i].writes.map(w => w[0]));
+  rows = "";
+  Object.keys(mem).map(Number).sort((a, b) => a - b).forEach(line => {
+    rows += "<tr><td>" + hex(line, 5) + "</td><td>";
+    mem[line].forEach((b, k) => {
+      const t = hex(b, 2);
+      rows += (fresh.has(line + k) ? "<span class=written>" + t + "</span>" : t) + " ";
+    });
+    rows += "</td></tr>";
+  });
+  document.getElementById("mem").innerHTML = rows;
+}
+document.getElementById("prev").onclick = () => show(cur - 1);
+document.getElementById("next").onclick = () => show(cur + 1);
+document.onkeydown = e => {
+  if (e.key === "ArrowUp" || e.key === "ArrowLeft") { show(cur - 1); e.preventDefault(); }
+  if (e.key === "ArrowDown" || e.key === "ArrowRight") { show(cur + 1); e.preventDefault(); }
+};
+if (trace.steps.length) show(0);
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,203 @@
+use std::io;
+
+use sixemu_core::cpu::Cpu;
+
+/// Where option ROMs go when no segment is given.
+pub const OPTION_ROM_SEGMENT: u16 = 0xc800;
+/// Option ROMs are looked for on 2 KiB boundaries between these
+/// segments, as the PC/XT BIOS does.
+pub const SCAN_START: u16 = 0xc000;
+pub const SCAN_END: u16 = 0xf000;
+/// Upper memory blocks may be placed between these segments.
+pub const UMB_START: u16 = 0xa000;
+pub const UMB_END: u16 = 0xf000;
+/// Instructions an option ROM may run in its init routine before it is
+/// given up on.
+pub const INIT_LIMIT: u64 = 10_000_000;
+
+// return address pushed for the far call into a ROM; reaching it means
+// the init routine has returned
+const RETURN_CS: u16 = 0xffff;
+const RETURN_IP: u16 = 0x0000;
+
+/// An option ROM found by `scan_option_roms`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptionRom {
+    pub segment: u16,
+    /// Size in bytes from the header (blocks of 512).
+    pub size: usize,
+}
+
+/// Copies a ROM image to `segment`:0000 and maps it read-only.
+pub fn load_rom(cpu: &mut Cpu, image: &[u8], segment: u16) -> io::Result<()> {
+    let start = segment as usize * 16;
+    let end = start + image.len();
+    if image.is_empty() || end > 0x100000 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} bytes do not fit at {:04x}:0000", image.len(), segment),
+        ));
+    }
+    cpu.mem.cursor.get_mut()[start..end].copy_from_slice(image);
+    cpu.rom.push((start as u32, end as u32));
+    Ok(())
+}
+
+/// Looks for the 55AA signature on every 2 KiB boundary from C000 to
+/// EFFF. A ROM counts when its bytes, as many as the length byte says,
+/// add up to zero.
+pub fn scan_option_roms(cpu: &Cpu) -> Vec<OptionRom> {
+    let mem = cpu.mem.cursor.get_ref();
+    let mut roms = Vec::new();
+    let mut segment = SCAN_START as u32;
+    while segment < SCAN_END as u32 {
+        let start = segment as usize * 16;
+        let size = mem[start + 2] as usize * 512;
+        let valid = mem[start] == 0x55
+            && mem[start + 1] == 0xaa
+            && size > 0
+            && start + size <= mem.len()
+            && mem[start..start + size].iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0;
+        if valid {
+            roms.push(OptionRom {
+                segment: segment as u16,
+                size,
+            });
+            segment += size.div_ceil(2048) as u32 * 0x80;
+        } else {
+            segment += 0x80;
+        }
+    }
+    roms
+}
+
+/// Far calls the init routine at offset 3 of `rom` and runs it until it
+/// returns, passing any pending host interrupt to `host`. The registers
+/// are restored afterwards; what the routine did to memory (hooked
+/// vectors, its data) stays.
+pub fn init_option_rom(cpu: &mut Cpu, rom: OptionRom, mut host: impl FnMut(&mut Cpu, u8)) -> Result<(), String> {
+    let regs = cpu.regs.clone();
+    let prog_size = cpu.prog_size;
+    // fetch stops at prog_size, which only covers the loaded program
+    cpu.prog_size = 0x10000;
+    cpu.regs.sp = cpu.regs.sp.wrapping_sub(4);
+    let stack = cpu.regs.get_ss() + cpu.regs.sp as u32;
+    cpu.write_mem_u16(stack, RETURN_IP);
+    cpu.write_mem_u16(stack + 2, RETURN_CS);
+    cpu.regs.cs = rom.segment;
+    cpu.regs.ip = 3;
+
+    let mut result = Err(format!("option rom at {:04x} did not return", rom.segment));
+    for _ in 0..INIT_LIMIT {
+        if (cpu.regs.cs, cpu.regs.ip) == (RETURN_CS, RETURN_IP) {
+            result = Ok(());
+            break;
+        }
+        let Some(inst) = cpu.fetch() else {
+            result = Err(match cpu.decode_fault.take() {
+                Some(why) => format!("option rom at {:04x}: {}", rom.segment, why),
+                None => format!("option rom at {:04x} ran off its segment", rom.segment),
+            });
+            break;
+        };
+        cpu.execute(&inst);
+        if let Some(vector) = cpu.host_int.take() {
+            host(cpu, vector);
+        }
+        if cpu.halt {
+            result = Err(format!("option rom at {:04x} halted", rom.segment));
+            break;
+        }
+    }
+
+    cpu.regs = regs;
+    cpu.prog_size = prog_size;
+    cpu.halt = false;
+    cpu.exit_reason = None;
+    result
+}
+
+/// Parses upper memory blocks written the way EMM386 takes them,
+/// `C800-CFFF,D000-D7FF`: hex segments, both ends included. Returns
+/// (first segment, segment past the end) pairs in address order.
+pub fn parse_umbs(list: &str) -> Result<Vec<(u16, u16)>, String> {
+    let mut umbs = Vec::new();
+    for range in list.split(',').map(str::trim) {
+        let seg = |s: &str| u16::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok();
+        let (start, last) = match range.split_once('-').map(|(a, b)| (seg(a), seg(b))) {
+            Some((Some(start), Some(last))) => (start, last),
+            _ => return Err(format!("{}: expected a range like c800-cfff", range)),
+        };
+        if last <= start {
+            return Err(format!("{}: the range ends before it starts", range));
+        }
+        if start < UMB_START || last >= UMB_END {
+            return Err(format!("{}: upper memory blocks go between a000 and efff", range));
+        }
+        umbs.push((start, last + 1));
+    }
+    umbs.sort();
+    if umbs.windows(2).any(|w| w[0].1 > w[1].0) {
+        return Err(format!("{}: upper memory blocks overlap", list));
+    }
+    Ok(umbs)
+}
+
+fn write_mcb(mem: &mut [u8], segment: u16, kind: u8, owner: u16, size: u16, name: &[u8]) {
+    let at = segment as usize * 16;
+    mem[at..at + 16].fill(0);
+    mem[at] = kind;
+    mem[at + 1..at + 3].copy_from_slice(&owner.to_le_bytes());
+    mem[at + 3..at + 5].copy_from_slice(&size.to_le_bytes());
+    mem[at + 8..at + 8 + name.len()].copy_from_slice(name);
+}
+
+/// Makes `umbs` the only RAM in the adapter area: the rest of C000-EFFF
+/// that holds no ROM ignores writes, like an empty socket, so memory
+/// probes find just the configured blocks. Call after the ROMs are
+/// loaded.
+///
+/// The blocks are laid out as a DOS memory chain: a free block for each
+/// UMB, and a system (`SC`) block in its last paragraph spanning the hole
+/// up to the next one, as DOS does with DOS=UMB. Returns the segment of
+/// the first block for linking the chain to conventional memory.
+pub fn map_umbs(cpu: &mut Cpu, umbs: &[(u16, u16)]) -> Result<u16, String> {
+    let Some(&(first, _)) = umbs.first() else {
+        return Err("no upper memory blocks".to_string());
+    };
+    for &(start, end) in umbs {
+        let (lo, hi) = (start as u32 * 16, end as u32 * 16);
+        if let Some(&(rom, _)) = cpu.rom.iter().find(|&&(a, b)| a < hi && lo < b) {
+            return Err(format!(
+                "umb {:04x}-{:04x} overlaps the rom at {:05x}",
+                start,
+                end - 1,
+                rom
+            ));
+        }
+        if end - start < 2 {
+            return Err(format!("umb {:04x}-{:04x} is too small", start, end - 1));
+        }
+    }
+
+    let mut hole = SCAN_START as u32 * 16;
+    for &(start, end) in umbs.iter().chain([&(UMB_END, UMB_END)]) {
+        let start = (start as u32 * 16).max(hole);
+        if hole < start {
+            cpu.rom.push((hole, start));
+        }
+        hole = hole.max(end as u32 * 16);
+    }
+
+    let mem = cpu.mem.cursor.get_mut();
+    for (i, &(start, end)) in umbs.iter().enumerate() {
+        match umbs.get(i + 1) {
+            Some(&(next, _)) => {
+                write_mcb(mem, start, b'M', 0, end - start - 2, b"");
+                write_mcb(mem, end - 1, b'M', 8, next - end, b"SC");
+            }
+            None => write_mcb(mem, start, b'Z', 0, end - start - 1, b""),
+        }
+    }
+    Ok(first)
+}
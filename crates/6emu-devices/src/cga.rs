@@ -0,0 +1,195 @@
+//! The registers of the color adapter. The mode control and color
+//! select registers decide how the renderers in `video` show video
+//! memory. The status register at 3DAh follows a raster timed from the
+//! cycle count, so software that waits for retrace before touching
+//! video memory, as a great deal of it does, sees the beam come round
+//! sixty times a second of emulated time.
+
+use std::{cell::Cell, ops::RangeInclusive, rc::Rc};
+
+use sixemu_core::{
+    cpu::DeviceState,
+    machine::{Device, Irq},
+};
+
+/// Mode control, color select, status, and the two ports that clear
+/// and set the light pen latch.
+pub const CGA_PORTS: RangeInclusive<u16> = 0x3d8..=0x3dc;
+pub const CGA_MODE: u16 = 0x3d8;
+pub const CGA_COLOR: u16 = 0x3d9;
+const CGA_STATUS: u16 = 0x3da;
+const CLEAR_PEN: u16 = 0x3db;
+const SET_PEN: u16 = 0x3dc;
+
+/// CPU clocks per scan line: 912 dots of the 14.318 MHz dot clock, at
+/// three dots a CPU clock.
+pub const LINE_CYCLES: u64 = 304;
+/// Scan lines per frame, 59.92 frames a second.
+pub const FRAME_LINES: u64 = 262;
+/// CPU clocks of a line spent drawing the 640 visible dots.
+const DISPLAY_CYCLES: u64 = 640 / 3;
+const DISPLAY_LINES: u64 = 200;
+// the BIOS's CRTC setup starts vertical sync 224 lines down, and the
+// 6845 holds it for 16
+const VSYNC: RangeInclusive<u64> = 224..=239;
+
+/// Status bits.
+pub const STATUS_NO_DISPLAY: u8 = 0x01;
+pub const STATUS_PEN_TRIGGERED: u8 = 0x02;
+pub const STATUS_PEN_OFF: u8 = 0x04;
+pub const STATUS_VRETRACE: u8 = 0x08;
+
+/// Mode control bits.
+pub const MODE_80_COLUMNS: u8 = 0x01;
+pub const MODE_GRAPHICS: u8 = 0x02;
+/// Turns off the color burst: grays on a composite monitor, and the
+/// third 320x200 palette on an RGB one.
+pub const MODE_NO_BURST: u8 = 0x04;
+pub const MODE_ENABLE: u8 = 0x08;
+pub const MODE_640: u8 = 0x10;
+pub const MODE_BLINK: u8 = 0x20;
+
+/// The mode control and color select registers as last written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Registers {
+    pub mode: u8,
+    pub color: u8,
+}
+
+impl Default for Registers {
+    /// What the BIOS sets for 80x25 color text.
+    fn default() -> Self {
+        Self { mode: 0x29, color: 0x30 }
+    }
+}
+
+impl Registers {
+    pub fn graphics(self) -> bool {
+        self.mode & MODE_GRAPHICS != 0
+    }
+
+    pub fn hires(self) -> bool {
+        self.mode & MODE_640 != 0
+    }
+
+    /// Whether anything is shown at all.
+    pub fn enabled(self) -> bool {
+        self.mode & MODE_ENABLE != 0
+    }
+
+    /// Whether attribute bit 7 blinks rather than brightens the
+    /// background.
+    pub fn blink(self) -> bool {
+        self.mode & MODE_BLINK != 0
+    }
+
+    /// The colors of pixel values 0-3 in 320x200 graphics: the
+    /// background, then the palette color select bit 5 picks (green,
+    /// red, brown or cyan, magenta, white; cyan, red, white with the
+    /// color burst off), made bright by bit 4.
+    pub fn palette(self) -> [u8; 4] {
+        let bright = if self.color & 0x10 != 0 { 8 } else { 0 };
+        let [a, b, c] = if self.mode & MODE_NO_BURST != 0 {
+            [3, 4, 7]
+        } else if self.color & 0x20 != 0 {
+            [3, 5, 7]
+        } else {
+            [2, 4, 6]
+        };
+        [self.color & 0x0f, a | bright, b | bright, c | bright]
+    }
+
+    /// The color of set pixels in 640x200 graphics.
+    pub fn foreground(self) -> u8 {
+        self.color & 0x0f
+    }
+}
+
+/// Where the beam is at CPU cycle `now`, as (scan line, clock within
+/// the line).
+pub fn beam(now: u64) -> (u64, u64) {
+    let line = now / LINE_CYCLES;
+    (line % FRAME_LINES, now % LINE_CYCLES)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cga {
+    registers: Rc<Cell<Registers>>,
+    pen: bool,
+    // the cycle of the last port access, for the debugger
+    now: u64,
+}
+
+impl Cga {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mode control and color select registers, shared with a
+    /// renderer that holds on to them after the device is attached.
+    pub fn registers(&self) -> Rc<Cell<Registers>> {
+        self.registers.clone()
+    }
+
+    /// The status register at CPU cycle `now`. There is no light pen, so
+    /// its switch always reads as open.
+    pub fn status(&self, now: u64) -> u8 {
+        let (line, dot) = beam(now);
+        let mut status = STATUS_PEN_OFF;
+        if line >= DISPLAY_LINES || dot >= DISPLAY_CYCLES {
+            status |= STATUS_NO_DISPLAY;
+        }
+        if VSYNC.contains(&line) {
+            status |= STATUS_VRETRACE;
+        }
+        if self.pen {
+            status |= STATUS_PEN_TRIGGERED;
+        }
+        status
+    }
+}
+
+impl Device for Cga {
+    fn name(&self) -> &str {
+        "cga"
+    }
+
+    fn ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![CGA_PORTS]
+    }
+
+    fn port_read(&mut self, port: u16, now: u64, _irq: &mut Irq) -> u8 {
+        self.now = now;
+        match port {
+            CGA_STATUS => self.status(now),
+            // write-only
+            _ => 0xff,
+        }
+    }
+
+    fn port_write(&mut self, port: u16, val: u8, now: u64, _irq: &mut Irq) {
+        self.now = now;
+        let registers = self.registers.get();
+        match port {
+            CGA_MODE => self.registers.set(Registers { mode: val, ..registers }),
+            CGA_COLOR => self.registers.set(Registers { color: val, ..registers }),
+            CLEAR_PEN => self.pen = false,
+            SET_PEN => self.pen = true,
+            _ => {}
+        }
+    }
+
+    fn debug_state(&self) -> Option<DeviceState> {
+        let (line, dot) = beam(self.now);
+        let registers = self.registers.get();
+        Some(DeviceState {
+            name: "cga".to_string(),
+            fields: vec![
+                ("mode", format!("{:02x}", registers.mode)),
+                ("color", format!("{:02x}", registers.color)),
+                ("status", format!("{:02x}", self.status(self.now))),
+                ("beam", format!("line {} clock {}", line, dot)),
+            ],
+        })
+    }
+}
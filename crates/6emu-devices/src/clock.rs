@@ -0,0 +1,221 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sixemu_core::cpu::{Cpu, DeviceState, IoDevice, CPU_HZ};
+
+/// Vector of the BIOS time-of-day services.
+pub const TIME_INT: u8 = 0x1a;
+/// Index and data ports of the CMOS real-time clock.
+pub const CMOS_INDEX: u16 = 0x70;
+pub const CMOS_DATA: u16 = 0x71;
+
+// the 8253 input clock divided by the BIOS's 65536 count gives the
+// 18.2 Hz tick
+const PIT_HZ: u128 = 1_193_182;
+
+/// Where the machine's idea of "now" comes from. `Virtual` derives it
+/// from the cycle count so runs are reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Clock {
+    #[default]
+    Host,
+    /// Starts at `epoch` (seconds since 1970) when the run starts.
+    Virtual { epoch: u64 },
+}
+
+impl Clock {
+    /// Time since 1970 after `cycles` CPU clocks.
+    pub fn unix_time(&self, cycles: u64) -> Duration {
+        match *self {
+            Clock::Host => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+            Clock::Virtual { epoch } => {
+                let nanos = cycles as u128 * 1_000_000_000 / CPU_HZ as u128;
+                Duration::from_secs(epoch) + Duration::from_nanos(nanos as u64)
+            }
+        }
+    }
+
+    /// BIOS ticks since midnight.
+    pub fn ticks(&self, cycles: u64) -> u32 {
+        let nanos = self.unix_time(cycles).as_nanos() % (86_400 * 1_000_000_000);
+        (nanos * PIT_HZ / (65_536 * 1_000_000_000)) as u32
+    }
+}
+
+/// A calendar date and time of day, UTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0 is Sunday.
+    pub weekday: u8,
+}
+
+impl DateTime {
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        // days since 1970-01-01 to a civil date
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: (rem / 3600) as u8,
+            minute: (rem % 3600 / 60) as u8,
+            second: (rem % 60) as u8,
+            // 1970-01-01 was a Thursday
+            weekday: ((days + 4).rem_euclid(7)) as u8,
+        }
+    }
+
+    pub fn to_unix(&self) -> u64 {
+        // the inverse of `from_unix`
+        let year = self.year - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let month = self.month as i64;
+        let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        (days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64).max(0) as u64
+    }
+}
+
+/// Parses seconds since 1970 or `YYYY-MM-DD[THH:MM[:SS]]` (UTC).
+pub fn parse_epoch(s: &str) -> Option<u64> {
+    if let Ok(secs) = s.parse() {
+        return Some(secs);
+    }
+    let (date, time) = s.split_once(['T', ' ']).unwrap_or((s, "00:00"));
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || !(2..=3).contains(&time.len()) {
+        return None;
+    }
+    let dt = DateTime {
+        year: date[0].parse().ok()?,
+        month: date[1].parse().ok().filter(|m| (1..=12).contains(m))?,
+        day: date[2].parse().ok().filter(|d| (1..=31).contains(d))?,
+        hour: time[0].parse().ok().filter(|&h| h < 24)?,
+        minute: time[1].parse().ok().filter(|&m| m < 60)?,
+        second: time.get(2).map_or(Some(0), |s| s.parse().ok().filter(|&s| s < 60))?,
+        weekday: 0,
+    };
+    (dt.year >= 1970).then(|| dt.to_unix())
+}
+
+fn bcd(val: u8) -> u8 {
+    (val / 10) << 4 | (val % 10)
+}
+
+/// Services an `int 1ah` left pending in `cpu.host_int`: the tick count
+/// (AH=00h) and the real-time clock (AH=02h time, AH=04h date) read from
+/// `clock`. Setting the time is accepted and ignored.
+pub fn int1a(cpu: &mut Cpu, clock: Clock) {
+    let now = cpu.cycles();
+    let dt = DateTime::from_unix(clock.unix_time(now).as_secs());
+    cpu.regs.flags.clear_cf();
+    match cpu.regs.get_ah() {
+        0x00 => {
+            let ticks = clock.ticks(now);
+            cpu.regs.cx = (ticks >> 16) as u16;
+            cpu.regs.dx = ticks as u16;
+            cpu.regs.set_al(0);
+        }
+        0x02 => {
+            cpu.regs.set_ch(bcd(dt.hour));
+            cpu.regs.set_cl(bcd(dt.minute));
+            cpu.regs.set_dh(bcd(dt.second));
+            cpu.regs.set_dl(0);
+        }
+        0x04 => {
+            cpu.regs.set_ch(bcd((dt.year / 100) as u8));
+            cpu.regs.set_cl(bcd((dt.year % 100) as u8));
+            cpu.regs.set_dh(bcd(dt.month));
+            cpu.regs.set_dl(bcd(dt.day));
+        }
+        0x01 | 0x03 | 0x05 => {}
+        _ => cpu.regs.flags.set_cf(),
+    }
+}
+
+/// The MC146818 clock and CMOS RAM behind ports 70h/71h. The time
+/// registers read `clock` in BCD, 24-hour mode; the other bytes are
+/// plain RAM.
+pub struct Cmos {
+    clock: Clock,
+    index: u8,
+    ram: [u8; 64],
+}
+
+impl Cmos {
+    pub fn new(clock: Clock) -> Self {
+        let mut ram = [0u8; 64];
+        // 32.768 kHz time base; BCD, 24 hours; battery good
+        ram[0x0a] = 0x26;
+        ram[0x0b] = 0x02;
+        ram[0x0d] = 0x80;
+        Self { clock, index: 0, ram }
+    }
+}
+
+impl IoDevice for Cmos {
+    fn handles(&self, port: u16) -> bool {
+        port == CMOS_INDEX || port == CMOS_DATA
+    }
+
+    fn read(&mut self, port: u16, now: u64) -> u8 {
+        if port == CMOS_INDEX {
+            return 0xff;
+        }
+        let dt = DateTime::from_unix(self.clock.unix_time(now).as_secs());
+        match self.index {
+            0x00 => bcd(dt.second),
+            0x02 => bcd(dt.minute),
+            0x04 => bcd(dt.hour),
+            0x06 => dt.weekday + 1,
+            0x07 => bcd(dt.day),
+            0x08 => bcd(dt.month),
+            0x09 => bcd((dt.year % 100) as u8),
+            0x32 => bcd((dt.year / 100) as u8),
+            i => self.ram[i as usize],
+        }
+    }
+
+    fn write(&mut self, port: u16, val: u8, _now: u64) {
+        if port == CMOS_INDEX {
+            // bit 7 masks NMI
+            self.index = val & 0x3f;
+        } else if !matches!(self.index, 0x00..=0x09 | 0x0c | 0x0d | 0x32) {
+            self.ram[self.index as usize] = val;
+        }
+    }
+
+    fn debug_state(&self) -> Option<DeviceState> {
+        let reg = |i: usize| format!("{:02x}", self.ram[i]);
+        Some(DeviceState {
+            name: "cmos".to_string(),
+            fields: vec![
+                ("index", format!("{:02x}", self.index)),
+                ("A", reg(0x0a)),
+                ("B", reg(0x0b)),
+                ("D", reg(0x0d)),
+                ("alarm", format!("{}:{}:{}", reg(0x05), reg(0x03), reg(0x01))),
+            ],
+        })
+    }
+}
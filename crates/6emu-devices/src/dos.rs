@@ -0,0 +1,132 @@
+use std::io::{self, BufRead, Write};
+
+use sixemu_core::cpu::Cpu;
+
+/// Vector of the DOS function dispatcher.
+pub const DOS_INT: u8 = 0x21;
+
+// what DOS hands back for a character read at end of file
+const CTRL_Z: u8 = 0x1a;
+// error codes in AX with CF set
+const BAD_FUNCTION: u16 = 0x01;
+const BAD_HANDLE: u16 = 0x06;
+
+/// Where the guest's standard input comes from, for filter programs
+/// run in a pipeline: `int 21h` reads take their bytes from `input`,
+/// blocking until it has some.
+pub struct Console {
+    input: Box<dyn BufRead>,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn BufRead>) -> Self {
+        Self { input }
+    }
+
+    /// The host's standard input.
+    pub fn stdin() -> Self {
+        Self::new(Box::new(io::stdin().lock()))
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.input.fill_buf().ok()?.first()?;
+        self.input.consume(1);
+        Some(byte)
+    }
+
+    // up to `max` bytes, fewer only at the end of the input, as reading
+    // a redirected file does
+    fn bytes(&mut self, max: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        while out.len() < max {
+            let Ok(buf) = self.input.fill_buf() else { break };
+            if buf.is_empty() {
+                break;
+            }
+            let n = buf.len().min(max - out.len());
+            out.extend_from_slice(&buf[..n]);
+            self.input.consume(n);
+        }
+        out
+    }
+
+    fn at_eof(&mut self) -> bool {
+        self.input.fill_buf().map_or(true, |buf| buf.is_empty())
+    }
+}
+
+/// Services the console side of an `int 21h` left pending in
+/// `cpu.host_int`: character and `$`-string output (AH=02h, 09h), the
+/// character reads (AH=01h with echo, 06h, 07h, 08h), input status
+/// (AH=0Bh), and reading handle 0 and writing handles 1 and 2 (AH=3Fh,
+/// 40h). Reads wait for the host's input; past its end a character
+/// reads as Ctrl-Z and AH=3Fh comes back short. Output goes to
+/// `cpu.output`, except for handle 2, which is the host's stderr. Other
+/// functions fail with CF set and are noted in `cpu.faults`.
+pub fn int21(cpu: &mut Cpu, console: &mut Console) {
+    let function = cpu.regs.get_ah();
+    match function {
+        0x01 | 0x07 | 0x08 => {
+            let c = console.byte().unwrap_or(CTRL_Z);
+            if function == 0x01 {
+                cpu.output.push(c);
+            }
+            cpu.regs.set_al(c);
+        }
+        0x02 => cpu.output.push(cpu.regs.get_dl()),
+        // direct console I/O: DL=FFh reads, ZF set once the input is done
+        0x06 if cpu.regs.get_dl() == 0xff => match console.byte() {
+            Some(c) => {
+                cpu.regs.set_al(c);
+                cpu.regs.flags.clear_zf();
+            }
+            None => {
+                cpu.regs.set_al(0);
+                cpu.regs.flags.set_zf();
+            }
+        },
+        0x06 => cpu.output.push(cpu.regs.get_dl()),
+        0x09 => {
+            // at most the whole segment when the `$` is missing
+            for i in 0..=u16::MAX {
+                let c = cpu.read_mem_u8(cpu.data_addr(cpu.regs.dx.wrapping_add(i)));
+                if c == b'$' {
+                    break;
+                }
+                cpu.output.push(c);
+            }
+        }
+        0x0b => cpu.regs.set_al(if console.at_eof() { 0x00 } else { 0xff }),
+        0x3f if cpu.regs.bx == 0 => {
+            let bytes = console.bytes(cpu.regs.cx as usize);
+            for (i, &c) in bytes.iter().enumerate() {
+                let at = cpu.data_addr(cpu.regs.dx.wrapping_add(i as u16));
+                cpu.write_mem_u8(at, c);
+            }
+            cpu.regs.ax = bytes.len() as u16;
+            cpu.regs.flags.clear_cf();
+        }
+        0x40 if matches!(cpu.regs.bx, 1 | 2) => {
+            let bytes: Vec<u8> = (0..cpu.regs.cx)
+                .map(|i| cpu.read_mem_u8(cpu.data_addr(cpu.regs.dx.wrapping_add(i))))
+                .collect();
+            if cpu.regs.bx == 2 {
+                let _ = io::stderr().write_all(&bytes);
+            } else {
+                cpu.output.extend(bytes);
+            }
+            cpu.regs.ax = cpu.regs.cx;
+            cpu.regs.flags.clear_cf();
+        }
+        0x3f | 0x40 => {
+            cpu.regs.ax = BAD_HANDLE;
+            cpu.regs.flags.set_cf();
+        }
+        _ => {
+            cpu.faults
+                .push(format!("int 21h function {:02x}h is not implemented", function));
+            cpu.regs.ax = BAD_FUNCTION;
+            cpu.regs.flags.set_cf();
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::clock::DateTime;
+
+const DIR_ENTRY: usize = 32;
+const ATTR_VOLUME: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const DELETED: u8 = 0xe5;
+const END_OF_CHAIN: u16 = 0xfff;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// A FAT12 floppy image held in memory. Only the root directory is
+/// supported, with plain 8.3 names.
+pub struct Fat12 {
+    image: Vec<u8>,
+    bytes_per_sector: usize,
+    sectors_per_cluster: usize,
+    fat_start: usize,
+    fat_size: usize,
+    fats: usize,
+    root_start: usize,
+    root_entries: usize,
+    data_start: usize,
+    clusters: u16,
+}
+
+impl Fat12 {
+    /// Reads the geometry from the boot sector's BIOS parameter block.
+    pub fn open(image: Vec<u8>) -> io::Result<Self> {
+        if image.len() < 512 {
+            return Err(invalid("image is smaller than a boot sector".to_string()));
+        }
+        let u16_at = |off: usize| u16::from_le_bytes([image[off], image[off + 1]]) as usize;
+        let bytes_per_sector = u16_at(0x0b);
+        let sectors_per_cluster = image[0x0d] as usize;
+        let reserved = u16_at(0x0e);
+        let fats = image[0x10] as usize;
+        let root_entries = u16_at(0x11);
+        let mut total = u16_at(0x13);
+        if total == 0 {
+            total = u32::from_le_bytes([image[0x20], image[0x21], image[0x22], image[0x23]]) as usize;
+        }
+        let fat_sectors = u16_at(0x16);
+        if !matches!(bytes_per_sector, 128 | 256 | 512 | 1024 | 2048 | 4096)
+            || sectors_per_cluster == 0
+            || fats == 0
+            || fat_sectors == 0
+        {
+            return Err(invalid("no FAT BIOS parameter block in the boot sector".to_string()));
+        }
+
+        let root_sectors = (root_entries * DIR_ENTRY).div_ceil(bytes_per_sector);
+        let data_sector = reserved + fats * fat_sectors + root_sectors;
+        if data_sector > total {
+            return Err(invalid(format!(
+                "the boot sector puts the data area at sector {} of {}",
+                data_sector, total
+            )));
+        }
+        let clusters = (total - data_sector) / sectors_per_cluster;
+        if clusters >= 4085 {
+            return Err(invalid(format!("{} clusters is too many for FAT12", clusters)));
+        }
+        // entries 0 and 1 are reserved ahead of the clusters'
+        let fat_bytes = ((clusters + 2) * 3).div_ceil(2);
+        if fat_bytes > fat_sectors * bytes_per_sector {
+            return Err(invalid(format!(
+                "a FAT of {} bytes cannot hold {} clusters",
+                fat_sectors * bytes_per_sector,
+                clusters
+            )));
+        }
+        if (reserved + fats * fat_sectors) * bytes_per_sector > image.len() {
+            return Err(invalid("the FATs run past the end of the image".to_string()));
+        }
+        if image.len() < total * bytes_per_sector {
+            return Err(invalid(format!(
+                "image is {} bytes but its boot sector describes {}",
+                image.len(),
+                total * bytes_per_sector
+            )));
+        }
+
+        Ok(Self {
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_start: reserved * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            fats,
+            root_start: (reserved + fats * fat_sectors) * bytes_per_sector,
+            root_entries,
+            data_start: data_sector * bytes_per_sector,
+            clusters: clusters as u16,
+            image,
+        })
+    }
+
+    pub fn into_image(self) -> Vec<u8> {
+        self.image
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn cluster_offset(&self, cluster: u16) -> usize {
+        self.data_start + (cluster as usize - 2) * self.cluster_size()
+    }
+
+    // entries are 12 bits, packed two to three bytes
+    fn fat_entry(&self, cluster: u16) -> u16 {
+        let off = self.fat_start + cluster as usize * 3 / 2;
+        let v = u16::from_le_bytes([self.image[off], self.image[off + 1]]);
+        if cluster & 1 == 1 {
+            v >> 4
+        } else {
+            v & 0xfff
+        }
+    }
+
+    fn set_fat_entry(&mut self, cluster: u16, val: u16) {
+        for fat in 0..self.fats {
+            let off = self.fat_start + fat * self.fat_size + cluster as usize * 3 / 2;
+            if cluster & 1 == 1 {
+                self.image[off] = (self.image[off] & 0x0f) | ((val << 4) as u8);
+                self.image[off + 1] = (val >> 4) as u8;
+            } else {
+                self.image[off] = val as u8;
+                self.image[off + 1] = (self.image[off + 1] & 0xf0) | ((val >> 8) as u8 & 0x0f);
+            }
+        }
+    }
+
+    fn valid_cluster(&self, cluster: u16) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+
+    fn chain(&self, first: u16) -> io::Result<Vec<u16>> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while self.valid_cluster(cluster) {
+            if chain.len() > self.clusters as usize {
+                return Err(invalid("cluster chain loops".to_string()));
+            }
+            chain.push(cluster);
+            cluster = self.fat_entry(cluster);
+        }
+        Ok(chain)
+    }
+
+    fn entry(&self, index: usize) -> &[u8] {
+        let off = self.root_start + index * DIR_ENTRY;
+        &self.image[off..off + DIR_ENTRY]
+    }
+
+    fn find(&self, name: &[u8; 11]) -> Option<usize> {
+        for i in 0..self.root_entries {
+            let entry = self.entry(i);
+            match entry[0] {
+                0 => return None,
+                DELETED => continue,
+                _ if entry[11] & ATTR_VOLUME != 0 => continue,
+                _ if entry[..11] == name[..] => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn lookup(&self, name: &str) -> io::Result<usize> {
+        let short = short_name(name)?;
+        let index = self
+            .find(&short)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{}: no such file", name)))?;
+        if self.entry(index)[11] & ATTR_DIRECTORY != 0 {
+            return Err(Error::other(format!("{}: is a directory", name)));
+        }
+        Ok(index)
+    }
+
+    /// Names and sizes of the files in the root directory.
+    pub fn list(&self) -> Vec<(String, u32)> {
+        let mut files = Vec::new();
+        for i in 0..self.root_entries {
+            let entry = self.entry(i);
+            if entry[0] == 0 {
+                break;
+            }
+            if entry[0] == DELETED || entry[11] & (ATTR_VOLUME | ATTR_DIRECTORY) != 0 {
+                continue;
+            }
+            let base = String::from_utf8_lossy(&entry[..8]).trim_end().to_string();
+            let ext = String::from_utf8_lossy(&entry[8..11]).trim_end().to_string();
+            let name = if ext.is_empty() { base } else { format!("{}.{}", base, ext) };
+            files.push((name, u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]])));
+        }
+        files
+    }
+
+    pub fn read_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(self.lookup(name)?);
+        let size = u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]) as usize;
+        let first = u16::from_le_bytes([entry[26], entry[27]]);
+
+        let mut data = Vec::with_capacity(size);
+        for cluster in self.chain(first)? {
+            let off = self.cluster_offset(cluster);
+            data.extend_from_slice(&self.image[off..off + self.cluster_size()]);
+        }
+        if data.len() < size {
+            return Err(invalid(format!("{}: cluster chain is shorter than the file", name)));
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    /// Creates or replaces a file in the root directory. The image is
+    /// left untouched if there is not enough room.
+    pub fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let short = short_name(name)?;
+        let existing = match self.find(&short) {
+            Some(_) => Some(self.lookup(name)?),
+            None => None,
+        };
+        let index = match existing {
+            Some(i) => i,
+            None => (0..self.root_entries)
+                .find(|&i| matches!(self.entry(i)[0], 0 | DELETED))
+                .ok_or_else(|| Error::other("root directory is full"))?,
+        };
+
+        let old = match existing {
+            Some(i) => {
+                let entry = self.entry(i);
+                self.chain(u16::from_le_bytes([entry[26], entry[27]]))?
+            }
+            None => Vec::new(),
+        };
+        let needed = data.len().div_ceil(self.cluster_size());
+        let free: Vec<u16> = (2..self.clusters + 2)
+            .filter(|&c| self.fat_entry(c) == 0 || old.contains(&c))
+            .take(needed)
+            .collect();
+        if free.len() < needed {
+            return Err(Error::other(format!(
+                "{}: not enough free space ({} bytes needed)",
+                name,
+                data.len()
+            )));
+        }
+
+        for &cluster in &old {
+            self.set_fat_entry(cluster, 0);
+        }
+        for (i, (&cluster, chunk)) in free.iter().zip(data.chunks(self.cluster_size())).enumerate() {
+            let next = free.get(i + 1).copied().unwrap_or(END_OF_CHAIN);
+            self.set_fat_entry(cluster, next);
+            let off = self.cluster_offset(cluster);
+            let size = self.cluster_size();
+            self.image[off..off + size].fill(0);
+            self.image[off..off + chunk.len()].copy_from_slice(chunk);
+        }
+
+        let (time, date) = dos_timestamp(SystemTime::now());
+        let first = free.first().copied().unwrap_or(0);
+        let off = self.root_start + index * DIR_ENTRY;
+        let entry = &mut self.image[off..off + DIR_ENTRY];
+        if existing.is_none() {
+            entry.fill(0);
+            entry[..11].copy_from_slice(&short);
+            entry[11] = ATTR_ARCHIVE;
+            entry[14..16].copy_from_slice(&time.to_le_bytes());
+            entry[16..18].copy_from_slice(&date.to_le_bytes());
+        }
+        entry[18..20].copy_from_slice(&date.to_le_bytes());
+        entry[22..24].copy_from_slice(&time.to_le_bytes());
+        entry[24..26].copy_from_slice(&date.to_le_bytes());
+        entry[26..28].copy_from_slice(&first.to_le_bytes());
+        entry[28..32].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Converts `name.ext` to the space-padded, upper-case directory form.
+pub fn short_name(name: &str) -> io::Result<[u8; 11]> {
+    let bad = || Error::new(ErrorKind::InvalidInput, format!("{}: not an 8.3 file name", name));
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let allowed = |c: u8| c.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&c);
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(allowed)
+    {
+        return Err(bad());
+    }
+    let mut short = [b' '; 11];
+    for (i, c) in base.bytes().enumerate() {
+        short[i] = c.to_ascii_uppercase();
+    }
+    for (i, c) in ext.bytes().enumerate() {
+        short[8 + i] = c.to_ascii_uppercase();
+    }
+    Ok(short)
+}
+
+// DOS packs times as hhhhhmmm mmmsssss (two-second units) and dates as
+// yyyyyyym mmmddddd with years counted from 1980
+fn dos_timestamp(now: SystemTime) -> (u16, u16) {
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let dt = DateTime::from_unix(secs);
+    let time = ((dt.hour as u16) << 11) | ((dt.minute as u16) << 5) | (dt.second as u16 / 2);
+    let date = (((dt.year - 1980).clamp(0, 127) as u16) << 9) | ((dt.month as u16) << 5) | dt.day as u16;
+    (time, date)
+}
@@ -0,0 +1,255 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use sixemu_core::cpu::Cpu;
+
+/// Vector of the BIOS disk services.
+pub const DISK_INT: u8 = 0x13;
+pub const SECTOR_SIZE: usize = 512;
+
+// INT 13h status codes
+const OK: u8 = 0x00;
+const BAD_COMMAND: u8 = 0x01;
+const WRITE_PROTECTED: u8 = 0x03;
+const SECTOR_NOT_FOUND: u8 = 0x04;
+const MEDIA_CHANGED: u8 = 0x06;
+const NOT_READY: u8 = 0x80;
+
+/// Cylinders, heads and sectors per track of a standard floppy format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub cylinders: u16,
+    pub heads: u8,
+    pub sectors: u8,
+    /// Drive type reported by INT 13h AH=08h.
+    pub drive_type: u8,
+}
+
+impl Geometry {
+    /// Guesses the format from the image size.
+    pub fn from_size(size: usize) -> Option<Self> {
+        let (cylinders, heads, sectors, drive_type) = match size / 1024 {
+            160 => (40, 1, 8, 1),
+            180 => (40, 1, 9, 1),
+            320 => (40, 2, 8, 1),
+            360 => (40, 2, 9, 1),
+            720 => (80, 2, 9, 3),
+            1200 => (80, 2, 15, 2),
+            1440 => (80, 2, 18, 4),
+            2880 => (80, 2, 36, 6),
+            _ => return None,
+        };
+        Some(Self {
+            cylinders,
+            heads,
+            sectors,
+            drive_type,
+        })
+    }
+
+    pub(crate) fn lba(&self, cylinder: u16, head: u8, sector: u8) -> Option<usize> {
+        if cylinder >= self.cylinders || head >= self.heads || sector == 0 || sector > self.sectors {
+            return None;
+        }
+        Some((cylinder as usize * self.heads as usize + head as usize) * self.sectors as usize + sector as usize - 1)
+    }
+}
+
+/// A floppy image in a drive. Writes stay in memory until `flush`.
+pub struct Disk {
+    pub path: PathBuf,
+    pub geometry: Geometry,
+    pub read_only: bool,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl Disk {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let geometry = Geometry::from_size(data.len()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {} bytes is not a floppy size", path.display(), data.len()),
+            )
+        })?;
+        let read_only = OpenOptions::new().write(true).open(path).is_err();
+        Ok(Self {
+            path: path.to_path_buf(),
+            geometry,
+            read_only,
+            data,
+            dirty: false,
+        })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            fs::write(&self.path, &self.data)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/// Drives A: and B:, serviced through INT 13h. Media can be swapped
+/// while the guest runs; AH=16h reports the change once.
+#[derive(Default)]
+pub struct Floppy {
+    pub drives: [Option<Disk>; 2],
+    changed: [bool; 2],
+    status: u8,
+}
+
+/// Drive index for a name like `a`, `B` or `b:`.
+pub fn drive_index(name: &str) -> Option<usize> {
+    match name.trim_end_matches(':').to_ascii_lowercase().as_str() {
+        "a" => Some(0),
+        "b" => Some(1),
+        _ => None,
+    }
+}
+
+impl Floppy {
+    /// Puts `path` into `drive`, ejecting (and flushing) what was there.
+    pub fn insert(&mut self, drive: usize, path: &Path) -> io::Result<()> {
+        let disk = Disk::open(path)?;
+        self.eject(drive)?;
+        self.drives[drive] = Some(disk);
+        self.changed[drive] = true;
+        Ok(())
+    }
+
+    /// Removes the disk, writing back any changes. Ejecting an empty
+    /// drive does nothing.
+    pub fn eject(&mut self, drive: usize) -> io::Result<()> {
+        if let Some(mut disk) = self.drives[drive].take() {
+            self.changed[drive] = true;
+            disk.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for disk in self.drives.iter_mut().flatten() {
+            disk.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Copies the boot sector of `drive` to 0000:7C00 and points CS:IP at
+    /// it with DL holding the drive number.
+    pub fn boot(&mut self, cpu: &mut Cpu, drive: usize) -> io::Result<()> {
+        let disk = self.drives[drive]
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no disk in the boot drive"))?;
+        cpu.mem.cursor.get_mut()[0x7c00..0x7c00 + SECTOR_SIZE].copy_from_slice(&disk.data[..SECTOR_SIZE]);
+        cpu.regs.cs = 0;
+        cpu.regs.ip = 0x7c00;
+        cpu.regs.set_dl(drive as u8);
+        cpu.load_addr = 0x7c00;
+        cpu.prog_size = 0x7c00 + SECTOR_SIZE as u64;
+        self.changed[drive] = false;
+        Ok(())
+    }
+
+    fn finish(&mut self, cpu: &mut Cpu, status: u8) {
+        self.status = status;
+        cpu.regs.set_ah(status);
+        if status == OK {
+            cpu.regs.flags.clear_cf();
+        } else {
+            cpu.regs.flags.set_cf();
+        }
+    }
+
+    /// Services an `int 13h` left pending in `cpu.host_int`.
+    pub fn int13(&mut self, cpu: &mut Cpu) {
+        let drive = cpu.regs.get_dl() as usize;
+        let function = cpu.regs.get_ah();
+        if drive > 1 {
+            return self.finish(cpu, BAD_COMMAND);
+        }
+
+        match function {
+            // reset
+            0x00 => self.finish(cpu, OK),
+            // status of the last operation
+            0x01 => {
+                let status = self.status;
+                cpu.regs.set_al(status);
+                self.finish(cpu, status);
+            }
+            // read, write, verify sectors
+            0x02..=0x04 => {
+                let Some(disk) = &mut self.drives[drive] else {
+                    cpu.regs.set_al(0);
+                    return self.finish(cpu, NOT_READY);
+                };
+                if function == 0x03 && disk.read_only {
+                    cpu.regs.set_al(0);
+                    return self.finish(cpu, WRITE_PROTECTED);
+                }
+
+                let count = cpu.regs.get_al() as usize;
+                let cl = cpu.regs.get_cl();
+                let cylinder = cpu.regs.get_ch() as u16 | ((cl as u16 & 0xc0) << 2);
+                let (head, sector) = (cpu.regs.get_dh(), cl & 0x3f);
+                let Some(start) = disk.geometry.lba(cylinder, head, sector) else {
+                    cpu.regs.set_al(0);
+                    return self.finish(cpu, SECTOR_NOT_FOUND);
+                };
+                let total = disk.data.len() / SECTOR_SIZE;
+                let done = count.min(total - start);
+                let buffer = cpu.regs.get_es() + cpu.regs.bx as u32;
+                let mem = cpu.mem.cursor.get_mut();
+                for i in 0..done * SECTOR_SIZE {
+                    // the buffer wraps at 1 MiB; memory stops a byte
+                    // short of it, and that byte reads as 0
+                    let addr = (buffer as usize + i) & 0xfffff;
+                    let off = start * SECTOR_SIZE + i;
+                    match (function, mem.get_mut(addr)) {
+                        (0x02, Some(b)) => *b = disk.data[off],
+                        (0x03, b) => disk.data[off] = b.map_or(0, |b| *b),
+                        _ => {}
+                    }
+                }
+                if function == 0x03 && done > 0 {
+                    disk.dirty = true;
+                }
+                cpu.regs.set_al(done as u8);
+                self.finish(cpu, if done == count { OK } else { SECTOR_NOT_FOUND });
+            }
+            // drive parameters
+            0x08 => {
+                let Some(disk) = &self.drives[drive] else {
+                    return self.finish(cpu, NOT_READY);
+                };
+                let g = disk.geometry;
+                let max_cylinder = g.cylinders - 1;
+                cpu.regs.set_bl(g.drive_type);
+                cpu.regs.set_ch(max_cylinder as u8);
+                cpu.regs.set_cl(g.sectors | ((max_cylinder >> 2) as u8 & 0xc0));
+                cpu.regs.set_dh(g.heads - 1);
+                cpu.regs.set_dl(self.drives.iter().filter(|d| d.is_some()).count() as u8);
+                cpu.regs.set_al(0);
+                self.finish(cpu, OK);
+            }
+            // drive type: a floppy with a change line
+            0x15 => {
+                cpu.regs.flags.clear_cf();
+                cpu.regs.set_ah(0x02);
+            }
+            // has the disk been changed
+            0x16 => {
+                let status = if self.changed[drive] { MEDIA_CHANGED } else { OK };
+                self.changed[drive] = false;
+                self.finish(cpu, status);
+            }
+            _ => self.finish(cpu, BAD_COMMAND),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// the pace is checked this many times per emulated second
+const CHECKS_PER_SECOND: u64 = 100;
+
+/// Caps emulated instructions per host second by sleeping, e.g. to keep
+/// a background run from pinning a core. This is a plain rate limit,
+/// not cycle-accurate pacing.
+pub struct Governor {
+    limit: Option<u64>,
+    start: Instant,
+    // instruction count when the current limit took effect
+    base: u64,
+    next_check: u64,
+}
+
+impl Governor {
+    pub fn new(limit: Option<u64>) -> Self {
+        let mut governor = Self {
+            limit: None,
+            start: Instant::now(),
+            base: 0,
+            next_check: 0,
+        };
+        governor.set_limit(limit, 0);
+        governor
+    }
+
+    /// Instructions per second, `None` for as fast as possible.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Changes the cap from now on; `instructions` is the current count.
+    pub fn set_limit(&mut self, limit: Option<u64>, instructions: u64) {
+        self.limit = limit.filter(|&n| n > 0);
+        self.start = Instant::now();
+        self.base = instructions;
+        self.next_check = instructions;
+    }
+
+    /// Sleeps if the guest is ahead of the cap; call between instructions.
+    pub fn poll(&mut self, instructions: u64) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        if instructions < self.next_check {
+            return;
+        }
+        self.next_check = instructions + (limit / CHECKS_PER_SECOND).max(1);
+        let due = Duration::from_secs_f64((instructions - self.base) as f64 / limit as f64);
+        if let Some(ahead) = due.checked_sub(self.start.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
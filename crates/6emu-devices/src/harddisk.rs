@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use sixemu_core::cpu::{Cpu, Segment};
+
+use crate::floppy::{Floppy, Geometry, SECTOR_SIZE};
+
+/// Bytes read from or written to the image file at a time.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Chunks kept in memory before the least recently used is dropped.
+pub const CACHED_CHUNKS: usize = 64;
+
+struct Chunk {
+    data: Vec<u8>,
+    dirty: bool,
+    used: u64,
+}
+
+/// A disk image read in chunks on demand instead of loaded whole. Only
+/// changed chunks are written back, when evicted or on `flush`.
+pub struct LazyImage {
+    file: File,
+    len: u64,
+    pub read_only: bool,
+    chunks: HashMap<u64, Chunk>,
+    max_chunks: usize,
+    clock: u64,
+}
+
+impl LazyImage {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::with_cache(path, CACHED_CHUNKS)
+    }
+
+    pub fn with_cache(path: &Path, max_chunks: usize) -> io::Result<Self> {
+        let (file, read_only) = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => (file, false),
+            Err(_) => (File::open(path)?, true),
+        };
+        Ok(Self {
+            len: file.metadata()?.len(),
+            file,
+            read_only,
+            chunks: HashMap::new(),
+            max_chunks: max_chunks.max(1),
+            clock: 0,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of chunks currently held in memory.
+    pub fn cached(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn write_back(file: &mut File, index: u64, chunk: &mut Chunk) -> io::Result<()> {
+        if chunk.dirty {
+            file.seek(SeekFrom::Start(index * CHUNK_SIZE as u64))?;
+            file.write_all(&chunk.data)?;
+            chunk.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn chunk(&mut self, index: u64) -> io::Result<&mut Chunk> {
+        self.clock += 1;
+        if !self.chunks.contains_key(&index) {
+            if self.chunks.len() >= self.max_chunks {
+                let oldest = *self.chunks.iter().min_by_key(|(_, c)| c.used).map(|(i, _)| i).unwrap();
+                let mut chunk = self.chunks.remove(&oldest).unwrap();
+                Self::write_back(&mut self.file, oldest, &mut chunk)?;
+            }
+            let start = index * CHUNK_SIZE as u64;
+            let size = (self.len - start).min(CHUNK_SIZE as u64) as usize;
+            let mut data = vec![0u8; size];
+            self.file.seek(SeekFrom::Start(start))?;
+            self.file.read_exact(&mut data)?;
+            self.chunks.insert(index, Chunk { data, dirty: false, used: 0 });
+        }
+        let chunk = self.chunks.get_mut(&index).unwrap();
+        chunk.used = self.clock;
+        Ok(chunk)
+    }
+
+    // calls `f` with each (chunk, range in chunk, range in buffer) covering
+    // `len` bytes at `offset`
+    fn each_chunk(
+        &mut self,
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(&mut Chunk, std::ops::Range<usize>, std::ops::Range<usize>),
+    ) -> io::Result<()> {
+        if offset + len as u64 > self.len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "past the end of the image"));
+        }
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let index = pos / CHUNK_SIZE as u64;
+            let start = (pos % CHUNK_SIZE as u64) as usize;
+            let n = (CHUNK_SIZE - start).min(len - done);
+            f(self.chunk(index)?, start..start + n, done..done + n);
+            done += n;
+        }
+        Ok(())
+    }
+
+    pub fn read(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.each_chunk(offset, buf.len(), |chunk, c, b| buf[b].copy_from_slice(&chunk.data[c]))
+    }
+
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "image is read-only"));
+        }
+        self.each_chunk(offset, data.len(), |chunk, c, b| {
+            chunk.data[c].copy_from_slice(&data[b]);
+            chunk.dirty = true;
+        })
+    }
+
+    /// Writes the changed chunks back to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for (&index, chunk) in self.chunks.iter_mut() {
+            Self::write_back(&mut self.file, index, chunk)?;
+        }
+        self.file.flush()
+    }
+}
+
+/// A hard disk image with the usual 16 heads, 63 sectors translation.
+pub struct HardDisk {
+    pub path: PathBuf,
+    pub geometry: Geometry,
+    pub image: LazyImage,
+}
+
+impl HardDisk {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let image = LazyImage::open(path)?;
+        let sectors = image.len() / SECTOR_SIZE as u64;
+        let cylinders = (sectors / (16 * 63)).min(1024) as u16;
+        if cylinders == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: too small for a hard disk image", path.display()),
+            ));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            geometry: Geometry {
+                cylinders,
+                heads: 16,
+                sectors: 63,
+                drive_type: 0,
+            },
+            image,
+        })
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.image.len() / SECTOR_SIZE as u64
+    }
+}
+
+// INT 13h status codes
+const OK: u8 = 0x00;
+const BAD_COMMAND: u8 = 0x01;
+const WRITE_PROTECTED: u8 = 0x03;
+const SECTOR_NOT_FOUND: u8 = 0x04;
+const WRITE_FAULT: u8 = 0xcc;
+const NOT_READY: u8 = 0xaa;
+
+/// Hard disks 80h and up, serviced through INT 13h including the LBA
+/// extensions (AH=41h..43h).
+#[derive(Default)]
+pub struct HardDisks {
+    pub disks: Vec<HardDisk>,
+    status: u8,
+}
+
+impl HardDisks {
+    pub fn flush(&mut self) -> io::Result<()> {
+        for disk in &mut self.disks {
+            disk.image.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, cpu: &mut Cpu, status: u8) {
+        self.status = status;
+        cpu.regs.set_ah(status);
+        if status == OK {
+            cpu.regs.flags.clear_cf();
+        } else {
+            cpu.regs.flags.set_cf();
+        }
+    }
+
+    // moves `count` sectors starting at `lba` between the disk and memory
+    // at `buffer`; returns how many were transferred and the status
+    fn transfer(disk: &mut HardDisk, cpu: &mut Cpu, write: bool, lba: u64, count: usize, buffer: u32) -> (usize, u8) {
+        if write && disk.image.read_only {
+            return (0, WRITE_PROTECTED);
+        }
+        if lba >= disk.sectors() {
+            return (0, SECTOR_NOT_FOUND);
+        }
+        let Some(offset) = lba.checked_mul(SECTOR_SIZE as u64) else {
+            return (0, SECTOR_NOT_FOUND);
+        };
+        let done = (count as u64).min(disk.sectors() - lba) as usize;
+        let mut data = vec![0u8; done * SECTOR_SIZE];
+        let mem = cpu.mem.cursor.get_mut();
+        // the buffer wraps at 1 MiB as on the floppy
+        let res = if write {
+            for (i, b) in data.iter_mut().enumerate() {
+                *b = mem.get((buffer as usize + i) & 0xfffff).map_or(0, |b| *b);
+            }
+            disk.image.write(offset, &data)
+        } else {
+            disk.image.read(offset, &mut data).map(|_| {
+                for (i, b) in data.iter().enumerate() {
+                    if let Some(m) = mem.get_mut((buffer as usize + i) & 0xfffff) {
+                        *m = *b;
+                    }
+                }
+            })
+        };
+        match res {
+            Err(_) => (0, WRITE_FAULT),
+            Ok(_) if done < count => (done, SECTOR_NOT_FOUND),
+            Ok(_) => (done, OK),
+        }
+    }
+
+    /// Services an `int 13h` for a drive number with bit 7 set.
+    pub fn int13(&mut self, cpu: &mut Cpu) {
+        let function = cpu.regs.get_ah();
+        let index = (cpu.regs.get_dl() & 0x7f) as usize;
+        if function == 0x01 {
+            let status = self.status;
+            cpu.regs.set_al(status);
+            return self.finish(cpu, status);
+        }
+        let count = self.disks.len() as u8;
+        let Some(disk) = self.disks.get_mut(index) else {
+            return self.finish(cpu, NOT_READY);
+        };
+
+        match function {
+            // reset
+            0x00 => self.finish(cpu, OK),
+            // read, write, verify sectors
+            0x02..=0x04 => {
+                let cl = cpu.regs.get_cl();
+                let cylinder = cpu.regs.get_ch() as u16 | ((cl as u16 & 0xc0) << 2);
+                let Some(lba) = disk.geometry.lba(cylinder, cpu.regs.get_dh(), cl & 0x3f) else {
+                    cpu.regs.set_al(0);
+                    return self.finish(cpu, SECTOR_NOT_FOUND);
+                };
+                let count = cpu.regs.get_al() as usize;
+                let buffer = cpu.regs.get_es() + cpu.regs.bx as u32;
+                let (done, status) = match function {
+                    0x04 => (count, OK),
+                    _ => Self::transfer(disk, cpu, function == 0x03, lba as u64, count, buffer),
+                };
+                cpu.regs.set_al(done as u8);
+                self.finish(cpu, status);
+            }
+            // drive parameters
+            0x08 => {
+                let g = disk.geometry;
+                let max_cylinder = g.cylinders - 1;
+                cpu.regs.set_ch(max_cylinder as u8);
+                cpu.regs.set_cl(g.sectors | ((max_cylinder >> 2) as u8 & 0xc0));
+                cpu.regs.set_dh(g.heads - 1);
+                cpu.regs.set_dl(count);
+                cpu.regs.set_al(0);
+                self.finish(cpu, OK);
+            }
+            // drive type: fixed disk, CX:DX sectors
+            0x15 => {
+                let sectors = disk.sectors().min(u32::MAX as u64) as u32;
+                cpu.regs.cx = (sectors >> 16) as u16;
+                cpu.regs.dx = sectors as u16;
+                cpu.regs.flags.clear_cf();
+                cpu.regs.set_ah(0x03);
+            }
+            // extensions present
+            0x41 if cpu.regs.bx == 0x55aa => {
+                cpu.regs.bx = 0xaa55;
+                cpu.regs.cx = 0x0001;
+                self.finish(cpu, OK);
+                cpu.regs.set_ah(0x01);
+            }
+            // extended read/write from the disk address packet at DS:SI:
+            // size, 0, count, buffer offset, buffer segment, 64-bit LBA.
+            // The packet wraps within DS like any other operand.
+            0x42 | 0x43 => {
+                let si = cpu.regs.si;
+                let at = |cpu: &Cpu, off: u16| cpu.ea(&Segment::Ds, si.wrapping_add(off) as u32);
+                let mut packet = [0u8; 16];
+                for (off, b) in packet.iter_mut().enumerate() {
+                    *b = cpu.read_mem_u8(at(cpu, off as u16));
+                }
+                let u16_at = |off: usize| u16::from_le_bytes([packet[off], packet[off + 1]]);
+                let count = u16_at(2) as usize;
+                let buffer = (u16_at(6) as u32 * 16 + u16_at(4) as u32) & 0xfffff;
+                let lba = u64::from_le_bytes(packet[8..].try_into().unwrap());
+                let (done, status) = Self::transfer(disk, cpu, function == 0x43, lba, count, buffer);
+                for (off, b) in (done as u16).to_le_bytes().into_iter().enumerate() {
+                    cpu.write_mem_u8(at(cpu, 2 + off as u16), b);
+                }
+                self.finish(cpu, status);
+            }
+            _ => self.finish(cpu, BAD_COMMAND),
+        }
+    }
+}
+
+/// Routes an `int 13h` to the floppy or hard disk services by DL.
+pub fn int13(cpu: &mut Cpu, floppy: &mut Floppy, disks: &mut HardDisks) {
+    if cpu.regs.get_dl() & 0x80 != 0 {
+        disks.int13(cpu);
+    } else {
+        floppy.int13(cpu);
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+
+use sixemu_core::cpu::Cpu;
+
+use crate::font::{cp437_byte, CodePage};
+
+/// Vector of the BIOS keyboard services.
+pub const KEYBOARD_INT: u8 = 0x16;
+
+// a run of keys from XT scan code `first`: what each types unshifted,
+// shifted and with AltGr, '\0' where it types nothing
+type Row = (u8, &'static str, &'static str, &'static str);
+
+const US: [Row; 5] = [
+    (0x02, "1234567890-=", "!@#$%^&*()_+", ""),
+    (0x10, "qwertyuiop[]", "QWERTYUIOP{}", ""),
+    (0x1e, "asdfghjkl;'`", "ASDFGHJKL:\"~", ""),
+    (0x2b, "\\zxcvbnm,./", "|ZXCVBNM<>?", ""),
+    (0x39, " ", " ", ""),
+];
+// the ISO keyboards have one more key, left of Z, at 56h
+const UK: [Row; 6] = [
+    (0x02, "1234567890-=", "!\"£$%^&*()_+", "\0\0\0€"),
+    (0x10, "qwertyuiop[]", "QWERTYUIOP{}", ""),
+    (0x1e, "asdfghjkl;'`", "ASDFGHJKL:@¬", ""),
+    (0x2b, "#zxcvbnm,./", "~ZXCVBNM<>?", ""),
+    (0x39, " ", " ", ""),
+    (0x56, "\\", "|", ""),
+];
+const DE: [Row; 6] = [
+    (0x02, "1234567890ß´", "!\"§$%&/()=?`", "\0²³\0\0\0{[]}\\"),
+    (0x10, "qwertzuiopü+", "QWERTZUIOPÜ*", "@\0€\0\0\0\0\0\0\0\0~"),
+    (0x1e, "asdfghjklöä^", "ASDFGHJKLÖÄ°", ""),
+    (0x2b, "#yxcvbnm,.-", "'YXCVBNM;:_", "\0\0\0\0\0\0\0µ"),
+    (0x39, " ", " ", ""),
+    (0x56, "<", ">", "|"),
+];
+const FR: [Row; 6] = [
+    (0x02, "&é\"'(-è_çà)=", "1234567890°+", "\0~#{[|`\\^@]}"),
+    (0x10, "azertyuiop^$", "AZERTYUIOP¨£", "\0\0€\0\0\0\0\0\0\0\0¤"),
+    (0x1e, "qsdfghjklmù²", "QSDFGHJKLM%", ""),
+    (0x2b, "*wxcvbn,;:!", "µWXCVBN?./§", ""),
+    (0x39, " ", " ", ""),
+    (0x56, "<", ">", ""),
+];
+
+/// The host's keyboard layout, which says which key was pressed for
+/// each character typed: a German `z` is the key a US keyboard has `y`
+/// on, and `@` is AltGr with Q.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Layout {
+    #[default]
+    Us,
+    Uk,
+    De,
+    Fr,
+}
+
+/// What is held with a key to type a character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modifier {
+    None,
+    Shift,
+    AltGr,
+}
+const ENTER: u8 = 0x1c;
+const ESC: u8 = 0x01;
+const BACKSPACE: u8 = 0x0e;
+const TAB: u8 = 0x0f;
+
+/// Scan codes of the function and cursor keys, which carry no ASCII;
+/// F2-F10 follow F1.
+pub const F1: u8 = 0x3b;
+pub const HOME: u8 = 0x47;
+pub const UP: u8 = 0x48;
+pub const PGUP: u8 = 0x49;
+pub const LEFT: u8 = 0x4b;
+pub const RIGHT: u8 = 0x4d;
+pub const END: u8 = 0x4f;
+pub const DOWN: u8 = 0x50;
+pub const PGDN: u8 = 0x51;
+pub const INS: u8 = 0x52;
+pub const DEL: u8 = 0x53;
+
+// the keys held for the others: an XT has no AltGr, so that is both
+const LEFT_SHIFT: u8 = 0x2a;
+const CTRL: u8 = 0x1d;
+const ALT: u8 = 0x38;
+// set in the scan code a key sends when it is let go
+const BREAK: u8 = 0x80;
+
+impl Layout {
+    /// Parses `us`, `uk`, `de` or `fr`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "us" => Some(Self::Us),
+            "uk" => Some(Self::Uk),
+            "de" => Some(Self::De),
+            "fr" => Some(Self::Fr),
+            _ => None,
+        }
+    }
+
+    fn rows(self) -> &'static [Row] {
+        match self {
+            Self::Us => &US,
+            Self::Uk => &UK,
+            Self::De => &DE,
+            Self::Fr => &FR,
+        }
+    }
+
+    /// The XT scan code of the key that types `c`, and what is held
+    /// with it.
+    pub fn position(self, c: char) -> Option<(u8, Modifier)> {
+        if c == '\0' {
+            return None;
+        }
+        let column = |row: &Row, n| [row.1, row.2, row.3][n];
+        [Modifier::None, Modifier::Shift, Modifier::AltGr]
+            .into_iter()
+            .enumerate()
+            .find_map(|(n, modifier)| {
+                self.rows().iter().find_map(|row| {
+                    let at = column(row, n).chars().position(|k| k == c)?;
+                    Some((row.0 + at as u8, modifier))
+                })
+            })
+    }
+
+    /// The BIOS word for typing `c`: the scan code high and the code
+    /// page 437 character low, which is how the BIOS of a machine set up
+    /// for this layout reports it. Control characters come from Ctrl
+    /// and a letter.
+    pub fn key(self, c: char) -> Option<u16> {
+        let (scan, ascii) = match c {
+            '\x1b' => (ESC, 0x1b),
+            '\x08' | '\x7f' => (BACKSPACE, 0x08),
+            '\t' => (TAB, b'\t'),
+            '\r' | '\n' => (ENTER, b'\r'),
+            '\x01'..='\x1a' => (self.position((c as u8 + b'a' - 1) as char)?.0, c as u8),
+            _ => (self.position(c)?.0, cp437_byte(c)?),
+        };
+        Some((scan as u16) << 8 | ascii as u16)
+    }
+}
+
+/// What the keyboard sends for the BIOS word `key` typed on `layout`:
+/// the make codes of the keys held for it, the key's own make and break
+/// codes, then the held keys' break codes.
+pub fn scan_codes(key: u16, layout: Layout) -> Vec<u8> {
+    let [ascii, scan] = key.to_le_bytes();
+    let held: &[u8] = match ascii {
+        // function and cursor keys, or Alt with a key
+        0 if (F1..F1 + 10).contains(&scan) || (HOME..=DEL).contains(&scan) => &[],
+        0 => &[ALT],
+        0x01..=0x1a if ![ESC, BACKSPACE, TAB, ENTER].contains(&scan) => &[CTRL],
+        _ => match layout.position(CodePage::Cp437.char(ascii)) {
+            Some((_, Modifier::Shift)) => &[LEFT_SHIFT],
+            Some((_, Modifier::AltGr)) => &[CTRL, ALT],
+            _ => &[],
+        },
+    };
+    let mut codes = held.to_vec();
+    codes.extend([scan, scan | BREAK]);
+    codes.extend(held.iter().rev().map(|code| code | BREAK));
+    codes
+}
+
+// the character `bytes` starts with, if it is UTF-8, and what follows
+fn next_char(bytes: &[u8]) -> (Option<char>, &[u8]) {
+    let len = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    match bytes.get(..len).and_then(|c| std::str::from_utf8(c).ok()) {
+        Some(c) => (c.chars().next(), &bytes[len..]),
+        None => (None, &bytes[1..]),
+    }
+}
+
+/// The keys that type `text`, for pasting it: a line break, CR, LF or
+/// both, is one Enter, and characters with no key are left out.
+pub fn paste_keys(text: &str, layout: Layout) -> Vec<u16> {
+    let mut keys = Vec::new();
+    let mut after_cr = false;
+    for c in text.chars() {
+        if !(after_cr && c == '\n') {
+            keys.extend(layout.key(c));
+        }
+        after_cr = c == '\r';
+    }
+    keys
+}
+
+/// The BIOS word of a key with no ASCII, such as a cursor key.
+pub fn extended(scan: u8) -> u16 {
+    (scan as u16) << 8
+}
+
+/// Turns what a VT100-style terminal sends into BIOS key words: UTF-8
+/// characters, typed on `layout`, the escape sequences of the cursor,
+/// editing and F1-F10 keys, and Esc followed by a character as Alt and
+/// that key. Sequences it does not know are dropped.
+pub fn terminal_keys(bytes: &[u8], layout: Layout) -> Vec<u16> {
+    let mut keys = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        if rest[0] != 0x1b || rest.len() == 1 {
+            let (c, tail) = next_char(rest);
+            rest = tail;
+            keys.extend(c.and_then(|c| layout.key(c)));
+            continue;
+        }
+        rest = &rest[1..];
+        match rest {
+            [b'[' | b'O', final_byte @ (b'A'..=b'D' | b'H' | b'F' | b'P'..=b'S'), tail @ ..] => {
+                rest = tail;
+                keys.push(extended(match final_byte {
+                    b'A' => UP,
+                    b'B' => DOWN,
+                    b'C' => RIGHT,
+                    b'D' => LEFT,
+                    b'H' => HOME,
+                    b'F' => END,
+                    f => F1 + (f - b'P'),
+                }));
+            }
+            [b'[', tail @ ..] => {
+                // ESC [ n ~, parameters and all
+                let len = tail
+                    .iter()
+                    .position(|b| !(b.is_ascii_digit() || *b == b';'))
+                    .unwrap_or(tail.len());
+                let (params, tail) = tail.split_at(len);
+                let Some((&final_byte, tail)) = tail.split_first() else {
+                    break;
+                };
+                rest = tail;
+                let n = params.split(|&b| b == b';').next().unwrap_or_default();
+                let n: u8 = std::str::from_utf8(n).ok().and_then(|n| n.parse().ok()).unwrap_or(0);
+                let scan = match (final_byte, n) {
+                    (b'~', 1 | 7) => HOME,
+                    (b'~', 2) => INS,
+                    (b'~', 3) => DEL,
+                    (b'~', 4 | 8) => END,
+                    (b'~', 5) => PGUP,
+                    (b'~', 6) => PGDN,
+                    (b'~', 11..=15) => F1 + n - 11,
+                    (b'~', 17..=21) => F1 + 5 + n - 17,
+                    _ => continue,
+                };
+                keys.push(extended(scan));
+            }
+            [_, ..] => {
+                let (c, tail) = next_char(rest);
+                rest = tail;
+                keys.extend(c.and_then(|c| layout.key(c)).map(|key| key & 0xff00));
+            }
+            [] => unreachable!(),
+        }
+    }
+    keys
+}
+
+/// Services an `int 16h` left pending in `cpu.host_int` from `keys`, the
+/// keys typed and not yet read: read (AH=00h/10h, AX=0 when none is
+/// waiting), peek with ZF set when none is (AH=01h/11h), the shift
+/// state, always none held (AH=02h/12h), and stuffing CX into the queue
+/// (AH=05h).
+pub fn int16(cpu: &mut Cpu, keys: &mut VecDeque<u16>) {
+    match cpu.regs.get_ah() {
+        0x00 | 0x10 => cpu.regs.ax = keys.pop_front().unwrap_or(0),
+        0x01 | 0x11 => match keys.front() {
+            Some(&key) => {
+                cpu.regs.ax = key;
+                cpu.regs.flags.clear_zf();
+            }
+            None => cpu.regs.flags.set_zf(),
+        },
+        0x02 | 0x12 => cpu.regs.set_al(0),
+        0x05 => {
+            keys.push_back(cpu.regs.cx);
+            cpu.regs.set_al(0);
+        }
+        _ => {}
+    }
+}
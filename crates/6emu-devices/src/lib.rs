@@ -0,0 +1,41 @@
+#[cfg(feature = "bios")]
+pub mod bios;
+#[cfg(feature = "video")]
+pub mod cga;
+pub mod clock;
+#[cfg(feature = "dos")]
+pub mod dos;
+#[cfg(feature = "disk")]
+pub mod fat12;
+#[cfg(feature = "disk")]
+pub mod floppy;
+#[cfg(any(feature = "video", feature = "keyboard"))]
+pub mod font;
+pub mod governor;
+#[cfg(feature = "disk")]
+pub mod harddisk;
+#[cfg(feature = "keyboard")]
+pub mod keyboard;
+#[cfg(feature = "timer")]
+pub mod pit;
+#[cfg(feature = "keyboard")]
+pub mod ppi;
+#[cfg(feature = "timer")]
+pub mod timer;
+#[cfg(feature = "serial")]
+pub mod uart;
+#[cfg(feature = "video")]
+pub mod video;
+
+// the tests drive the whole PC
+#[cfg(all(
+    test,
+    feature = "video",
+    feature = "keyboard",
+    feature = "timer",
+    feature = "serial",
+    feature = "disk",
+    feature = "bios",
+    feature = "dos"
+))]
+mod test;
@@ -0,0 +1,263 @@
+//! The 8253 programmable interval timer: three 16-bit counters clocked
+//! at 1.19 MHz, a quarter of the CPU clock. Channel 0 raises IRQ 0 on
+//! each rising edge of its output; channel 1 refreshed DRAM and channel
+//! 2 drives the speaker, so their outputs go nowhere here yet.
+//!
+//! Counters are worked out from the cycle count when they are read
+//! rather than stepped, so an idle timer costs nothing. A counter the
+//! guest has not programmed does not count; the BIOS's own tick is
+//! `timer::SystemTimer`.
+
+use std::ops::RangeInclusive;
+
+use sixemu_core::{
+    cpu::DeviceState,
+    machine::{Device, Irq},
+};
+
+/// Counters 0-2 and the mode register.
+pub const PIT_PORTS: RangeInclusive<u16> = 0x40..=0x43;
+const PIT_CONTROL: u16 = 0x43;
+/// CPU clocks per count.
+pub const PIT_DIVISOR: u64 = 4;
+/// The line channel 0 raises.
+pub const TIMER_IRQ: u8 = 0;
+
+/// Which bytes of the count a port access reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Low,
+    High,
+    // low byte first, then high
+    Both,
+}
+
+#[derive(Debug, Clone)]
+struct Counter {
+    mode: u8,
+    access: Access,
+    bcd: bool,
+    // counts per cycle of the output, 0 standing for 65536
+    reload: u32,
+    // the low byte of a count written as two bytes
+    low: Option<u8>,
+    // PIT clock the count was loaded at; None until one is
+    start: Option<u64>,
+    // the next rising edge of the output, in PIT clocks
+    edge: Option<u64>,
+    latch: Option<u16>,
+    // the next read of a two-byte value is of its high byte
+    read_high: bool,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self {
+            mode: 0,
+            access: Access::Both,
+            bcd: false,
+            reload: 0x10000,
+            low: None,
+            start: None,
+            edge: None,
+            latch: None,
+            read_high: false,
+        }
+    }
+}
+
+impl Counter {
+    // the count at PIT clock `t`
+    fn value(&self, t: u64) -> u16 {
+        let Some(start) = self.start else {
+            return self.reload as u16;
+        };
+        let elapsed = t.saturating_sub(start);
+        let reload = self.reload as u64;
+        match self.mode {
+            // after the terminal count the counter wraps and carries on
+            0 | 4 => (reload.wrapping_sub(elapsed) & 0xffff) as u16,
+            2 => (reload - elapsed % reload) as u16,
+            // counts down by two each half of the square wave
+            3 => {
+                let half = (reload / 2).max(1);
+                (reload - 2 * (elapsed % half)) as u16
+            }
+            // waiting for a gate trigger the PC never gives
+            _ => self.reload as u16,
+        }
+    }
+
+    fn load(&mut self, count: u16, t: u64) {
+        self.reload = if count == 0 { 0x10000 } else { count as u32 };
+        self.start = Some(t);
+        self.edge = match self.mode {
+            0 | 2 | 3 | 4 => Some(t + self.reload as u64),
+            _ => None,
+        };
+    }
+
+    fn write(&mut self, val: u8, t: u64) {
+        match self.access {
+            Access::Low => self.load(val as u16, t),
+            Access::High => self.load((val as u16) << 8, t),
+            Access::Both => match self.low.take() {
+                Some(low) => self.load(u16::from_le_bytes([low, val]), t),
+                None => {
+                    // mode 0 stops counting until the count is complete
+                    if self.mode == 0 {
+                        self.start = None;
+                        self.edge = None;
+                    }
+                    self.low = Some(val);
+                }
+            },
+        }
+    }
+
+    fn read(&mut self, t: u64) -> u8 {
+        let val = self.latch.unwrap_or_else(|| self.value(t));
+        let [low, high] = val.to_le_bytes();
+        match self.access {
+            Access::Low => {
+                self.latch = None;
+                low
+            }
+            Access::High => {
+                self.latch = None;
+                high
+            }
+            Access::Both => {
+                self.read_high = !self.read_high;
+                if self.read_high {
+                    low
+                } else {
+                    self.latch = None;
+                    high
+                }
+            }
+        }
+    }
+
+    // whether the output rose since the last call, moving on to the
+    // next edge
+    fn rose(&mut self, t: u64) -> bool {
+        match self.edge {
+            Some(edge) if t >= edge => {
+                self.edge = matches!(self.mode, 2 | 3).then_some(edge + self.reload as u64);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn describe(&self, t: u64) -> String {
+        match self.start {
+            Some(_) => format!(
+                "mode {}{}, reload {}, count {}",
+                self.mode,
+                if self.bcd { " bcd" } else { "" },
+                self.reload,
+                self.value(t)
+            ),
+            None => "idle".to_string(),
+        }
+    }
+}
+
+/// The timer, with no counter programmed.
+#[derive(Debug, Clone, Default)]
+pub struct Pit {
+    counters: [Counter; 3],
+    // the PIT clock at the last tick, for the debugger
+    now: u64,
+}
+
+impl Pit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Channel `n`'s count at CPU cycle `now`.
+    pub fn count(&self, n: usize, now: u64) -> u16 {
+        self.counters[n].value(now / PIT_DIVISOR)
+    }
+}
+
+impl Device for Pit {
+    fn name(&self) -> &str {
+        "pit"
+    }
+
+    fn ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![PIT_PORTS]
+    }
+
+    fn irqs(&self) -> Vec<u8> {
+        vec![TIMER_IRQ]
+    }
+
+    fn port_read(&mut self, port: u16, now: u64, _irq: &mut Irq) -> u8 {
+        match port {
+            PIT_CONTROL => 0xff,
+            _ => self.counters[(port & 3) as usize].read(now / PIT_DIVISOR),
+        }
+    }
+
+    fn port_write(&mut self, port: u16, val: u8, now: u64, _irq: &mut Irq) {
+        let t = now / PIT_DIVISOR;
+        if port != PIT_CONTROL {
+            self.counters[(port & 3) as usize].write(val, t);
+            return;
+        }
+        // the 8254's read-back command; an 8253 ignores it
+        let Some(counter) = self.counters.get_mut((val >> 6) as usize) else {
+            return;
+        };
+        let access = match (val >> 4) & 3 {
+            0 => {
+                // latches the count until it is read
+                if counter.latch.is_none() {
+                    counter.latch = Some(counter.value(t));
+                    counter.read_high = false;
+                }
+                return;
+            }
+            1 => Access::Low,
+            2 => Access::High,
+            _ => Access::Both,
+        };
+        // modes 6 and 7 are 2 and 3 again
+        let mode = match (val >> 1) & 7 {
+            mode @ 6..=7 => mode - 4,
+            mode => mode,
+        };
+        *counter = Counter {
+            mode,
+            access,
+            bcd: val & 1 != 0,
+            ..Counter::default()
+        };
+    }
+
+    fn tick(&mut self, now: u64, irq: &mut Irq) {
+        self.now = now / PIT_DIVISOR;
+        if self.counters[0].rose(self.now) {
+            irq.raise(TIMER_IRQ);
+        }
+        for counter in &mut self.counters[1..] {
+            counter.rose(self.now);
+        }
+    }
+
+    fn debug_state(&self) -> Option<DeviceState> {
+        Some(DeviceState {
+            name: "pit".to_string(),
+            fields: vec![
+                ("ch0", self.counters[0].describe(self.now)),
+                ("ch1", self.counters[1].describe(self.now)),
+                ("ch2", self.counters[2].describe(self.now)),
+            ],
+        })
+    }
+}
@@ -0,0 +1,195 @@
+//! The 8255 programmable peripheral interface as the XT wires it: port A
+//! (60h) reads the keyboard's scan code, port B (61h) drives the speaker
+//! gate, the keyboard's clock and clear lines and picks which half of
+//! the configuration DIP switches port C (62h) shows, and 63h takes the
+//! mode word the BIOS writes at power on.
+//!
+//! The keyboard sends the scan codes queued for it one at a time, each
+//! raising IRQ 1 and held on port A until the guest clears it with port
+//! B bit 7. It answers a reset, the clock held low and let go, with its
+//! self-test code, which is what a BIOS looks for at POST.
+
+use std::{cell::RefCell, collections::VecDeque, ops::RangeInclusive, rc::Rc};
+
+use sixemu_core::{
+    cpu::DeviceState,
+    machine::{Device, Irq},
+};
+
+/// Ports A, B and C and the mode register.
+pub const PPI_PORTS: RangeInclusive<u16> = 0x60..=0x63;
+const PORT_A: u16 = 0x60;
+const PORT_B: u16 = 0x61;
+const PORT_C: u16 = 0x62;
+/// The line the keyboard raises when a scan code is ready.
+pub const KEYBOARD_IRQ: u8 = 1;
+/// What the keyboard sends after a reset when it passed its self test.
+pub const SELF_TEST_PASSED: u8 = 0xaa;
+/// Scan codes the keyboard holds before it drops keys, sending this
+/// code in their place.
+pub const KEYBOARD_BUFFER: usize = 16;
+pub const OVERRUN: u8 = 0xff;
+
+/// Port B bits.
+pub const PB_TIMER2_GATE: u8 = 0x01;
+pub const PB_SPEAKER: u8 = 0x02;
+/// Port C shows switches 5-8 rather than 1-4.
+pub const PB_HIGH_SWITCHES: u8 = 0x08;
+/// Clear to hold the keyboard's clock low, which resets it.
+pub const PB_KEYBOARD_CLOCK: u8 = 0x40;
+/// Set to clear the scan code and acknowledge IRQ 1.
+pub const PB_KEYBOARD_CLEAR: u8 = 0x80;
+
+/// SW1 of an XT with one floppy drive, no 8087, 640K and an 80-column
+/// color adapter. A set bit is a switch turned off, and the BIOS reads
+/// the byte straight into the low half of the equipment word.
+pub const DEFAULT_SWITCHES: u8 = 0x2d;
+
+#[derive(Debug, Clone)]
+pub struct Ppi {
+    switches: u8,
+    // the last values written to port B and the mode register
+    port_b: u8,
+    mode: u8,
+    scan: u8,
+    // a code is on port A that the guest has not cleared
+    full: bool,
+    // scan codes typed and not yet sent
+    keyboard: Rc<RefCell<VecDeque<u8>>>,
+    // the keyboard's clock was held low, so it resets once let go
+    resetting: bool,
+}
+
+impl Default for Ppi {
+    fn default() -> Self {
+        Self::new(DEFAULT_SWITCHES)
+    }
+}
+
+impl Ppi {
+    /// A PPI reading `switches` from its DIP switches, bit 0 for switch 1.
+    pub fn new(switches: u8) -> Self {
+        Self {
+            switches,
+            port_b: PB_KEYBOARD_CLOCK,
+            // ports A and C in, B out
+            mode: 0x99,
+            scan: 0,
+            full: false,
+            keyboard: Rc::default(),
+            resetting: false,
+        }
+    }
+
+    /// The keyboard's queue of scan codes, for whatever types on it to
+    /// fill with `type_codes`.
+    pub fn keyboard(&self) -> Rc<RefCell<VecDeque<u8>>> {
+        self.keyboard.clone()
+    }
+
+    /// Port C: the switches in the low four bits. Timer 2's output, the
+    /// I/O channel check and the parity error in the high ones are never
+    /// set.
+    pub fn port_c(&self) -> u8 {
+        if self.port_b & PB_HIGH_SWITCHES != 0 {
+            self.switches >> 4
+        } else {
+            self.switches & 0x0f
+        }
+    }
+
+    fn write_port_b(&mut self, val: u8, irq: &mut Irq) {
+        self.port_b = val;
+        if val & PB_KEYBOARD_CLOCK == 0 {
+            self.resetting = true;
+        }
+        if val & PB_KEYBOARD_CLEAR != 0 {
+            self.scan = 0;
+            self.full = false;
+            irq.lower(KEYBOARD_IRQ);
+        } else if self.resetting && val & PB_KEYBOARD_CLOCK != 0 {
+            // the keyboard's answer arrives once it may send again,
+            // ahead of anything typed before
+            self.resetting = false;
+            self.keyboard.borrow_mut().clear();
+            self.send(SELF_TEST_PASSED, irq);
+        }
+    }
+
+    fn send(&mut self, scan: u8, irq: &mut Irq) {
+        self.scan = scan;
+        self.full = true;
+        irq.raise(KEYBOARD_IRQ);
+    }
+}
+
+/// Queues `codes` for the keyboard of `Ppi::keyboard` to send, as far as
+/// its buffer has room.
+pub fn type_codes(keyboard: &RefCell<VecDeque<u8>>, codes: &[u8]) {
+    let mut queue = keyboard.borrow_mut();
+    for &code in codes {
+        match queue.len() {
+            n if n < KEYBOARD_BUFFER - 1 => queue.push_back(code),
+            n if n == KEYBOARD_BUFFER - 1 => queue.push_back(OVERRUN),
+            _ => break,
+        }
+    }
+}
+
+impl Device for Ppi {
+    fn name(&self) -> &str {
+        "ppi"
+    }
+
+    fn ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![PPI_PORTS]
+    }
+
+    fn irqs(&self) -> Vec<u8> {
+        vec![KEYBOARD_IRQ]
+    }
+
+    fn port_read(&mut self, port: u16, _now: u64, _irq: &mut Irq) -> u8 {
+        match port {
+            PORT_A => self.scan,
+            PORT_B => self.port_b,
+            PORT_C => self.port_c(),
+            // the mode register cannot be read back
+            _ => 0xff,
+        }
+    }
+
+    fn port_write(&mut self, port: u16, val: u8, _now: u64, irq: &mut Irq) {
+        match port {
+            PORT_B => self.write_port_b(val, irq),
+            // ports A and C are inputs
+            PORT_A | PORT_C => {}
+            _ => self.mode = val,
+        }
+    }
+
+    fn tick(&mut self, _now: u64, irq: &mut Irq) {
+        // nothing goes out while the clock is held or the last code is
+        // still waiting to be read
+        if self.full || self.port_b & (PB_KEYBOARD_CLOCK | PB_KEYBOARD_CLEAR) != PB_KEYBOARD_CLOCK {
+            return;
+        }
+        let next = self.keyboard.borrow_mut().pop_front();
+        if let Some(scan) = next {
+            self.send(scan, irq);
+        }
+    }
+
+    fn debug_state(&self) -> Option<DeviceState> {
+        Some(DeviceState {
+            name: "ppi".to_string(),
+            fields: vec![
+                ("mode", format!("{:02x}", self.mode)),
+                ("port b", format!("{:02x}", self.port_b)),
+                ("switches", format!("{:02x}", self.switches)),
+                ("scan", format!("{:02x}", self.scan)),
+                ("typed", format!("{} codes", self.keyboard.borrow().len())),
+            ],
+        })
+    }
+}
@@ -0,0 +1,1085 @@
+use sixemu_core::{
+    asm::assemble,
+    cpu::{Cpu, IoDevice, RunExit, Segment, CLOCKS_PER_INSTRUCTION, CPU_HZ},
+    machine::Machine,
+};
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bios::{self, OptionRom},
+    cga::{self, Cga, FRAME_LINES, LINE_CYCLES},
+    clock::{self, Clock, Cmos, DateTime},
+    dos::{self, Console},
+    fat12::Fat12,
+    floppy::{self, Floppy},
+    font::CodePage,
+    governor::Governor,
+    harddisk::{self, HardDisk, HardDisks, LazyImage, CHUNK_SIZE},
+    keyboard::{self, Layout, Modifier},
+    pit::{Pit, PIT_DIVISOR},
+    ppi::{self, Ppi},
+    timer::{Pacing, SystemTimer, TICK_CYCLES},
+    uart::{self, Uart},
+    video,
+};
+
+#[test]
+fn text_screen_dump() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov ax, 0xb800
+        mov ds, ax
+        mov word [0], 0x0748
+        mov word [0xa2], 0x0769
+        hlt",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    cpu.fire();
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::text_cell(mem, 1, 1), (b'i', 7));
+    let screen = video::text_screen(mem);
+    assert_eq!(screen.lines().count(), video::TEXT_ROWS);
+    assert!(screen.starts_with("H\n i\n\n"));
+}
+
+#[test]
+fn text_screen_render() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov ax,0xb800; mov ds,ax; mov word [0xa2],0x1e48; hlt
+    cpu.load_code_vec(&[
+        0xb8, 0x00, 0xb8, 0x8e, 0xd8, 0xc7, 0x06, 0xa2, 0x00, 0x48, 0x1e, 0xf4,
+    ]);
+    cpu.fire();
+    let pixels = video::render_text(cpu.mem.cursor.get_ref());
+    assert_eq!(pixels.len(), video::TEXT_WIDTH * video::TEXT_HEIGHT);
+    // 'H' in yellow on blue at row 1, column 1: its glyph row 2 is .#....#.
+    let line = (13 + 2) * video::TEXT_WIDTH + 8;
+    assert_eq!(&pixels[line..line + 8], &[1, 14, 1, 1, 1, 1, 14, 1]);
+    // untouched cells are black
+    assert!(pixels[..video::TEXT_WIDTH * 13].iter().all(|&p| p == 0));
+    assert_eq!(&video::to_rgb(&pixels[line + 1..line + 2]), &[0xff, 0xff, 0x55]);
+}
+
+#[test]
+fn text_screen_dirty_cells() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov ax, 0xb800
+        mov ds, ax
+        mov word [0xa2], 0x1e48
+        mov byte [0xa6], 'i'
+        hlt",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    cpu.dirty = Some(video::text_dirty());
+    // the whole screen to begin with
+    let span = cpu.dirty.as_mut().unwrap().take().unwrap();
+    assert_eq!(video::dirty_cells(cpu.mem.cursor.get_ref(), span), 0..video::TEXT_ROWS * video::TEXT_COLS);
+    cpu.fire();
+    let span = cpu.dirty.as_mut().unwrap().take().unwrap();
+    assert_eq!(video::dirty_cells(cpu.mem.cursor.get_ref(), span), 81..84);
+
+    // drawing just those cells matches drawing it all
+    let mut pixels = vec![0; video::TEXT_WIDTH * video::TEXT_HEIGHT];
+    video::render_cells(cpu.mem.cursor.get_ref(), &mut pixels, 81..84);
+    assert_eq!(pixels, video::render_text(cpu.mem.cursor.get_ref()));
+
+    // writing what is already there changes nothing
+    cpu.halt = false;
+    cpu.regs.ip = 0;
+    cpu.fire();
+    assert_eq!(cpu.dirty.as_mut().unwrap().take(), None);
+}
+
+#[test]
+fn video_cursor_and_pages() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let int10 = |cpu: &mut Cpu, ax: u16, bx: u16, cx: u16, dx: u16| {
+        (cpu.regs.ax, cpu.regs.bx, cpu.regs.cx, cpu.regs.dx) = (ax, bx, cx, dx);
+        video::int10(cpu);
+    };
+    assert_eq!(video::cursor(cpu.mem.cursor.get_ref()), None);
+    int10(&mut cpu, 0x0003, 0, 0, 0);
+    assert_eq!(video::text_cell(cpu.mem.cursor.get_ref(), 24, 79), (b' ', 0x07));
+    assert_eq!(video::cursor(cpu.mem.cursor.get_ref()), Some((0, 0)));
+
+    // the cursor of page 1 moves without the one shown
+    int10(&mut cpu, 0x0200, 0x0100, 0, 0x0a05);
+    int10(&mut cpu, 0x0300, 0x0100, 0, 0);
+    assert_eq!((cpu.regs.dx, cpu.regs.cx), (0x0a05, 0x0607));
+    assert_eq!(video::cursor(cpu.mem.cursor.get_ref()), Some((0, 0)));
+
+    // three stars on page 1, then hello on a line of its own
+    int10(&mut cpu, 0x092a, 0x011e, 3, 0);
+    int10(&mut cpu, 0x0500, 0, 0, 0);
+    assert_eq!(video::text_cell(cpu.mem.cursor.get_ref(), 10, 5), (b' ', 0x07));
+    int10(&mut cpu, 0x0501, 0, 0, 0);
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::cursor(mem), Some((10, 5)));
+    assert_eq!(video::text_cell(mem, 10, 7), (b'*', 0x1e));
+    assert_eq!(video::text_cell(mem, 10, 8), (b' ', 0x07));
+    for c in "\r\nhello!\x08".bytes() {
+        int10(&mut cpu, 0x0e00 | c as u16, 0, 0, 0);
+    }
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::text_cell(mem, 11, 4), (b'o', 0x07));
+    assert_eq!(video::cursor(mem), Some((11, 5)));
+    int10(&mut cpu, 0x0800, 0x0100, 0, 0);
+    assert_eq!(cpu.regs.ax, 0x0721);
+    int10(&mut cpu, 0x0f00, 0, 0, 0);
+    assert_eq!((cpu.regs.ax, cpu.regs.get_bh()), (0x5003, 1));
+
+    // only the page shown counts as changed, and turning the cursor off hides it
+    let span = 0xb8000..0xb8002;
+    assert!(video::dirty_cells(cpu.mem.cursor.get_ref(), span).is_empty());
+    assert_eq!(video::dirty_cells(cpu.mem.cursor.get_ref(), 0xb9000 + 160..0xb9000 + 164), 80..82);
+    int10(&mut cpu, 0x0100, 0, 0x2000, 0);
+    assert_eq!(video::cursor(cpu.mem.cursor.get_ref()), None);
+}
+
+#[test]
+fn video_scrolling() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let int10 = |cpu: &mut Cpu, ax: u16, bx: u16, cx: u16, dx: u16| {
+        (cpu.regs.ax, cpu.regs.bx, cpu.regs.cx, cpu.regs.dx) = (ax, bx, cx, dx);
+        video::int10(cpu);
+    };
+    int10(&mut cpu, 0x0003, 0, 0, 0);
+    // a line per row, the last one in yellow on blue
+    for row in 0..video::TEXT_ROWS as u16 {
+        int10(&mut cpu, 0x0200, 0, 0, row << 8);
+        int10(&mut cpu, 0x0900 | (b'a' as u16 + row), 0x07 + (row / 24) * 0x17, 2, 0);
+    }
+    // a line feed on the last row scrolls the screen up
+    int10(&mut cpu, 0x0e0a, 0, 0, 0);
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::cursor(mem), Some((24, 0)));
+    assert_eq!(video::text_cell(mem, 0, 1), (b'b', 0x07));
+    assert_eq!(video::text_cell(mem, 23, 0), (b'y', 0x1e));
+    assert_eq!(video::text_cell(mem, 24, 0), (b' ', 0x1e));
+    // and so does running off the end of it
+    int10(&mut cpu, 0x0200, 0, 0, 0x184f);
+    int10(&mut cpu, 0x0e21, 0, 0, 0);
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::text_cell(mem, 23, 79), (b'!', 0x1e));
+    assert_eq!(video::text_cell(mem, 0, 0), (b'c', 0x07));
+    assert_eq!(video::cursor(mem), Some((24, 0)));
+
+    // a window two rows high scrolled down by one, then cleared
+    int10(&mut cpu, 0x0701, 0x4f00, 0x0000, 0x0101);
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(video::text_cell(mem, 0, 0), (b' ', 0x4f));
+    assert_eq!(video::text_cell(mem, 1, 1), (b'c', 0x07));
+    assert_eq!(video::text_cell(mem, 2, 0), (b'e', 0x07));
+    int10(&mut cpu, 0x0600, 0x7000, 0x0000, 0x0101);
+    assert_eq!(video::text_cell(cpu.mem.cursor.get_ref(), 1, 1), (b' ', 0x70));
+}
+
+#[test]
+fn text_attributes_as_ansi() {
+    assert_eq!(video::ansi_attribute(0x07, true), "\x1b[0;37;40m");
+    // yellow on blue: the CGA's brown and blue are ANSI's yellow and blue
+    assert_eq!(video::ansi_attribute(0x1e, true), "\x1b[0;93;44m");
+    assert_eq!(video::ansi_attribute(0x4c, true), "\x1b[0;91;41m");
+    // bit 7 blinks, or brightens the background with blinking off
+    assert_eq!(video::ansi_attribute(0x9f, true), "\x1b[0;97;44;5m");
+    assert_eq!(video::ansi_attribute(0x9f, false), "\x1b[0;97;104m");
+}
+
+#[test]
+fn pit_irq0() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // channel 0 as a rate generator every 100 counts, 50 instructions;
+    // the handler counts ticks in BX until there are 3, then latches
+    // channel 2's count into CX
+    let code = assemble(
+        "mov word [0x100], tick
+        mov word [0x102], 0
+        mov al, 0x34
+        out 0x43, al
+        mov al, 100
+        out 0x40, al
+        mov al, 0
+        out 0x40, al
+        mov al, 0xb6
+        out 0x43, al
+        mov ax, 1000
+        out 0x42, al
+        mov al, ah
+        out 0x42, al
+        sti
+    idle:
+        cmp bx, 3
+        jne idle
+        mov al, 0x80
+        out 0x43, al
+        in al, 0x42
+        mov cl, al
+        in al, 0x42
+        mov ch, al
+        hlt
+    tick:
+        inc bx
+        iret",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    let mut machine = Machine::new(cpu);
+    // the program sits on the usual vectors
+    machine.bus.irq_base = 0x40;
+    machine.attach(Box::new(Pit::new())).unwrap();
+    assert_eq!(machine.run_until(|_| false), RunExit::Halted);
+    // three periods, give or take the instructions around them
+    let counts = machine.cpu.cycles() / PIT_DIVISOR;
+    assert!((300..400).contains(&counts), "{} counts", counts);
+    // the square wave counts down by two from 1000, and was latched a
+    // few instructions before the end
+    let cx = machine.cpu.regs.cx;
+    assert!(cx.is_multiple_of(2) && cx <= 1000, "{}", cx);
+    let states = machine.cpu.device_states();
+    assert_eq!(states[0].name, "pit");
+    assert_eq!(states[0].fields[0].1.split(',').next(), Some("mode 2"));
+    assert_eq!(states[0].fields[1], ("ch1", "idle".to_string()));
+}
+
+#[test]
+fn ppi_switches_and_keyboard_reset() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // reads both halves of the switches into DX, then resets the
+    // keyboard and waits for its answer on IRQ 1, as a BIOS does
+    let code = assemble(
+        "mov word [0x104], key
+        mov word [0x106], 0
+        mov al, 0x99
+        out 0x63, al
+        mov al, 0x40
+        out 0x61, al
+        in al, 0x62
+        mov dl, al
+        mov al, 0x48
+        out 0x61, al
+        in al, 0x62
+        mov dh, al
+        mov al, 0x08
+        out 0x61, al
+        mov al, 0xc8
+        out 0x61, al
+        mov al, 0x48
+        out 0x61, al
+        sti
+    idle:
+        test bl, bl
+        jz idle
+        hlt
+    key:
+        in al, 0x60
+        mov bl, al
+        in al, 0x61
+        or al, 0x80
+        out 0x61, al
+        and al, 0x7f
+        out 0x61, al
+        iret",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    let mut machine = Machine::new(cpu);
+    machine.bus.irq_base = 0x40;
+    machine.attach(Box::new(Ppi::new(0x6d))).unwrap();
+    assert_eq!(machine.run_until(|_| false), RunExit::Halted);
+    assert_eq!(machine.cpu.regs.dx, 0x060d);
+    assert_eq!(machine.cpu.regs.get_bl(), ppi::SELF_TEST_PASSED);
+    // acknowledged, and nothing more to read
+    assert_eq!(machine.bus.pending(), 0);
+    assert_eq!(machine.cpu.io_read(0x60), 0);
+}
+
+#[test]
+fn cga_retrace() {
+    let cga = Cga::new();
+    let status = |line: u64, clock: u64| cga.status(line * LINE_CYCLES + clock);
+    assert_eq!(status(0, 0), cga::STATUS_PEN_OFF);
+    assert_eq!(status(0, 250), cga::STATUS_PEN_OFF | cga::STATUS_NO_DISPLAY);
+    assert_eq!(status(210, 0), cga::STATUS_PEN_OFF | cga::STATUS_NO_DISPLAY);
+    let retrace = cga::STATUS_PEN_OFF | cga::STATUS_NO_DISPLAY | cga::STATUS_VRETRACE;
+    assert_eq!(status(230, 0), retrace);
+    assert_eq!(status(FRAME_LINES + 230, 0), retrace);
+
+    // waiting for the end of a retrace, as programs do before drawing
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov dx, 0x3da
+    start:
+        in al, dx
+        test al, 8
+        jz start
+    end:
+        in al, dx
+        test al, 8
+        jnz end
+        mov dx, 0x3dc
+        out dx, al
+        mov dx, 0x3da
+        in al, dx
+        hlt",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    let mut machine = Machine::new(cpu);
+    machine.attach(Box::new(Cga::new())).unwrap();
+    assert_eq!(machine.run_until(|_| false), RunExit::Halted);
+    let cycles = machine.cpu.cycles();
+    assert!((240 * LINE_CYCLES..241 * LINE_CYCLES).contains(&cycles), "{}", cycles);
+    assert_eq!(machine.cpu.regs.get_al() & cga::STATUS_PEN_TRIGGERED, cga::STATUS_PEN_TRIGGERED);
+}
+
+#[test]
+fn cga_palette_and_composite() {
+    let registers = cga::Registers { mode: 0x0a, color: 0x21 };
+    assert_eq!(registers.palette(), [1, 3, 5, 7]);
+    let bright = cga::Registers { mode: 0x0e, color: 0x11 };
+    assert_eq!(bright.palette(), [1, 11, 12, 15]);
+
+    // mode 4 and the first palette through int 10h reach the adapter
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let cga = Cga::new();
+    let shared = cga.registers();
+    let mut machine = Machine::new(cpu);
+    machine.attach(Box::new(cga)).unwrap();
+    let cpu = &mut machine.cpu;
+    (cpu.regs.ax, cpu.regs.bx) = (0x0004, 0);
+    video::int10(cpu);
+    (cpu.regs.ax, cpu.regs.bx) = (0x0b00, 0x0100);
+    video::int10(cpu);
+    assert_eq!(shared.get(), cga::Registers { mode: 0x2a, color: 0x10 });
+
+    // pixels 0-3 on the first line, and pixel 3 on the second
+    let mem = cpu.mem.cursor.get_mut();
+    mem[video::TEXT_BASE] = 0x1b;
+    mem[video::TEXT_BASE + 0x2000] = 0xc0;
+    let pixels = video::render_graphics(mem, shared.get(), false);
+    assert_eq!(&pixels[..8], &[0, 0, 10, 10, 12, 12, 14, 14]);
+    assert_eq!(&pixels[video::GRAPHICS_WIDTH..video::GRAPHICS_WIDTH + 3], &[14, 14, 0]);
+
+    // 640x200 in the foreground color, or a color per nibble composite
+    let hires = cga::Registers { mode: 0x1a, color: 0x0e };
+    let pixels = video::render_graphics(mem, hires, false);
+    assert_eq!(&pixels[..8], &[0, 0, 0, 14, 14, 0, 14, 14]);
+    let pixels = video::render_graphics(mem, hires, true);
+    assert_eq!(&pixels[..8], &[17, 17, 17, 17, 27, 27, 27, 27]);
+    let gray = cga::Registers { mode: 0x1e, ..hires };
+    assert_eq!(video::render_graphics(mem, gray, true)[3], 14);
+
+    // lines stretched to the text screen's height, or nothing when off
+    let screen = video::render_screen(mem, shared.get(), false);
+    assert_eq!(screen.len(), video::TEXT_WIDTH * video::TEXT_HEIGHT);
+    assert_eq!(&screen[..4], &[0, 0, 10, 10]);
+    let off = cga::Registers { mode: 0x22, ..shared.get() };
+    assert!(video::render_screen(mem, off, false).iter().all(|&p| p == 0));
+
+    // bit 7 of an attribute brightens the background once blink is off
+    mem[video::TEXT_BASE..video::TEXT_BASE + 2].copy_from_slice(&[b' ', 0x9f]);
+    let blink = cga::Registers::default();
+    assert_eq!(video::render_screen(mem, blink, false)[0], 1);
+    let steady = cga::Registers { mode: 0x09, ..blink };
+    assert_eq!(video::render_screen(mem, steady, false)[0], 9);
+}
+
+// a freshly formatted 1.44M floppy: 512-byte sectors, 1 per cluster,
+// 2 FATs of 9 sectors, 224 root entries, 2880 sectors
+fn blank_floppy() -> Vec<u8> {
+    let mut image = vec![0u8; 2880 * 512];
+    image[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+    image[0x0d] = 1;
+    image[0x0e..0x10].copy_from_slice(&1u16.to_le_bytes());
+    image[0x10] = 2;
+    image[0x11..0x13].copy_from_slice(&224u16.to_le_bytes());
+    image[0x13..0x15].copy_from_slice(&2880u16.to_le_bytes());
+    image[0x15] = 0xf0;
+    image[0x16..0x18].copy_from_slice(&9u16.to_le_bytes());
+    for fat in [512, 512 + 9 * 512] {
+        image[fat..fat + 3].copy_from_slice(&[0xf0, 0xff, 0xff]);
+    }
+    image
+}
+
+#[test]
+fn fat12_copy() {
+    let mut fs = Fat12::open(blank_floppy()).unwrap();
+    let big: Vec<u8> = (0..1300u32).map(|i| i as u8).collect();
+    fs.write_file("big.bin", &big).unwrap();
+    fs.write_file("HELLO.TXT", b"hi there").unwrap();
+    fs.write_file("empty", b"").unwrap();
+
+    // survives a round trip through the image bytes
+    let fs = Fat12::open(fs.into_image()).unwrap();
+    assert_eq!(fs.read_file("BIG.BIN").unwrap(), big);
+    assert_eq!(fs.read_file("hello.txt").unwrap(), b"hi there");
+    assert_eq!(fs.read_file("EMPTY").unwrap(), b"");
+    assert_eq!(
+        fs.list(),
+        vec![("BIG.BIN".to_string(), 1300), ("HELLO.TXT".to_string(), 8), ("EMPTY".to_string(), 0)]
+    );
+
+    let image = fs.into_image();
+    // big.bin took clusters 2..=4, hello.txt cluster 5; both FAT copies
+    // agree: 2->3, 3->4, 4->end, 5->end
+    assert_eq!(&image[512..521], &[0xf0, 0xff, 0xff, 0x03, 0x40, 0x00, 0xff, 0xff, 0xff]);
+    assert_eq!(image[512..512 + 9 * 512], image[512 + 9 * 512..512 + 18 * 512]);
+    // the first data sector follows the root directory (19 + 14 sectors)
+    assert_eq!(&image[33 * 512..33 * 512 + 4], &[0, 1, 2, 3]);
+}
+
+#[test]
+fn fat12_overwrite() {
+    let mut fs = Fat12::open(blank_floppy()).unwrap();
+    fs.write_file("A.TXT", &[1; 2000]).unwrap();
+    fs.write_file("A.TXT", b"short").unwrap();
+    assert_eq!(fs.list().len(), 1);
+    assert_eq!(fs.read_file("A.TXT").unwrap(), b"short");
+
+    // the freed clusters are reused
+    fs.write_file("B.TXT", &[2; 1024]).unwrap();
+    let image = fs.into_image();
+    assert_eq!(image[33 * 512 + 512], 2);
+
+    let mut fs = Fat12::open(image).unwrap();
+    assert!(fs.read_file("C.TXT").is_err());
+    assert!(fs.write_file("TOOLONGNAME.TXT", b"").is_err());
+    assert!(fs.write_file("A B.TXT", b"").is_err());
+    // does not fit, and leaves the image alone
+    assert!(fs.write_file("HUGE", &vec![0; 1474560]).is_err());
+    assert_eq!(fs.read_file("A.TXT").unwrap(), b"short");
+
+    assert!(Fat12::open(vec![0; 1024]).is_err());
+    // a BPB whose FAT and root directory lie past its one sector, and
+    // one whose single FAT sector is too small for its clusters
+    let bpb = |total: u16, len: usize| {
+        let mut image = vec![0; len];
+        image[0x0b..0x18].copy_from_slice(&[0, 2, 1, 1, 0, 1, 16, 0, total as u8, (total >> 8) as u8, 0xf8, 1, 0]);
+        Fat12::open(image).err().map(|e| e.to_string())
+    };
+    assert_eq!(bpb(1, 512).unwrap(), "the boot sector puts the data area at sector 3 of 1");
+    assert_eq!(bpb(2000, 2000 * 512).unwrap(), "a FAT of 512 bytes cannot hold 1997 clusters");
+    assert_eq!(bpb(300, 300 * 512), None);
+}
+
+// a 1.44M image in the temp dir whose sector n is filled with n as u8
+fn temp_floppy(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("sixemu-{}-{}.img", std::process::id(), name));
+    let image: Vec<u8> = (0..2880 * 512).map(|i| (i / 512) as u8).collect();
+    fs::write(&path, image).unwrap();
+    path
+}
+
+fn run_with_floppy(cpu: &mut Cpu, floppy: &mut Floppy) {
+    cpu.host_ints.push(floppy::DISK_INT);
+    while let Some(i) = cpu.fetch() {
+        cpu.execute(&i);
+        if cpu.host_int.take() == Some(floppy::DISK_INT) {
+            floppy.int13(cpu);
+        }
+        if cpu.halt {
+            break;
+        }
+    }
+}
+
+#[test]
+fn floppy_read_write() {
+    let path = temp_floppy("rw");
+    let mut floppy = Floppy::default();
+    floppy.insert(0, &path).unwrap();
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // read 2 sectors from cylinder 0, head 1, sector 2 of A: to 0:0600,
+    // mark the first one and write it back to cylinder 1, head 0, sector 1:
+    // mov ax,0x0202; mov cx,0x0002; mov dx,0x0100; mov bx,0x600; int 0x13
+    // mov byte [0x600],0xaa; mov ax,0x0301; mov cx,0x0101; mov dx,0; int 0x13; hlt
+    cpu.load_code_vec(&[
+        0xb8, 0x02, 0x02, 0xb9, 0x02, 0x00, 0xba, 0x00, 0x01, 0xbb, 0x00, 0x06, 0xcd, 0x13, 0xc6,
+        0x06, 0x00, 0x06, 0xaa, 0xb8, 0x01, 0x03, 0xb9, 0x01, 0x01, 0xba, 0x00, 0x00, 0xcd, 0x13,
+        0xf4,
+    ]);
+    run_with_floppy(&mut cpu, &mut floppy);
+    let mem = cpu.mem.cursor.get_ref();
+    // (0 * 2 + 1) * 18 + 2 - 1 = 19
+    assert_eq!(mem[0x601], 19);
+    assert_eq!(mem[0x600 + 512], 20);
+    assert_eq!(cpu.regs.get_ah(), 0);
+    assert_eq!(cpu.regs.get_al(), 1);
+    assert!(!cpu.regs.flags.cf());
+
+    // nothing reaches the file before a flush
+    assert_eq!(fs::read(&path).unwrap()[36 * 512], 36);
+    floppy.eject(0).unwrap();
+    let image = fs::read(&path).unwrap();
+    assert_eq!(&image[36 * 512..36 * 512 + 2], &[0xaa, 19]);
+
+    // a buffer at FFFF:0000 wraps to the bottom of memory, past the
+    // last byte, which memory stops short of
+    floppy.insert(0, &path).unwrap();
+    let mut call = |cpu: &mut Cpu, ax: u16, cx: u16| {
+        (cpu.regs.ax, cpu.regs.cx, cpu.regs.dx, cpu.regs.bx) = (ax, cx, 0, 0);
+        cpu.regs.es = 0xffff;
+        floppy.int13(cpu);
+        (cpu.regs.get_ah(), cpu.regs.get_al())
+    };
+    // read sector 3, write it back to cylinder 1, head 0, sector 2
+    assert_eq!(call(&mut cpu, 0x0201, 0x0003), (0, 1));
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(mem.len(), 0xfffff);
+    assert!(mem[0xffff0..].iter().chain(&mem[..0x1f0]).all(|&b| b == 2));
+    assert_eq!(mem[0x1f0], 0);
+    assert_eq!(call(&mut cpu, 0x0301, 0x0102), (0, 1));
+    floppy.eject(0).unwrap();
+    let sector = &fs::read(&path).unwrap()[37 * 512..38 * 512];
+    assert_eq!((sector[14], sector[15], sector[16]), (2, 0, 2));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn floppy_swap() {
+    let (a, b) = (temp_floppy("a"), temp_floppy("b"));
+    let mut floppy = Floppy::default();
+    assert_eq!(floppy::drive_index("B:"), Some(1));
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(floppy::DISK_INT);
+    let mut call = |floppy: &mut Floppy, ax: u16, dx: u16| {
+        cpu.regs.ax = ax;
+        cpu.regs.dx = dx;
+        floppy.int13(&mut cpu);
+        (cpu.regs.get_ah(), cpu.regs.flags.cf())
+    };
+
+    // empty drive
+    assert_eq!(call(&mut floppy, 0x0201, 1), (0x80, true));
+    floppy.insert(1, &b).unwrap();
+    // the change is reported once
+    assert_eq!(call(&mut floppy, 0x1600, 1), (0x06, true));
+    assert_eq!(call(&mut floppy, 0x1600, 1), (0x00, false));
+    floppy.insert(1, &a).unwrap();
+    assert_eq!(floppy.drives[1].as_ref().unwrap().path, a);
+    assert_eq!(call(&mut floppy, 0x1600, 1), (0x06, true));
+    floppy.eject(1).unwrap();
+    assert_eq!(call(&mut floppy, 0x0800, 1), (0x80, true));
+    // drives beyond B:
+    assert_eq!(call(&mut floppy, 0x0000, 2), (0x01, true));
+
+    assert!(floppy.insert(0, &env::temp_dir().join("sixemu-missing.img")).is_err());
+    fs::remove_file(a).unwrap();
+    fs::remove_file(b).unwrap();
+}
+
+#[test]
+fn lazy_image_chunks() {
+    let path = env::temp_dir().join(format!("sixemu-{}-lazy.img", std::process::id()));
+    fs::write(&path, vec![7u8; 4 * CHUNK_SIZE]).unwrap();
+    let mut image = LazyImage::with_cache(&path, 2).unwrap();
+
+    // a write straddling chunks 0 and 1
+    image.write(CHUNK_SIZE as u64 - 2, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(image.cached(), 2);
+    let mut buf = [0u8; 6];
+    image.read(CHUNK_SIZE as u64 - 3, &mut buf).unwrap();
+    assert_eq!(buf, [7, 1, 2, 3, 4, 7]);
+    assert_eq!(fs::read(&path).unwrap()[CHUNK_SIZE - 2], 7);
+
+    // reading two more chunks evicts both dirty ones, which are written back
+    image.read(2 * CHUNK_SIZE as u64, &mut buf).unwrap();
+    image.read(3 * CHUNK_SIZE as u64, &mut buf).unwrap();
+    assert_eq!(image.cached(), 2);
+    let file = fs::read(&path).unwrap();
+    assert_eq!(&file[CHUNK_SIZE - 2..CHUNK_SIZE + 2], &[1, 2, 3, 4]);
+
+    image.write(3 * CHUNK_SIZE as u64, &[9]).unwrap();
+    image.flush().unwrap();
+    assert_eq!(fs::read(&path).unwrap()[3 * CHUNK_SIZE], 9);
+    assert!(image.read(4 * CHUNK_SIZE as u64 - 1, &mut buf).is_err());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn harddisk_int13() {
+    // 20 cylinders of 16 heads and 63 sectors, sector n filled with n as u8
+    let path = env::temp_dir().join(format!("sixemu-{}-hd.img", std::process::id()));
+    let image: Vec<u8> = (0..20 * 16 * 63 * 512).map(|i| (i / 512) as u8).collect();
+    fs::write(&path, image).unwrap();
+    let mut disks = HardDisks::default();
+    disks.disks.push(HardDisk::open(&path).unwrap());
+    let mut floppy = Floppy::default();
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(floppy::DISK_INT);
+    // mov ax,0x0201; mov cx,0x0103; mov dx,0x0280; mov bx,0x600; int 0x13
+    // mov ah,0x41; mov bx,0x55aa; int 0x13
+    // mov ah,0x42; mov si,0x500; int 0x13; hlt
+    cpu.load_code_vec(&[
+        0xb8, 0x01, 0x02, 0xb9, 0x03, 0x01, 0xba, 0x80, 0x02, 0xbb, 0x00, 0x06, 0xcd, 0x13, 0xb4,
+        0x41, 0xbb, 0xaa, 0x55, 0xcd, 0x13, 0xb4, 0x42, 0xbe, 0x00, 0x05, 0xcd, 0x13, 0xf4,
+    ]);
+    // disk address packet: 2 sectors from LBA 1000 to 0070:0000
+    let packet = [16, 0, 2, 0, 0, 0, 0x70, 0, 0xe8, 0x03, 0, 0, 0, 0, 0, 0];
+    cpu.mem.cursor.get_mut()[0x500..0x510].copy_from_slice(&packet);
+
+    let mut extensions = None;
+    while let Some(i) = cpu.fetch() {
+        cpu.execute(&i);
+        if cpu.host_int.take() == Some(floppy::DISK_INT) {
+            harddisk::int13(&mut cpu, &mut floppy, &mut disks);
+            if cpu.regs.bx == 0xaa55 && extensions.is_none() {
+                extensions = Some(cpu.regs.get_ah());
+            }
+        }
+        if cpu.halt {
+            break;
+        }
+    }
+    let mem = cpu.mem.cursor.get_ref();
+    // cylinder 1, head 2, sector 3: (1 * 16 + 2) * 63 + 2 = 1136
+    assert_eq!(mem[0x600], (1136 % 256) as u8);
+    assert_eq!(extensions, Some(0x01));
+    assert_eq!(mem[0x700], (1000 % 256) as u8);
+    assert_eq!(mem[0x900], (1001 % 256) as u8);
+    assert!(!cpu.regs.flags.cf());
+
+    // the packet at DS:SI, with the count written back as the status
+    let ext = |cpu: &mut Cpu, disks: &mut HardDisks, function: u8, si: u16, lba: u64| {
+        let mut packet = [16, 0, 1, 0, 0, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        packet[8..].copy_from_slice(&lba.to_le_bytes());
+        for (off, b) in packet.into_iter().enumerate() {
+            let at = cpu.ea(&Segment::Ds, si.wrapping_add(off as u16) as u32);
+            cpu.write_mem_u8(at, b);
+        }
+        (cpu.regs.si, cpu.regs.dx) = (si, 0x0080);
+        cpu.regs.set_ah(function);
+        disks.int13(cpu);
+        let count = cpu.ea(&Segment::Ds, si.wrapping_add(2) as u32);
+        (cpu.regs.get_ah(), cpu.regs.flags.cf(), cpu.read_mem_u8(count))
+    };
+    let sectors = disks.disks[0].sectors();
+    assert_eq!(ext(&mut cpu, &mut disks, 0x42, 0x500, sectors), (0x04, true, 0));
+    assert_eq!(ext(&mut cpu, &mut disks, 0x42, 0x500, u64::MAX), (0x04, true, 0));
+    // a packet straddling the end of DS wraps to its start
+    cpu.regs.ds = 0x0100;
+    assert_eq!(ext(&mut cpu, &mut disks, 0x42, 0xfff8, 77), (0x00, false, 1));
+    assert_eq!(cpu.mem.cursor.get_ref()[0x700], 77);
+    cpu.regs.ds = 0;
+    // sector 5 to FFFF:0000 wraps to the bottom of memory, past the
+    // byte memory stops short of
+    (cpu.regs.ax, cpu.regs.cx, cpu.regs.dx, cpu.regs.bx, cpu.regs.es) = (0x0201, 0x0005, 0x0080, 0, 0xffff);
+    disks.int13(&mut cpu);
+    assert_eq!((cpu.regs.get_ah(), cpu.regs.get_al()), (0, 1));
+    let mem = cpu.mem.cursor.get_ref();
+    assert!(mem[0xffff0..].iter().chain(&mem[..0x1f0]).all(|&b| b == 4));
+    cpu.regs.es = 0;
+    disks.disks[0].image.read_only = true;
+    assert_eq!(ext(&mut cpu, &mut disks, 0x43, 0x500, 1), (0x03, true, 0));
+    fs::remove_file(path).unwrap();
+}
+
+// a one-block option rom whose init writes 1234h to 0000:0500, tries to
+// write its own image and returns
+fn option_rom() -> Vec<u8> {
+    let mut rom = vec![0u8; 512];
+    rom[..3].copy_from_slice(&[0x55, 0xaa, 0x01]);
+    // xor ax,ax; mov ds,ax; mov word [0x500],0x1234; mov byte [cs:0x10],0x99;
+    // mov ax,0xbeef; retf
+    rom[3..25].copy_from_slice(&[
+        0x31, 0xc0, 0x8e, 0xd8, 0xc7, 0x06, 0x00, 0x05, 0x34, 0x12, 0x2e, 0xc6, 0x06, 0x10, 0x00, 0x99, 0xb8,
+        0xef, 0xbe, 0xcb, 0x00, 0x00,
+    ]);
+    let sum = rom.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    rom[511] = sum.wrapping_neg();
+    rom
+}
+
+#[test]
+fn option_rom_init() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.regs.ax = 0x4242;
+    let rom = option_rom();
+    bios::load_rom(&mut cpu, &rom, 0xc800).unwrap();
+    let mut bad = rom.clone();
+    bad[100] = 1;
+    bios::load_rom(&mut cpu, &bad, 0xd000).unwrap();
+
+    let roms = bios::scan_option_roms(&cpu);
+    assert_eq!(roms, vec![OptionRom { segment: 0xc800, size: 512 }]);
+    bios::init_option_rom(&mut cpu, roms[0], |_, _| {}).unwrap();
+
+    let mem = cpu.mem.cursor.get_ref();
+    assert_eq!(&mem[0x500..0x502], &[0x34, 0x12]);
+    assert_eq!(mem[0xc8010], rom[0x10]);
+    assert_eq!(cpu.regs.ax, 0x4242);
+    assert_eq!((cpu.regs.cs, cpu.regs.ip, cpu.regs.ds), (0, 0, 0));
+}
+
+#[test]
+fn upper_memory_blocks() {
+    assert_eq!(bios::parse_umbs("d000-d7ff, c800-cfff"), Ok(vec![(0xc800, 0xd000), (0xd000, 0xd800)]));
+    assert!(bios::parse_umbs("c800-d000,cc00-cfff").is_err());
+    assert!(bios::parse_umbs("f000-f7ff").is_err());
+    assert!(bios::parse_umbs("c800").is_err());
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    bios::load_rom(&mut cpu, &option_rom(), 0xc800).unwrap();
+    assert!(bios::map_umbs(&mut cpu, &[(0xc800, 0xcc00)]).is_err());
+    let umbs = bios::parse_umbs("cc00-cfff,e000-efff").unwrap();
+    assert_eq!(bios::map_umbs(&mut cpu, &umbs), Ok(0xcc00));
+
+    // writes stick in the blocks only
+    for (addr, kept) in [(0xcc100, true), (0xef000, true), (0xd0000, false), (0xc0000, false)] {
+        cpu.write_mem_u8(addr, 0x5a);
+        assert_eq!(cpu.read_mem_u8(addr) == 0x5a, kept, "{:05x}", addr);
+    }
+
+    // walk the chain: free, SC over the hole, free to the end
+    let mem = cpu.mem.cursor.get_ref();
+    let mut segment = 0xcc00usize;
+    let mut blocks = Vec::new();
+    loop {
+        let mcb = &mem[segment * 16..segment * 16 + 16];
+        let owner = u16::from_le_bytes([mcb[1], mcb[2]]);
+        let size = u16::from_le_bytes([mcb[3], mcb[4]]) as usize;
+        blocks.push((segment, owner, size));
+        if mcb[0] == b'Z' {
+            break;
+        }
+        assert_eq!(mcb[0], b'M');
+        segment += size + 1;
+    }
+    assert_eq!(blocks, vec![(0xcc00, 0, 0x3fe), (0xcfff, 8, 0x1000), (0xe000, 0, 0xfff)]);
+    assert_eq!(segment + blocks[2].2 + 1, 0xf000);
+}
+
+#[test]
+fn serial_null_modem() {
+    let (a, b) = uart::null_modem();
+    let mut sender = Cpu::init();
+    sender.test_mode();
+    sender.io.push(Box::new(Uart::new(uart::COM1, Box::new(a))));
+    // mov dx,0x3f8; mov al,'h'; out dx,al; mov al,'i'; out dx,al; hlt
+    sender.load_code_vec(&[0xba, 0xf8, 0x03, 0xb0, 0x68, 0xee, 0xb0, 0x69, 0xee, 0xf4]);
+
+    let mut receiver = Cpu::init();
+    receiver.test_mode();
+    receiver.io.push(Box::new(Uart::new(uart::COM1, Box::new(b))));
+    // wait: mov dx,0x3fd; in al,dx; test al,1; jz wait; mov dx,0x3f8;
+    // in al,dx; out 0xe9,al; cmp al,'i'; jne wait; hlt
+    receiver.load_code_vec(&[
+        0xba, 0xfd, 0x03, 0xec, 0xa8, 0x01, 0x74, 0xf8, 0xba, 0xf8, 0x03, 0xec, 0xe6, 0xe9, 0x3c, 0x69, 0x75,
+        0xee, 0xf4,
+    ]);
+
+    // the receiver starts first and has to wait for the bytes
+    for _ in 0..1000 {
+        for cpu in [&mut receiver, &mut sender] {
+            if !cpu.halt {
+                if let Some(inst) = cpu.fetch() {
+                    cpu.execute(&inst);
+                }
+            }
+        }
+    }
+    assert!(sender.halt && receiver.halt);
+    assert_eq!(receiver.output, b"hi");
+}
+
+#[test]
+fn serial_loopback() {
+    let (a, _b) = uart::null_modem();
+    let mut uart = Uart::new(uart::COM2, Box::new(a));
+    // DTR, RTS, OUT2 and loopback
+    uart.write(0x2fc, 0x1b, 0);
+    assert_eq!(uart.read(0x2fe, 0), 0xb0);
+    uart.write(0x2f8, 0x55, 0);
+    assert_eq!(uart.read(0x2fd, 0) & 1, 1);
+    assert_eq!(uart.read(0x2f8, 0), 0x55);
+    assert_eq!(uart.read(0x2fd, 0) & 1, 0);
+    // divisor latch
+    uart.write(0x2fb, 0x83, 0);
+    uart.write(0x2f8, 0x01, 0);
+    assert_eq!((uart.read(0x2f8, 0), uart.read(0x2f9, 0)), (0x01, 0x00));
+    uart.write(0x2fb, 0x03, 0);
+    assert!(!uart.handles(0x3f8));
+}
+
+#[test]
+fn device_debug_state() {
+    let (a, _b) = uart::null_modem();
+    let mut uart = Uart::new(uart::COM2, Box::new(a));
+    // loopback, two bytes waiting
+    uart.write(0x2fc, 0x10, 0);
+    uart.write(0x2f8, 0x41, 0);
+    uart.write(0x2f8, 0x42, 0);
+    let state = uart.debug_state().unwrap();
+    assert_eq!(state.name, "com2");
+    let field = |name: &str| state.fields.iter().find(|(label, _)| *label == name).map(|(_, v)| v.as_str());
+    assert_eq!(field("MCR"), Some("10"));
+    assert_eq!(field("divisor"), Some("000c"));
+    assert_eq!(field("rx"), Some("41 42"));
+
+    let mut cpu = Cpu::init();
+    cpu.io.push(Box::new(Cmos::new(Clock::Virtual { epoch: 0 })));
+    cpu.io.push(Box::new(uart));
+    let names: Vec<String> = cpu.device_states().into_iter().map(|d| d.name).collect();
+    assert_eq!(names, ["cmos", "com2"]);
+    let timer = SystemTimer::new(Pacing::Cycles, &mut cpu, Clock::Virtual { epoch: 0 });
+    assert_eq!(timer.debug_state().fields[1], ("next tick", format!("cycle {}", TICK_CYCLES)));
+}
+
+#[test]
+fn virtual_clock() {
+    let epoch = clock::parse_epoch("2024-02-29T23:59:58").unwrap();
+    assert_eq!(epoch, 1709251198);
+    assert_eq!(clock::parse_epoch("1709251198"), Some(epoch));
+    assert_eq!(clock::parse_epoch("2024-13-01"), None);
+    let dt = DateTime::from_unix(epoch);
+    assert_eq!((dt.year, dt.month, dt.day, dt.weekday), (2024, 2, 29, 4));
+    assert_eq!(dt.to_unix(), epoch);
+
+    // the same program sees the same time on every run; three seconds
+    // of cycles later it is March
+    let clock = Clock::Virtual { epoch };
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.io.push(Box::new(Cmos::new(clock)));
+    cpu.stats.instructions = 3 * CPU_HZ / CLOCKS_PER_INSTRUCTION;
+    cpu.regs.set_ah(0x04);
+    clock::int1a(&mut cpu, clock);
+    assert_eq!((cpu.regs.cx, cpu.regs.dx), (0x2024, 0x0301));
+    cpu.regs.set_ah(0x00);
+    clock::int1a(&mut cpu, clock);
+    assert_eq!((cpu.regs.cx, cpu.regs.dx), (0, 18));
+
+    // mov al,8; out 0x70,al; in al,0x71; mov ah,al; mov al,7; out 0x70,al; in al,0x71; hlt
+    cpu.load_code_vec(&[0xb0, 0x08, 0xe6, 0x70, 0xe4, 0x71, 0x88, 0xc4, 0xb0, 0x07, 0xe6, 0x70, 0xe4, 0x71, 0xf4]);
+    cpu.fire();
+    assert_eq!(cpu.regs.ax, 0x0301);
+}
+
+#[test]
+fn timer_ticks_by_cycles() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    // mov word [0x70],handler; mov word [0x72],0; sti; mov bx,3
+    // wait: mov ax,[0x600]; cmp ax,bx; jb wait; hlt
+    // handler: inc word [0x600]; iret
+    cpu.load_code_vec(&[
+        0xc7, 0x06, 0x70, 0x00, 0x18, 0x00, 0xc7, 0x06, 0x72, 0x00, 0x00, 0x00, 0xfb, 0xbb, 0x03, 0x00, 0xa1, 0x00,
+        0x06, 0x39, 0xd8, 0x72, 0xf9, 0xf4, 0xff, 0x06, 0x00, 0x06, 0xcf,
+    ]);
+    let mut timer = SystemTimer::new(Pacing::Cycles, &mut cpu, Clock::Virtual { epoch: 0 });
+    while !cpu.halt && cpu.stats.instructions < 1_000_000 {
+        let inst = cpu.fetch().unwrap();
+        cpu.execute(&inst);
+        timer.poll(&mut cpu);
+    }
+    assert!(cpu.halt);
+    // the third tick lands after exactly 3 * TICK_CYCLES clocks
+    assert_eq!(cpu.cycles() / TICK_CYCLES, 3);
+    assert_eq!(&cpu.mem.cursor.get_ref()[0x46c..0x470], &[3, 0, 0, 0]);
+}
+
+#[test]
+fn governor_caps_speed() {
+    let mut governor = Governor::new(Some(100_000));
+    let start = Instant::now();
+    for n in 0..=20_000 {
+        governor.poll(n);
+    }
+    assert!(start.elapsed() >= Duration::from_millis(190));
+
+    governor.set_limit(Some(0), 20_000);
+    assert_eq!(governor.limit(), None);
+}
+
+#[test]
+fn dos_console_filter() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    cpu.host_ints.push(dos::DOS_INT);
+    cpu.exits.dos = true;
+    let mut console = Console::new(Box::new(&b"hi, dos"[..]));
+    // upcase stdin a byte at a time through ah=3fh and ah=02h, then
+    // ah=4ch at the end of the input
+    cpu.load_code_vec(&[
+        0x0e, 0x1f, 0xb4, 0x3f, 0x31, 0xdb, 0xb9, 0x01, 0x00, 0xba, 0x00, 0x01, 0xcd, 0x21, 0x85, 0xc0, 0x74, 0x15,
+        0xa0, 0x00, 0x01, 0x3c, 0x61, 0x72, 0x06, 0x3c, 0x7a, 0x77, 0x02, 0x2c, 0x20, 0xb4, 0x02, 0x88, 0xc2, 0xcd,
+        0x21, 0xeb, 0xdb, 0xb8, 0x07, 0x4c, 0xcd, 0x21,
+    ]);
+    while !cpu.halt {
+        let inst = cpu.fetch().unwrap();
+        cpu.execute(&inst);
+        if let Some(vector) = cpu.host_int.take() {
+            assert_eq!(vector, dos::DOS_INT);
+            dos::int21(&mut cpu, &mut console);
+        }
+    }
+    assert_eq!(cpu.output, b"HI, DOS");
+    assert_eq!(cpu.exit_code, Some(7));
+
+    // past the end a character reads as ctrl-z; $-strings and unknown
+    // functions
+    cpu.regs.set_ah(0x08);
+    dos::int21(&mut cpu, &mut console);
+    assert_eq!(cpu.regs.get_al(), 0x1a);
+    cpu.write_mem_u8(0x200, b'o');
+    cpu.write_mem_u8(0x201, b'k');
+    cpu.write_mem_u8(0x202, b'$');
+    cpu.regs.dx = 0x200;
+    cpu.regs.set_ah(0x09);
+    dos::int21(&mut cpu, &mut console);
+    assert!(cpu.output.ends_with(b"ok"));
+    cpu.regs.set_ah(0x3d);
+    dos::int21(&mut cpu, &mut console);
+    assert!(cpu.regs.flags.cf());
+    assert_eq!(cpu.faults.len(), 1);
+}
+
+#[test]
+fn terminal_keys_to_bios() {
+    // a, A, Enter, Backspace, Ctrl-C, up, PgDn, F1 both ways, F10, Alt-x
+    let keys = keyboard::terminal_keys(b"aA\r\x7f\x03\x1b[A\x1b[6~\x1bOP\x1b[11~\x1b[21~\x1bx", Layout::Us);
+    assert_eq!(
+        keys,
+        [0x1e61, 0x1e41, 0x1c0d, 0x0e08, 0x2e03, 0x4800, 0x5100, 0x3b00, 0x3b00, 0x4400, 0x2d00]
+    );
+    // a lone Esc is the key itself; unknown sequences are dropped
+    assert_eq!(keyboard::terminal_keys(b"\x1b[99~\x1b", Layout::Us), [0x011b]);
+    // pasted lines end in one Enter however they were broken
+    assert_eq!(
+        keyboard::paste_keys("a\r\nb\nc\r\u{2603}", Layout::Us),
+        [0x1e61, 0x1c0d, 0x3062, 0x1c0d, 0x2e63, 0x1c0d]
+    );
+
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let mut queue = keys[..2].iter().copied().collect();
+    cpu.regs.set_ah(0x01);
+    keyboard::int16(&mut cpu, &mut queue);
+    assert_eq!((cpu.regs.ax, cpu.regs.flags.zf()), (0x1e61, false));
+    for want in [0x1e61, 0x1e41, 0] {
+        cpu.regs.set_ah(0x00);
+        keyboard::int16(&mut cpu, &mut queue);
+        assert_eq!(cpu.regs.ax, want);
+    }
+    cpu.regs.set_ah(0x01);
+    keyboard::int16(&mut cpu, &mut queue);
+    assert!(cpu.regs.flags.zf());
+}
+
+#[test]
+fn keyboard_scan_codes() {
+    // a, A, Ctrl-C, up, Alt-x, and @ with AltGr on a German keyboard
+    assert_eq!(keyboard::scan_codes(0x1e61, Layout::Us), [0x1e, 0x9e]);
+    assert_eq!(keyboard::scan_codes(0x1e41, Layout::Us), [0x2a, 0x1e, 0x9e, 0xaa]);
+    assert_eq!(keyboard::scan_codes(0x2e03, Layout::Us), [0x1d, 0x2e, 0xae, 0x9d]);
+    assert_eq!(keyboard::scan_codes(0x4800, Layout::Us), [0x48, 0xc8]);
+    assert_eq!(keyboard::scan_codes(0x2d00, Layout::Us), [0x38, 0x2d, 0xad, 0xb8]);
+    assert_eq!(keyboard::scan_codes(0x1c0d, Layout::Us), [0x1c, 0x9c]);
+    assert_eq!(
+        keyboard::scan_codes(0x1040, Layout::De),
+        [0x1d, 0x38, 0x10, 0x90, 0xb8, 0x9d]
+    );
+
+    // an int 9 handler storing each code at [0x200+di] and clearing it
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let code = assemble(
+        "mov word [0x104], key
+        mov word [0x106], 0
+        mov di, 0x200
+        sti
+    idle:
+        cmp di, 0x204
+        jne idle
+        hlt
+    key:
+        in al, 0x60
+        stosb
+        in al, 0x61
+        or al, 0x80
+        out 0x61, al
+        and al, 0x7f
+        out 0x61, al
+        iret",
+    )
+    .unwrap();
+    cpu.load_code_vec(&code);
+    let mut machine = Machine::new(cpu);
+    machine.bus.irq_base = 0x40;
+    let ppi = Ppi::default();
+    let typed = ppi.keyboard();
+    machine.attach(Box::new(ppi)).unwrap();
+    ppi::type_codes(&typed, &keyboard::scan_codes(0x1e41, Layout::Us));
+    assert_eq!(machine.run_until(|_| false), RunExit::Halted);
+    assert_eq!(machine.cpu.mem.cursor.get_ref()[0x200..0x204], [0x2a, 0x1e, 0x9e, 0xaa]);
+
+    // a full keyboard drops what comes after and says so
+    ppi::type_codes(&typed, &[0x1e; 20]);
+    let queue = typed.borrow();
+    assert_eq!(queue.len(), ppi::KEYBOARD_BUFFER);
+    assert_eq!(queue.back(), Some(&ppi::OVERRUN));
+}
+
+#[test]
+fn code_pages() {
+    let box_437: String = [0xc9, 0xcd, 0xbb, 0x01, 0x82]
+        .map(|b| CodePage::Cp437.char(b))
+        .iter()
+        .collect();
+    assert_eq!(box_437, "╔═╗☺é");
+    // 850 swaps some of the box drawing for Latin-1
+    assert_eq!(CodePage::Cp850.char(0xc9), '╔');
+    assert_eq!(CodePage::Cp850.char(0xb5), 'Á');
+    assert_eq!(CodePage::Cp437.char(0xb5), '╡');
+    assert_eq!(CodePage::Cp850.byte('Ø'), Some(0x9d));
+    assert_eq!(CodePage::Cp437.byte('Ø'), None);
+    assert_eq!(CodePage::Cp437.byte('A'), Some(0x41));
+    assert_eq!(CodePage::parse("850"), Some(CodePage::Cp850));
+}
+
+#[test]
+fn keyboard_layouts() {
+    // z is where US keyboards have y, and the ISO key left of it
+    assert_eq!(Layout::De.position('z'), Some((0x15, Modifier::None)));
+    assert_eq!(Layout::De.position('@'), Some((0x10, Modifier::AltGr)));
+    assert_eq!(Layout::De.position('|'), Some((0x56, Modifier::AltGr)));
+    assert_eq!(Layout::Fr.position('1'), Some((0x02, Modifier::Shift)));
+    assert_eq!(Layout::Uk.position('\\'), Some((0x56, Modifier::None)));
+    assert_eq!(Layout::Us.position('\\'), Some((0x2b, Modifier::None)));
+    // what is typed arrives as UTF-8 and reaches the guest in code page
+    // 437; a character it lacks, such as the euro, is dropped
+    assert_eq!(
+        keyboard::terminal_keys("zä€ß".as_bytes(), Layout::De),
+        [0x157a, 0x2884, 0x0ce1]
+    );
+    // Ctrl-A and Alt-Q follow the AZERTY keys
+    assert_eq!(keyboard::terminal_keys(b"\x01\x1bq", Layout::Fr), [0x1001, 0x1e00]);
+    assert_eq!(Layout::parse("de"), Some(Layout::De));
+    assert_eq!(Layout::parse("dvorak"), None);
+}
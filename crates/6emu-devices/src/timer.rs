@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use sixemu_core::cpu::{Cpu, DeviceState};
+
+use crate::clock::Clock;
+
+/// The user hook the BIOS timer handler calls on every tick.
+pub const USER_TICK_INT: u8 = 0x1c;
+/// CPU clocks per timer tick: the 8253 counts at a quarter of the CPU
+/// clock and the BIOS programs channel 0 for 65536 counts.
+pub const TICK_CYCLES: u64 = 4 * 65536;
+/// Host time per tick when paced by the wall clock.
+pub const TICK_PERIOD: Duration = Duration::from_nanos(54_925_493);
+
+// tick count and midnight flag in the BIOS data area
+const BDA_TICKS: usize = 0x46c;
+const BDA_MIDNIGHT: usize = 0x470;
+const TICKS_PER_DAY: u32 = 0x1800b0;
+
+/// What decides when the next tick is due.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Pacing {
+    /// Every `TICK_CYCLES` emulated clocks, however fast the host runs.
+    #[default]
+    Cycles,
+    /// Every 55 ms of host time, for interactive use.
+    WallClock,
+}
+
+/// The BIOS side of IRQ0: keeps the tick count at 0040:006C and calls
+/// INT 1Ch when the guest has hooked it, holding the call while
+/// interrupts are disabled.
+#[derive(Clone)]
+pub struct SystemTimer {
+    pacing: Pacing,
+    next: u64,
+    last: Instant,
+    // a tick waiting for the guest to enable interrupts
+    pending: bool,
+}
+
+impl SystemTimer {
+    /// Starts the tick count at the time of day `clock` reports.
+    pub fn new(pacing: Pacing, cpu: &mut Cpu, clock: Clock) -> Self {
+        let ticks = clock.ticks(cpu.cycles());
+        cpu.mem.cursor.get_mut()[BDA_TICKS..BDA_TICKS + 4].copy_from_slice(&ticks.to_le_bytes());
+        Self {
+            pacing,
+            next: cpu.cycles() + TICK_CYCLES,
+            last: Instant::now(),
+            pending: false,
+        }
+    }
+
+    /// The pacing, when the next tick is due and whether one waits for
+    /// the guest to enable interrupts.
+    pub fn debug_state(&self) -> DeviceState {
+        let (pacing, next) = match self.pacing {
+            Pacing::Cycles => ("cycles", format!("cycle {}", self.next)),
+            Pacing::WallClock => (
+                "wall",
+                format!("in {} ms", TICK_PERIOD.saturating_sub(self.last.elapsed()).as_millis()),
+            ),
+        };
+        DeviceState {
+            name: "timer".to_string(),
+            fields: vec![
+                ("pacing", pacing.to_string()),
+                ("next tick", next),
+                ("pending", self.pending.to_string()),
+            ],
+        }
+    }
+
+    /// Runs the timer interrupt if a tick is due; call between
+    /// instructions. A host that falls behind catches up one tick per
+    /// call.
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        if self.pending && cpu.interrupt(USER_TICK_INT) {
+            self.pending = false;
+        }
+        match self.pacing {
+            Pacing::Cycles if cpu.cycles() >= self.next => self.next += TICK_CYCLES,
+            Pacing::WallClock if self.last.elapsed() >= TICK_PERIOD => self.last += TICK_PERIOD,
+            _ => return,
+        }
+
+        let mem = cpu.mem.cursor.get_mut();
+        let mut ticks = u32::from_le_bytes(mem[BDA_TICKS..BDA_TICKS + 4].try_into().unwrap()) + 1;
+        if ticks >= TICKS_PER_DAY {
+            ticks = 0;
+            mem[BDA_MIDNIGHT] = 1;
+        }
+        mem[BDA_TICKS..BDA_TICKS + 4].copy_from_slice(&ticks.to_le_bytes());
+
+        let vector = USER_TICK_INT as usize * 4;
+        if mem[vector..vector + 4] != [0; 4] {
+            self.pending = !cpu.interrupt(USER_TICK_INT);
+        }
+    }
+}
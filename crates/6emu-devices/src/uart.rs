@@ -0,0 +1,233 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use sixemu_core::cpu::{DeviceState, IoDevice};
+
+/// Base ports of the first two serial adapters.
+pub const COM1: u16 = 0x3f8;
+pub const COM2: u16 = 0x2f8;
+
+// line status bits
+const DATA_READY: u8 = 0x01;
+const THR_EMPTY: u8 = 0x20;
+const TX_EMPTY: u8 = 0x40;
+// modem control bits
+const LOOPBACK: u8 = 0x10;
+// line control bits
+const DLAB: u8 = 0x80;
+
+/// The far end of the cable a UART is plugged into.
+pub trait Link {
+    fn send(&mut self, byte: u8);
+    /// A byte from the peer, if one has arrived.
+    fn recv(&mut self) -> Option<u8>;
+}
+
+impl Link for TcpStream {
+    fn send(&mut self, byte: u8) {
+        let _ = self.write_all(&[byte]);
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        let mut buf = [0u8];
+        match self.read(&mut buf) {
+            Ok(1) => Some(buf[0]),
+            _ => None,
+        }
+    }
+}
+
+/// One end of an in-process null-modem cable, for two machines in the
+/// same process.
+pub struct Pipe {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+}
+
+impl Link for Pipe {
+    fn send(&mut self, byte: u8) {
+        let _ = self.tx.send(byte);
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Both ends of a cable, each sending to the other.
+pub fn null_modem() -> (Pipe, Pipe) {
+    let (a_tx, b_rx) = mpsc::channel();
+    let (b_tx, a_rx) = mpsc::channel();
+    (Pipe { tx: a_tx, rx: a_rx }, Pipe { tx: b_tx, rx: b_rx })
+}
+
+/// The host's stdin and stdout, for a guest that talks to a serial port
+/// from inside a pipeline. A thread reads stdin so `recv` never waits.
+pub struct Stdio {
+    rx: Receiver<u8>,
+}
+
+impl Link for Stdio {
+    fn send(&mut self, byte: u8) {
+        let mut out = io::stdout().lock();
+        let _ = out.write_all(&[byte]).and_then(|_| out.flush());
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        self.rx.try_recv().ok()
+    }
+}
+
+pub fn stdio_link() -> Stdio {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            match byte {
+                Ok(byte) if tx.send(byte).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+    Stdio { rx }
+}
+
+/// Opens the TCP side of a cable between two emulator instances:
+/// `listen:HOST:PORT` waits for the other instance to connect, anything
+/// else is an address to connect to.
+pub fn tcp_link(spec: &str) -> io::Result<TcpStream> {
+    let stream = match spec.strip_prefix("listen:") {
+        Some(addr) => TcpListener::bind(addr)?.accept()?.0,
+        None => TcpStream::connect(spec)?,
+    };
+    stream.set_nodelay(true)?;
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+/// An 8250 serial port. Received bytes queue up without overruns;
+/// interrupts are not raised, so guests poll the line status register.
+/// Loopback mode (MCR bit 4) works, as port detection relies on it.
+pub struct Uart {
+    base: u16,
+    link: Box<dyn Link>,
+    rx: VecDeque<u8>,
+    divisor: u16,
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scratch: u8,
+}
+
+impl Uart {
+    pub fn new(base: u16, link: Box<dyn Link>) -> Self {
+        Self {
+            base,
+            link,
+            rx: VecDeque::new(),
+            // 9600 baud
+            divisor: 12,
+            ier: 0,
+            lcr: 0x03,
+            mcr: 0,
+            scratch: 0,
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.mcr & LOOPBACK == 0 {
+            while let Some(byte) = self.link.recv() {
+                self.rx.push_back(byte);
+            }
+        }
+    }
+
+    fn modem_status(&self) -> u8 {
+        if self.mcr & LOOPBACK != 0 {
+            // DTR, RTS, OUT1, OUT2 come back as DSR, CTS, RI, DCD
+            let m = self.mcr;
+            ((m & 0x01) << 5) | ((m & 0x02) << 3) | ((m & 0x0c) << 4)
+        } else {
+            // CTS, DSR and DCD: the cable is always there
+            0xb0
+        }
+    }
+}
+
+impl IoDevice for Uart {
+    fn handles(&self, port: u16) -> bool {
+        (self.base..self.base + 8).contains(&port)
+    }
+
+    fn read(&mut self, port: u16, _now: u64) -> u8 {
+        let dlab = self.lcr & DLAB != 0;
+        match port - self.base {
+            0 if dlab => self.divisor as u8,
+            0 => {
+                self.poll();
+                self.rx.pop_front().unwrap_or(0)
+            }
+            1 if dlab => (self.divisor >> 8) as u8,
+            1 => self.ier,
+            // interrupt identification: what would be pending
+            2 => {
+                self.poll();
+                if self.ier & 0x01 != 0 && !self.rx.is_empty() {
+                    0x04
+                } else if self.ier & 0x02 != 0 {
+                    0x02
+                } else {
+                    0x01
+                }
+            }
+            3 => self.lcr,
+            4 => self.mcr,
+            5 => {
+                self.poll();
+                THR_EMPTY | TX_EMPTY | if self.rx.is_empty() { 0 } else { DATA_READY }
+            }
+            6 => self.modem_status(),
+            _ => self.scratch,
+        }
+    }
+
+    fn write(&mut self, port: u16, val: u8, _now: u64) {
+        let dlab = self.lcr & DLAB != 0;
+        match port - self.base {
+            0 if dlab => self.divisor = (self.divisor & 0xff00) | val as u16,
+            0 if self.mcr & LOOPBACK != 0 => self.rx.push_back(val),
+            0 => self.link.send(val),
+            1 if dlab => self.divisor = (self.divisor & 0x00ff) | ((val as u16) << 8),
+            1 => self.ier = val & 0x0f,
+            3 => self.lcr = val,
+            4 => self.mcr = val & 0x1f,
+            7 => self.scratch = val,
+            _ => {}
+        }
+    }
+
+    fn debug_state(&self) -> Option<DeviceState> {
+        let name = match self.base {
+            COM1 => "com1".to_string(),
+            COM2 => "com2".to_string(),
+            base => format!("uart@{:03x}", base),
+        };
+        let rx: Vec<String> = self.rx.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(DeviceState {
+            name,
+            fields: vec![
+                ("divisor", format!("{:04x}", self.divisor)),
+                ("IER", format!("{:02x}", self.ier)),
+                ("LCR", format!("{:02x}", self.lcr)),
+                ("MCR", format!("{:02x}", self.mcr)),
+                ("MSR", format!("{:02x}", self.modem_status())),
+                ("scratch", format!("{:02x}", self.scratch)),
+                ("rx", rx.join(" ")),
+            ],
+        })
+    }
+}
@@ -0,0 +1,493 @@
+use std::ops::Range;
+
+use sixemu_core::{cpu::Cpu, mem::Dirty};
+
+use crate::{
+    cga::{self, Registers},
+    font::{CP437_8X13, GLYPH_HEIGHT, GLYPH_WIDTH},
+};
+
+/// Instructions per video frame: ~60 Hz at 4.77 MHz with an average of
+/// eight clocks per instruction.
+pub const FRAME_INSTRUCTIONS: u64 = 10_000;
+
+/// Physical address of the color text buffer (B800:0000).
+pub const TEXT_BASE: usize = 0xb8000;
+pub const TEXT_COLS: usize = 80;
+pub const TEXT_ROWS: usize = 25;
+/// The 16K of the color adapter holds four 80x25 pages, 4K apart.
+pub const TEXT_PAGES: usize = 4;
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// Vector of the BIOS video services.
+pub const VIDEO_INT: u8 = 0x10;
+
+// what the BIOS keeps about the screen in its data area
+const BDA_MODE: usize = 0x449;
+const BDA_COLUMNS: usize = 0x44a;
+const BDA_PAGE_SIZE: usize = 0x44c;
+const BDA_PAGE_START: usize = 0x44e;
+// a word per page, column low and row high
+const BDA_CURSOR: usize = 0x450;
+// start scan line high, end low
+const BDA_CURSOR_SHAPE: usize = 0x460;
+const BDA_PAGE: usize = 0x462;
+// the values last written to the mode control and color select registers
+const BDA_CGA_MODE: usize = 0x465;
+const BDA_CGA_COLOR: usize = 0x466;
+// what the BIOS writes to the mode control register for modes 0-7
+const MODE_CONTROL: [u8; 8] = [0x2c, 0x28, 0x2d, 0x29, 0x2a, 0x2e, 0x1e, 0x29];
+
+// the two-line underline of the color adapter's 8-line cells
+const DEFAULT_SHAPE: u16 = 0x0607;
+// a start line with bit 5 set turns the cursor off
+const CURSOR_OFF: u16 = 0x2000;
+const BLANK: u16 = 0x0720;
+
+fn bda_u16(mem: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([mem[at], mem[at + 1]])
+}
+
+fn set_bda_u16(cpu: &mut Cpu, at: usize, val: u16) {
+    cpu.mem.cursor.get_mut()[at..at + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+/// Where the page being shown starts: B800:0000 plus the offset the
+/// BIOS keeps for it, page 0 on a machine nobody set up.
+pub fn page_base(mem: &[u8]) -> usize {
+    TEXT_BASE + (bda_u16(mem, BDA_PAGE_START) as usize).min((TEXT_PAGES - 1) * PAGE_SIZE)
+}
+
+/// The cursor on the page being shown as (row, column), or None while
+/// it is turned off or off the screen, or no BIOS has set the screen up.
+pub fn cursor(mem: &[u8]) -> Option<(usize, usize)> {
+    if bda_u16(mem, BDA_COLUMNS) == 0 {
+        return None;
+    }
+    let page = mem[BDA_PAGE] as usize % TEXT_PAGES;
+    let [col, row] = bda_u16(mem, BDA_CURSOR + page * 2).to_le_bytes();
+    let (row, col) = (row as usize, col as usize);
+    let shown = bda_u16(mem, BDA_CURSOR_SHAPE) & CURSOR_OFF == 0;
+    (shown && row < TEXT_ROWS && col < TEXT_COLS).then_some((row, col))
+}
+
+/// The cursor's first and last scan lines, of 8.
+pub fn cursor_shape(mem: &[u8]) -> (u8, u8) {
+    let [end, start] = bda_u16(mem, BDA_CURSOR_SHAPE).to_le_bytes();
+    (start & 0x1f, end & 0x1f)
+}
+
+/// Character cell at `row`/`col` of the page being shown: (character,
+/// attribute).
+pub fn text_cell(mem: &[u8], row: usize, col: usize) -> (u8, u8) {
+    let at = page_base(mem) + (row * TEXT_COLS + col) * 2;
+    (
+        mem.get(at).copied().unwrap_or(0),
+        mem.get(at + 1).copied().unwrap_or(0),
+    )
+}
+
+/// Tracks the guest's changes to the text pages, for `cpu.dirty`.
+pub fn text_dirty() -> Dirty {
+    Dirty::new(TEXT_BASE as u32..(TEXT_BASE + TEXT_PAGES * PAGE_SIZE) as u32)
+}
+
+/// The cells of the page being shown, numbered row by row from 0, that
+/// a changed span of the text pages covers; empty when the change was
+/// to another page.
+pub fn dirty_cells(mem: &[u8], span: Range<u32>) -> Range<usize> {
+    let cells = TEXT_ROWS * TEXT_COLS;
+    let base = page_base(mem);
+    // the end rounds up to take in the whole of its last cell
+    let cell = |at: u32, up: usize| ((at as usize + up).saturating_sub(base) / 2).min(cells);
+    let start = cell(span.start, 0);
+    start..cell(span.end, 1).max(start)
+}
+
+/// The text screen as plain text, one line per row with trailing blanks
+/// trimmed. NUL shows as a blank and other non-ASCII bytes as `.`.
+pub fn text_screen(mem: &[u8]) -> String {
+    let mut out = String::new();
+    for row in 0..TEXT_ROWS {
+        let line: String = (0..TEXT_COLS)
+            .map(|col| match text_cell(mem, row, col).0 {
+                0 => ' ',
+                c @ 0x20..=0x7e => c as char,
+                _ => '.',
+            })
+            .collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Size of the text screen rendered to pixels.
+pub const TEXT_WIDTH: usize = TEXT_COLS * GLYPH_WIDTH;
+pub const TEXT_HEIGHT: usize = TEXT_ROWS * GLYPH_HEIGHT;
+
+pub const CGA_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xaa, 0x55, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+/// The 16 colors a composite monitor shows for the patterns of four
+/// 640x200 pixels that make one cycle of the color carrier, leftmost
+/// pixel in bit 3, decoded as an NTSC set would.
+pub const COMPOSITE_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x6f, 0x30],
+    [0x37, 0x26, 0xda],
+    [0x20, 0x95, 0xff],
+    [0x96, 0x10, 0x50],
+    [0x80, 0x80, 0x80],
+    [0xcd, 0x37, 0xff],
+    [0xb6, 0xa6, 0xff],
+    [0x49, 0x59, 0x00],
+    [0x32, 0xc8, 0x00],
+    [0x80, 0x80, 0x80],
+    [0x69, 0xef, 0xaf],
+    [0xdf, 0x6a, 0x00],
+    [0xc8, 0xd9, 0x25],
+    [0xff, 0x90, 0xcf],
+    [0xff, 0xff, 0xff],
+];
+
+/// The colors `render_screen` picks from: the 16 CGA colors, then the
+/// 16 composite ones.
+pub const SCREEN_PALETTE: [[u8; 3]; 32] = {
+    let mut palette = [[0; 3]; 32];
+    let mut i = 0;
+    while i < 16 {
+        palette[i] = CGA_PALETTE[i];
+        palette[i + 16] = COMPOSITE_PALETTE[i];
+        i += 1;
+    }
+    palette
+};
+
+/// The SGR escape that shows text attribute `attr` in the 16 colors of
+/// an ANSI terminal, bright ones as the aixterm codes 90-97 and 100-107.
+/// With `blink` set, attribute bit 7 blinks rather than brightens the
+/// background.
+pub fn ansi_attribute(attr: u8, blink: bool) -> String {
+    // the CGA's blue, green, red bits to ANSI's red, green, blue
+    let ansi = |color: u8| (color & 2) | (color >> 2 & 1) | (color << 2 & 4);
+    let (fg, bg) = (attr & 0x0f, attr >> 4);
+    let bg = if blink { bg & 7 } else { bg };
+    let fg = if fg & 8 != 0 { 90 } else { 30 } + ansi(fg & 7);
+    let bg = if bg & 8 != 0 { 100 } else { 40 } + ansi(bg & 7);
+    let blinking = if blink && attr & 0x80 != 0 { ";5" } else { "" };
+    format!("\x1b[0;{};{}{}m", fg, bg, blinking)
+}
+
+/// Size of the graphics modes' frame, in 640x200 pixels.
+pub const GRAPHICS_WIDTH: usize = 640;
+pub const GRAPHICS_HEIGHT: usize = 200;
+
+/// Renders the text screen to CGA palette indices, one byte per pixel.
+/// Attribute bit 7 is taken as blink, so backgrounds use 8 colors.
+pub fn render_text(mem: &[u8]) -> Vec<u8> {
+    let mut pixels = vec![0u8; TEXT_WIDTH * TEXT_HEIGHT];
+    render_cells(mem, &mut pixels, 0..TEXT_ROWS * TEXT_COLS);
+    pixels
+}
+
+/// Renders just `cells` (see `dirty_cells`) into `pixels`, a frame
+/// `render_text` made.
+pub fn render_cells(mem: &[u8], pixels: &mut [u8], cells: Range<usize>) {
+    draw_cells(mem, pixels, cells, true);
+}
+
+/// Renders the graphics modes at 640x200 to CGA palette indices, or with
+/// `composite` the 640-pixel mode to `SCREEN_PALETTE`'s composite
+/// colors; 320-pixel modes come out with each pixel doubled.
+pub fn render_graphics(mem: &[u8], registers: Registers, composite: bool) -> Vec<u8> {
+    let mut pixels = vec![0u8; GRAPHICS_WIDTH * GRAPHICS_HEIGHT];
+    let palette = registers.palette();
+    // with the color burst off a composite monitor shows grays, left to
+    // the RGB colors here
+    let artifacts = composite && registers.mode & cga::MODE_NO_BURST == 0;
+    for (y, line) in pixels.chunks_mut(GRAPHICS_WIDTH).enumerate() {
+        // odd lines are in the second 8K
+        let row = TEXT_BASE + (y & 1) * 0x2000 + (y / 2) * GRAPHICS_WIDTH / 8;
+        let bytes = &mem[row..row + GRAPHICS_WIDTH / 8];
+        if !registers.hires() {
+            for (x, pair) in line.chunks_mut(2).enumerate() {
+                let val = bytes[x / 4] >> (6 - 2 * (x % 4)) & 3;
+                pair.fill(palette[val as usize]);
+            }
+        } else if artifacts {
+            for (x, group) in line.chunks_mut(4).enumerate() {
+                let nibble = bytes[x / 2] >> (4 - 4 * (x % 2)) & 0x0f;
+                group.fill(16 + nibble);
+            }
+        } else {
+            for (x, p) in line.iter_mut().enumerate() {
+                let set = bytes[x / 8] & (0x80 >> (x % 8)) != 0;
+                *p = if set { registers.foreground() } else { 0 };
+            }
+        }
+    }
+    pixels
+}
+
+/// Renders what the adapter shows, as its registers say, to indices in
+/// `SCREEN_PALETTE` at `TEXT_WIDTH` by `TEXT_HEIGHT`: the text screen
+/// (with bright backgrounds when blinking is off), the graphics modes
+/// stretched to the same height, or black with the video turned off.
+pub fn render_screen(mem: &[u8], registers: Registers, composite: bool) -> Vec<u8> {
+    let mut pixels = vec![0u8; TEXT_WIDTH * TEXT_HEIGHT];
+    if !registers.enabled() {
+        return pixels;
+    }
+    if !registers.graphics() {
+        draw_cells(mem, &mut pixels, 0..TEXT_ROWS * TEXT_COLS, registers.blink());
+        return pixels;
+    }
+    let graphics = render_graphics(mem, registers, composite);
+    for (y, line) in pixels.chunks_mut(TEXT_WIDTH).enumerate() {
+        let from = y * GRAPHICS_HEIGHT / TEXT_HEIGHT * GRAPHICS_WIDTH;
+        line.copy_from_slice(&graphics[from..from + GRAPHICS_WIDTH]);
+    }
+    pixels
+}
+
+fn draw_cells(mem: &[u8], pixels: &mut [u8], cells: Range<usize>, blink: bool) {
+    let background = if blink { 0x07 } else { 0x0f };
+    for cell in cells {
+        let (row, col) = (cell / TEXT_COLS, cell % TEXT_COLS);
+        let (ch, attr) = text_cell(mem, row, col);
+        let (fg, bg) = (attr & 0x0f, (attr >> 4) & background);
+        for (y, bits) in CP437_8X13[ch as usize].iter().enumerate() {
+            let line = (row * GLYPH_HEIGHT + y) * TEXT_WIDTH + col * GLYPH_WIDTH;
+            for x in 0..GLYPH_WIDTH {
+                pixels[line + x] = if bits & (0x80 >> x) > 0 { fg } else { bg };
+            }
+        }
+    }
+}
+
+/// Expands palette indices to RGB triples.
+pub fn to_rgb(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|&p| SCREEN_PALETTE[p as usize & 31])
+        .collect()
+}
+
+/// Sets up the screen's part of the BIOS data area as the BIOS does at
+/// power on: 80x25 color text on page 0, the cursor at the top left.
+/// What is in the text buffer is kept.
+pub fn init_bda(cpu: &mut Cpu) {
+    set_mode(cpu, 0x83);
+}
+
+// the physical address of `row`, `col` on `page`
+fn cell_addr(page: u8, row: u8, col: u8) -> u32 {
+    (TEXT_BASE + page as usize * PAGE_SIZE + (row as usize * TEXT_COLS + col as usize) * 2) as u32
+}
+
+fn set_mode(cpu: &mut Cpu, mode: u8) {
+    let control = MODE_CONTROL[(mode & 7) as usize];
+    let color = if mode & 0x7f == 6 { 0x3f } else { 0x30 };
+    let mem = cpu.mem.cursor.get_mut();
+    mem[BDA_MODE] = mode & 0x7f;
+    mem[BDA_CGA_MODE] = control;
+    mem[BDA_CGA_COLOR] = color;
+    mem[BDA_PAGE] = 0;
+    mem[BDA_CURSOR..BDA_CURSOR + 16].fill(0);
+    set_bda_u16(cpu, BDA_COLUMNS, TEXT_COLS as u16);
+    set_bda_u16(cpu, BDA_PAGE_SIZE, PAGE_SIZE as u16);
+    set_bda_u16(cpu, BDA_PAGE_START, 0);
+    set_bda_u16(cpu, BDA_CURSOR_SHAPE, DEFAULT_SHAPE);
+    cpu.io_write(cga::CGA_MODE, control);
+    cpu.io_write(cga::CGA_COLOR, color);
+    // bit 7 keeps what is on the screen; graphics clear to color 0
+    if mode & 0x80 == 0 {
+        let blank = if control & cga::MODE_GRAPHICS != 0 { 0 } else { BLANK };
+        for at in (TEXT_BASE..TEXT_BASE + TEXT_PAGES * PAGE_SIZE).step_by(2) {
+            cpu.write_mem_u16(at as u32, blank);
+        }
+    }
+}
+
+// AH=0Bh: BH=0 sets the background (the border in text) and with BL bit
+// 4 the bright palette, BH=1 picks the 320x200 palette
+fn set_palette(cpu: &mut Cpu) {
+    let bl = cpu.regs.get_bl();
+    let mut color = cpu.mem.cursor.get_ref()[BDA_CGA_COLOR];
+    match cpu.regs.get_bh() {
+        0 => color = color & 0xe0 | bl & 0x1f,
+        1 => color = color & !0x20 | (bl & 1) << 5,
+        _ => return,
+    }
+    cpu.mem.cursor.get_mut()[BDA_CGA_COLOR] = color;
+    cpu.io_write(cga::CGA_COLOR, color);
+}
+
+fn cursor_of(cpu: &Cpu, page: u8) -> (u8, u8) {
+    let [col, row] = bda_u16(cpu.mem.cursor.get_ref(), BDA_CURSOR + (page as usize % TEXT_PAGES) * 2).to_le_bytes();
+    (row, col)
+}
+
+fn set_cursor(cpu: &mut Cpu, page: u8, row: u8, col: u8) {
+    set_bda_u16(cpu, BDA_CURSOR + (page as usize % TEXT_PAGES) * 2, u16::from_le_bytes([col, row]));
+}
+
+// AL `count` times from the cursor on, with `attr` or keeping the
+// attributes there, as far as the end of the page
+fn write_chars(cpu: &mut Cpu, page: u8, c: u8, attr: Option<u8>, count: u16) {
+    let (row, col) = cursor_of(cpu, page);
+    let first = row as usize * TEXT_COLS + col as usize;
+    let page_base = cell_addr(page % TEXT_PAGES as u8, 0, 0);
+    for cell in (first..TEXT_ROWS * TEXT_COLS).take(count as usize) {
+        let at = page_base + cell as u32 * 2;
+        cpu.write_mem_u8(at, c);
+        if let Some(attr) = attr {
+            cpu.write_mem_u8(at + 1, attr);
+        }
+    }
+}
+
+/// A rectangle of the screen, inclusive, as AH=06h and 07h take it.
+struct Window {
+    top: u8,
+    left: u8,
+    bottom: u8,
+    right: u8,
+}
+
+// moves the rows of `window` on `page` up (or down) by `lines`, filling
+// the rows left behind with blanks in `attr`; 0 lines, or more than the
+// window has, blanks all of it
+fn scroll(cpu: &mut Cpu, page: u8, window: Window, lines: u8, up: bool, attr: u8) {
+    let bottom = window.bottom.min(TEXT_ROWS as u8 - 1);
+    let right = window.right.min(TEXT_COLS as u8 - 1);
+    if window.top > bottom || window.left > right {
+        return;
+    }
+    let height = bottom - window.top + 1;
+    let lines = if lines == 0 || lines > height { height } else { lines };
+    let blank = u16::from_le_bytes([b' ', attr]);
+    for i in 0..height {
+        // rows are copied in the direction that reads each before it is
+        // written over
+        let row = if up { window.top + i } else { bottom - i };
+        let from = if up {
+            row.checked_add(lines).filter(|&r| r <= bottom)
+        } else {
+            row.checked_sub(lines).filter(|&r| r >= window.top)
+        };
+        for col in window.left..=right {
+            let val = match from {
+                Some(from) => cpu.read_mem_u16(cell_addr(page, from, col)),
+                None => blank,
+            };
+            cpu.write_mem_u16(cell_addr(page, row, col), val);
+        }
+    }
+}
+
+// AH=0Eh: writes AL at the cursor of the page being shown and moves it
+// on; CR, LF, backspace and bell do what a terminal does. A line feed on
+// the last row scrolls the page up, the new row taking the attribute of
+// the cell the cursor was on.
+fn teletype(cpu: &mut Cpu, c: u8) {
+    let page = cpu.mem.cursor.get_ref()[BDA_PAGE] % TEXT_PAGES as u8;
+    let (mut row, mut col) = cursor_of(cpu, page);
+    let (row_now, col_now) = (row.min(TEXT_ROWS as u8 - 1), col.min(TEXT_COLS as u8 - 1));
+    match c {
+        0x07 => {}
+        0x08 => col = col.saturating_sub(1),
+        b'\r' => col = 0,
+        b'\n' => row += 1,
+        _ => {
+            cpu.write_mem_u8(cell_addr(page, row_now, col_now), c);
+            col += 1;
+            if col as usize == TEXT_COLS {
+                col = 0;
+                row += 1;
+            }
+        }
+    }
+    if row as usize >= TEXT_ROWS {
+        let attr = cpu.read_mem_u8(cell_addr(page, row_now, col_now) + 1);
+        let screen = Window {
+            top: 0,
+            left: 0,
+            bottom: TEXT_ROWS as u8 - 1,
+            right: TEXT_COLS as u8 - 1,
+        };
+        scroll(cpu, page, screen, 1, true, attr);
+        row = TEXT_ROWS as u8 - 1;
+    }
+    set_cursor(cpu, page, row, col);
+}
+
+/// Services an `int 10h` left pending in `cpu.host_int` for the 80x25
+/// color text modes: setting the mode (AH=00h, which clears all four
+/// pages unless AL bit 7 is set, and programs the adapter for the
+/// graphics modes too), the cursor's shape and position (AH=01h, 02h,
+/// 03h), the page shown (AH=05h), reading and writing characters at the
+/// cursor (AH=08h, 09h, 0Ah), scrolling a window of the page shown up
+/// or down (AH=06h, 07h), the graphics palette (AH=0Bh), teletype output
+/// (AH=0Eh, which scrolls at the bottom) and the current mode (AH=0Fh).
+/// The cursors and the page live in the BIOS data area, where renderers
+/// find them. Other functions do nothing.
+pub fn int10(cpu: &mut Cpu) {
+    let page = cpu.regs.get_bh();
+    match cpu.regs.get_ah() {
+        0x00 => set_mode(cpu, cpu.regs.get_al()),
+        0x01 => set_bda_u16(cpu, BDA_CURSOR_SHAPE, cpu.regs.cx),
+        0x02 => set_cursor(cpu, page, cpu.regs.get_dh(), cpu.regs.get_dl()),
+        0x03 => {
+            let (row, col) = cursor_of(cpu, page);
+            cpu.regs.dx = u16::from_le_bytes([col, row]);
+            cpu.regs.cx = bda_u16(cpu.mem.cursor.get_ref(), BDA_CURSOR_SHAPE);
+        }
+        0x05 => {
+            let page = cpu.regs.get_al() % TEXT_PAGES as u8;
+            cpu.mem.cursor.get_mut()[BDA_PAGE] = page;
+            set_bda_u16(cpu, BDA_PAGE_START, page as u16 * PAGE_SIZE as u16);
+        }
+        0x08 => {
+            let (row, col) = cursor_of(cpu, page);
+            let at = cell_addr(page % TEXT_PAGES as u8, row.min(TEXT_ROWS as u8 - 1), col.min(TEXT_COLS as u8 - 1));
+            cpu.regs.ax = cpu.read_mem_u16(at);
+        }
+        0x09 => write_chars(cpu, page, cpu.regs.get_al(), Some(cpu.regs.get_bl()), cpu.regs.cx),
+        0x0a => write_chars(cpu, page, cpu.regs.get_al(), None, cpu.regs.cx),
+        0x06 | 0x07 => {
+            let shown = cpu.mem.cursor.get_ref()[BDA_PAGE] % TEXT_PAGES as u8;
+            let [left, top] = cpu.regs.cx.to_le_bytes();
+            let [right, bottom] = cpu.regs.dx.to_le_bytes();
+            let window = Window { top, left, bottom, right };
+            scroll(cpu, shown, window, cpu.regs.get_al(), cpu.regs.get_ah() == 0x06, cpu.regs.get_bh());
+        }
+        0x0b => set_palette(cpu),
+        0x0e => teletype(cpu, cpu.regs.get_al()),
+        0x0f => {
+            let mem = cpu.mem.cursor.get_ref();
+            let (mode, page) = (mem[BDA_MODE], mem[BDA_PAGE]);
+            cpu.regs.set_al(mode);
+            cpu.regs.set_ah(TEXT_COLS as u8);
+            cpu.regs.set_bh(page);
+        }
+        _ => {}
+    }
+}
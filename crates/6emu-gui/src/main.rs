@@ -0,0 +1,390 @@
+use std::{cell::RefCell, collections::VecDeque, env::args, ops::Range, process::exit, rc::Rc};
+
+use sixemu_core::{
+    cpu::Cpu,
+    machine::{Bus, Device},
+};
+use sixemu_devices::{
+    cga::{Cga, Registers},
+    clock::Clock,
+    font::{CP437_8X13, GLYPH_HEIGHT, GLYPH_WIDTH},
+    keyboard::{self, Layout},
+    pit::Pit,
+    ppi::{self, Ppi},
+    timer::{Pacing, SystemTimer},
+    video::{self, CGA_PALETTE, FRAME_INSTRUCTIONS, TEXT_COLS, TEXT_HEIGHT, TEXT_ROWS, TEXT_WIDTH},
+};
+use minifb::{InputCallback, Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+const SIDEBAR_COLS: usize = 21;
+const WIDTH: usize = TEXT_WIDTH + SIDEBAR_COLS * GLYPH_WIDTH;
+const HEIGHT: usize = TEXT_HEIGHT;
+
+// sidebar rows of the two buttons
+const RUN_ROW: usize = 21;
+const STEP_ROW: usize = 23;
+
+// the vector IRQ 1 is taken through
+const KEYBOARD_IRQ_VECTOR: u8 = 9;
+
+fn print_usement() {
+    println!("Usage: ./6emu-gui options");
+
+    println!("   -f binary file");
+    println!("   --hypercall N service INT N on the host (AH=3 reads a key typed in the window)");
+    println!("   Keys typed in the window also reach int 16h and the keyboard on port 60h");
+    println!("   F5 or the Run button pauses/resumes, F10 or Step runs one instruction");
+
+    exit(1);
+}
+
+/// Typed characters, handed to the guest through the hypercall interrupt.
+struct Typed(Rc<RefCell<VecDeque<u8>>>);
+
+impl InputCallback for Typed {
+    fn add_char(&mut self, c: u32) {
+        if (0x20..0x7f).contains(&c) {
+            self.0.borrow_mut().push_back(c as u8);
+        }
+    }
+}
+
+fn rgb(color: u8) -> u32 {
+    let [r, g, b] = CGA_PALETTE[color as usize & 15];
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+fn draw_str(buf: &mut [u32], col: usize, row: usize, text: &str, fg: u8, bg: u8) {
+    for (i, c) in text.bytes().enumerate() {
+        let x0 = TEXT_WIDTH + (col + i) * GLYPH_WIDTH;
+        for (y, bits) in CP437_8X13[c as usize].iter().enumerate() {
+            let line = (row * GLYPH_HEIGHT + y) * WIDTH + x0;
+            for x in 0..GLYPH_WIDTH {
+                buf[line + x] = rgb(if bits & (0x80 >> x) > 0 { fg } else { bg });
+            }
+        }
+    }
+}
+
+/// The keyboard as the guest sees it: BIOS words for `int 16h` and
+/// scan codes for the keyboard behind the PPI.
+struct Keyboard {
+    keys: VecDeque<u16>,
+    scan_codes: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl Keyboard {
+    fn press(&mut self, cpu: &Cpu, key: u16) {
+        self.keys.push_back(key);
+        // a guest with no keyboard handler is not sent through a null
+        // vector
+        if cpu.vector(KEYBOARD_IRQ_VECTOR) != (0, 0) {
+            ppi::type_codes(&self.scan_codes, &keyboard::scan_codes(key, Layout::Us));
+        }
+    }
+}
+
+/// The guest's screen as last drawn.
+struct Screen {
+    pixels: Vec<u8>,
+    page: usize,
+    cursor: Option<(usize, usize)>,
+    // whether it showed text rather than graphics
+    text: bool,
+}
+
+// copies the rows of glyphs covering `cells` from the screen to the window
+fn copy_rows(buf: &mut [u32], screen: &Screen, cells: Range<usize>) {
+    let rows = cells.start / TEXT_COLS * GLYPH_HEIGHT..(cells.end - 1) / TEXT_COLS * GLYPH_HEIGHT + GLYPH_HEIGHT;
+    for y in rows {
+        for x in 0..TEXT_WIDTH {
+            buf[y * WIDTH + x] = rgb(screen.pixels[y * TEXT_WIDTH + x]);
+        }
+    }
+}
+
+fn draw(buf: &mut [u32], screen: &mut Screen, cpu: &mut Cpu, registers: Registers, running: bool, done: bool) {
+    let dirty = cpu.dirty.get_or_insert_with(video::text_dirty);
+    let mem = cpu.mem.cursor.get_ref();
+    let text = registers.enabled() && !registers.graphics();
+    if !text {
+        // the graphics modes, or nothing with the video off, redrawn
+        // whole every frame
+        dirty.take();
+        screen.pixels = video::render_screen(mem, registers, false);
+        copy_rows(buf, screen, 0..TEXT_ROWS * TEXT_COLS);
+        screen.cursor = None;
+    } else {
+        // only the cells the guest changed, and the rows of glyphs they
+        // are on
+        let page = video::page_base(mem);
+        if page != screen.page || !screen.text {
+            screen.page = page;
+            dirty.mark_all();
+        }
+        if let Some(span) = dirty.take() {
+            let cells = video::dirty_cells(mem, span);
+            if !cells.is_empty() {
+                video::render_cells(mem, &mut screen.pixels, cells.clone());
+                copy_rows(buf, screen, cells);
+            }
+        }
+        // the cursor is painted over the window only, so it goes away
+        // with the row of the cell it was on
+        if let Some((row, col)) = screen.cursor.take() {
+            let cell = row * TEXT_COLS + col;
+            copy_rows(buf, screen, cell..cell + 1);
+        }
+        if let Some((row, col)) = video::cursor(mem) {
+            let (start, end) = video::cursor_shape(mem);
+            // scan lines of the adapter's 8-line cells, stretched to ours
+            let lines = start as usize * GLYPH_HEIGHT / 8..(end as usize + 1) * GLYPH_HEIGHT / 8;
+            let fg = rgb(video::text_cell(mem, row, col).1 & 15);
+            for y in lines.start..lines.end.min(GLYPH_HEIGHT) {
+                let line = (row * GLYPH_HEIGHT + y) * WIDTH + col * GLYPH_WIDTH;
+                buf[line..line + GLYPH_WIDTH].fill(fg);
+            }
+            screen.cursor = Some((row, col));
+        }
+    }
+    screen.text = text;
+    for y in 0..HEIGHT {
+        buf[y * WIDTH + TEXT_WIDTH..(y + 1) * WIDTH].fill(rgb(1));
+    }
+
+    let r = &cpu.regs;
+    let lines = [
+        format!("AX {:04x}  BX {:04x}", r.ax, r.bx),
+        format!("CX {:04x}  DX {:04x}", r.cx, r.dx),
+        format!("SI {:04x}  DI {:04x}", r.si, r.di),
+        format!("SP {:04x}  BP {:04x}", r.sp, r.bp),
+        String::new(),
+        format!("CS {:04x}  DS {:04x}", r.cs, r.ds),
+        format!("ES {:04x}  SS {:04x}", r.es, r.ss),
+        format!("IP {:04x}", r.ip),
+        String::new(),
+        format!(
+            "{}{}{}{}{}{}{}{}{}",
+            if r.flags.of() { 'O' } else { '-' },
+            if r.flags.df() { 'D' } else { '-' },
+            if r.flags.i_f() { 'I' } else { '-' },
+            if r.flags.tf() { 'T' } else { '-' },
+            if r.flags.sf() { 'S' } else { '-' },
+            if r.flags.zf() { 'Z' } else { '-' },
+            if r.flags.af() { 'A' } else { '-' },
+            if r.flags.pf() { 'P' } else { '-' },
+            if r.flags.cf() { 'C' } else { '-' },
+        ),
+        String::new(),
+        format!("{} instructions", cpu.stats.instructions),
+        format!("{} cycles", cpu.cycles()),
+    ];
+    for (row, line) in lines.iter().enumerate() {
+        draw_str(buf, 1, row + 1, line, 15, 1);
+    }
+
+    let status = if done {
+        if cpu.halt {
+            "halted"
+        } else {
+            "finished"
+        }
+    } else if running {
+        "running"
+    } else {
+        "paused"
+    };
+    draw_str(buf, 1, 14, status, 14, 1);
+    draw_str(buf, 1, RUN_ROW, if running { " F5 Pause " } else { " F5 Run   " }, 0, 7);
+    draw_str(buf, 1, STEP_ROW, " F10 Step ", 0, 7);
+}
+
+// the BIOS services the window stands in for
+fn service(cpu: &mut Cpu, vector: u8, keyboard: &mut Keyboard) {
+    match vector {
+        video::VIDEO_INT => video::int10(cpu),
+        // a read with nothing typed runs the int again until a key is
+        keyboard::KEYBOARD_INT if matches!(cpu.regs.get_ah(), 0x00 | 0x10) && keyboard.keys.is_empty() => {
+            cpu.regs.ip = cpu.regs.ip.wrapping_sub(2);
+        }
+        keyboard::KEYBOARD_INT => keyboard::int16(cpu, &mut keyboard.keys),
+        _ => {}
+    }
+}
+
+// runs one instruction and the devices, false once the program is over
+fn step(cpu: &mut Cpu, timer: &mut SystemTimer, bus: &mut Bus, keyboard: &mut Keyboard) -> bool {
+    match cpu.fetch() {
+        Some(i) => {
+            cpu.execute(&i);
+            timer.poll(cpu);
+            bus.tick(cpu);
+            if let Some(vector) = cpu.host_int.take() {
+                service(cpu, vector, keyboard);
+            }
+            !cpu.halt
+        }
+        None => {
+            if let Some(why) = &cpu.decode_fault {
+                eprintln!("{:04x}:{:04x}: {}", cpu.regs.cs, cpu.regs.ip, why);
+            }
+            false
+        }
+    }
+}
+
+// the BIOS word of a key that types no character
+fn special_key(key: Key) -> Option<u16> {
+    let scan = match key {
+        Key::F1 => keyboard::F1,
+        Key::F2 => keyboard::F1 + 1,
+        Key::F3 => keyboard::F1 + 2,
+        Key::F4 => keyboard::F1 + 3,
+        Key::F6 => keyboard::F1 + 5,
+        Key::F7 => keyboard::F1 + 6,
+        Key::F8 => keyboard::F1 + 7,
+        Key::F9 => keyboard::F1 + 8,
+        Key::Home => keyboard::HOME,
+        Key::Up => keyboard::UP,
+        Key::PageUp => keyboard::PGUP,
+        Key::Left => keyboard::LEFT,
+        Key::Right => keyboard::RIGHT,
+        Key::End => keyboard::END,
+        Key::Down => keyboard::DOWN,
+        Key::PageDown => keyboard::PGDN,
+        Key::Insert => keyboard::INS,
+        Key::Delete => keyboard::DEL,
+        _ => return None,
+    };
+    Some(keyboard::extended(scan))
+}
+
+fn main() {
+    let mut cpu = Cpu::init();
+    cpu.test_mode();
+    let mut args = args();
+    let mut file_found = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "-f" {
+            if let Some(name) = args.next() {
+                cpu.load_code(&name);
+                file_found = true;
+            } else {
+                print_usement();
+            }
+        } else if arg == "--hypercall" {
+            let n = args.next().and_then(|n| match n.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                None => n.parse().ok(),
+            });
+            match n {
+                Some(v) => cpu.hypercall = Some(v),
+                None => print_usement(),
+            }
+        }
+    }
+
+    if !file_found {
+        print_usement();
+    }
+
+    // the adapter, timer and keyboard, with the BIOS's video and
+    // keyboard services on the host
+    video::init_bda(&mut cpu);
+    cpu.host_ints.extend([video::VIDEO_INT, keyboard::KEYBOARD_INT]);
+    let mut bus = Bus::default();
+    let cga = Cga::new();
+    let registers = cga.registers();
+    let ppi = Ppi::default();
+    let mut keyboard = Keyboard { keys: VecDeque::new(), scan_codes: ppi.keyboard() };
+    let devices: [Box<dyn Device>; 3] = [Box::new(Pit::new()), Box::new(ppi), Box::new(cga)];
+    for device in devices {
+        bus.attach(&mut cpu, device).expect("the devices have ports of their own");
+    }
+
+    let mut window = match Window::new("6emu", WIDTH, HEIGHT, WindowOptions::default()) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("Failed to open window: {}", e);
+            exit(1);
+        }
+    };
+    window.set_target_fps(60);
+    let typed = Rc::new(RefCell::new(VecDeque::new()));
+    window.set_input_callback(Box::new(Typed(typed.clone())));
+
+    let mut buf = vec![0u32; WIDTH * HEIGHT];
+    let mut screen = Screen {
+        pixels: vec![0u8; TEXT_WIDTH * TEXT_HEIGHT],
+        page: video::TEXT_BASE,
+        cursor: None,
+        text: true,
+    };
+    // someone is watching, so the timer keeps to real time
+    let mut timer = SystemTimer::new(Pacing::WallClock, &mut cpu, Clock::Host);
+    let mut running = true;
+    let mut done = false;
+    let mut mouse_was_down = false;
+
+    while window.is_open() {
+        let mut toggle = window.is_key_pressed(Key::F5, KeyRepeat::No);
+        let mut single = window.is_key_pressed(Key::F10, KeyRepeat::Yes);
+
+        let mouse_down = window.get_mouse_down(MouseButton::Left);
+        if mouse_down && !mouse_was_down {
+            if let Some((x, y)) = window.get_mouse_pos(MouseMode::Discard) {
+                let (col, row) = (x as usize, y as usize / GLYPH_HEIGHT);
+                if col >= TEXT_WIDTH {
+                    toggle |= row == RUN_ROW;
+                    single |= row == STEP_ROW;
+                }
+            }
+        }
+        mouse_was_down = mouse_down;
+
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            let c = match key {
+                Key::Enter => 0x0d,
+                Key::Backspace => 0x08,
+                Key::Tab => 0x09,
+                Key::Escape => 0x1b,
+                key => {
+                    if let Some(key) = special_key(key) {
+                        keyboard.press(&cpu, key);
+                    }
+                    continue;
+                }
+            };
+            typed.borrow_mut().push_back(c);
+        }
+        for c in typed.borrow_mut().drain(..) {
+            if let Some(key) = Layout::Us.key(c as char) {
+                keyboard.press(&cpu, key);
+            }
+            cpu.input.push_back(c);
+        }
+
+        if toggle {
+            running = !running;
+        }
+        if !done {
+            if running {
+                for _ in 0..FRAME_INSTRUCTIONS {
+                    if !step(&mut cpu, &mut timer, &mut bus, &mut keyboard) {
+                        done = true;
+                        break;
+                    }
+                }
+            } else if single {
+                done = !step(&mut cpu, &mut timer, &mut bus, &mut keyboard);
+            }
+        }
+
+        draw(&mut buf, &mut screen, &mut cpu, registers.get(), running, done);
+        if let Err(e) = window.update_with_buffer(&buf, WIDTH, HEIGHT) {
+            eprintln!("Failed to update window: {}", e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sixemu_core::fuzz;
+
+fuzz_target!(
+    // libfuzzer aborts on any panic, but faults the guest causes are
+    // panics the harness turns into an outcome; the emulator's own bugs
+    // still get out of it and abort
+    init: std::panic::set_hook(Box::new(|_| {})),
+    |program: &[u8]| {
+        fuzz::run(program, fuzz::LIMIT);
+    }
+);
@@ -7,13 +7,16 @@ import json
 
 server = None
 path_to_emu = None
+metrics = None
 
 def compile_asm(asm):
     temp_file = open("temp-asm.s", mode="w")
     temp_file.write(asm)
     temp_file.flush()
     command = ['nasm', '-f', 'bin', '-o', '/dev/stdout', 'temp-asm.s']
-    command2 = [path_to_emu, '--stdin']
+    command2 = [path_to_emu, '--stdin', '--devices']
+    if metrics is not None:
+        command2.append('--stats')
     result = subprocess.run(command, capture_output=True)
     if result.returncode != 0 or len(result.stderr)>0:
         temp_file.close()
@@ -25,10 +28,35 @@ def compile_asm(asm):
     pathlib.Path.unlink("temp-asm.s")
     if len(stderr) > 0:
         return False, stderr
+    if metrics is not None:
+        record_run(stdout)
     return True, stdout
 
+def record_run(stdout):
+    metrics["runs"] += 1
+    try:
+        stats = json.loads(stdout).get("stats", {})
+    except ValueError:
+        return
+    for name, value in stats.items():
+        metrics[name] = metrics.get(name, 0) + value
+
+def render_metrics():
+    lines = []
+    for name, value in metrics.items():
+        metric = f"emu8086_{name}_total"
+        lines.append(f"# TYPE {metric} counter")
+        lines.append(f"{metric} {value}")
+    return "\n".join(lines) + "\n"
+
 class Foo(BaseHTTPRequestHandler):
     def do_GET(self):
+        if self.path == "/metrics" and metrics is not None:
+            self.send_response(200)
+            self.send_header("Content-Type", "text/plain; version=0.0.4")
+            self.end_headers()
+            self.wfile.write(render_metrics().encode())
+            return
         self.send_response(200)
         self.send_header("Content-Type", "text/html")
         self.send_header("Access-Control-Allow-Origin", "*")
@@ -61,11 +89,15 @@ class Foo(BaseHTTPRequestHandler):
 def main():
     global path_to_emu
     global server
+    global metrics
     parser = argparse.ArgumentParser(usage="python3 server.py -f path/to/emu")
     parser.add_argument("-f", "--file", required=True, help="path to emu required")
     parser.add_argument("-p", "--port", help="port", default="8023")
+    parser.add_argument("--metrics", action="store_true", help="serve Prometheus metrics at /metrics")
     args = parser.parse_args()
     path_to_emu = args.file
+    if args.metrics:
+        metrics = {"runs": 0}
     print(f"serving on port localhost:{args.port}")
     server = HTTPServer(("localhost", int(args.port)), Foo)
     server.serve_forever()